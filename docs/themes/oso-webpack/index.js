@@ -29,20 +29,30 @@ import('monaco-editor-core').then(monaco => {
   monaco.languages.setMonarchTokensProvider('polar', {
     keywords: [
       'type',
+      'union',
+      'module',
+      'export',
+      'import',
+      'const',
       // TODO: want to make these keywords, but then variables named 'resource' are highlighted
       //'resource',
       //'actor',
       'and',
+      'collect',
+      'count',
       'cut',
       'debug',
       'forall',
       'if',
       'in',
       'matches',
+      'max',
+      'min',
       'new',
       'not',
       'or',
-      'print'
+      'print',
+      'sum'
     ],
     operators: [
       '=',
@@ -0,0 +1,133 @@
+// Checks the TypeScript declarations in the generated `polar_wasm_api.d.ts` against the values
+// the WASM API actually produces. ts-jest typechecks this file, so accessing a field that isn't
+// declared fails to compile, and the assertions fail when the API returns a field or variant
+// that isn't declared.
+import { Polar as FfiPolar } from './polar_wasm_api';
+import type {
+  Diagnostic,
+  FilterPlan,
+  Message,
+  PartialResult,
+  QueryEvent,
+  Term,
+  TraceResult,
+  Types,
+} from './polar_wasm_api';
+
+const integer = (n: number): Term => ({ value: { Number: { Integer: n } } });
+
+const load = (polar: FfiPolar, src: string) =>
+  polar.load([{ src, filename: 'test.polar' }]);
+
+describe('generated WASM declarations', () => {
+  test('describe query events', () => {
+    const polar = new FfiPolar();
+    polar.registerConstant('z', {
+      value: {
+        ExternalInstance: {
+          instance_id: 1,
+          constructor: undefined,
+          repr: undefined,
+        },
+      },
+    });
+    load(polar, 'f(1); g(x) if x.y;');
+
+    const query = polar.newQueryFromStr('f(x)', true);
+    const result: QueryEvent = query.nextEvent();
+    expect(result).toStrictEqual({
+      Result: { bindings: expect.any(Map), trace: expect.any(Object) },
+    });
+    if (typeof result === 'string' || !('Result' in result))
+      throw new Error('expected a result');
+    const x: Term | undefined = result.Result.bindings.get('x');
+    expect(x).toStrictEqual(integer(1));
+    const trace = result.Result.trace as TraceResult;
+    expect(trace).toStrictEqual({
+      trace: { node: expect.any(Object), children: expect.any(Array) },
+      formatted: expect.any(String),
+    });
+    expect(query.nextEvent()).toStrictEqual({ Done: { result: true } });
+
+    const call: QueryEvent = polar.newQueryFromStr('g(z)', false).nextEvent();
+    expect(call).toStrictEqual({
+      ExternalCall: {
+        call_id: expect.any(Number),
+        instance: expect.any(Object),
+        attribute: 'y',
+        args: undefined,
+        kwargs: undefined,
+      },
+    });
+  });
+
+  test('describe messages', () => {
+    const polar = new FfiPolar();
+    load(polar, 'f(1);');
+    const message: Message | undefined = polar.nextMessage();
+    expect(message).toStrictEqual({ kind: 'Warning', msg: expect.any(String) });
+    expect(polar.nextMessage()).toBeUndefined();
+  });
+
+  test('describe diagnostics', () => {
+    const polar = new FfiPolar();
+    const diagnostics: Diagnostic[] = polar.diagnosticLoad([
+      { src: 'f() if g();', filename: 'test.polar' },
+    ]);
+    expect(diagnostics).toStrictEqual([
+      {
+        severity: 'error',
+        kind: 'ValidationError::UndefinedRuleCall',
+        message: 'Call to undefined rule: g()',
+        formatted: expect.any(String),
+        range: {
+          start: { row: 0, column: 7 },
+          end: { row: expect.any(Number), column: expect.any(Number) },
+        },
+        filename: 'test.polar',
+      },
+      {
+        severity: 'warning',
+        kind: 'ValidationWarning::MissingAllowRule',
+        message: expect.any(String),
+        formatted: expect.any(String),
+        range: undefined,
+        filename: undefined,
+      },
+    ]);
+  });
+
+  test('describe filter plans', () => {
+    const polar = new FfiPolar();
+    const types: Types = new Map([
+      ['Repo', new Map([['id', { Base: { class_tag: 'Integer' } }]])],
+    ]);
+    const partialResults: PartialResult[] = [
+      {
+        bindings: new Map([
+          [
+            'resource',
+            { value: { Expression: { operator: 'And', args: [] } } },
+          ],
+        ]),
+      },
+    ];
+    const plan: FilterPlan = polar.buildFilterPlan(
+      types,
+      partialResults,
+      'resource',
+      'Repo'
+    );
+    expect(plan).toStrictEqual({
+      result_sets: [
+        {
+          requests: expect.any(Map),
+          resolve_order: expect.any(Array),
+          result_id: expect.any(Number),
+        },
+      ],
+    });
+    const [request] = plan.result_sets[0].requests.values();
+    expect(request).toStrictEqual({ class_tag: 'Repo', constraints: [] });
+  });
+});
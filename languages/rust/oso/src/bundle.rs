@@ -0,0 +1,227 @@
+//! Policy bundles: versioned, verifiable collections of Polar files.
+//!
+//! A bundle is either a directory or a `.tar`, `.tar.gz` or `.tgz` archive containing a
+//! `manifest.json` file at its root, e.g.:
+//!
+//! ```json
+//! {
+//!   "oso_version": "^0.23",
+//!   "files": ["main.polar", "roles/org.polar"],
+//!   "classes": ["User", "Organization"],
+//!   "constants": ["MAX_DEPTH"],
+//!   "sha256": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+//! }
+//! ```
+//!
+//! - `oso_version` is a [semver requirement](https://docs.rs/semver/1/semver/struct.VersionReq.html)
+//!   that the running version of oso must satisfy.
+//! - `files` are the Polar files to load, in order, relative to the root of the bundle.
+//! - `classes` and `constants` are the names that must be registered before the bundle is loaded.
+//! - `sha256` is the hex-encoded SHA-256 hash of the bundle's contents, as computed by
+//!   [`Manifest::content_hash`].
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path};
+
+use flate2::read::GzDecoder;
+use polar_core::sources::Source;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::OsoError;
+
+/// Name of the manifest file at the root of every bundle.
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+/// Describes the contents and requirements of a policy bundle.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub oso_version: String,
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub classes: Vec<String>,
+    #[serde(default)]
+    pub constants: Vec<String>,
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// Compute the hash recorded in `sha256` for `files`, a list of `(path, contents)` pairs in
+    /// the order they're listed in the manifest.
+    ///
+    /// The hash covers each path and its contents, each followed by a NUL byte, so renaming or
+    /// reordering files changes the hash.
+    pub fn content_hash<'a, I>(files: I) -> String
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let mut hasher = Sha256::new();
+        for (path, contents) in files {
+            hasher.update(path.as_bytes());
+            hasher.update([0]);
+            hasher.update(contents.as_bytes());
+            hasher.update([0]);
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// A policy bundle whose manifest has been parsed and whose contents have been verified against
+/// the manifest's hash and version requirement.
+#[derive(Clone, Debug)]
+pub struct Bundle {
+    pub manifest: Manifest,
+    pub(crate) sources: Vec<Source>,
+}
+
+fn invalid(path: &Path, msg: impl Into<String>) -> OsoError {
+    OsoError::InvalidBundle {
+        path: path.to_string_lossy().into_owned(),
+        msg: msg.into(),
+    }
+}
+
+/// Normalize a path within a bundle, rejecting paths that could escape the bundle.
+fn normalize(path: &Path) -> Option<String> {
+    let mut parts = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// The files in a bundle, keyed by their normalized path.
+enum Contents<'a> {
+    Dir(&'a Path),
+    Archive(HashMap<String, String>),
+}
+
+impl<'a> Contents<'a> {
+    fn open(path: &'a Path) -> crate::Result<Self> {
+        if path.is_dir() {
+            return Ok(Self::Dir(path));
+        }
+
+        let name = path.to_string_lossy();
+        let file = File::open(path)?;
+        let reader: Box<dyn Read> = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Box::new(GzDecoder::new(file))
+        } else if name.ends_with(".tar") {
+            Box::new(file)
+        } else {
+            return Err(invalid(
+                path,
+                "expected a directory or a .tar, .tar.gz or .tgz archive",
+            ));
+        };
+
+        let mut files = HashMap::new();
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            if let Some(key) = normalize(&entry.path()?) {
+                let mut src = String::new();
+                entry.read_to_string(&mut src)?;
+                files.insert(key, src);
+            }
+        }
+        Ok(Self::Archive(files))
+    }
+
+    fn read(&self, key: &str) -> crate::Result<Option<String>> {
+        match self {
+            Self::Dir(path) => match std::fs::read_to_string(path.join(key)) {
+                Ok(src) => Ok(Some(src)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            Self::Archive(files) => Ok(files.get(key).cloned()),
+        }
+    }
+}
+
+impl Bundle {
+    /// Read and verify the bundle at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let contents = Contents::open(path)?;
+
+        let manifest = contents
+            .read(MANIFEST_FILENAME)?
+            .ok_or_else(|| invalid(path, format!("missing {}", MANIFEST_FILENAME)))?;
+        let manifest: Manifest = serde_json::from_str(&manifest)
+            .map_err(|e| invalid(path, format!("malformed {}: {}", MANIFEST_FILENAME, e)))?;
+
+        let required = VersionReq::parse(&manifest.oso_version).map_err(|e| {
+            invalid(
+                path,
+                format!("invalid oso_version {:?}: {}", manifest.oso_version, e),
+            )
+        })?;
+        let version = Version::parse(env!("CARGO_PKG_VERSION")).expect("valid crate version");
+        if !required.matches(&version) {
+            return Err(invalid(
+                path,
+                format!(
+                    "requires oso {}, but this is oso {}",
+                    manifest.oso_version, version
+                ),
+            ));
+        }
+
+        let mut sources = Vec::with_capacity(manifest.files.len());
+        for file in &manifest.files {
+            let key = normalize(Path::new(file))
+                .ok_or_else(|| invalid(path, format!("invalid file path {:?}", file)))?;
+            let filename = path.join(&key).to_string_lossy().into_owned();
+            if !key.ends_with(".polar") {
+                return Err(OsoError::IncorrectFileType { filename });
+            }
+            let src = contents
+                .read(&key)?
+                .ok_or_else(|| invalid(path, format!("missing file {}", file)))?;
+            sources.push(Source {
+                src,
+                filename: Some(filename),
+            });
+        }
+
+        let hash = Manifest::content_hash(
+            manifest
+                .files
+                .iter()
+                .zip(&sources)
+                .map(|(file, source)| (file.as_str(), source.src.as_str())),
+        );
+        if !hash.eq_ignore_ascii_case(&manifest.sha256) {
+            return Err(invalid(
+                path,
+                format!(
+                    "content hash {} does not match manifest hash {}",
+                    hash, manifest.sha256
+                ),
+            ));
+        }
+
+        Ok(Self { manifest, sources })
+    }
+
+    /// The Polar sources listed in the manifest, in order.
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+}
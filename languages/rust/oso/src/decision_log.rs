@@ -0,0 +1,272 @@
+//! Log the authorization decisions an [`Oso`](crate::Oso) instance makes, e.g. for an audit
+//! trail. Set a [`DecisionLogger`] with
+//! [`Oso::set_decision_logger`](crate::Oso::set_decision_logger).
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use polar_core::polar::Polar;
+use polar_core::traces::RuleSpan;
+use serde::Serialize;
+
+use crate::host::Host;
+use crate::query::ResultSet;
+use crate::PolarValue;
+
+/// The call that made a decision.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum DecisionKind {
+    IsAllowed,
+    GetAllowedActions,
+    QueryRule { name: String },
+}
+
+/// A copy of a value passed to or returned from a decision that doesn't hold on to application
+/// data. An application instance is summarized by its class and the value of its `id` attribute,
+/// if the class registers one. The values of its other registered attributes are only kept if
+/// [`DecisionLogger::capture_fields`] asks for them; instances nested in attribute values only keep
+/// their class.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum ValueSummary {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Map(BTreeMap<String, ValueSummary>),
+    List(Vec<ValueSummary>),
+    Variable(String),
+    Instance {
+        class: String,
+        id: Option<Box<ValueSummary>>,
+        attributes: BTreeMap<String, ValueSummary>,
+    },
+    /// A value removed by [`ValueSummary::redact`].
+    Redacted,
+}
+
+/// How much of an application instance to summarize.
+#[derive(Clone, Copy, PartialEq)]
+enum Detail {
+    Class,
+    Id,
+    Fields,
+}
+
+impl ValueSummary {
+    pub(crate) fn new(value: &PolarValue, host: &mut Host, fields: bool) -> Self {
+        let detail = if fields { Detail::Fields } else { Detail::Id };
+        Self::summarize(value, host, detail)
+    }
+
+    fn summarize(value: &PolarValue, host: &mut Host, detail: Detail) -> Self {
+        match value {
+            PolarValue::Integer(i) => Self::Integer(*i),
+            PolarValue::Float(f) => Self::Float(*f),
+            PolarValue::String(s) => Self::String(s.clone()),
+            PolarValue::Boolean(b) => Self::Boolean(*b),
+            PolarValue::Map(map) => Self::Map(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), Self::summarize(v, host, detail)))
+                    .collect(),
+            ),
+            PolarValue::List(list) => Self::List(
+                list.iter()
+                    .map(|v| Self::summarize(v, host, detail))
+                    .collect(),
+            ),
+            PolarValue::Variable(name) => Self::Variable(name.clone()),
+            PolarValue::Instance(instance) => {
+                let class = instance.name(host).to_owned();
+                let names = match instance.class(host) {
+                    Ok(class) if detail != Detail::Class => class.attribute_names(),
+                    _ => vec![],
+                };
+                let mut attributes = BTreeMap::new();
+                for name in names {
+                    if name != "id" && detail != Detail::Fields {
+                        continue;
+                    }
+                    if let Ok(value) = instance.get_attr(name, host) {
+                        let value = Self::summarize(&value, host, Detail::Class);
+                        attributes.insert(name.to_owned(), value);
+                    }
+                }
+                Self::Instance {
+                    class,
+                    id: attributes.remove("id").map(Box::new),
+                    attributes,
+                }
+            }
+        }
+    }
+
+    /// Replace the value of every attribute or map key called `name`, at any depth, with
+    /// [`ValueSummary::Redacted`].
+    pub fn redact(&mut self, name: &str) {
+        let fields = match self {
+            Self::Instance { id, attributes, .. } => {
+                match id {
+                    Some(id) if name == "id" => **id = Self::Redacted,
+                    Some(id) => id.redact(name),
+                    None => {}
+                }
+                attributes
+            }
+            Self::Map(fields) => fields,
+            Self::List(list) => {
+                list.iter_mut().for_each(|value| value.redact(name));
+                return;
+            }
+            _ => return,
+        };
+        for (key, value) in fields.iter_mut() {
+            if key == name {
+                *value = Self::Redacted;
+            } else {
+                value.redact(name);
+            }
+        }
+    }
+}
+
+/// What a decision came to.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum Outcome {
+    /// Whether `is_allowed` allowed the action.
+    Allowed(bool),
+    /// The actions `get_allowed_actions` allowed.
+    Actions(Vec<ValueSummary>),
+    /// The number of results taken from a `query_rule` query.
+    Results(usize),
+    /// The error the decision failed with.
+    Error(String),
+}
+
+/// An authorization decision, passed to [`DecisionLogger::log`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Decision {
+    pub kind: DecisionKind,
+    /// The actor, action and resource for `is_allowed` and `get_allowed_actions` (where the
+    /// action is the variable `action`), or the rule's arguments for `query_rule`.
+    pub args: Vec<ValueSummary>,
+    pub outcome: Outcome,
+    /// The time spent evaluating the query.
+    pub duration: Duration,
+    /// The [version](crate::Oso::policy_version) of the policy that made the decision.
+    pub policy_version: String,
+    /// The rules applied to reach the results, if [`DecisionLogger::rule_spans`] asks for them.
+    pub rules: Vec<RuleSpan>,
+}
+
+/// Receives the decisions made by `is_allowed`, `get_allowed_actions` and `query_rule`.
+///
+/// `is_allowed` and `get_allowed_actions` log their decision before they return. A
+/// `query_rule` query logs its decision when it's dropped, counting the results taken from it.
+pub trait DecisionLogger: Send + Sync {
+    fn log(&self, decision: &Decision);
+
+    /// Whether to log a decision of this kind. Called before the decision is made, so that
+    /// decisions that aren't sampled cost nothing.
+    fn sample(&self, _kind: &DecisionKind) -> bool {
+        true
+    }
+
+    /// Remove sensitive values from `decision` before it's logged, e.g. with
+    /// [`ValueSummary::redact`].
+    fn redact(&self, _decision: &mut Decision) {}
+
+    /// Whether to keep the values of every registered attribute of the application instances
+    /// in a decision, rather than only their `id`. Each attribute is read with its getter for
+    /// every logged decision, so this makes logging slower.
+    fn capture_fields(&self) -> bool {
+        false
+    }
+
+    /// Whether to find the rules applied to reach each result. The queries are traced to do
+    /// so, which makes them slower.
+    fn rule_spans(&self) -> bool {
+        false
+    }
+}
+
+/// A decision being made, logged when the query making it is dropped.
+pub(crate) struct DecisionRecord {
+    logger: Arc<dyn DecisionLogger>,
+    polar: Arc<Polar>,
+    decision: Decision,
+}
+
+impl DecisionRecord {
+    pub(crate) fn new(
+        logger: Arc<dyn DecisionLogger>,
+        polar: Arc<Polar>,
+        kind: DecisionKind,
+        args: Vec<ValueSummary>,
+        policy_version: String,
+    ) -> Self {
+        let outcome = match kind {
+            DecisionKind::IsAllowed => Outcome::Allowed(false),
+            DecisionKind::GetAllowedActions => Outcome::Actions(vec![]),
+            DecisionKind::QueryRule { .. } => Outcome::Results(0),
+        };
+        Self {
+            logger,
+            polar,
+            decision: Decision {
+                kind,
+                args,
+                outcome,
+                duration: Duration::default(),
+                policy_version,
+                rules: vec![],
+            },
+        }
+    }
+
+    /// Whether the query needs a trace to find the rules it applied.
+    pub(crate) fn traced(&self) -> bool {
+        self.logger.rule_spans()
+    }
+
+    /// Record the next result of the query, which took `elapsed` to find.
+    pub(crate) fn record(
+        &mut self,
+        result: Option<&crate::Result<ResultSet>>,
+        elapsed: Duration,
+        host: &mut Host,
+    ) {
+        self.decision.duration += elapsed;
+        match result {
+            Some(Ok(result)) => {
+                match &mut self.decision.outcome {
+                    Outcome::Allowed(allowed) => *allowed = true,
+                    Outcome::Actions(actions) => {
+                        let action = result.get("action");
+                        let fields = self.logger.capture_fields();
+                        actions
+                            .extend(action.map(|action| ValueSummary::new(&action, host, fields)));
+                    }
+                    Outcome::Results(results) => *results += 1,
+                    Outcome::Error(_) => {}
+                }
+                if let Some(trace) = result.trace() {
+                    for span in self.polar.rule_spans(&trace.trace) {
+                        if !self.decision.rules.contains(&span) {
+                            self.decision.rules.push(span);
+                        }
+                    }
+                }
+            }
+            Some(Err(e)) => self.decision.outcome = Outcome::Error(e.to_string()),
+            None => {}
+        }
+    }
+}
+
+impl Drop for DecisionRecord {
+    fn drop(&mut self) {
+        self.logger.redact(&mut self.decision);
+        self.logger.log(&self.decision);
+    }
+}
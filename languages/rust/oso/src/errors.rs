@@ -40,6 +40,9 @@ pub enum OsoError {
     #[error("{operation} are unimplemented in the oso Rust library")]
     UnimplementedOperation { operation: String },
 
+    #[error("Invalid policy bundle {path}: {msg}")]
+    InvalidBundle { path: String, msg: String },
+
     #[error("Inline query failed {location}")]
     InlineQueryFailedError { location: String },
 
@@ -5,6 +5,8 @@ use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 
+use polar_core::data_filtering::Type;
+
 use crate::errors::{InvalidCallError, OsoError};
 
 use super::class_method::{
@@ -56,6 +58,8 @@ pub struct Class {
     attributes: Attributes,
     /// Instance methods on `T` that expect a list of `PolarValue`s, and an instance of `&T`
     instance_methods: InstanceMethods,
+    /// The types of fields on `T`, used for data filtering
+    pub fields: HashMap<String, Type>,
     /// Class methods on `T`
     class_methods: ClassMethods,
 
@@ -105,6 +109,11 @@ impl Class {
         attr.clone().invoke(args)
     }
 
+    /// The names of the attributes registered with `add_attribute_getter`.
+    pub(crate) fn attribute_names(&self) -> Vec<&'static str> {
+        self.attributes.keys().copied().collect()
+    }
+
     fn get_method(&self, name: &str) -> Option<InstanceMethod> {
         tracing::trace!({class=%self.name, name}, "get_method");
         if self.type_id == TypeId::of::<Class>() {
@@ -147,6 +156,7 @@ where
                 constructor: None,
                 attributes: HashMap::new(),
                 instance_methods: InstanceMethods::new(),
+                fields: HashMap::new(),
                 class_methods: ClassMethods::new(),
                 class_check: Arc::new(|type_id| TypeId::of::<T>() == type_id),
                 equality_check: Arc::from(equality_not_supported()),
@@ -255,6 +265,42 @@ where
         self
     }
 
+    /// Declare the type of a field for data filtering, e.g. `class.add_field_type("name", "String")`.
+    /// `class_tag` is the name of a registered class.
+    pub fn add_field_type(mut self, name: &str, class_tag: &str) -> Self {
+        self.class.fields.insert(
+            name.to_string(),
+            Type::Base {
+                class_tag: class_tag.to_string(),
+            },
+        );
+        self
+    }
+
+    /// Declare a field that relates `T` to another class for data filtering. The field
+    /// holds the `other_class_tag` objects whose `other_field` is equal to `my_field` on
+    /// this object. `kind` is `"one"` or `"many"`.
+    /// `class.add_relation("posts", "many", "Post", "id", "author_id")`
+    pub fn add_relation(
+        mut self,
+        name: &str,
+        kind: &str,
+        other_class_tag: &str,
+        my_field: &str,
+        other_field: &str,
+    ) -> Self {
+        self.class.fields.insert(
+            name.to_string(),
+            Type::Relation {
+                kind: kind.to_string(),
+                other_class_tag: other_class_tag.to_string(),
+                my_field: my_field.to_string(),
+                other_field: other_field.to_string(),
+            },
+        );
+        self
+    }
+
     /// Set the name of the polar class.
     pub fn name(mut self, name: &str) -> Self {
         self.class.name = name.to_string();
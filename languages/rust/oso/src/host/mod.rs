@@ -13,6 +13,7 @@ mod value;
 
 pub use class::{Class, ClassBuilder, Instance};
 pub use from_polar::{FromPolar, FromPolarList};
+use polar_core::data_filtering::Types;
 use polar_core::terms::{Operator, Symbol};
 pub use to_polar::{PolarIterator, ToPolar, ToPolarList};
 pub use value::PolarValue;
@@ -123,6 +124,14 @@ impl Host {
         Ok(())
     }
 
+    /// The field types of every registered class, for data filtering.
+    pub fn types(&self) -> Types {
+        self.classes
+            .iter()
+            .map(|(name, class)| (name.clone(), class.fields.clone()))
+            .collect()
+    }
+
     pub fn get_instance(&self, id: u64) -> crate::Result<&class::Instance> {
         tracing::trace!("instances: {:?}", self.instances.keys().collect::<Vec<_>>());
         self.instances
@@ -75,6 +75,8 @@
 pub mod macros;
 
 pub(crate) mod builtins;
+mod bundle;
+pub mod decision_log;
 pub mod errors;
 mod extras;
 mod host;
@@ -82,10 +84,18 @@ mod oso;
 mod query;
 
 pub use crate::oso::{Action, Oso};
+pub use bundle::{Bundle, Manifest, MANIFEST_FILENAME};
+pub use decision_log::{Decision, DecisionKind, DecisionLogger, Outcome, ValueSummary};
 pub use errors::{OsoError, Result};
 pub use host::{Class, ClassBuilder, FromPolar, FromPolarList, PolarValue, ToPolar, ToPolarList};
 pub use query::{Query, ResultSet};
 
+pub use polar_core::data_filtering::{FilterPlan, Record};
+pub use polar_core::diagram::{DiagramFormat, ModelDiagram};
+pub use polar_core::expand::{Grant, Implication, UsersetTree};
+pub use polar_core::permission_matrix::{MatrixFormat, PermissionMatrix};
+pub use polar_core::traces::RuleSpan;
+
 use polar_core::polar::Polar;
 
 /// Classes that can be used as types in Polar policies.
@@ -1,16 +1,24 @@
 //! Communicate with the Polar virtual machine: load rules, make queries, etc/
+use polar_core::data_filtering::FilterPlan;
+use polar_core::diagram::ModelDiagram;
+use polar_core::expand::UsersetTree;
+use polar_core::permission_matrix::PermissionMatrix;
 use polar_core::sources::Source;
-use polar_core::terms::{Call, Symbol, Term, Value};
+use polar_core::terms::{
+    Call, Dictionary, InstanceLiteral, Operation, Operator, Pattern, Symbol, Term, Value,
+};
 
+use std::any::TypeId;
 use std::collections::HashSet;
 use std::fs::File;
 use std::hash::Hash;
 use std::io::Read;
 use std::sync::Arc;
 
+use crate::decision_log::{DecisionKind, DecisionLogger, DecisionRecord, ValueSummary};
 use crate::host::Host;
-use crate::query::Query;
-use crate::{FromPolar, OsoError, PolarValue, ToPolar, ToPolarList};
+use crate::query::{Query, ResultSet};
+use crate::{FromPolar, Manifest, OsoError, PolarValue, ToPolar, ToPolarList};
 
 /// Oso is the main struct you interact with. It is an instance of the Oso authorization library
 /// and contains the polar language knowledge base and query engine.
@@ -18,6 +26,8 @@ use crate::{FromPolar, OsoError, PolarValue, ToPolar, ToPolarList};
 pub struct Oso {
     inner: Arc<polar_core::polar::Polar>,
     host: Host,
+    decision_logger: Option<Arc<dyn DecisionLogger>>,
+    policy_version: String,
 }
 
 impl Default for Oso {
@@ -52,7 +62,12 @@ impl Oso {
         let inner = Arc::new(polar_core::polar::Polar::new());
         let host = Host::new(inner.clone());
 
-        let mut oso = Self { inner, host };
+        let mut oso = Self {
+            inner,
+            host,
+            decision_logger: None,
+            policy_version: policy_version(&[]),
+        };
 
         for class in crate::builtins::classes() {
             oso.register_class(class)
@@ -63,7 +78,75 @@ impl Oso {
         oso
     }
 
+    /// Build the `allow(args)` query behind an authorization decision. Polar runs it as
+    /// `allow(args) and not deny(args)` while any `deny` rules are loaded.
+    fn authorization_query(&self, query_host: &mut Host, args: Vec<PolarValue>) -> Term {
+        let args = args.iter().map(|value| value.to_term(query_host)).collect();
+        Term::new_from_ffi(Value::Call(Call {
+            name: Symbol("allow".to_owned()),
+            args,
+            kwargs: None,
+        }))
+    }
+
+    /// Run an authorization query built by [`Oso::authorization_query`].
+    fn query_authorization(&self, kind: DecisionKind, args: impl ToPolarList) -> Query {
+        let mut query_host = self.host.clone();
+        let args = args.to_polar_list();
+        let decision = self.record_decision(kind, &args, &mut query_host);
+        let trace = decision.iter().any(DecisionRecord::traced);
+        let query_term = self.authorization_query(&mut query_host, args);
+        let query = self.inner.new_query_from_term(query_term, trace);
+        check_messages!(self.inner);
+        Query::new(query, query_host).with_decision(decision)
+    }
+
+    /// Start recording a decision for the decision logger, if there is one and it samples the
+    /// decision.
+    fn record_decision(
+        &self,
+        kind: DecisionKind,
+        args: &[PolarValue],
+        query_host: &mut Host,
+    ) -> Option<DecisionRecord> {
+        let logger = self.decision_logger.as_ref()?;
+        if !logger.sample(&kind) {
+            return None;
+        }
+        let fields = logger.capture_fields();
+        let args = args
+            .iter()
+            .map(|arg| ValueSummary::new(arg, query_host, fields))
+            .collect();
+        Some(DecisionRecord::new(
+            logger.clone(),
+            self.inner.clone(),
+            kind,
+            args,
+            self.policy_version.clone(),
+        ))
+    }
+
+    /// Log every decision made by [`Oso::is_allowed`], [`Oso::get_allowed_actions`] and
+    /// [`Oso::query_rule`] with `logger`, replacing any logger set before.
+    pub fn set_decision_logger(&mut self, logger: Arc<dyn DecisionLogger>) {
+        self.decision_logger = Some(logger);
+    }
+
+    /// Stop logging decisions.
+    pub fn clear_decision_logger(&mut self) {
+        self.decision_logger = None;
+    }
+
+    /// A SHA-256 hash of the names and contents of the loaded policy files, which changes
+    /// whenever a different policy is loaded. Decisions are logged with the version of the
+    /// policy that made them.
+    pub fn policy_version(&self) -> &str {
+        &self.policy_version
+    }
+
     /// High level interface for authorization decisions. Makes an allow query with the given actor, action and resource and returns true or false.
+    /// Any matching `deny` rule overrides the `allow` rules.
     pub fn is_allowed<Actor, Action, Resource>(
         &self,
         actor: Actor,
@@ -75,7 +158,8 @@ impl Oso {
         Action: ToPolar,
         Resource: ToPolar,
     {
-        let mut query = self.query_rule("allow", (actor, action, resource)).unwrap();
+        let mut query =
+            self.query_authorization(DecisionKind::IsAllowed, (actor, action, resource));
         match query.next() {
             Some(Ok(_)) => Ok(true),
             Some(Err(e)) => Err(e),
@@ -85,6 +169,8 @@ impl Oso {
 
     /// Get the actions actor is allowed to take on resource.
     /// Returns a [std::collections::HashSet] of actions, typed according the return value.
+    /// Actions that a `deny` rule matches are left out. If the actor is allowed any action, it
+    /// is left out as well when any `deny` rule could match.
     /// # Examples
     /// ```ignore
     /// oso.load_str(r#"allow(actor: Actor{name: "sally"}, action, resource: Widget{id: 1}) if
@@ -106,12 +192,10 @@ impl Oso {
         Resource: ToPolar,
         T: FromPolar + Eq + Hash,
     {
-        let mut query = self
-            .query_rule(
-                "allow",
-                (actor, PolarValue::Variable("action".to_owned()), resource),
-            )
-            .unwrap();
+        let mut query = self.query_authorization(
+            DecisionKind::GetAllowedActions,
+            (actor, PolarValue::Variable("action".to_owned()), resource),
+        );
 
         let mut set = HashSet::new();
         loop {
@@ -129,9 +213,175 @@ impl Oso {
         Ok(set)
     }
 
+    /// Get a filter plan for the actors of type `Actor` that are allowed to take `action`
+    /// on `resource`, e.g. to list the users who can view a document. The plan is built from
+    /// the field types declared with [`ClassBuilder::add_field_type`](crate::ClassBuilder::add_field_type)
+    /// and [`ClassBuilder::add_relation`](crate::ClassBuilder::add_relation), and is resolved
+    /// against your data store by fetching its requests in order. Actors that a `deny` rule
+    /// matches are excluded from the plan.
+    /// # Examples
+    /// ```ignore
+    /// oso.load_str(r#"allow(user: User, "read", doc: Doc) if user.org_id = doc.org_id;"#);
+    ///
+    /// let plan = oso.authorized_actors::<User, _, _>("read", doc)?;
+    /// ```
+    pub fn authorized_actors<Actor, Action, Resource>(
+        &self,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<FilterPlan>
+    where
+        Actor: 'static,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let class_tag = self
+            .host
+            .get_class_by_type_id(TypeId::of::<Actor>())?
+            .name
+            .clone();
+        let actor = Symbol("actor".to_owned());
+
+        let mut query_host = self.host.clone();
+        query_host.accept_expression = true;
+        let query_term = self.authorization_query(
+            &mut query_host,
+            (PolarValue::Variable(actor.0.clone()), action, resource).to_polar_list(),
+        );
+        let mut query = self.inner.new_query_from_term(query_term, false);
+        check_messages!(self.inner);
+
+        // Constrain the actor to the class, leaving it otherwise unbound so that the
+        // query returns the constraints on it.
+        let pattern = Pattern::Instance(InstanceLiteral {
+            tag: Symbol(class_tag.clone()),
+            fields: Dictionary::new(),
+        });
+        let isa = Operation {
+            operator: Operator::Isa,
+            args: vec![
+                Term::new_from_ffi(Value::Variable(actor.clone())),
+                Term::new_from_ffi(Value::Pattern(pattern)),
+            ],
+        };
+        let constraint = Operation {
+            operator: Operator::And,
+            args: vec![Term::new_from_ffi(Value::Expression(isa))],
+        };
+        query.bind(
+            actor.clone(),
+            Term::new_from_ffi(Value::Expression(constraint)),
+        )?;
+
+        let mut results = vec![];
+        for result in Query::new(query, query_host) {
+            results.push(result?.into_event());
+        }
+        let plan =
+            self.inner
+                .build_filter_plan(self.host.types(), results, &actor.0, &class_tag)?;
+        Ok(plan)
+    }
+
+    /// Expand `permission` (or a role) on `resource` into the tree of actors that have it: one
+    /// branch per shorthand rule in the resource's block, following relations to related resources
+    /// with `has_relation`, down to the actors that hand-written `has_role` and `has_permission`
+    /// rules grant it to. Each of those leaves names the rule that granted it, and the relationship
+    /// store fact the rule matched, if any.
+    pub fn expand<Resource: ToPolar>(
+        &self,
+        resource: Resource,
+        permission: &str,
+    ) -> crate::Result<UsersetTree> {
+        let mut query_host = self.host.clone();
+        query_host.accept_expression = true;
+        let resource = resource.to_polar().to_term(&mut query_host);
+        // Run every query with the same host, so that instances returned by one query (e.g.,
+        // related resources) can be passed to the next.
+        let mut query_host = Some(query_host);
+        let tree = self.inner.expand(resource, permission, |query| {
+            // Each query hands the host back once it's done, before the next one starts.
+            let host = query_host.take().ok_or_else(|| OsoError::Custom {
+                message: "expand ran a query before the previous one finished".to_owned(),
+            })?;
+            let mut query = Query::new(query, host);
+            let answers = query
+                .by_ref()
+                .map(|result| result.map(ResultSet::into_answer))
+                .collect::<crate::Result<Vec<_>>>();
+            query_host = Some(query.into_host());
+            answers
+        })?;
+        check_messages!(self.inner);
+        Ok(tree)
+    }
+
+    /// Compute a role × permission matrix for each resource block in the loaded policy, following
+    /// the shorthand rules (including those through relations and global roles). Export them with
+    /// [`MatrixFormat::export`](crate::MatrixFormat::export).
+    pub fn permission_matrices(&self) -> Vec<PermissionMatrix> {
+        self.inner.permission_matrices()
+    }
+
+    /// Draw a diagram of the loaded policy's resource blocks: their types, roles and permissions,
+    /// connected by relations and shorthand rules. If `rule_calls` is true, the call graph of the
+    /// policy's rules is drawn as well. Render it with
+    /// [`DiagramFormat::export`](crate::DiagramFormat::export).
+    pub fn model_diagram(&self, rule_calls: bool) -> ModelDiagram {
+        self.inner.model_diagram(rule_calls)
+    }
+
+    /// Add a fact to the relationship store that policies query with the built-in
+    /// `relationship(subject, relation, object)` predicate. An application instance only matches
+    /// itself; to refer to objects by type and ID instead, load the facts from a file with
+    /// [`Oso::load_relationships`].
+    pub fn add_relationship<Subject: ToPolar, Object: ToPolar>(
+        &mut self,
+        subject: Subject,
+        relation: &str,
+        object: Object,
+    ) {
+        let subject = subject.to_polar().to_term(&mut self.host);
+        let object = object.to_polar().to_term(&mut self.host);
+        self.inner.add_relationship(subject, relation, object);
+    }
+
+    /// Load facts into the relationship store from a `.json` or `.csv` file. A JSON file holds an
+    /// array of `{"subject": ..., "relation": ..., "object": ...}` objects, where an object
+    /// `{"type": "User", "id": 1}` refers to the application object of that type and ID. A CSV file
+    /// has one `subject,relation,object` line per fact, with `User:1` for such a reference.
+    pub fn load_relationships<P: AsRef<std::path::Path>>(&mut self, path: P) -> crate::Result<()> {
+        let path = path.as_ref();
+        let json = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => true,
+            Some("csv") => false,
+            _ => {
+                return lazy_error!(
+                    "relationship files must have the .json or .csv extension. {} does not.",
+                    path.display()
+                )
+            }
+        };
+        let mut f = File::open(path)?;
+        let mut src = String::new();
+        f.read_to_string(&mut src)?;
+        if json {
+            self.inner.load_relationships_json(&src)?;
+        } else {
+            self.inner.load_relationships_csv(&src)?;
+        }
+        Ok(())
+    }
+
+    /// Remove every fact from the relationship store.
+    pub fn clear_relationships(&mut self) {
+        self.inner.clear_relationships();
+    }
+
     /// Clear out all files and rules that have been loaded.
     pub fn clear_rules(&mut self) -> crate::Result<()> {
         self.inner.clear_rules();
+        self.policy_version = policy_version(&[]);
         check_messages!(self.inner);
         Ok(())
     }
@@ -153,7 +403,9 @@ impl Oso {
     // Register MROs, load Polar code, and check inline queries.
     fn load_sources(&mut self, sources: Vec<Source>) -> crate::Result<()> {
         self.host.register_mros()?;
+        let version = policy_version(&sources);
         self.inner.load(sources)?;
+        self.policy_version = version;
         self.check_inline_queries()
     }
 
@@ -195,6 +447,33 @@ impl Oso {
         self.load_sources(sources)
     }
 
+    /// Load a policy bundle: a directory or a `.tar`, `.tar.gz` or `.tgz` archive containing a
+    /// `manifest.json` that lists the bundle's Polar files along with the oso version, classes
+    /// and constants it requires. See [`Manifest`](crate::Manifest) for the manifest format.
+    ///
+    /// The bundle's contents are verified against the manifest's hash, and every class and
+    /// constant declared in the manifest must be registered before the bundle is loaded.
+    pub fn load_bundle<P: AsRef<std::path::Path>>(&mut self, path: P) -> crate::Result<()> {
+        let bundle = crate::Bundle::open(path.as_ref())?;
+
+        for name in &bundle.manifest.classes {
+            self.host.get_class(name)?;
+        }
+        {
+            let kb = self.inner.kb.read().unwrap();
+            for name in &bundle.manifest.constants {
+                if !kb.is_constant(&Symbol(name.clone())) {
+                    return Err(OsoError::InvalidBundle {
+                        path: path.as_ref().to_string_lossy().into_owned(),
+                        msg: format!("constant {} has not been registered", name),
+                    });
+                }
+            }
+        }
+
+        self.load_sources(bundle.sources)
+    }
+
     /// Load a string of polar source directly.
     /// # Examples
     /// ```ignore
@@ -229,8 +508,13 @@ impl Oso {
     #[must_use = "Query that is not consumed does nothing."]
     pub fn query_rule(&self, name: &str, args: impl ToPolarList) -> crate::Result<Query> {
         let mut query_host = self.host.clone();
+        let args = args.to_polar_list();
+        let kind = DecisionKind::QueryRule {
+            name: name.to_owned(),
+        };
+        let decision = self.record_decision(kind, &args, &mut query_host);
+        let trace = decision.iter().any(DecisionRecord::traced);
         let args = args
-            .to_polar_list()
             .iter()
             .map(|value| value.to_term(&mut query_host))
             .collect();
@@ -240,9 +524,9 @@ impl Oso {
             kwargs: None,
         });
         let query_term = Term::new_from_ffi(query_value);
-        let query = self.inner.new_query_from_term(query_term, false);
+        let query = self.inner.new_query_from_term(query_term, trace);
         check_messages!(self.inner);
-        let query = Query::new(query, query_host);
+        let query = Query::new(query, query_host).with_decision(decision);
         Ok(query)
     }
 
@@ -273,6 +557,16 @@ impl Oso {
     }
 }
 
+/// The version of a policy loaded from `sources`: the hash of their names and contents.
+fn policy_version(sources: &[Source]) -> String {
+    Manifest::content_hash(sources.iter().map(|source| {
+        (
+            source.filename.as_deref().unwrap_or(""),
+            source.src.as_str(),
+        )
+    }))
+}
+
 // Make sure the `Oso` object is threadsafe
 #[cfg(test)]
 static_assertions::assert_impl_all!(Oso: Send, Sync);
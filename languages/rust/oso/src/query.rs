@@ -1,12 +1,15 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::time::Instant;
 
+use crate::decision_log::DecisionRecord;
 use crate::errors::OsoError;
 use crate::host::{Host, Instance, PolarIterator};
 use crate::{FromPolar, PolarValue};
 
 use polar_core::events::*;
 use polar_core::terms::*;
+use polar_core::traces::TraceResult;
 
 impl Iterator for Query {
     type Item = crate::Result<ResultSet>;
@@ -20,6 +23,8 @@ pub struct Query {
     /// Stores a map from call_id to the iterator the call iterates through
     iterators: HashMap<u64, PolarIterator>,
     host: Host,
+    /// The decision the query makes, if it's logged.
+    decision: Option<DecisionRecord>,
 }
 
 impl Query {
@@ -28,14 +33,34 @@ impl Query {
             iterators: HashMap::new(),
             inner,
             host,
+            decision: None,
         }
     }
 
+    /// Record the results of the query as `decision`, logged when the query is dropped.
+    pub(crate) fn with_decision(self, decision: Option<DecisionRecord>) -> Self {
+        Self { decision, ..self }
+    }
+
     pub fn source(&self) -> String {
         self.inner.source_info()
     }
 
+    /// The host, along with any instances cached while running the query.
+    pub(crate) fn into_host(self) -> Host {
+        self.host
+    }
+
     pub fn next_result(&mut self) -> Option<crate::Result<ResultSet>> {
+        let start = Instant::now();
+        let result = self.next_query_result();
+        if let Some(decision) = &mut self.decision {
+            decision.record(result.as_ref(), start.elapsed(), &mut self.host);
+        }
+        result
+    }
+
+    fn next_query_result(&mut self) -> Option<crate::Result<ResultSet>> {
         loop {
             let event = self.inner.next()?;
             check_messages!(self.inner);
@@ -47,8 +72,9 @@ impl Query {
             let result = match event {
                 QueryEvent::None => Ok(()),
                 QueryEvent::Done { .. } => return None,
-                QueryEvent::Result { bindings, .. } => {
-                    return Some(ResultSet::from_bindings(bindings, self.host.clone()));
+                QueryEvent::Result { bindings, trace } => {
+                    let result = ResultSet::from_bindings(bindings, self.host.clone());
+                    return Some(result.map(|result| ResultSet { trace, ..result }));
                 }
                 QueryEvent::MakeExternal {
                     instance_id,
@@ -273,6 +299,7 @@ impl Query {
 pub struct ResultSet {
     bindings: polar_core::kb::Bindings,
     host: crate::host::Host,
+    trace: Option<TraceResult>,
 }
 
 impl ResultSet {
@@ -294,7 +321,11 @@ This may mean you performed an operation in your policy over an unbound variable
             }
         }
 
-        Ok(Self { bindings, host })
+        Ok(Self {
+            bindings,
+            host,
+            trace: None,
+        })
     }
 
     /// Return the keys in bindings.
@@ -325,6 +356,16 @@ This may mean you performed an operation in your policy over an unbound variable
     pub fn into_event(self) -> ResultEvent {
         ResultEvent::new(self.bindings)
     }
+
+    /// The trace of the query that found this result, if it was traced.
+    pub(crate) fn trace(&self) -> Option<&TraceResult> {
+        self.trace.as_ref()
+    }
+
+    /// The bindings along with the trace of the query, if it was traced.
+    pub(crate) fn into_answer(self) -> (polar_core::kb::Bindings, Option<TraceResult>) {
+        (self.bindings, self.trace)
+    }
 }
 
 impl std::fmt::Debug for ResultSet {
@@ -1,16 +1,19 @@
 //! Code for making interactive Oso queries from a REPL.
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use rustyline::error::ReadlineError;
 use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::Editor;
 use rustyline_derive::{Completer, Helper, Highlighter, Hinter};
 
-use oso::Oso;
+use oso::{DiagramFormat, MatrixFormat, Oso};
+use polar_core::diagram::model_diagram_from_sources;
 use polar_core::formatting::to_polar::ToPolarString;
+use polar_core::permission_matrix::permission_matrices_from_sources;
+use polar_core::sources::Source;
 
 use std::env;
-use std::fs::OpenOptions;
+use std::fs::{self, OpenOptions};
 
 /// Build the App for handling command line parameters
 fn build_app() -> App<'static, 'static> {
@@ -23,6 +26,69 @@ fn build_app() -> App<'static, 'static> {
                 .multiple(true)
                 .help("Specify one or more .polar files to load"),
         )
+        .arg(
+            Arg::with_name("relationships")
+                .long("relationships")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("FILE")
+                .help("Load facts for the relationship predicate from a .json or .csv file"),
+        )
+        .subcommand(
+            SubCommand::with_name("matrix")
+                .about("Print the role × permission matrix of each resource block")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["markdown", "csv", "json"])
+                        .default_value("markdown")
+                        .help("Output format"),
+                )
+                .arg(
+                    Arg::with_name("FILES")
+                        .multiple(true)
+                        .required(true)
+                        .help("Specify one or more .polar files to load"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diagram")
+                .about(
+                    "Print a diagram of the resource blocks, their relations and shorthand rules",
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["dot", "mermaid"])
+                        .default_value("dot")
+                        .help("Output format"),
+                )
+                .arg(
+                    Arg::with_name("rules")
+                        .long("rules")
+                        .help("Also draw the call graph of the policy's rules"),
+                )
+                .arg(
+                    Arg::with_name("FILES")
+                        .multiple(true)
+                        .required(true)
+                        .help("Specify one or more .polar files to load"),
+                ),
+        )
+}
+
+/// Read the Polar files given to a subcommand.
+fn read_sources(matches: &ArgMatches) -> anyhow::Result<Vec<Source>> {
+    let mut sources = vec![];
+    for filename in matches.values_of("FILES").unwrap() {
+        let src = fs::read_to_string(filename)?;
+        let filename = Some(filename.to_owned());
+        sources.push(Source { filename, src });
+    }
+    Ok(sources)
 }
 
 /// Attempt to create a new temporary directory to store
@@ -111,13 +177,41 @@ impl Drop for Repl {
 
 pub fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
-    let mut repl = Repl::new();
-    let mut oso = Oso::new();
 
     let matches = build_app().get_matches();
+    if let Some(matches) = matches.subcommand_matches("matrix") {
+        // Read the files ourselves instead of loading them into `oso`, so that the classes the
+        // policy refers to don't have to be registered.
+        let sources = read_sources(matches)?;
+        let format: MatrixFormat = matches
+            .value_of("format")
+            .unwrap()
+            .parse()
+            .map_err(anyhow::Error::msg)?;
+        let matrices = permission_matrices_from_sources(sources)?;
+        print!("{}", format.export(&matrices));
+        return Ok(());
+    }
+    if let Some(matches) = matches.subcommand_matches("diagram") {
+        let sources = read_sources(matches)?;
+        let format: DiagramFormat = matches
+            .value_of("format")
+            .unwrap()
+            .parse()
+            .map_err(anyhow::Error::msg)?;
+        let diagram = model_diagram_from_sources(sources, matches.is_present("rules"))?;
+        print!("{}", format.export(&diagram));
+        return Ok(());
+    }
+
+    let mut repl = Repl::new();
+    let mut oso = Oso::new();
     if matches.is_present("FILES") {
         oso.load_files(matches.values_of("FILES").unwrap().collect())?;
     }
+    for path in matches.values_of("relationships").into_iter().flatten() {
+        oso.load_relationships(path)?;
+    }
 
     loop {
         // get input
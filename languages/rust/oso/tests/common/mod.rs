@@ -1,5 +1,8 @@
 #![allow(dead_code)]
+use std::collections::BTreeMap;
+
 use oso::Oso;
+use polar_core::terms::{Dictionary, Symbol, Term, Value};
 
 pub struct OsoTest {
     pub oso: Oso,
@@ -99,3 +102,12 @@ impl OsoTest {
 pub fn setup() {
     let _ = tracing_subscriber::fmt::try_init();
 }
+
+/// A record returned by a data store fetch, a dictionary of `fields`.
+pub fn record(fields: &[(&str, Value)]) -> Term {
+    let fields = fields
+        .iter()
+        .map(|(name, value)| (Symbol(name.to_string()), Term::from(value.clone())))
+        .collect::<BTreeMap<_, _>>();
+    Term::from(Value::Dictionary(Dictionary { fields }))
+}
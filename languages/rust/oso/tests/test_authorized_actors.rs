@@ -0,0 +1,118 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::record;
+use oso::{Oso, OsoError, PolarClass, Record};
+use polar_core::terms::{Term, Value};
+
+#[derive(PolarClass, Clone)]
+struct User {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    org_id: i64,
+}
+
+#[derive(PolarClass, Clone)]
+struct Membership {
+    #[polar(attribute)]
+    user_id: i64,
+    #[polar(attribute)]
+    doc_id: i64,
+    #[polar(attribute)]
+    role: String,
+}
+
+#[derive(PolarClass, Clone)]
+struct Doc {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    org_id: i64,
+}
+
+const POLICY: &str = r#"
+allow(user: User, "read", doc: Doc) if user.org_id = doc.org_id;
+allow(user: User, "edit", doc: Doc) if
+    m in user.memberships and
+    m.doc_id = doc.id and
+    m.role = "editor";
+"#;
+
+fn test_oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(
+        User::get_polar_class_builder()
+            .add_field_type("id", "Integer")
+            .add_field_type("org_id", "Integer")
+            .add_relation("memberships", "many", "Membership", "id", "user_id")
+            .build(),
+    )
+    .unwrap();
+    oso.register_class(
+        Membership::get_polar_class_builder()
+            .add_field_type("user_id", "Integer")
+            .add_field_type("doc_id", "Integer")
+            .add_field_type("role", "String")
+            .build(),
+    )
+    .unwrap();
+    oso.register_class(Doc::get_polar_class()).unwrap();
+    oso.load_str(POLICY).unwrap();
+    oso
+}
+
+/// Users 1 and 2 are in org 1 and user 3 is in org 2. User 1 is an editor of doc 1 and
+/// user 3 is a viewer of it.
+fn records() -> HashMap<String, Vec<Term>> {
+    let user =
+        |id: i64, org_id: i64| record(&[("id", Value::from(id)), ("org_id", Value::from(org_id))]);
+    let membership = |user_id: i64, role: &str| {
+        record(&[
+            ("user_id", Value::from(user_id)),
+            ("doc_id", Value::from(1)),
+            ("role", Value::from(role.to_owned())),
+        ])
+    };
+    maplit::hashmap! {
+        "User".to_owned() => vec![user(1, 1), user(2, 1), user(3, 2)],
+        "Membership".to_owned() => vec![membership(1, "editor"), membership(3, "viewer")],
+    }
+}
+
+fn authorized_user_ids(oso: &Oso, action: &str, doc: Doc) -> Vec<i64> {
+    let plan = oso.authorized_actors::<User, _, _>(action, doc).unwrap();
+    let mut ids = plan
+        .execute(&records())
+        .unwrap()
+        .iter()
+        .map(|user| match user.field("id").unwrap().value() {
+            Value::Number(n) => format!("{}", n).parse().unwrap(),
+            value => panic!("unexpected id {:?}", value),
+        })
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn test_authorized_actors() {
+    let oso = test_oso();
+    let doc = |org_id| Doc { id: 1, org_id };
+
+    assert_eq!(authorized_user_ids(&oso, "read", doc(1)), vec![1, 2]);
+    assert_eq!(authorized_user_ids(&oso, "read", doc(2)), vec![3]);
+    assert_eq!(authorized_user_ids(&oso, "edit", doc(1)), vec![1]);
+    assert!(authorized_user_ids(&oso, "delete", doc(1)).is_empty());
+}
+
+#[test]
+fn test_authorized_actors_unregistered_class() {
+    #[derive(PolarClass, Clone)]
+    struct Robot;
+
+    let oso = test_oso();
+    let result = oso.authorized_actors::<Robot, _, _>("read", Doc { id: 1, org_id: 1 });
+    assert!(matches!(result, Err(OsoError::MissingClassError { .. })));
+}
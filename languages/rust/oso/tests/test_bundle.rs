@@ -0,0 +1,140 @@
+use std::fs;
+use std::path::Path;
+
+use oso::{Manifest, Oso, OsoError, PolarClass, MANIFEST_FILENAME};
+
+#[derive(PolarClass, Clone)]
+struct User {
+    #[polar(attribute)]
+    name: String,
+}
+
+const FILES: &[(&str, &str)] = &[
+    (
+        "main.polar",
+        "allow(user: User, \"read\", _) if is_admin(user);",
+    ),
+    (
+        "roles/admin.polar",
+        "is_admin(user: User) if user.name = ADMIN;",
+    ),
+];
+
+fn manifest() -> Manifest {
+    Manifest {
+        oso_version: format!("={}", env!("CARGO_PKG_VERSION")),
+        files: FILES.iter().map(|(path, _)| path.to_string()).collect(),
+        classes: vec!["User".to_owned()],
+        constants: vec!["ADMIN".to_owned()],
+        sha256: Manifest::content_hash(FILES.iter().copied()),
+    }
+}
+
+fn write_bundle(dir: &Path, manifest: &Manifest) {
+    fs::write(
+        dir.join(MANIFEST_FILENAME),
+        serde_json::to_string(manifest).unwrap(),
+    )
+    .unwrap();
+    for (path, src) in FILES {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, src).unwrap();
+    }
+}
+
+fn test_oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_constant("alice", "ADMIN").unwrap();
+    oso
+}
+
+fn assert_loaded(oso: &Oso) {
+    let alice = User {
+        name: "alice".to_owned(),
+    };
+    let bob = User {
+        name: "bob".to_owned(),
+    };
+    assert!(oso.is_allowed(alice, "read", "doc").unwrap());
+    assert!(!oso.is_allowed(bob, "read", "doc").unwrap());
+}
+
+fn assert_invalid(err: OsoError, expected: &str) {
+    match err {
+        OsoError::InvalidBundle { msg, .. } => assert!(msg.contains(expected), "{}", msg),
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_load_bundle_from_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    write_bundle(dir.path(), &manifest());
+
+    let mut oso = test_oso();
+    oso.load_bundle(dir.path()).unwrap();
+    assert_loaded(&oso);
+}
+
+#[test]
+fn test_load_bundle_from_archive() {
+    let dir = tempfile::tempdir().unwrap();
+    write_bundle(dir.path(), &manifest());
+
+    let archive = tempfile::Builder::new()
+        .suffix(".tar.gz")
+        .tempfile()
+        .unwrap();
+    let encoder = flate2::write::GzEncoder::new(archive.reopen().unwrap(), Default::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder.append_dir_all(".", dir.path()).unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+
+    let mut oso = test_oso();
+    oso.load_bundle(archive.path()).unwrap();
+    assert_loaded(&oso);
+}
+
+#[test]
+fn test_load_bundle_validates_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut tampered = manifest();
+    tampered.sha256 = Manifest::content_hash(FILES[..1].iter().copied());
+    write_bundle(dir.path(), &tampered);
+    assert_invalid(
+        test_oso().load_bundle(dir.path()).unwrap_err(),
+        "does not match manifest hash",
+    );
+
+    let mut incompatible = manifest();
+    incompatible.oso_version = "<0.1".to_owned();
+    write_bundle(dir.path(), &incompatible);
+    assert_invalid(
+        test_oso().load_bundle(dir.path()).unwrap_err(),
+        "requires oso <0.1",
+    );
+
+    let mut escaping = manifest();
+    escaping.files.push("../other.polar".to_owned());
+    write_bundle(dir.path(), &escaping);
+    assert_invalid(
+        test_oso().load_bundle(dir.path()).unwrap_err(),
+        "invalid file path",
+    );
+
+    // Declared classes and constants must be registered.
+    write_bundle(dir.path(), &manifest());
+    let mut oso = Oso::new();
+    assert!(matches!(
+        oso.load_bundle(dir.path()).unwrap_err(),
+        OsoError::MissingClassError { name } if name == "User"
+    ));
+    oso.register_class(User::get_polar_class()).unwrap();
+    assert_invalid(
+        oso.load_bundle(dir.path()).unwrap_err(),
+        "constant ADMIN has not been registered",
+    );
+}
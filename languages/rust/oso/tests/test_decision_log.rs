@@ -0,0 +1,286 @@
+use std::sync::{Arc, Mutex};
+
+use oso::{
+    Decision, DecisionKind, DecisionLogger, Manifest, Oso, Outcome, PolarClass, RuleSpan,
+    ValueSummary,
+};
+
+#[derive(PolarClass, Clone)]
+struct User {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    name: String,
+    #[polar(attribute)]
+    email: String,
+}
+
+#[derive(PolarClass, Clone)]
+struct Repo {
+    #[polar(attribute)]
+    owner: User,
+}
+
+const POLICY: &str = r#"allow(user: User, action, repo: Repo) if
+  action in ["pull", "push"] and
+  user.name = repo.owner.name;
+allow(_: User, "pull", _: Repo);
+
+is_owner(user: User, repo: Repo) if user.name = repo.owner.name;
+"#;
+
+#[derive(Default)]
+struct TestLogger {
+    decisions: Mutex<Vec<Decision>>,
+    skip_is_allowed: bool,
+    capture_fields: bool,
+    rule_spans: bool,
+}
+
+impl TestLogger {
+    fn decisions(&self) -> Vec<Decision> {
+        self.decisions.lock().unwrap().clone()
+    }
+}
+
+impl DecisionLogger for TestLogger {
+    fn log(&self, decision: &Decision) {
+        self.decisions.lock().unwrap().push(decision.clone());
+    }
+
+    fn sample(&self, kind: &DecisionKind) -> bool {
+        !(self.skip_is_allowed && kind == &DecisionKind::IsAllowed)
+    }
+
+    fn redact(&self, decision: &mut Decision) {
+        for arg in &mut decision.args {
+            arg.redact("email");
+        }
+    }
+
+    fn capture_fields(&self) -> bool {
+        self.capture_fields
+    }
+
+    fn rule_spans(&self) -> bool {
+        self.rule_spans
+    }
+}
+
+fn test_oso(logger: &Arc<TestLogger>) -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Repo::get_polar_class()).unwrap();
+    oso.load_str(POLICY).unwrap();
+    oso.set_decision_logger(logger.clone());
+    oso
+}
+
+fn user(name: &str) -> User {
+    User {
+        id: name.len() as i64,
+        name: name.to_owned(),
+        email: format!("{}@example.com", name),
+    }
+}
+
+fn summary(class: &str, id: Option<i64>, attributes: Vec<(&str, ValueSummary)>) -> ValueSummary {
+    ValueSummary::Instance {
+        class: class.to_owned(),
+        id: id.map(|id| Box::new(ValueSummary::Integer(id))),
+        attributes: attributes
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect(),
+    }
+}
+
+#[test]
+fn test_log_is_allowed() {
+    let logger = Arc::new(TestLogger::default());
+    let oso = test_oso(&logger);
+    let repo = Repo {
+        owner: user("alice"),
+    };
+    assert!(oso.is_allowed(user("alice"), "push", repo.clone()).unwrap());
+    assert!(!oso.is_allowed(user("bob"), "push", repo).unwrap());
+
+    let decisions = logger.decisions();
+    assert_eq!(decisions.len(), 2);
+    let decision = &decisions[0];
+    assert_eq!(decision.kind, DecisionKind::IsAllowed);
+    // Instances are summarized by their class and id, if they have one.
+    assert_eq!(
+        decision.args,
+        vec![
+            summary("User", Some(5), vec![]),
+            ValueSummary::String("push".to_owned()),
+            summary("Repo", None, vec![]),
+        ]
+    );
+    assert_eq!(decision.outcome, Outcome::Allowed(true));
+    assert_eq!(decision.policy_version, oso.policy_version());
+    assert!(decision.rules.is_empty());
+    assert_eq!(decisions[1].outcome, Outcome::Allowed(false));
+}
+
+#[test]
+fn test_capture_fields() {
+    let logger = Arc::new(TestLogger {
+        capture_fields: true,
+        ..TestLogger::default()
+    });
+    let oso = test_oso(&logger);
+    let repo = Repo {
+        owner: user("alice"),
+    };
+    assert!(oso.is_allowed(user("alice"), "push", repo).unwrap());
+    assert_eq!(
+        logger.decisions()[0].args,
+        vec![
+            summary(
+                "User",
+                Some(5),
+                vec![
+                    ("email", ValueSummary::Redacted),
+                    ("name", ValueSummary::String("alice".to_owned())),
+                ]
+            ),
+            ValueSummary::String("push".to_owned()),
+            // Nested instances are summarized by their class.
+            summary("Repo", None, vec![("owner", summary("User", None, vec![]))]),
+        ]
+    );
+}
+
+#[test]
+fn test_log_get_allowed_actions_and_query_rule() {
+    let logger = Arc::new(TestLogger::default());
+    let oso = test_oso(&logger);
+    let repo = Repo { owner: user("bob") };
+    let actions: Vec<String> = oso
+        .get_allowed_actions(user("alice"), repo.clone())
+        .map(|actions| actions.into_iter().collect())
+        .unwrap();
+    assert_eq!(actions, vec!["pull".to_owned()]);
+
+    let mut query = oso.query_rule("is_owner", (user("bob"), repo)).unwrap();
+    assert!(query.next().unwrap().is_ok());
+    // The decision is logged once the query is dropped.
+    assert_eq!(logger.decisions().len(), 1);
+    drop(query);
+
+    let decisions = logger.decisions();
+    assert_eq!(decisions[0].kind, DecisionKind::GetAllowedActions);
+    assert_eq!(
+        decisions[0].args[1],
+        ValueSummary::Variable("action".to_owned())
+    );
+    assert_eq!(
+        decisions[0].outcome,
+        Outcome::Actions(vec![ValueSummary::String("pull".to_owned())])
+    );
+    assert_eq!(
+        decisions[1].kind,
+        DecisionKind::QueryRule {
+            name: "is_owner".to_owned()
+        }
+    );
+    assert_eq!(decisions[1].outcome, Outcome::Results(1));
+
+    let mut query = oso.query_rule("is_owner", (user("bob"), 1)).unwrap();
+    assert!(query.next().is_none());
+    drop(query);
+    assert_eq!(logger.decisions()[2].outcome, Outcome::Results(0));
+}
+
+#[test]
+fn test_log_errors() {
+    let logger = Arc::new(TestLogger::default());
+    let mut oso = test_oso(&logger);
+    oso.clear_rules().unwrap();
+    oso.load_str("f(x) if x.missing;").unwrap();
+    let results: Vec<_> = oso.query_rule("f", (user("alice"),)).unwrap().collect();
+    assert!(results[0].is_err());
+    assert!(matches!(
+        &logger.decisions()[0].outcome,
+        Outcome::Error(message) if message.contains("missing")
+    ));
+}
+
+#[test]
+fn test_sample_decisions() {
+    let logger = Arc::new(TestLogger {
+        skip_is_allowed: true,
+        ..TestLogger::default()
+    });
+    let mut oso = test_oso(&logger);
+    let repo = Repo {
+        owner: user("alice"),
+    };
+    oso.is_allowed(user("alice"), "pull", repo.clone()).unwrap();
+    oso.query_rule("is_owner", (user("alice"), repo.clone()))
+        .unwrap()
+        .for_each(drop);
+    assert_eq!(logger.decisions().len(), 1);
+
+    oso.clear_decision_logger();
+    oso.query_rule("is_owner", (user("alice"), repo))
+        .unwrap()
+        .for_each(drop);
+    assert_eq!(logger.decisions().len(), 1);
+}
+
+#[test]
+fn test_log_rule_spans() {
+    let logger = Arc::new(TestLogger {
+        rule_spans: true,
+        ..TestLogger::default()
+    });
+    let oso = test_oso(&logger);
+    let repo = Repo {
+        owner: user("alice"),
+    };
+    assert!(oso.is_allowed(user("bob"), "pull", repo.clone()).unwrap());
+    assert!(oso.is_allowed(user("alice"), "pull", repo).unwrap());
+
+    let decisions = logger.decisions();
+    assert_eq!(
+        decisions[0].rules,
+        vec![RuleSpan {
+            filename: None,
+            line: 4,
+            column: 1,
+            text: r#"allow(_: User, "pull", _: Repo);"#.to_owned(),
+        }]
+    );
+    assert_eq!(
+        decisions[1].rules,
+        vec![RuleSpan {
+            filename: None,
+            line: 1,
+            column: 1,
+            text: POLICY.lines().take(3).collect::<Vec<_>>().join("\n"),
+        }]
+    );
+}
+
+#[test]
+fn test_policy_version() {
+    let logger = Arc::new(TestLogger::default());
+    let mut oso = test_oso(&logger);
+    assert_eq!(
+        oso.policy_version(),
+        Manifest::content_hash(vec![("", POLICY)])
+    );
+
+    oso.clear_rules().unwrap();
+    let empty = Manifest::content_hash(vec![]);
+    assert_eq!(oso.policy_version(), empty);
+    oso.query_rule("is_owner", (1, 2)).unwrap().for_each(drop);
+    assert_eq!(logger.decisions()[0].policy_version, empty);
+    assert_eq!(
+        logger.decisions()[0].args,
+        vec![ValueSummary::Integer(1), ValueSummary::Integer(2)]
+    );
+}
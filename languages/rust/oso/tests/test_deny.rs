@@ -0,0 +1,151 @@
+mod common;
+
+use std::collections::{HashMap, HashSet};
+
+use common::record;
+use oso::{Oso, PolarClass, Record};
+use polar_core::terms::{Term, Value};
+
+#[derive(PolarClass, Clone)]
+struct User {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    org_id: i64,
+    #[polar(attribute)]
+    contractor: bool,
+}
+
+#[derive(PolarClass, Clone)]
+struct Doc {
+    #[polar(attribute)]
+    org_id: i64,
+    #[polar(attribute)]
+    confidential: bool,
+}
+
+fn test_oso(policy: &str) -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(
+        User::get_polar_class_builder()
+            .add_field_type("id", "Integer")
+            .add_field_type("org_id", "Integer")
+            .add_field_type("contractor", "Boolean")
+            .build(),
+    )
+    .unwrap();
+    oso.register_class(Doc::get_polar_class()).unwrap();
+    oso.load_str(policy).unwrap();
+    oso
+}
+
+fn user(id: i64, contractor: bool) -> User {
+    User {
+        id,
+        org_id: 1,
+        contractor,
+    }
+}
+
+fn doc(confidential: bool) -> Doc {
+    Doc {
+        org_id: 1,
+        confidential,
+    }
+}
+
+const POLICY: &str = r#"
+allow(user: User, action, doc: Doc) if
+    action in ["read", "comment"] and
+    user.org_id = doc.org_id;
+
+deny(user, "read", doc: Doc) if
+    user.contractor = true and doc.confidential;
+"#;
+
+#[test]
+fn test_deny_overrides_allow() {
+    let oso = test_oso(POLICY);
+
+    assert!(oso.is_allowed(user(1, false), "read", doc(true)).unwrap());
+    assert!(oso.is_allowed(user(2, true), "read", doc(false)).unwrap());
+    assert!(!oso.is_allowed(user(2, true), "read", doc(true)).unwrap());
+    assert!(oso.is_allowed(user(2, true), "comment", doc(true)).unwrap());
+
+    let actions: HashSet<String> = oso.get_allowed_actions(user(2, true), doc(true)).unwrap();
+    assert_eq!(actions, maplit::hashset! {"comment".to_owned()});
+
+    // Querying `allow` directly, as the other hosts do, checks `deny` rules too.
+    let mut query = oso
+        .query_rule("allow", (user(2, true), "read", doc(true)))
+        .unwrap();
+    assert!(query.next().is_none());
+}
+
+#[test]
+fn test_deny_shorthand_rules() {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Doc::get_polar_class()).unwrap();
+    oso.load_str(
+        r#"
+        allow(actor, action, resource) if has_permission(actor, action, resource);
+
+        has_role(user: User, "member", doc: Doc) if user.org_id = doc.org_id;
+        has_role(user: User, "contractor", doc: Doc) if
+            user.contractor and user.org_id = doc.org_id;
+
+        actor User {}
+
+        resource Doc {
+            roles = ["member", "contractor"];
+            permissions = ["read"];
+
+            "read" if "member";
+            deny "read" if "contractor" and resource.confidential;
+        }
+        "#,
+    )
+    .unwrap();
+
+    assert!(oso.is_allowed(user(1, false), "read", doc(true)).unwrap());
+    assert!(oso.is_allowed(user(2, true), "read", doc(false)).unwrap());
+    assert!(!oso.is_allowed(user(2, true), "read", doc(true)).unwrap());
+
+    // A deny shorthand rule doesn't take the permission away from `has_permission` itself.
+    let mut query = oso
+        .query_rule("has_permission", (user(2, true), "read", doc(true)))
+        .unwrap();
+    assert!(query.next().unwrap().is_ok());
+}
+
+#[test]
+fn test_authorized_actors_with_deny() {
+    let oso = test_oso(POLICY);
+    let user = |id: i64, contractor: bool| {
+        record(&[
+            ("id", Value::from(id)),
+            ("org_id", Value::from(1)),
+            ("contractor", Value::Boolean(contractor)),
+        ])
+    };
+    let records: HashMap<String, Vec<Term>> = maplit::hashmap! {
+        "User".to_owned() => vec![user(1, false), user(2, true)],
+    };
+
+    let authorized_ids = |confidential: bool| {
+        let plan = oso
+            .authorized_actors::<User, _, _>("read", doc(confidential))
+            .unwrap();
+        let mut ids = plan
+            .execute(&records)
+            .unwrap()
+            .iter()
+            .map(|user| user.field("id").unwrap().to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    };
+    assert_eq!(authorized_ids(false), vec!["1", "2"]);
+    assert_eq!(authorized_ids(true), vec!["1"]);
+}
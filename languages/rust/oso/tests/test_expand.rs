@@ -0,0 +1,166 @@
+use std::io::Write;
+
+use oso::{Oso, PolarClass, UsersetTree};
+use polar_core::terms::ToPolarString;
+
+#[derive(PolarClass, Clone)]
+struct User {
+    #[polar(attribute)]
+    id: i64,
+}
+
+#[derive(PolarClass, Clone)]
+struct Org {
+    #[polar(attribute)]
+    id: String,
+}
+
+#[derive(PolarClass, Clone)]
+struct Repo {
+    #[polar(attribute)]
+    id: String,
+    #[polar(attribute)]
+    org: Org,
+    #[polar(attribute)]
+    public: bool,
+    #[polar(attribute)]
+    archived: bool,
+}
+
+const POLICY: &str = r#"
+allow(actor, action, resource) if has_permission(actor, action, resource);
+
+actor User {}
+
+resource Org {
+    roles = ["owner", "member"];
+
+    "member" if "owner";
+}
+
+resource Repo {
+    roles = ["reader", "maintainer"];
+    permissions = ["pull", "push"];
+    relations = { parent: Org };
+
+    "pull" if "reader";
+    "push" if "maintainer";
+    "reader" if "maintainer";
+    "reader" if "member" on "parent";
+    deny "push" if "reader" and resource.archived;
+}
+
+has_relation(org: Org, "parent", repo: Repo) if org = repo.org;
+
+has_role(user: User, role: String, resource: Resource) if relationship(user, role, resource);
+has_role(_: User, "reader", repo: Repo) if repo.public;
+"#;
+
+const RELATIONSHIPS: &str = "\
+subject,relation,object
+User:1,owner,Org:acme
+User:2,maintainer,Repo:anvil
+";
+
+fn test_oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Org::get_polar_class()).unwrap();
+    oso.register_class(Repo::get_polar_class()).unwrap();
+    oso.load_str(POLICY).unwrap();
+
+    let mut tempfile = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+    let file = tempfile.as_file_mut();
+    write!(file, "{}", RELATIONSHIPS).unwrap();
+    file.sync_all().unwrap();
+    oso.load_relationships(tempfile.path()).unwrap();
+    oso
+}
+
+fn repo(id: &str, public: bool) -> Repo {
+    Repo {
+        id: id.to_owned(),
+        org: Org {
+            id: "acme".to_owned(),
+        },
+        public,
+        archived: false,
+    }
+}
+
+/// Each grant in `tree` as `path: actor <- rule or relationship`, where `path` is the role or
+/// permission names from the root down to the grant, and `!` marks a `deny` branch.
+fn grants(tree: &UsersetTree) -> Vec<String> {
+    fn walk(tree: &UsersetTree, path: &str, grants: &mut Vec<String>) {
+        let path = format!("{}{}", path, tree.name);
+        for grant in &tree.grants {
+            let source = match &grant.relationship {
+                Some(relationship) => relationship.to_string(),
+                None => grant.rule.clone(),
+            };
+            grants.push(format!(
+                "{}: {} <- {}",
+                path,
+                grant.actor.to_polar(),
+                source
+            ));
+        }
+        for (prefix, implications) in [("/", &tree.implied), ("/!", &tree.denied)] {
+            for tree in implications.iter().flat_map(|i| &i.trees) {
+                walk(tree, &format!("{}{}", path, prefix), grants);
+            }
+        }
+    }
+    let mut grants = vec![];
+    walk(tree, "", &mut grants);
+    grants
+}
+
+#[test]
+fn test_expand() {
+    let oso = test_oso();
+
+    let tree = oso.expand(repo("anvil", false), "pull").unwrap();
+    let implied: Vec<_> = tree.implied.iter().map(|i| i.implier.as_str()).collect();
+    assert_eq!(implied, vec![r#""reader""#]);
+    assert_eq!(
+        grants(&tree),
+        vec![
+            r#"pull/reader/maintainer: _this matches User{} and _this.id = 2 <- relationship(User{id: 2}, "maintainer", Repo{id: "anvil"})"#,
+            r#"pull/reader/member/owner: _this matches User{} and _this.id = 1 <- relationship(User{id: 1}, "owner", Org{id: "acme"})"#,
+        ]
+    );
+
+    let tree = oso.expand(repo("anvil", true), "push").unwrap();
+    let denied = &tree.denied[0];
+    assert_eq!(denied.implier, r#""reader""#);
+    assert_eq!(denied.condition.as_deref(), Some("resource.archived"));
+    assert_eq!(
+        grants(&tree),
+        vec![
+            r#"push/maintainer: _this matches User{} and _this.id = 2 <- relationship(User{id: 2}, "maintainer", Repo{id: "anvil"})"#,
+            r#"push/!reader: _this matches User{} <- has_role(_: User, "reader", repo: Repo) if repo.public;"#,
+            r#"push/!reader/maintainer: _this matches User{} and _this.id = 2 <- relationship(User{id: 2}, "maintainer", Repo{id: "anvil"})"#,
+            r#"push/!reader/member/owner: _this matches User{} and _this.id = 1 <- relationship(User{id: 1}, "owner", Org{id: "acme"})"#,
+        ]
+    );
+}
+
+#[test]
+fn test_expand_errors() {
+    let oso = test_oso();
+    let err = oso.expand(repo("anvil", false), "delete").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(r#""delete" is not a role or permission"#),
+        "{}",
+        err
+    );
+    let err = oso.expand("anvil", "pull").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("is not an instance of any resource block"),
+        "{}",
+        err
+    );
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use common::record;
+use oso::{Oso, PolarClass, Record};
+use polar_core::terms::{Term, Value};
+
+#[derive(PolarClass, Clone)]
+struct User {
+    #[polar(attribute)]
+    id: i64,
+}
+
+#[derive(PolarClass, Clone)]
+struct Repo {
+    #[polar(attribute)]
+    id: String,
+}
+
+const POLICY: &str = r#"
+allow(actor, action, resource) if has_permission(actor, action, resource);
+
+actor User {}
+
+resource Repo {
+    roles = ["reader", "maintainer"];
+    permissions = ["pull", "push"];
+
+    "pull" if "reader";
+    "push" if "maintainer";
+    "reader" if "maintainer";
+}
+
+has_role(user: User, role: String, repo: Repo) if relationship(user, role, repo);
+"#;
+
+fn test_oso(relationships: &str, suffix: &str) -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(
+        User::get_polar_class_builder()
+            .add_field_type("id", "Integer")
+            .build(),
+    )
+    .unwrap();
+    oso.register_class(Repo::get_polar_class()).unwrap();
+    oso.load_str(POLICY).unwrap();
+
+    let mut tempfile = tempfile::Builder::new().suffix(suffix).tempfile().unwrap();
+    let file = tempfile.as_file_mut();
+    write!(file, "{}", relationships).unwrap();
+    file.sync_all().unwrap();
+    oso.load_relationships(tempfile.path()).unwrap();
+    oso
+}
+
+fn repo(id: &str) -> Repo {
+    Repo { id: id.to_owned() }
+}
+
+#[test]
+fn test_relationships_json() {
+    let oso = test_oso(
+        r#"[
+            {"subject": {"type": "User", "id": 1}, "relation": "maintainer", "object": {"type": "Repo", "id": "oso"}},
+            {"subject": {"type": "User", "id": 2}, "relation": "reader", "object": {"type": "Repo", "id": "oso"}}
+        ]"#,
+        ".json",
+    );
+
+    assert!(oso.is_allowed(User { id: 1 }, "push", repo("oso")).unwrap());
+    assert!(oso.is_allowed(User { id: 2 }, "pull", repo("oso")).unwrap());
+    assert!(!oso.is_allowed(User { id: 2 }, "push", repo("oso")).unwrap());
+    assert!(!oso
+        .is_allowed(User { id: 1 }, "pull", repo("polar"))
+        .unwrap());
+}
+
+#[test]
+fn test_relationships_filter_plan() {
+    let oso = test_oso(
+        "subject,relation,object\n\
+         User:1,maintainer,Repo:oso\n\
+         User:2,reader,Repo:oso\n\
+         User:3,reader,Repo:polar\n",
+        ".csv",
+    );
+    let records: HashMap<String, Vec<Term>> = maplit::hashmap! {
+        "User".to_owned() => (1..=3)
+            .map(|id| record(&[("id", Value::from(id))]))
+            .collect(),
+    };
+    let authorized_user_ids = |action: &str| {
+        let plan = oso
+            .authorized_actors::<User, _, _>(action, repo("oso"))
+            .unwrap();
+        let mut ids = plan
+            .execute(&records)
+            .unwrap()
+            .iter()
+            .map(|user| user.field("id").unwrap().to_string())
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    };
+
+    assert_eq!(authorized_user_ids("pull"), vec!["1", "2"]);
+    assert_eq!(authorized_user_ids("push"), vec!["1"]);
+}
+
+#[test]
+fn test_relationships_file_extension_check() {
+    let mut oso = Oso::new();
+    let err = oso.load_relationships("relationships.txt").unwrap_err();
+    assert!(err.to_string().contains(".json or .csv"), "{}", err);
+}
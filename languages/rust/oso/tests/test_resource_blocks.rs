@@ -239,3 +239,219 @@ fn test_resource_blocks() {
     };
     assert!(!empty(test.oso.query_rule("allow", (gabe, "edit", bug))));
 }
+
+#[test]
+fn test_conditional_shorthand_rules() {
+    common::setup();
+    let mut test = roles_test_oso();
+    test.load_str(
+        r#"
+      allow(actor, action, resource) if
+        has_permission(actor, action, resource);
+
+      has_role(user: User, name: String, resource: Resource) if
+        role in user.roles and
+        role.name = name and
+        role.resource = resource;
+
+      actor User {}
+
+      resource Org {
+        roles = [ "owner", "member" ];
+        permissions = [ "read", "invite", "leave" ];
+
+        "read" if "member" or "owner";
+        "invite" if "member" and resource.name = "oso" and actor.name != "gwen";
+        "leave" if "member" and not actor.name.is_empty();
+      }
+    "#,
+    );
+
+    let osohq = Org {
+        name: "oso".to_string(),
+    };
+    let apple = Org {
+        name: "apple".to_string(),
+    };
+    let role = |name: &str, org: &Org| Role {
+        name: name.to_string(),
+        resource: org.clone().to_polar(),
+    };
+    let dave = User {
+        name: "dave".to_string(),
+        roles: vec![role("owner", &osohq), role("member", &apple)],
+    };
+    let gwen = User {
+        name: "gwen".to_string(),
+        roles: vec![role("member", &osohq)],
+    };
+    let sam = User {
+        name: "sam".to_string(),
+        roles: vec![role("member", &osohq)],
+    };
+
+    let allowed = |user: &User, action: &str, org: &Org| {
+        !test
+            .oso
+            .query_rule("allow", (user.clone(), action.to_string(), org.clone()))
+            .unwrap()
+            .collect::<oso::Result<Vec<ResultSet>>>()
+            .unwrap()
+            .is_empty()
+    };
+
+    assert!(allowed(&dave, "read", &osohq));
+    assert!(allowed(&dave, "read", &apple));
+    assert!(allowed(&gwen, "read", &osohq));
+    assert!(!allowed(&gwen, "read", &apple));
+
+    assert!(allowed(&sam, "invite", &osohq));
+    assert!(!allowed(&gwen, "invite", &osohq));
+    assert!(!allowed(&dave, "invite", &osohq));
+    assert!(!allowed(&dave, "invite", &apple));
+
+    assert!(allowed(&gwen, "leave", &osohq));
+    assert!(!allowed(&gwen, "leave", &apple));
+}
+
+#[test]
+fn test_shorthand_rules_with_relation_paths() {
+    common::setup();
+    let mut test = roles_test_oso();
+    test.load_str(
+        r#"
+      allow(actor, action, resource) if
+        has_permission(actor, action, resource);
+
+      has_role(user: User, name: String, resource: Resource) if
+        role in user.roles and
+        role.name = name and
+        role.resource = resource;
+
+      actor User {}
+
+      resource Org {
+        roles = [ "owner" ];
+      }
+
+      resource Repo {
+        relations = { parent: Org };
+      }
+
+      has_relation(org: Org, "parent", repo: Repo) if
+        org = repo.org;
+
+      resource Issue {
+        permissions = [ "close" ];
+        relations = { parent: Repo };
+
+        "close" if "owner" on "parent.parent";
+      }
+
+      has_relation(repo: Repo, "parent", issue: Issue) if
+        repo = issue.repo;
+    "#,
+    );
+
+    let osohq = Org {
+        name: "oso".to_string(),
+    };
+    let bug = Issue {
+        name: "bug".to_string(),
+        repo: Repo {
+            name: "oso".to_string(),
+            org: osohq.clone(),
+        },
+    };
+    let laggy = Issue {
+        name: "laggy".to_string(),
+        repo: Repo {
+            name: "ios".to_string(),
+            org: Org {
+                name: "apple".to_string(),
+            },
+        },
+    };
+    let dave = User {
+        name: "dave".to_string(),
+        roles: vec![Role {
+            name: "owner".to_string(),
+            resource: osohq.to_polar(),
+        }],
+    };
+
+    let allowed = |issue: &Issue| {
+        !test
+            .oso
+            .query_rule("allow", (dave.clone(), "close", issue.clone()))
+            .unwrap()
+            .collect::<oso::Result<Vec<ResultSet>>>()
+            .unwrap()
+            .is_empty()
+    };
+    assert!(allowed(&bug));
+    assert!(!allowed(&laggy));
+}
+
+#[test]
+fn test_global_roles() {
+    common::setup();
+    let mut test = roles_test_oso();
+    test.load_str(
+        r#"
+      allow(actor, action, resource) if
+        has_permission(actor, action, resource);
+
+      has_role(user: User, name: String, resource: Resource) if
+        role in user.roles and
+        role.name = name and
+        role.resource = resource;
+
+      has_role(user: User, "admin") if
+        user.name = "root";
+
+      global {
+        roles = [ "admin" ];
+      }
+
+      actor User {}
+
+      resource Org {
+        roles = [ "member" ];
+        permissions = [ "read", "delete" ];
+
+        "read" if "member" or global "admin";
+        "delete" if global "admin";
+      }
+    "#,
+    );
+
+    let osohq = Org {
+        name: "oso".to_string(),
+    };
+    let root = User {
+        name: "root".to_string(),
+        roles: vec![],
+    };
+    let gwen = User {
+        name: "gwen".to_string(),
+        roles: vec![Role {
+            name: "member".to_string(),
+            resource: osohq.clone().to_polar(),
+        }],
+    };
+
+    let allowed = |user: &User, action: &str| {
+        !test
+            .oso
+            .query_rule("allow", (user.clone(), action.to_string(), osohq.clone()))
+            .unwrap()
+            .collect::<oso::Result<Vec<ResultSet>>>()
+            .unwrap()
+            .is_empty()
+    };
+    assert!(allowed(&root, "read"));
+    assert!(allowed(&root, "delete"));
+    assert!(allowed(&gwen, "read"));
+    assert!(!allowed(&gwen, "delete"));
+}
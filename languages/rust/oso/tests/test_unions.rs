@@ -0,0 +1,99 @@
+use oso::{Oso, PolarClass};
+
+#[derive(PolarClass, Clone)]
+struct User {
+    #[polar(attribute)]
+    name: String,
+}
+
+#[derive(PolarClass, Clone)]
+struct ServiceAccount {
+    #[polar(attribute)]
+    name: String,
+}
+
+#[derive(PolarClass, Clone)]
+struct Doc {
+    #[polar(attribute)]
+    owner: String,
+}
+
+fn test_oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(ServiceAccount::get_polar_class())
+        .unwrap();
+    oso.register_class(Doc::get_polar_class()).unwrap();
+    oso
+}
+
+#[test]
+fn test_union_specializers() {
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"
+        allow(principal: Principal, "read", doc: Doc) if principal.name = doc.owner;
+        union Principal = User | ServiceAccount;
+        "#,
+    )
+    .unwrap();
+
+    let doc = Doc {
+        owner: "alice".to_owned(),
+    };
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let service = ServiceAccount {
+        name: "alice".to_owned(),
+    };
+    assert!(oso.is_allowed(user, "read", doc.clone()).unwrap());
+    assert!(oso.is_allowed(service, "read", doc.clone()).unwrap());
+    assert!(!oso
+        .is_allowed(
+            Doc {
+                owner: "alice".to_owned()
+            },
+            "read",
+            doc.clone()
+        )
+        .unwrap());
+
+    let bob = User {
+        name: "bob".to_owned(),
+    };
+    assert!(!oso.is_allowed(bob, "read", doc).unwrap());
+}
+
+#[test]
+fn test_union_rule_types() {
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"
+        union Principal = User | ServiceAccount;
+        type owns(principal: Principal, doc: Doc);
+        owns(user: User, doc: Doc) if user.name = doc.owner;
+        "#,
+    )
+    .unwrap();
+
+    oso.clear_rules().unwrap();
+    let err = oso
+        .load_str(
+            r#"
+            union Principal = User | ServiceAccount;
+            type owns(user: User, doc: Doc);
+            owns(principal: Principal, doc: Doc) if principal.name = doc.owner;
+            "#,
+        )
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("because its member ServiceAccount does not"));
+
+    oso.clear_rules().unwrap();
+    let err = oso
+        .load_str("union Principal = User | Missing;")
+        .unwrap_err();
+    assert!(err.to_string().contains("Unregistered class: Missing"));
+}
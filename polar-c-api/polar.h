@@ -0,0 +1,345 @@
+/**
+ * We use the convention of zero as an error term,
+ * since we also use `null_ptr()` to indicate an error.
+ * So for consistency, a zero term is an error in both cases.
+ */
+#define polar_POLAR_FAILURE 0
+
+#define polar_POLAR_SUCCESS 1
+
+/**
+ * Version of the C ABI exposed by this library.
+ */
+#define polar_POLAR_ABI_VERSION 1
+
+typedef enum polar_DiagnosticSeverity {
+  polar_DiagnosticSeverity_Error = 0,
+  polar_DiagnosticSeverity_Warning = 1,
+} polar_DiagnosticSeverity;
+
+typedef enum polar_Encoding {
+  polar_Encoding_Json = 0,
+  polar_Encoding_MessagePack = 1,
+} polar_Encoding;
+
+/**
+ * Fine-grained error code, one per variant of the `ParseError`, `RuntimeError`,
+ * `OperationalError`, `ValidationError` and `ValidationWarning` enums.
+ *
+ * Codes are grouped by hundreds according to their `ErrorKindCode` and are never reused, so
+ * they're safe to persist or switch on from the host.
+ */
+typedef enum polar_ErrorCode {
+  polar_ErrorCode_ParseIntegerOverflow = 100,
+  polar_ErrorCode_ParseInvalidTokenCharacter = 101,
+  polar_ErrorCode_ParseInvalidToken = 102,
+  polar_ErrorCode_ParseUnrecognizedEof = 103,
+  polar_ErrorCode_ParseUnrecognizedToken = 104,
+  polar_ErrorCode_ParseExtraToken = 105,
+  polar_ErrorCode_ParseReservedWord = 106,
+  polar_ErrorCode_ParseInvalidFloat = 107,
+  polar_ErrorCode_ParseWrongValueType = 108,
+  polar_ErrorCode_ParseDuplicateKey = 109,
+  polar_ErrorCode_RuntimeArithmeticError = 200,
+  polar_ErrorCode_RuntimeUnsupported = 201,
+  polar_ErrorCode_RuntimeTypeError = 202,
+  polar_ErrorCode_RuntimeStackOverflow = 203,
+  polar_ErrorCode_RuntimeQueryTimeout = 204,
+  polar_ErrorCode_RuntimeApplication = 205,
+  polar_ErrorCode_RuntimeFileLoading = 206,
+  polar_ErrorCode_RuntimeIncompatibleBindings = 207,
+  polar_ErrorCode_RuntimeUnhandledPartial = 208,
+  polar_ErrorCode_RuntimeDataFilteringFieldMissing = 209,
+  polar_ErrorCode_RuntimeInvalidRegistration = 210,
+  polar_ErrorCode_RuntimeInvalidState = 211,
+  polar_ErrorCode_OperationalSerialization = 300,
+  polar_ErrorCode_OperationalUnknown = 301,
+  polar_ErrorCode_ValidationMissingRequiredRule = 400,
+  polar_ErrorCode_ValidationInvalidRule = 401,
+  polar_ErrorCode_ValidationInvalidRuleType = 402,
+  polar_ErrorCode_ValidationUndefinedRuleCall = 403,
+  polar_ErrorCode_ValidationResourceBlock = 404,
+  polar_ErrorCode_ValidationSingletonVariable = 405,
+  polar_ErrorCode_ValidationUnregisteredClass = 406,
+  polar_ErrorCode_ValidationInvalidUnion = 407,
+  polar_ErrorCode_ValidationInvalidModule = 408,
+  polar_ErrorCode_ValidationInvalidConstant = 409,
+  polar_ErrorCode_WarningAmbiguousPrecedence = 500,
+  polar_ErrorCode_WarningMissingAllowRule = 501,
+  polar_ErrorCode_WarningMissingHasPermissionRule = 502,
+  polar_ErrorCode_WarningUnknownSpecializer = 503,
+  polar_ErrorCode_WarningUnreachableDenyRule = 504,
+  polar_ErrorCode_WarningUnreachablePermission = 505,
+  polar_ErrorCode_WarningUnusedRole = 506,
+  polar_ErrorCode_WarningUnusedRelation = 507,
+  polar_ErrorCode_WarningEquivalentRoles = 508,
+} polar_ErrorCode;
+
+/**
+ * Top-level category of an error, mirroring `polar_core::error::ErrorKind`.
+ */
+typedef enum polar_ErrorKindCode {
+  polar_ErrorKindCode_Parse = 1,
+  polar_ErrorKindCode_Runtime = 2,
+  polar_ErrorKindCode_Operational = 3,
+  polar_ErrorKindCode_Validation = 4,
+  /**
+   * Only used for diagnostics with a `Warning` severity.
+   */
+  polar_ErrorKindCode_Warning = 5,
+} polar_ErrorKindCode;
+
+/**
+ * Opaque, owned list of diagnostics. Released by `polar_diagnostics_free`.
+ */
+typedef struct polar_Diagnostics polar_Diagnostics;
+
+typedef struct polar_Polar polar_Polar;
+
+typedef struct polar_Query polar_Query;
+
+/**
+ * Location of an error or warning within a loaded source.
+ *
+ * Rows and columns are zero-indexed. `filename` is null for sources loaded without a filename.
+ */
+typedef struct polar_ErrorContext {
+  char *filename;
+  uintptr_t start_row;
+  uintptr_t start_column;
+  uintptr_t end_row;
+  uintptr_t end_column;
+} polar_ErrorContext;
+
+/**
+ * A structured error.
+ *
+ * - `message` is the error message without any source context.
+ * - `formatted` is the full, human-readable message including source context (the same text
+ *   returned as `formatted` by `polar_get_error`).
+ * - `context` is null when the error has no associated source location.
+ *
+ * All pointers are owned by the `Error` and are released by `polar_error_free`.
+ */
+typedef struct polar_Error {
+  enum polar_ErrorKindCode kind;
+  enum polar_ErrorCode code;
+  char *message;
+  char *formatted;
+  struct polar_ErrorContext *context;
+} polar_Error;
+
+/**
+ * A single compile-time diagnostic produced by `polar_diagnostic_load`.
+ *
+ * For warnings, `error.kind` is `Warning` and `error.code` is one of the `Warning*` codes.
+ */
+typedef struct polar_Diagnostic {
+  enum polar_DiagnosticSeverity severity;
+  struct polar_Error error;
+} polar_Diagnostic;
+
+/**
+ * An owned byte buffer returned by the `*_encoded` functions and `polar_export_snapshot`.
+ * Released by `polar_buffer_free`.
+ */
+typedef struct polar_Buffer {
+  uint8_t *data;
+  uintptr_t len;
+} polar_Buffer;
+
+const char *polar_get_error(void);
+
+/**
+ * Take the last error as a structured `polar_Error`.
+ *
+ * Like `polar_get_error`, this clears the last error. Returns null if no error is present.
+ * The result must be released with `polar_error_free`.
+ */
+struct polar_Error *polar_take_error(void);
+
+/**
+ * Release a `polar_Error` returned by `polar_take_error`.
+ */
+int32_t polar_error_free(struct polar_Error *error);
+
+uint32_t polar_abi_version(void);
+
+struct polar_Polar *polar_new(void);
+
+int32_t polar_load(struct polar_Polar *polar_ptr, const char *sources);
+
+/**
+ * Load sources and return every diagnostic (errors and warnings) produced during the load.
+ *
+ * Unlike `polar_load`, warnings are returned in the list rather than being queued as messages.
+ * If any diagnostic is an error, the knowledge base is left empty.
+ *
+ * ## Returns
+ * - A `polar_Diagnostics` list that must be released with `polar_diagnostics_free`.
+ * - Null if `sources` could not be deserialized; see `polar_take_error`.
+ */
+struct polar_Diagnostics *polar_diagnostic_load(struct polar_Polar *polar_ptr, const char *sources);
+
+uintptr_t polar_diagnostics_len(const struct polar_Diagnostics *diagnostics);
+
+/**
+ * Borrow the diagnostic at `index`, or null if `index` is out of bounds.
+ */
+const struct polar_Diagnostic *polar_diagnostics_get(const struct polar_Diagnostics *diagnostics,
+                                                     uintptr_t index);
+
+/**
+ * Release a `polar_Diagnostics` list and every diagnostic it contains.
+ */
+int32_t polar_diagnostics_free(struct polar_Diagnostics *diagnostics);
+
+int32_t polar_clear_rules(struct polar_Polar *polar_ptr);
+
+int32_t polar_register_constant(struct polar_Polar *polar_ptr, const char *name, const char *value);
+
+int32_t polar_register_mro(struct polar_Polar *polar_ptr, const char *name, const char *mro);
+
+struct polar_Query *polar_next_inline_query(struct polar_Polar *polar_ptr, uint32_t trace);
+
+struct polar_Query *polar_new_query_from_term(struct polar_Polar *polar_ptr,
+                                              const char *query_term,
+                                              uint32_t trace);
+
+struct polar_Query *polar_new_query(struct polar_Polar *polar_ptr,
+                                    const char *query_str,
+                                    uint32_t trace);
+
+const char *polar_next_polar_message(struct polar_Polar *polar_ptr);
+
+const char *polar_next_query_event(struct polar_Query *query_ptr);
+
+/**
+ * Execute one debugger command for the given query.
+ *
+ * ## Returns
+ * - `0` on error.
+ * - `1` on success.
+ *
+ * ## Errors
+ * - Provided value is NULL.
+ * - Provided value contains malformed JSON.
+ * - Provided value cannot be parsed to a Term wrapping a Value::String.
+ * - Query.debug_command returns an error.
+ * - Anything panics during the parsing/execution of the provided command.
+ */
+int32_t polar_debug_command(struct polar_Query *query_ptr, const char *value);
+
+int32_t polar_call_result(struct polar_Query *query_ptr, uint64_t call_id, const char *value);
+
+int32_t polar_question_result(struct polar_Query *query_ptr, uint64_t call_id, int32_t result);
+
+int32_t polar_application_error(struct polar_Query *query_ptr, char *message);
+
+const char *polar_next_query_message(struct polar_Query *query_ptr);
+
+const char *polar_query_source_info(struct polar_Query *query_ptr);
+
+int32_t polar_bind(struct polar_Query *query_ptr, const char *name, const char *value);
+
+uint64_t polar_get_external_id(struct polar_Polar *polar_ptr);
+
+/**
+ * Required to free strings properly
+ */
+int32_t string_free(char *s);
+
+/**
+ * Recovers the original boxed version of `polar` so that
+ * it can be properly freed
+ */
+int32_t polar_free(struct polar_Polar *polar);
+
+/**
+ * Recovers the original boxed version of `query` so that
+ * it can be properly freed
+ */
+int32_t query_free(struct polar_Query *query);
+
+const char *polar_build_filter_plan(struct polar_Polar *polar_ptr,
+                                    const char *types,
+                                    const char *results,
+                                    const char *variable,
+                                    const char *class_tag);
+
+/**
+ * Release a `polar_Buffer` returned by one of the `*_encoded` functions or
+ * `polar_export_snapshot`.
+ */
+int32_t polar_buffer_free(struct polar_Buffer *buffer);
+
+/**
+ * Like `polar_next_query_event`, but encodes the event with `encoding`.
+ */
+struct polar_Buffer *polar_next_query_event_encoded(struct polar_Query *query_ptr,
+                                                    enum polar_Encoding encoding);
+
+/**
+ * Like `polar_call_result`, but decodes the `len` bytes at `data` with `encoding`.
+ *
+ * A null `data` indicates that the call has no more results.
+ */
+int32_t polar_call_result_encoded(struct polar_Query *query_ptr,
+                                  uint64_t call_id,
+                                  enum polar_Encoding encoding,
+                                  const uint8_t *data,
+                                  uintptr_t len);
+
+/**
+ * Like `polar_bind`, but decodes the `len` bytes at `data` with `encoding`.
+ */
+int32_t polar_bind_encoded(struct polar_Query *query_ptr,
+                           const char *name,
+                           enum polar_Encoding encoding,
+                           const uint8_t *data,
+                           uintptr_t len);
+
+/**
+ * Like `polar_register_constant`, but decodes the `len` bytes at `data` with `encoding`.
+ */
+int32_t polar_register_constant_encoded(struct polar_Polar *polar_ptr,
+                                        const char *name,
+                                        enum polar_Encoding encoding,
+                                        const uint8_t *data,
+                                        uintptr_t len);
+
+/**
+ * Like `polar_new_query_from_term`, but decodes the `len` bytes at `data` with `encoding`.
+ */
+struct polar_Query *polar_new_query_from_term_encoded(struct polar_Polar *polar_ptr,
+                                                      enum polar_Encoding encoding,
+                                                      const uint8_t *data,
+                                                      uintptr_t len,
+                                                      uint32_t trace);
+
+/**
+ * Like `polar_build_filter_plan`, but decodes `types` and `results` and encodes the resulting
+ * filter plan with `encoding`.
+ */
+struct polar_Buffer *polar_build_filter_plan_encoded(struct polar_Polar *polar_ptr,
+                                                     enum polar_Encoding encoding,
+                                                     const uint8_t *types,
+                                                     uintptr_t types_len,
+                                                     const uint8_t *results,
+                                                     uintptr_t results_len,
+                                                     const char *variable,
+                                                     const char *class_tag);
+
+/**
+ * Serialize the loaded policy into a snapshot that can be restored with `polar_load_snapshot`.
+ */
+struct polar_Buffer *polar_export_snapshot(struct polar_Polar *polar_ptr);
+
+/**
+ * Load the `len` bytes at `data`, a snapshot returned by `polar_export_snapshot`, in place of
+ * calling `polar_load`.
+ *
+ * Snapshots exported by a different version of the library are rejected. Constants must be
+ * registered before loading the snapshot, as they would be before `polar_load`.
+ */
+int32_t polar_load_snapshot(struct polar_Polar *polar_ptr, const uint8_t *data, uintptr_t len);
@@ -0,0 +1,294 @@
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr::null_mut;
+
+use polar_core::diagnostic::{self, Context};
+use polar_core::error::{
+    ErrorKind, OperationalError, ParseError, PolarError, RuntimeError, ValidationError,
+};
+use polar_core::warning::{PolarWarning, ValidationWarning};
+
+/// Top-level category of an error, mirroring `polar_core::error::ErrorKind`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKindCode {
+    Parse = 1,
+    Runtime = 2,
+    Operational = 3,
+    Validation = 4,
+    /// Only used for diagnostics with a `Warning` severity.
+    Warning = 5,
+}
+
+/// Fine-grained error code, one per variant of the `ParseError`, `RuntimeError`,
+/// `OperationalError`, `ValidationError` and `ValidationWarning` enums.
+///
+/// Codes are grouped by hundreds according to their `ErrorKindCode` and are never reused, so
+/// they're safe to persist or switch on from the host.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    ParseIntegerOverflow = 100,
+    ParseInvalidTokenCharacter = 101,
+    ParseInvalidToken = 102,
+    ParseUnrecognizedEof = 103,
+    ParseUnrecognizedToken = 104,
+    ParseExtraToken = 105,
+    ParseReservedWord = 106,
+    ParseInvalidFloat = 107,
+    ParseWrongValueType = 108,
+    ParseDuplicateKey = 109,
+
+    RuntimeArithmeticError = 200,
+    RuntimeUnsupported = 201,
+    RuntimeTypeError = 202,
+    RuntimeStackOverflow = 203,
+    RuntimeQueryTimeout = 204,
+    RuntimeApplication = 205,
+    RuntimeFileLoading = 206,
+    RuntimeIncompatibleBindings = 207,
+    RuntimeUnhandledPartial = 208,
+    RuntimeDataFilteringFieldMissing = 209,
+    RuntimeInvalidRegistration = 210,
+    RuntimeInvalidState = 211,
+
+    OperationalSerialization = 300,
+    OperationalUnknown = 301,
+
+    ValidationMissingRequiredRule = 400,
+    ValidationInvalidRule = 401,
+    ValidationInvalidRuleType = 402,
+    ValidationUndefinedRuleCall = 403,
+    ValidationResourceBlock = 404,
+    ValidationSingletonVariable = 405,
+    ValidationUnregisteredClass = 406,
+    ValidationInvalidUnion = 407,
+    ValidationInvalidModule = 408,
+    ValidationInvalidConstant = 409,
+
+    WarningAmbiguousPrecedence = 500,
+    WarningMissingAllowRule = 501,
+    WarningMissingHasPermissionRule = 502,
+    WarningUnknownSpecializer = 503,
+    WarningUnreachableDenyRule = 504,
+    WarningUnreachablePermission = 505,
+    WarningUnusedRole = 506,
+    WarningUnusedRelation = 507,
+    WarningEquivalentRoles = 508,
+}
+
+fn error_codes(kind: &ErrorKind) -> (ErrorKindCode, ErrorCode) {
+    use ErrorCode::*;
+    use ErrorKind::*;
+    match kind {
+        Parse(e) => (
+            ErrorKindCode::Parse,
+            match e {
+                ParseError::IntegerOverflow { .. } => ParseIntegerOverflow,
+                ParseError::InvalidTokenCharacter { .. } => ParseInvalidTokenCharacter,
+                ParseError::InvalidToken { .. } => ParseInvalidToken,
+                ParseError::UnrecognizedEOF { .. } => ParseUnrecognizedEof,
+                ParseError::UnrecognizedToken { .. } => ParseUnrecognizedToken,
+                ParseError::ExtraToken { .. } => ParseExtraToken,
+                ParseError::ReservedWord { .. } => ParseReservedWord,
+                ParseError::InvalidFloat { .. } => ParseInvalidFloat,
+                ParseError::WrongValueType { .. } => ParseWrongValueType,
+                ParseError::DuplicateKey { .. } => ParseDuplicateKey,
+            },
+        ),
+        Runtime(e) => (
+            ErrorKindCode::Runtime,
+            match e {
+                RuntimeError::ArithmeticError { .. } => RuntimeArithmeticError,
+                RuntimeError::Unsupported { .. } => RuntimeUnsupported,
+                RuntimeError::TypeError { .. } => RuntimeTypeError,
+                RuntimeError::StackOverflow { .. } => RuntimeStackOverflow,
+                RuntimeError::QueryTimeout { .. } => RuntimeQueryTimeout,
+                RuntimeError::Application { .. } => RuntimeApplication,
+                RuntimeError::FileLoading { .. } => RuntimeFileLoading,
+                RuntimeError::IncompatibleBindings { .. } => RuntimeIncompatibleBindings,
+                RuntimeError::UnhandledPartial { .. } => RuntimeUnhandledPartial,
+                RuntimeError::DataFilteringFieldMissing { .. } => RuntimeDataFilteringFieldMissing,
+                RuntimeError::InvalidRegistration { .. } => RuntimeInvalidRegistration,
+                RuntimeError::InvalidState { .. } => RuntimeInvalidState,
+            },
+        ),
+        Operational(e) => (
+            ErrorKindCode::Operational,
+            match e {
+                OperationalError::Serialization { .. } => OperationalSerialization,
+                OperationalError::Unknown => OperationalUnknown,
+            },
+        ),
+        Validation(e) => (
+            ErrorKindCode::Validation,
+            match e {
+                ValidationError::MissingRequiredRule { .. } => ValidationMissingRequiredRule,
+                ValidationError::InvalidRule { .. } => ValidationInvalidRule,
+                ValidationError::InvalidRuleType { .. } => ValidationInvalidRuleType,
+                ValidationError::UndefinedRuleCall { .. } => ValidationUndefinedRuleCall,
+                ValidationError::ResourceBlock { .. } => ValidationResourceBlock,
+                ValidationError::SingletonVariable { .. } => ValidationSingletonVariable,
+                ValidationError::UnregisteredClass { .. } => ValidationUnregisteredClass,
+                ValidationError::InvalidUnion { .. } => ValidationInvalidUnion,
+                ValidationError::InvalidModule { .. } => ValidationInvalidModule,
+                ValidationError::InvalidConstant { .. } => ValidationInvalidConstant,
+            },
+        ),
+    }
+}
+
+fn warning_code(kind: &ValidationWarning) -> ErrorCode {
+    use ErrorCode::*;
+    match kind {
+        ValidationWarning::AmbiguousPrecedence { .. } => WarningAmbiguousPrecedence,
+        ValidationWarning::MissingAllowRule => WarningMissingAllowRule,
+        ValidationWarning::MissingHasPermissionRule => WarningMissingHasPermissionRule,
+        ValidationWarning::UnknownSpecializer { .. } => WarningUnknownSpecializer,
+        ValidationWarning::UnreachableDenyRule { .. } => WarningUnreachableDenyRule,
+        ValidationWarning::UnreachablePermission { .. } => WarningUnreachablePermission,
+        ValidationWarning::UnusedRole { .. } => WarningUnusedRole,
+        ValidationWarning::UnusedRelation { .. } => WarningUnusedRelation,
+        ValidationWarning::EquivalentRoles { .. } => WarningEquivalentRoles,
+    }
+}
+
+/// Convert a Rust string into an owned C string, stripping any interior 0 bytes.
+pub(crate) fn owned_c_string(s: String) -> *mut c_char {
+    let s = CString::new(s).unwrap_or_else(|e| {
+        let bytes: Vec<u8> = e.into_vec().into_iter().filter(|b| *b != 0).collect();
+        CString::new(bytes).expect("0 bytes were removed")
+    });
+    s.into_raw()
+}
+
+/// Free a string previously returned by `owned_c_string`. Null pointers are ignored.
+unsafe fn free_c_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Location of an error or warning within a loaded source.
+///
+/// Rows and columns are zero-indexed. `filename` is null for sources loaded without a filename.
+#[repr(C)]
+pub struct ErrorContext {
+    pub filename: *mut c_char,
+    pub start_row: usize,
+    pub start_column: usize,
+    pub end_row: usize,
+    pub end_column: usize,
+}
+
+impl ErrorContext {
+    fn boxed(context: Option<Context>) -> *mut Self {
+        match context {
+            Some(Context { source, range }) => Box::into_raw(Box::new(Self {
+                filename: source.filename.map_or_else(null_mut, owned_c_string),
+                start_row: range.start.row,
+                start_column: range.start.column,
+                end_row: range.end.row,
+                end_column: range.end.column,
+            })),
+            None => null_mut(),
+        }
+    }
+
+    unsafe fn free(context: *mut Self) {
+        if !context.is_null() {
+            let context = Box::from_raw(context);
+            free_c_string(context.filename);
+        }
+    }
+}
+
+/// A structured error.
+///
+/// - `message` is the error message without any source context.
+/// - `formatted` is the full, human-readable message including source context (the same text
+///   returned as `formatted` by `polar_get_error`).
+/// - `context` is null when the error has no associated source location.
+///
+/// All pointers are owned by the `Error` and are released by `polar_error_free`.
+#[repr(C)]
+pub struct Error {
+    pub kind: ErrorKindCode,
+    pub code: ErrorCode,
+    pub message: *mut c_char,
+    pub formatted: *mut c_char,
+    pub context: *mut ErrorContext,
+}
+
+impl From<PolarError> for Error {
+    fn from(err: PolarError) -> Self {
+        let (kind, code) = error_codes(&err.kind);
+        let formatted = owned_c_string(err.to_string());
+        let message = owned_c_string(err.kind.to_string());
+        Self {
+            kind,
+            code,
+            message,
+            formatted,
+            context: ErrorContext::boxed(err.context),
+        }
+    }
+}
+
+impl Drop for Error {
+    fn drop(&mut self) {
+        unsafe {
+            free_c_string(self.message);
+            free_c_string(self.formatted);
+            ErrorContext::free(self.context);
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticSeverity {
+    Error = 0,
+    Warning = 1,
+}
+
+/// A single compile-time diagnostic produced by `polar_diagnostic_load`.
+///
+/// For warnings, `error.kind` is `Warning` and `error.code` is one of the `Warning*` codes.
+#[repr(C)]
+pub struct Diagnostic {
+    pub severity: DiagnosticSeverity,
+    pub error: Error,
+}
+
+impl From<PolarWarning> for Error {
+    fn from(warning: PolarWarning) -> Self {
+        let formatted = owned_c_string(warning.to_string());
+        let message = owned_c_string(warning.kind.to_string());
+        Self {
+            kind: ErrorKindCode::Warning,
+            code: warning_code(&warning.kind),
+            message,
+            formatted,
+            context: ErrorContext::boxed(warning.context),
+        }
+    }
+}
+
+impl From<diagnostic::Diagnostic> for Diagnostic {
+    fn from(diagnostic: diagnostic::Diagnostic) -> Self {
+        match diagnostic {
+            diagnostic::Diagnostic::Error(e) => Self {
+                severity: DiagnosticSeverity::Error,
+                error: e.into(),
+            },
+            diagnostic::Diagnostic::Warning(w) => Self {
+                severity: DiagnosticSeverity::Warning,
+                error: w.into(),
+            },
+        }
+    }
+}
+
+/// Opaque, owned list of diagnostics. Released by `polar_diagnostics_free`.
+pub struct Diagnostics(pub(crate) Vec<Diagnostic>);
@@ -1,6 +1,32 @@
+//! C API for the Polar engine.
+//!
+//! ## Ownership
+//!
+//! - `polar_Polar` and `polar_Query` handles are owned by the caller and must be released with
+//!   `polar_free` and `query_free` respectively.
+//! - Strings returned as `const char *` are owned by the caller and must be released with
+//!   `string_free`.
+//! - `polar_Error` values returned by `polar_take_error` are owned by the caller and must be
+//!   released with `polar_error_free`, which also releases every string they point to.
+//! - `polar_Diagnostics` lists returned by `polar_diagnostic_load` are owned by the caller and
+//!   must be released with `polar_diagnostics_free`. `polar_Diagnostic` pointers obtained from
+//!   `polar_diagnostics_get` are borrowed from the list and are invalidated when it is freed.
+//! - Arguments are borrowed; the library never takes ownership of caller-provided memory.
+//!
+//! ## Versioning
+//!
+//! `POLAR_ABI_VERSION` is bumped whenever a change to this crate would break existing callers,
+//! e.g., removing a function or changing the layout of a `#[repr(C)]` type. Hosts should compare
+//! the value compiled into their copy of `polar.h` with `polar_abi_version()` at startup.
+
+mod errors;
+
+pub use errors::{
+    Diagnostic, DiagnosticSeverity, Diagnostics, Error, ErrorCode, ErrorContext, ErrorKindCode,
+};
 pub use polar_core::polar::Polar;
 pub use polar_core::query::Query;
-use polar_core::{error, terms};
+use polar_core::{encoding::Encoding, error, sources::Source, terms};
 
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
@@ -37,6 +63,9 @@ macro_rules! box_ptr {
 pub const POLAR_FAILURE: i32 = 0;
 pub const POLAR_SUCCESS: i32 = 1;
 
+/// Version of the C ABI exposed by this library.
+pub const POLAR_ABI_VERSION: u32 = 1;
+
 /// Unwrap the result term and return a zero/null pointer in the failure case
 macro_rules! ffi_try {
     ($body:block) => {
@@ -75,6 +104,37 @@ pub extern "C" fn polar_get_error() -> *const c_char {
     })
 }
 
+/// Take the last error as a structured `polar_Error`.
+///
+/// Like `polar_get_error`, this clears the last error. Returns null if no error is present.
+/// The result must be released with `polar_error_free`.
+#[no_mangle]
+pub extern "C" fn polar_take_error() -> *mut Error {
+    ffi_try!({
+        match LAST_ERROR.with(|prev| prev.lock().unwrap().take()) {
+            Some(err) => box_ptr!(Error::from(err)),
+            None => null_mut(),
+        }
+    })
+}
+
+/// Release a `polar_Error` returned by `polar_take_error`.
+#[no_mangle]
+pub extern "C" fn polar_error_free(error: *mut Error) -> i32 {
+    ffi_try!({
+        if error.is_null() {
+            return POLAR_FAILURE;
+        }
+        std::mem::drop(unsafe { Box::from_raw(error) });
+        POLAR_SUCCESS
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn polar_abi_version() -> u32 {
+    POLAR_ABI_VERSION
+}
+
 #[no_mangle]
 pub extern "C" fn polar_new() -> *mut Polar {
     ffi_try!({ box_ptr!(Polar::new()) })
@@ -98,6 +158,72 @@ pub extern "C" fn polar_load(polar_ptr: *mut Polar, sources: *const c_char) -> i
     })
 }
 
+/// Load sources and return every diagnostic (errors and warnings) produced during the load.
+///
+/// Unlike `polar_load`, warnings are returned in the list rather than being queued as messages.
+/// If any diagnostic is an error, the knowledge base is left empty.
+///
+/// ## Returns
+/// - A `polar_Diagnostics` list that must be released with `polar_diagnostics_free`.
+/// - Null if `sources` could not be deserialized; see `polar_take_error`.
+#[no_mangle]
+pub extern "C" fn polar_diagnostic_load(
+    polar_ptr: *mut Polar,
+    sources: *const c_char,
+) -> *mut Diagnostics {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        let sources = unsafe { ffi_string!(sources) };
+        match serde_json::from_str::<Vec<Source>>(&sources) {
+            Ok(sources) => {
+                let diagnostics = polar.diagnostic_load(sources);
+                box_ptr!(Diagnostics(
+                    diagnostics.into_iter().map(Diagnostic::from).collect()
+                ))
+            }
+            Err(e) => {
+                set_error(error::OperationalError::Serialization { msg: e.to_string() }.into());
+                null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn polar_diagnostics_len(diagnostics: *const Diagnostics) -> usize {
+    ffi_try!({
+        assert!(!diagnostics.is_null());
+        unsafe { &*diagnostics }.0.len()
+    })
+}
+
+/// Borrow the diagnostic at `index`, or null if `index` is out of bounds.
+#[no_mangle]
+pub extern "C" fn polar_diagnostics_get(
+    diagnostics: *const Diagnostics,
+    index: usize,
+) -> *const Diagnostic {
+    ffi_try!({
+        assert!(!diagnostics.is_null());
+        match unsafe { &*diagnostics }.0.get(index) {
+            Some(diagnostic) => diagnostic,
+            None => null(),
+        }
+    })
+}
+
+/// Release a `polar_Diagnostics` list and every diagnostic it contains.
+#[no_mangle]
+pub extern "C" fn polar_diagnostics_free(diagnostics: *mut Diagnostics) -> i32 {
+    ffi_try!({
+        if diagnostics.is_null() {
+            return POLAR_FAILURE;
+        }
+        std::mem::drop(unsafe { Box::from_raw(diagnostics) });
+        POLAR_SUCCESS
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_clear_rules(polar_ptr: *mut Polar) -> i32 {
     ffi_try!({
@@ -485,3 +611,211 @@ pub extern "C" fn polar_build_filter_plan(
         }
     })
 }
+
+/// An owned byte buffer returned by the `*_encoded` functions and `polar_export_snapshot`.
+/// Released by `polar_buffer_free`.
+#[repr(C)]
+pub struct Buffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl From<Vec<u8>> for Buffer {
+    fn from(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        let data = Box::into_raw(bytes.into_boxed_slice()) as *mut u8;
+        Self { data, len }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        let bytes = std::ptr::slice_from_raw_parts_mut(self.data, self.len);
+        std::mem::drop(unsafe { Box::from_raw(bytes) });
+    }
+}
+
+/// Get a `&[u8]` back from a C-style pointer and length
+macro_rules! ffi_bytes {
+    ($data:ident, $len:ident) => {{
+        assert!(!$data.is_null());
+        std::slice::from_raw_parts($data, $len)
+    }};
+}
+
+/// Box an encoded value as a `Buffer`, or record the error and return null.
+fn buffer_ptr(bytes: error::PolarResult<Vec<u8>>) -> *mut Buffer {
+    match bytes {
+        Ok(bytes) => box_ptr!(Buffer::from(bytes)),
+        Err(e) => {
+            set_error(e);
+            null_mut()
+        }
+    }
+}
+
+/// Release a `polar_Buffer` returned by one of the `*_encoded` functions or
+/// `polar_export_snapshot`.
+#[no_mangle]
+pub extern "C" fn polar_buffer_free(buffer: *mut Buffer) -> i32 {
+    ffi_try!({
+        if buffer.is_null() {
+            return POLAR_FAILURE;
+        }
+        std::mem::drop(unsafe { Box::from_raw(buffer) });
+        POLAR_SUCCESS
+    })
+}
+
+/// Like `polar_next_query_event`, but encodes the event with `encoding`.
+#[no_mangle]
+pub extern "C" fn polar_next_query_event_encoded(
+    query_ptr: *mut Query,
+    encoding: Encoding,
+) -> *mut Buffer {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        buffer_ptr(query.next_event().and_then(|event| encoding.encode(&event)))
+    })
+}
+
+/// Like `polar_call_result`, but decodes the `len` bytes at `data` with `encoding`.
+///
+/// A null `data` indicates that the call has no more results.
+#[no_mangle]
+pub extern "C" fn polar_call_result_encoded(
+    query_ptr: *mut Query,
+    call_id: u64,
+    encoding: Encoding,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        let term = if data.is_null() {
+            Ok(None)
+        } else {
+            encoding.decode(unsafe { ffi_bytes!(data, len) }).map(Some)
+        };
+        match term.and_then(|term| query.call_result(call_id, term)) {
+            Ok(_) => POLAR_SUCCESS,
+            Err(e) => set_error(e),
+        }
+    })
+}
+
+/// Like `polar_bind`, but decodes the `len` bytes at `data` with `encoding`.
+#[no_mangle]
+pub extern "C" fn polar_bind_encoded(
+    query_ptr: *mut Query,
+    name: *const c_char,
+    encoding: Encoding,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        let name = unsafe { ffi_string!(name) };
+        let value = encoding.decode(unsafe { ffi_bytes!(data, len) });
+        match value.and_then(|value| query.bind(terms::Symbol::new(name.as_ref()), value)) {
+            Ok(_) => POLAR_SUCCESS,
+            Err(e) => set_error(e),
+        }
+    })
+}
+
+/// Like `polar_register_constant`, but decodes the `len` bytes at `data` with `encoding`.
+#[no_mangle]
+pub extern "C" fn polar_register_constant_encoded(
+    polar_ptr: *mut Polar,
+    name: *const c_char,
+    encoding: Encoding,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        let name = unsafe { ffi_string!(name) };
+        let value = encoding.decode(unsafe { ffi_bytes!(data, len) });
+        match value
+            .and_then(|value| polar.register_constant(terms::Symbol::new(name.as_ref()), value))
+        {
+            Ok(()) => POLAR_SUCCESS,
+            Err(e) => set_error(e),
+        }
+    })
+}
+
+/// Like `polar_new_query_from_term`, but decodes the `len` bytes at `data` with `encoding`.
+#[no_mangle]
+pub extern "C" fn polar_new_query_from_term_encoded(
+    polar_ptr: *mut Polar,
+    encoding: Encoding,
+    data: *const u8,
+    len: usize,
+    trace: u32,
+) -> *mut Query {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        let trace = trace != 0;
+        match encoding.decode(unsafe { ffi_bytes!(data, len) }) {
+            Ok(term) => box_ptr!(polar.new_query_from_term(term, trace)),
+            Err(e) => {
+                set_error(e);
+                null_mut()
+            }
+        }
+    })
+}
+
+/// Like `polar_build_filter_plan`, but decodes `types` and `results` and encodes the resulting
+/// filter plan with `encoding`.
+#[no_mangle]
+pub extern "C" fn polar_build_filter_plan_encoded(
+    polar_ptr: *mut Polar,
+    encoding: Encoding,
+    types: *const u8,
+    types_len: usize,
+    results: *const u8,
+    results_len: usize,
+    variable: *const c_char,
+    class_tag: *const c_char,
+) -> *mut Buffer {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        let variable = unsafe { ffi_string!(variable) };
+        let class_tag = unsafe { ffi_string!(class_tag) };
+        let types = encoding.decode(unsafe { ffi_bytes!(types, types_len) });
+        let results = encoding.decode(unsafe { ffi_bytes!(results, results_len) });
+        buffer_ptr(types.and_then(|types| {
+            results
+                .and_then(|results| polar.build_filter_plan(types, results, &variable, &class_tag))
+                .and_then(|plan| encoding.encode(&plan))
+        }))
+    })
+}
+
+/// Serialize the loaded policy into a snapshot that can be restored with `polar_load_snapshot`.
+#[no_mangle]
+pub extern "C" fn polar_export_snapshot(polar_ptr: *mut Polar) -> *mut Buffer {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        buffer_ptr(polar.export_snapshot())
+    })
+}
+
+/// Load the `len` bytes at `data`, a snapshot returned by `polar_export_snapshot`, in place of
+/// calling `polar_load`.
+///
+/// Snapshots exported by a different version of the library are rejected. Constants must be
+/// registered before loading the snapshot, as they would be before `polar_load`.
+#[no_mangle]
+pub extern "C" fn polar_load_snapshot(polar_ptr: *mut Polar, data: *const u8, len: usize) -> i32 {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        match polar.load_snapshot(unsafe { ffi_bytes!(data, len) }) {
+            Ok(_) => POLAR_SUCCESS,
+            Err(e) => set_error(e),
+        }
+    })
+}
@@ -0,0 +1,96 @@
+use std::ffi::{CStr, CString};
+
+use polar::*;
+
+fn c_str(s: &str) -> CString {
+    CString::new(s).unwrap()
+}
+
+unsafe fn rust_str<'a>(s: *const std::os::raw::c_char) -> &'a str {
+    assert!(!s.is_null());
+    CStr::from_ptr(s).to_str().unwrap()
+}
+
+#[test]
+fn header_declares_the_current_abi() {
+    let header = include_str!("../polar.h");
+    assert!(header.contains(&format!(
+        "#define polar_POLAR_ABI_VERSION {}",
+        POLAR_ABI_VERSION
+    )));
+    for decl in [
+        "struct polar_Error *polar_take_error(void);",
+        "int32_t polar_error_free(struct polar_Error *error);",
+        "uint32_t polar_abi_version(void);",
+        "struct polar_Diagnostics *polar_diagnostic_load(struct polar_Polar *polar_ptr, const char *sources);",
+        "uintptr_t polar_diagnostics_len(const struct polar_Diagnostics *diagnostics);",
+        "int32_t polar_diagnostics_free(struct polar_Diagnostics *diagnostics);",
+    ] {
+        assert!(header.contains(decl), "polar.h is missing `{}`", decl);
+    }
+    assert_eq!(polar_abi_version(), POLAR_ABI_VERSION);
+}
+
+#[test]
+fn diagnostic_load_returns_all_diagnostics() {
+    let polar = polar_new();
+    let sources = c_str(r#"[{"filename": "test.polar", "src": "f() if g();"}]"#);
+    let diagnostics = polar_diagnostic_load(polar, sources.as_ptr());
+    assert!(!diagnostics.is_null());
+    assert_eq!(polar_diagnostics_len(diagnostics), 2);
+
+    let error = unsafe { &*polar_diagnostics_get(diagnostics, 0) };
+    assert_eq!(error.severity, DiagnosticSeverity::Error);
+    assert_eq!(error.error.kind, ErrorKindCode::Validation);
+    assert_eq!(error.error.code, ErrorCode::ValidationUndefinedRuleCall);
+    assert_eq!(
+        unsafe { rust_str(error.error.message) },
+        "Call to undefined rule: g()"
+    );
+    let context = unsafe { &*error.error.context };
+    assert_eq!(unsafe { rust_str(context.filename) }, "test.polar");
+    assert_eq!((context.start_row, context.start_column), (0, 7));
+    assert_eq!((context.end_row, context.end_column), (0, 10));
+
+    let warning = unsafe { &*polar_diagnostics_get(diagnostics, 1) };
+    assert_eq!(warning.severity, DiagnosticSeverity::Warning);
+    assert_eq!(warning.error.kind, ErrorKindCode::Warning);
+    assert_eq!(warning.error.code, ErrorCode::WarningMissingAllowRule);
+    assert!(warning.error.context.is_null());
+
+    assert!(polar_diagnostics_get(diagnostics, 2).is_null());
+    assert_eq!(polar_diagnostics_free(diagnostics), POLAR_SUCCESS);
+    assert_eq!(polar_free(polar), POLAR_SUCCESS);
+}
+
+#[test]
+fn take_error_returns_structured_errors() {
+    assert!(polar_take_error().is_null());
+
+    let polar = polar_new();
+    let sources = c_str(r#"[{"filename": null, "src": "f(x) if x = 1"}]"#);
+    assert_eq!(polar_load(polar, sources.as_ptr()), POLAR_FAILURE);
+
+    let error = polar_take_error();
+    assert!(!error.is_null());
+    {
+        let error = unsafe { &*error };
+        assert_eq!(error.kind, ErrorKindCode::Parse);
+        assert_eq!(error.code, ErrorCode::ParseUnrecognizedEof);
+        let formatted = unsafe { rust_str(error.formatted) };
+        assert!(formatted.starts_with("hit the end of the file unexpectedly"));
+        let context = unsafe { &*error.context };
+        assert!(context.filename.is_null());
+    }
+    assert_eq!(polar_error_free(error), POLAR_SUCCESS);
+
+    // Taking the error clears it.
+    assert!(polar_take_error().is_null());
+
+    let bad_json = c_str("not json");
+    assert!(polar_diagnostic_load(polar, bad_json.as_ptr()).is_null());
+    let error = polar_take_error();
+    assert_eq!(unsafe { &*error }.code, ErrorCode::OperationalSerialization);
+    assert_eq!(polar_error_free(error), POLAR_SUCCESS);
+    assert_eq!(polar_free(polar), POLAR_SUCCESS);
+}
@@ -0,0 +1,99 @@
+use std::ffi::CString;
+
+use polar::*;
+use polar_core::{encoding::Encoding, terms::*};
+
+fn bytes<'a>(buffer: *mut Buffer) -> &'a [u8] {
+    assert!(!buffer.is_null());
+    let buffer = unsafe { &*buffer };
+    unsafe { std::slice::from_raw_parts(buffer.data, buffer.len) }
+}
+
+#[test]
+fn query_events_round_trip_through_message_pack() {
+    let polar = polar_new();
+    let sources = CString::new(r#"[{"filename": null, "src": "f(x) if x = -0.5;"}]"#).unwrap();
+    assert_eq!(polar_load(polar, sources.as_ptr()), POLAR_SUCCESS);
+
+    let term = Term::from(Value::Call(Call {
+        name: Symbol::new("f"),
+        args: vec![Term::from(Symbol::new("x"))],
+        kwargs: None,
+    }));
+    let encoded = Encoding::MessagePack.encode(&term).unwrap();
+    let query = polar_new_query_from_term_encoded(
+        polar,
+        Encoding::MessagePack,
+        encoded.as_ptr(),
+        encoded.len(),
+        0,
+    );
+    assert!(!query.is_null());
+
+    let event = polar_next_query_event_encoded(query, Encoding::MessagePack);
+    let value: serde_json::Value = Encoding::MessagePack.decode(bytes(event)).unwrap();
+    assert_eq!(
+        value["Result"]["bindings"]["x"]["value"]["Number"]["Float"],
+        -0.5
+    );
+    assert_eq!(polar_buffer_free(event), POLAR_SUCCESS);
+
+    let event = polar_next_query_event_encoded(query, Encoding::Json);
+    let value: serde_json::Value = serde_json::from_slice(bytes(event)).unwrap();
+    assert_eq!(value["Done"]["result"], true);
+    assert_eq!(polar_buffer_free(event), POLAR_SUCCESS);
+
+    assert_eq!(query_free(query), POLAR_SUCCESS);
+    assert_eq!(polar_free(polar), POLAR_SUCCESS);
+}
+
+#[test]
+fn malformed_input_sets_a_serialization_error() {
+    let polar = polar_new();
+    let garbage = [0xc1u8];
+    let query = polar_new_query_from_term_encoded(
+        polar,
+        Encoding::MessagePack,
+        garbage.as_ptr(),
+        garbage.len(),
+        0,
+    );
+    assert!(query.is_null());
+    let error = polar_take_error();
+    assert_eq!(unsafe { &*error }.code, ErrorCode::OperationalSerialization);
+    assert_eq!(polar_error_free(error), POLAR_SUCCESS);
+    assert_eq!(polar_free(polar), POLAR_SUCCESS);
+}
+
+#[test]
+fn snapshots_round_trip() {
+    let polar = polar_new();
+    let sources = CString::new(r#"[{"filename": null, "src": "f(1);"}]"#).unwrap();
+    assert_eq!(polar_load(polar, sources.as_ptr()), POLAR_SUCCESS);
+    let snapshot = polar_export_snapshot(polar);
+    assert_eq!(polar_free(polar), POLAR_SUCCESS);
+
+    let polar = polar_new();
+    let data = bytes(snapshot);
+    assert_eq!(
+        polar_load_snapshot(polar, data.as_ptr(), data.len()),
+        POLAR_SUCCESS
+    );
+    assert_eq!(
+        polar_load_snapshot(polar, data.as_ptr(), data.len()),
+        POLAR_FAILURE
+    );
+    let error = polar_take_error();
+    assert_eq!(unsafe { &*error }.code, ErrorCode::RuntimeFileLoading);
+    assert_eq!(polar_error_free(error), POLAR_SUCCESS);
+    assert_eq!(polar_buffer_free(snapshot), POLAR_SUCCESS);
+
+    let query = CString::new("f(1)").unwrap();
+    let query = polar_new_query(polar, query.as_ptr(), 0);
+    let event = polar_next_query_event_encoded(query, Encoding::Json);
+    let value: serde_json::Value = serde_json::from_slice(bytes(event)).unwrap();
+    assert!(value["Result"].is_object());
+    assert_eq!(polar_buffer_free(event), POLAR_SUCCESS);
+    assert_eq!(query_free(query), POLAR_SUCCESS);
+    assert_eq!(polar_free(polar), POLAR_SUCCESS);
+}
@@ -0,0 +1,188 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::counter::Counter;
+use crate::error::RuntimeError;
+use crate::events::QueryEvent;
+use crate::formatting::ToPolarString;
+use crate::runnable::Runnable;
+use crate::terms::{Numeric, Operator, Symbol, Term, Value};
+use crate::vm::{compare, Goal, PolarVirtualMachine};
+
+type Result<T> = core::result::Result<T, RuntimeError>;
+
+/// The aggregator implements the `count`, `collect`, `sum`, `min` and `max` operations in Polar.
+///
+/// It is a `Runnable` that runs `goal` to exhaustion using a copy of `vm`, collecting the value
+/// of `template` in each solution. The aggregate of those values is left in `result` for the
+/// parent VM to unify with the operation's result argument. Bindings made while running the
+/// goal are not visible outside of it.
+///
+/// `min` and `max` fail if the goal has no solutions; the others succeed with `0` or `[]`.
+///
+/// Aggregates can't be partially evaluated: constraints added while running the goal aren't
+/// returned to the parent VM, so a solution that constrains a partial is an error.
+#[derive(Clone)]
+pub struct Aggregator {
+    vm: PolarVirtualMachine,
+
+    /// The aggregate operation, used to compute the result and in error messages.
+    term: Term,
+    operator: Operator,
+    template: Term,
+
+    /// Variables in the goal and template, which must not be partials in a solution.
+    variables: HashSet<Symbol>,
+
+    /// Values of the template in each solution so far.
+    values: Vec<Term>,
+
+    /// The aggregate to return to the parent VM.
+    result: Rc<RefCell<Option<Term>>>,
+}
+
+impl Aggregator {
+    pub fn new(
+        vm: &PolarVirtualMachine,
+        term: Term,
+        template: Term,
+        goal: Term,
+        result: Rc<RefCell<Option<Term>>>,
+    ) -> Self {
+        let operator = term.value().as_expression().unwrap().operator;
+        let mut variables = HashSet::new();
+        goal.variables(&mut variables);
+        template.variables(&mut variables);
+        Self {
+            vm: vm.clone_with_goals(vec![Goal::Query { term: goal }]),
+            term,
+            operator,
+            template,
+            variables,
+            values: vec![],
+            result,
+        }
+    }
+
+    fn aggregate(&mut self) -> Result<Option<Term>> {
+        let values = std::mem::take(&mut self.values);
+        let value = match self.operator {
+            Operator::Count => Value::from(values.len() as i64),
+            Operator::Collect => Value::List(values),
+            Operator::Sum => {
+                let mut sum = Numeric::Integer(0);
+                for value in values {
+                    sum = match value.value() {
+                        Value::Number(n) => {
+                            (sum + *n).ok_or_else(|| RuntimeError::ArithmeticError {
+                                term: self.term.clone(),
+                            })?
+                        }
+                        _ => return Err(self.not_a_number(&value)),
+                    };
+                }
+                Value::Number(sum)
+            }
+            Operator::Min | Operator::Max => {
+                let op = if self.operator == Operator::Min {
+                    Operator::Lt
+                } else {
+                    Operator::Gt
+                };
+                let mut best: Option<Term> = None;
+                for value in values {
+                    best = match best {
+                        Some(best) if !compare(op, &value, &best, Some(&self.term))? => Some(best),
+                        _ => Some(value),
+                    };
+                }
+                return Ok(best);
+            }
+            _ => {
+                return Err(RuntimeError::InvalidState {
+                    msg: format!("{} is not an aggregate", self.term.to_polar()),
+                })
+            }
+        };
+        Ok(Some(self.term.clone_with_value(value)))
+    }
+
+    fn not_a_number(&self, value: &Term) -> RuntimeError {
+        self.vm.type_error(
+            value,
+            format!(
+                "can only sum numbers, got {} in {}",
+                value.to_polar(),
+                self.term.to_polar()
+            ),
+        )
+    }
+}
+
+/// A Runnable that collects the solutions of a query and aggregates them:
+///
+/// 1. If the aggregate is defined (every aggregate except `min` and `max` of no values), pass
+///    it back to the parent VM and return true.
+/// 2. Otherwise, return false.
+impl Runnable for Aggregator {
+    fn run(&mut self, _: Option<&mut Counter>) -> Result<QueryEvent> {
+        loop {
+            // Pass most events through, but collect results and aggregate them.
+            match self.vm.run(None)? {
+                QueryEvent::Done { .. } => {
+                    let result = self.aggregate()?;
+                    let defined = result.is_some();
+                    *self.result.borrow_mut() = result;
+                    return Ok(QueryEvent::Done { result: defined });
+                }
+                QueryEvent::Result { .. } => {
+                    if self.variables.iter().any(|var| self.vm.is_constrained(var)) {
+                        return Err(RuntimeError::Unsupported {
+                            msg: format!(
+                                "cannot use {} with partial evaluation",
+                                self.operator.to_polar()
+                            ),
+                            term: self.term.clone(),
+                        });
+                    }
+                    let value = self.vm.deref(&self.template);
+                    let mut vars = HashSet::new();
+                    value.variables(&mut vars);
+                    if self.operator != Operator::Count && !vars.is_empty() {
+                        return Err(self.vm.type_error(
+                            &self.template,
+                            format!(
+                                "{} is not bound in a solution of {}",
+                                self.template.to_polar(),
+                                self.term.to_polar()
+                            ),
+                        ));
+                    }
+                    self.values.push(value);
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn external_question_result(&mut self, call_id: u64, answer: bool) -> Result<()> {
+        self.vm.external_question_result(call_id, answer)
+    }
+
+    fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> Result<()> {
+        self.vm.external_call_result(call_id, term)
+    }
+
+    fn debug_command(&mut self, command: &str) -> Result<()> {
+        self.vm.debug_command(command)
+    }
+
+    fn clone_runnable(&self) -> Box<dyn Runnable> {
+        Box::new(self.clone())
+    }
+
+    fn handle_error(&mut self, error: RuntimeError) -> Result<QueryEvent> {
+        self.vm.handle_error(error)
+    }
+}
@@ -323,7 +323,12 @@ impl BindingManager {
     /// Get constraints on variable `variable`. If the variable is in a cycle,
     /// the cycle is expressed as a partial.
     pub fn get_constraints(&self, variable: &Symbol) -> Operation {
-        match self._variable_state(variable) {
+        self.get_constraints_at_point(variable, &self.bsp())
+    }
+
+    /// Get constraints on variable `variable` at `bsp`.
+    pub fn get_constraints_at_point(&self, variable: &Symbol, bsp: &Bsp) -> Operation {
+        match self._variable_state_at_point(variable, bsp) {
             BindingManagerVariableState::Unbound => op!(And),
             BindingManagerVariableState::Bound(val) => {
                 op!(And, term!(op!(Unify, term!(variable.clone()), val)))
@@ -337,6 +342,15 @@ impl BindingManager {
         self.variable_state_at_point(variable, &self.bsp())
     }
 
+    /// Whether `variable` has constraints. Unlike `variable_state`, this is false for variables
+    /// that are only bound to other unbound variables.
+    pub fn is_constrained(&self, variable: &Symbol) -> bool {
+        matches!(
+            self._variable_state(variable),
+            BindingManagerVariableState::Partial(_)
+        )
+    }
+
     pub fn variable_state_at_point(&self, variable: &Symbol, bsp: &Bsp) -> VariableState {
         let index = bsp.bindings_index;
         let mut next = variable;
@@ -1,9 +1,14 @@
 use std::{
+    cmp::Reverse,
     collections::{HashMap, HashSet},
+    fmt,
     hash::Hash,
 };
 
-use crate::{counter::Counter, error::RuntimeError, events::ResultEvent, terms::*};
+use crate::{
+    counter::Counter, error::RuntimeError, events::ResultEvent, formatting::ToPolarString,
+    terms::*, vm::compare,
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -56,6 +61,51 @@ pub enum ConstraintKind {
     Contains, // The field is a collection that contains the value.
     Neq,
     Nin,
+    Lt,  // The field is less than the value.
+    Leq, // The field is less than or equal to the value.
+    Gt,  // The field is greater than the value.
+    Geq, // The field is greater than or equal to the value.
+}
+
+impl ConstraintKind {
+    /// The comparison that holds with the operands swapped, e.g. `x < y` iff `y > x`.
+    fn flip(&self) -> Self {
+        match self {
+            Self::Lt => Self::Gt,
+            Self::Leq => Self::Geq,
+            Self::Gt => Self::Lt,
+            Self::Geq => Self::Leq,
+            other => other.clone(),
+        }
+    }
+
+    /// The comparison operator for range constraints.
+    fn comparison(&self) -> Option<Operator> {
+        match self {
+            Self::Lt => Some(Operator::Lt),
+            Self::Leq => Some(Operator::Leq),
+            Self::Gt => Some(Operator::Gt),
+            Self::Geq => Some(Operator::Geq),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ConstraintKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            Self::Eq => "=",
+            Self::In => "IN",
+            Self::Contains => "CONTAINS",
+            Self::Neq => "!=",
+            Self::Nin => "NOT IN",
+            Self::Lt => "<",
+            Self::Leq => "<=",
+            Self::Gt => ">",
+            Self::Geq => ">=",
+        };
+        write!(f, "{}", op)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
@@ -104,6 +154,8 @@ struct VarInfo {
     contained_values: Vec<(Term, VarName)>,                  // 1 in x
     field_relationships: Vec<(VarName, FieldName, VarName)>, // x.a = y
     in_relationships: Vec<(VarName, VarName)>,               // x in y
+    comparisons: Vec<(VarName, ConstraintKind, VarName)>,    // x < y
+    negations: Vec<VarInfo>,                                 // not (x.a = 1 and ...)
     counter: Counter,
 }
 
@@ -112,10 +164,12 @@ struct Vars {
     variables: Map<VarId, Set<VarName>>,
     field_relationships: Map<VarId, Set<(FieldName, VarId)>>,
     uncycles: Map<VarId, Set<VarId>>,
+    comparisons: Map<VarId, Set<(ConstraintKind, VarId)>>,
     in_relationships: Set<(VarId, VarId)>,
     eq_values: Map<VarId, Term>,
     contained_values: Map<VarId, Set<Term>>,
     types: Map<VarId, TypeName>,
+    negations: Vec<Vars>,
     this_id: VarId,
 }
 
@@ -128,6 +182,121 @@ pub fn build_filter_plan(
     FilterPlan::build(types, partial_results, variable, class_tag)
 }
 
+impl Constraint {
+    /// The values an `=` or `in` check accepts.
+    fn values(&self) -> Option<TermList> {
+        match (&self.kind, &self.value) {
+            (ConstraintKind::Eq, ConstraintValue::Term(t)) => Some(vec![t.clone()]),
+            (ConstraintKind::In, ConstraintValue::Term(t)) => t.value().as_list().ok().cloned(),
+            _ => None,
+        }
+    }
+
+    /// Whether this constraint implies `other`. Only considers constraints on the same field
+    /// with literal values.
+    fn implies(&self, other: &Self) -> bool {
+        use ConstraintKind::*;
+        let (mine, theirs) = match (&self.value, &other.value) {
+            (ConstraintValue::Term(mine), ConstraintValue::Term(theirs))
+                if self.field == other.field =>
+            {
+                (mine, theirs)
+            }
+            _ => return false,
+        };
+        let holds =
+            |op, left: &Term, right: &Term| compare(op, left, right, Some(left)).unwrap_or(false);
+        let contains = |list: &Term, value: &Term| {
+            list.value().as_list().map(|list| list.contains(value)).ok()
+        };
+
+        match (&self.kind, &other.kind) {
+            (Eq, In) => contains(theirs, mine) == Some(true),
+            (Eq, Nin) => contains(theirs, mine) == Some(false),
+            (Eq, Neq) => mine != theirs,
+            (Eq, kind) => kind.comparison().is_some_and(|op| holds(op, mine, theirs)),
+            // Bounds: `x < 3` implies `x < 5` and `x <= 3`, but `x <= 3` doesn't imply `x < 3`.
+            (Leq, Lt) => holds(Operator::Lt, mine, theirs),
+            (Lt | Leq, Lt | Leq) => holds(Operator::Leq, mine, theirs),
+            (Geq, Gt) => holds(Operator::Gt, mine, theirs),
+            (Gt | Geq, Gt | Geq) => holds(Operator::Geq, mine, theirs),
+            _ => false,
+        }
+    }
+}
+
+impl Constraint {
+    /// Whether `record` satisfies this constraint. A reference to
+    /// another result is satisfied by any of its records, so e.g. `x IN r1` and `x < r1`
+    /// hold if `x` is equal to or less than some record in `r1`, and `x NOT IN r1` holds
+    /// if `x` is different from all of them.
+    fn check<R: Record>(&self, record: &R, results: &Map<Id, Vec<R>>) -> Result<bool> {
+        let left = record_field(record, self.field.as_deref())?;
+        match &self.value {
+            ConstraintValue::Term(right) => self.kind.check(&left, right),
+            ConstraintValue::Field(field) => {
+                let right = record_field(record, Some(field))?;
+                self.kind.check(&left, &right)
+            }
+            ConstraintValue::Ref(Ref { field, result_id }) => {
+                let refs = match results.get(result_id) {
+                    Some(refs) => refs,
+                    None => {
+                        return err_invalid(format!(
+                            "Result {} is used before it is resolved",
+                            result_id
+                        ))
+                    }
+                };
+                let kind = match self.kind {
+                    ConstraintKind::In | ConstraintKind::Neq | ConstraintKind::Nin => {
+                        ConstraintKind::Eq
+                    }
+                    ref kind => kind.clone(),
+                };
+                let mut found = false;
+                for other in refs {
+                    let right = record_field(other, field.as_deref())?;
+                    if kind.check(&left, &right)? {
+                        found = true;
+                        break;
+                    }
+                }
+                Ok(match self.kind {
+                    ConstraintKind::Neq | ConstraintKind::Nin => !found,
+                    _ => found,
+                })
+            }
+        }
+    }
+}
+
+impl ConstraintKind {
+    /// Whether `left` and `right` satisfy this constraint.
+    fn check(&self, left: &Term, right: &Term) -> Result<bool> {
+        fn list(term: &Term) -> Result<&TermList> {
+            term.value()
+                .as_list()
+                .or_else(|_| err_invalid(format!("Expected a list, got {}", term.to_polar())))
+        }
+        match self {
+            Self::Eq => Ok(left == right),
+            Self::Neq => Ok(left != right),
+            Self::In => Ok(list(right)?.contains(left)),
+            Self::Nin => Ok(!list(right)?.contains(left)),
+            Self::Contains => Ok(list(left)?.contains(right)),
+            kind => {
+                let operator = kind.comparison().unwrap();
+                let context = Term::from(Operation {
+                    operator,
+                    args: vec![left.clone(), right.clone()],
+                });
+                compare(operator, left, right, Some(&context))
+            }
+        }
+    }
+}
+
 impl From<Term> for Constraint {
     fn from(term: Term) -> Self {
         Self {
@@ -286,6 +455,26 @@ impl VarInfo {
         }
     }
 
+    fn do_compare(mut self, kind: ConstraintKind, left: &Term, right: &Term) -> Result<Self> {
+        match (self.undot(left), self.undot(right)) {
+            (Value::Variable(_), _) | (_, Value::Variable(_)) => {
+                let (l, r) = (self.symbolize(left), self.symbolize(right));
+                self.comparisons.push((l, kind, r));
+                Ok(self)
+            }
+            _ => err_unsupported(
+                format!(
+                    "Unsupported comparison: {} {} {}",
+                    left.to_polar(),
+                    kind,
+                    right.to_polar()
+                ),
+                // TODO(gj): reconstruct operation?
+                left.clone(),
+            ),
+        }
+    }
+
     fn do_in(mut self, left: &Term, right: &Term) -> Result<Self> {
         match (self.undot(left), self.undot(right)) {
             (Value::Variable(l), Value::Variable(r)) => {
@@ -308,6 +497,27 @@ impl VarInfo {
         }
     }
 
+    /// A negated conjunction is processed on its own, sharing only `_this` with the
+    /// enclosing expression, and later becomes an anti-join on `_this`.
+    fn do_not(mut self, term: &Term) -> Result<Self> {
+        let negated = match term.value().as_expression() {
+            Ok(op) if op.operator == Operator::And => op.clone(),
+            Ok(_) => op!(And, term.clone()),
+            _ => {
+                return err_unsupported(
+                    format!("Unsupported negation: not {}", term.to_polar()),
+                    term.clone(),
+                )
+            }
+        };
+        let info = Self {
+            counter: self.counter.clone(),
+            ..Default::default()
+        };
+        self.negations.push(info.process_exp(&negated)?);
+        Ok(self)
+    }
+
     /// Process an expression in the context of this VarInfo. Just does side effects.
     fn process_exp(self, exp: &Operation) -> Result<Self> {
         use Operator::*;
@@ -317,7 +527,12 @@ impl VarInfo {
             Dot if args.len() == 2 => self.do_dot(&args[0], &args[1]),
             Isa if args.len() == 2 => self.do_isa(&args[0], &args[1]),
             Neq if args.len() == 2 => self.do_neq(&args[0], &args[1]),
+            Not if args.len() == 1 => self.do_not(&args[0]),
             In if args.len() == 2 => self.do_in(&args[0], &args[1]),
+            Lt if args.len() == 2 => self.do_compare(ConstraintKind::Lt, &args[0], &args[1]),
+            Leq if args.len() == 2 => self.do_compare(ConstraintKind::Leq, &args[0], &args[1]),
+            Gt if args.len() == 2 => self.do_compare(ConstraintKind::Gt, &args[0], &args[1]),
+            Geq if args.len() == 2 => self.do_compare(ConstraintKind::Geq, &args[0], &args[1]),
             Unify | Eq | Assign if args.len() == 2 => self.do_unify(&args[0], &args[1]),
             _ => err_unsupported(
                 format!(
@@ -359,7 +574,18 @@ impl FilterPlan {
         // to get POLAR_LOG to work in all libs, wasm etc...) but that's what I'm doing today.
         // At some point surface this info better.
         let explain = std::env::var("POLAR_EXPLAIN").is_ok();
+        let plan = Self::build_unoptimized(types, partial_results, var, class_tag, explain)?;
+        Ok(plan.optimize(explain))
+    }
 
+    /// Build a plan with one result set per partial result, before [`FilterPlan::optimize`].
+    fn build_unoptimized(
+        types: Types,
+        partial_results: PartialResults,
+        var: &str,
+        class_tag: &str,
+        explain: bool,
+    ) -> Result<FilterPlan> {
         if explain {
             eprintln!("\n===Data Filtering Query===");
             eprintln!("\n==Bindings==")
@@ -388,83 +614,174 @@ impl FilterPlan {
             })
             .collect::<Result<Vec<ResultSet>>>()?;
 
-        Ok(FilterPlan { result_sets }.optimize(explain))
+        Ok(FilterPlan { result_sets })
     }
 
-    fn optimize(self, explain: bool) -> Self {
+    fn optimize(mut self, explain: bool) -> Self {
         if explain {
             eprintln!("== Raw Filter Plan ==");
-            self.explain();
-            eprintln!("\nOptimizing...")
+            eprintln!("{}", self);
+            eprintln!("Optimizing...")
+        }
+        for result_set in &mut self.result_sets {
+            result_set.optimize(explain);
         }
         self.opt_pass(explain)
     }
 
     fn opt_pass(mut self, explain: bool) -> Self {
         // Remove duplicate result set in a union.
-        match self.result_sets.iter().enumerate().find_map(|(i, rs1)| {
+        if let Some(plan_id) = self.result_sets.iter().enumerate().find_map(|(i, rs1)| {
             self.result_sets
                 .iter()
                 .enumerate()
-                .find_map(|(j, rs2)| (i != j && rs1 == rs2).then(|| j))
+                .find_map(|(j, rs2)| (i != j && rs1.equivalent(rs2)).then(|| j))
         }) {
-            None => self.opt_fin(explain),
-            Some(plan_id) => {
-                if explain {
-                    eprintln!("* Removed duplicate result set.")
-                }
-                self.result_sets.remove(plan_id);
-                self.opt_pass(explain)
+            if explain {
+                eprintln!("* Removed duplicate result set.")
             }
+            self.result_sets.remove(plan_id);
+            return self.opt_pass(explain);
         }
-        // Possible future optimization ideas.
-        // * If two result sets are almost the same except for a single fetch
-        //   that only has a single field check and the field is different, we
-        //   can merge the two result sets and turn the field check into an `in`.
-        //   This is basically "un-expanding" either an `in` or and `or` from the policy.
-        //   This could be hard to find.
+
+        // Merge two result sets that only differ in the value of a single field check into
+        // one result set with an `in` check. This "un-expands" an `in` or `or` from the policy.
+        if let Some((i, j, merged)) = self.result_sets.iter().enumerate().find_map(|(i, rs1)| {
+            self.result_sets
+                .iter()
+                .enumerate()
+                .skip(i + 1)
+                .find_map(|(j, rs2)| rs1.merge(rs2).map(|merged| (i, j, merged)))
+        }) {
+            if explain {
+                eprintln!("* Merged result sets {} and {}.", i, j)
+            }
+            self.result_sets[i] = merged;
+            self.result_sets.remove(j);
+            return self.opt_pass(explain);
+        }
+
+        self.opt_fin(explain)
     }
 
     fn opt_fin(self, explain: bool) -> Self {
         if explain {
             eprintln!("Done\n");
             eprintln!("== Optimized Filter Plan ==");
-            self.explain()
+            eprintln!("{}", self)
         }
         self
     }
+}
 
-    fn explain(&self) {
-        eprintln!("UNION");
+/// A record that a filter plan can be executed against in memory; see
+/// [`FilterPlan::execute`].
+pub trait Record: Clone + PartialEq {
+    /// The value of `field` on this record, or `None` if it doesn't have one.
+    fn field(&self, field: &str) -> Option<Term>;
+
+    /// This record as a term, to compare against values in the plan (e.g., an
+    /// external instance for a host object).
+    fn to_term(&self) -> Term;
+}
+
+/// Dictionaries are records whose fields are their keys.
+impl Record for Term {
+    fn field(&self, field: &str) -> Option<Term> {
+        match self.value() {
+            Value::Dictionary(Dictionary { fields }) => fields.get(&Symbol::new(field)).cloned(),
+            _ => None,
+        }
+    }
+
+    fn to_term(&self) -> Term {
+        self.clone()
+    }
+}
+
+impl FilterPlan {
+    /// Execute the plan against in-memory `records` of each type. This is a reference
+    /// implementation of what a data filtering adapter does, for testing; it isn't meant to
+    /// be fast. Types with no entry in `records` have no records. Returns the matching
+    /// records without duplicates.
+    pub fn execute<R: Record>(&self, records: &Map<TypeName, Vec<R>>) -> Result<Vec<R>> {
+        let mut matches = vec![];
+        for result_set in &self.result_sets {
+            for record in result_set.execute(records)? {
+                if !matches.contains(&record) {
+                    matches.push(record);
+                }
+            }
+        }
+        Ok(matches)
+    }
+}
+
+/// Renders the plan as pseudo-SQL: one query per result set, combined with `UNION`. Each
+/// request is a named subquery, and `this` refers to the fetched object itself.
+impl fmt::Display for FilterPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.result_sets.is_empty() {
+            return writeln!(f, "-- no results");
+        }
         for (i, result_set) in self.result_sets.iter().enumerate() {
-            eprintln!("  =Result Set: {}=", i);
-            for id in &result_set.resolve_order {
-                let fetch_request = result_set.requests.get(id).unwrap();
-                eprintln!("    {}: Fetch {}", id, fetch_request.class_tag);
-                for constraint in &fetch_request.constraints {
-                    let op = match constraint.kind {
-                        ConstraintKind::Eq => "=",
-                        ConstraintKind::In => "in",
-                        ConstraintKind::Nin => "not in",
-                        ConstraintKind::Neq => "!=",
-                        ConstraintKind::Contains => "contains",
-                    };
-                    let field = &constraint.field;
-                    let value = match &constraint.value {
-                        ConstraintValue::Term(t) => t.to_polar(),
-                        ConstraintValue::Field(f) => format!("FIELD({})", f),
-                        ConstraintValue::Ref(r) => {
-                            let inside = match &r.field {
-                                Some(f) => format!("{}.{}", r.result_id, f),
-                                _ => format!("{}", r.result_id),
-                            };
-                            format!("REF({})", inside)
-                        }
-                    };
-                    eprintln!("          {:?} {} {}", field, op, value);
+            if i > 0 {
+                writeln!(f, "UNION")?;
+            }
+            write!(f, "{}", result_set)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ResultSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dependencies = self
+            .resolve_order
+            .iter()
+            .filter(|id| **id != self.result_id)
+            .collect::<Vec<_>>();
+        if !dependencies.is_empty() {
+            writeln!(f, "WITH")?;
+            for (i, id) in dependencies.iter().enumerate() {
+                let request = &self.requests[id];
+                let sep = if i + 1 < dependencies.len() { "," } else { "" };
+                write!(f, "  r{} AS (SELECT * FROM {}", id, request.class_tag)?;
+                for (i, constraint) in request.constraints.iter().enumerate() {
+                    let keyword = if i == 0 { "WHERE" } else { "AND" };
+                    write!(f, " {} {}", keyword, constraint)?;
                 }
+                writeln!(f, "){}", sep)?;
             }
         }
+        let request = &self.requests[&self.result_id];
+        writeln!(f, "SELECT * FROM {}", request.class_tag)?;
+        for (i, constraint) in request.constraints.iter().enumerate() {
+            let keyword = if i == 0 { "WHERE" } else { "  AND" };
+            writeln!(f, "{} {}", keyword, constraint)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{} {} ", field, self.kind)?,
+            None => write!(f, "this {} ", self.kind)?,
+        }
+        match &self.value {
+            ConstraintValue::Term(t) => write!(f, "{}", t.to_polar()),
+            ConstraintValue::Field(field) => write!(f, "{}", field),
+            ConstraintValue::Ref(Ref {
+                field: Some(field),
+                result_id,
+            }) => write!(f, "(SELECT {} FROM r{})", field, result_id),
+            ConstraintValue::Ref(Ref {
+                field: None,
+                result_id,
+            }) => write!(f, "(SELECT * FROM r{})", result_id),
+        }
     }
 }
 
@@ -486,6 +803,141 @@ impl ResultSet {
         }
     }
 
+    /// Whether two result sets make the same requests, regardless of the order of their
+    /// constraints.
+    fn equivalent(&self, other: &Self) -> bool {
+        self.result_id == other.result_id
+            && self.requests.len() == other.requests.len()
+            && self.requests.iter().all(|(id, request)| {
+                other
+                    .requests
+                    .get(id)
+                    .is_some_and(|other| request.equivalent(other))
+            })
+    }
+
+    /// Merge two result sets that are equivalent except for a single `=` or `in` check on the
+    /// same field of one request, with different values.
+    fn merge(&self, other: &Self) -> Option<Self> {
+        if self.result_id != other.result_id || self.requests.len() != other.requests.len() {
+            return None;
+        }
+        let mut differing =
+            self.requests
+                .iter()
+                .filter_map(|(id, request)| match other.requests.get(id) {
+                    Some(other) if request.equivalent(other) => None,
+                    other => Some((id, request, other)),
+                });
+        let (id, request, other) = match (differing.next(), differing.next()) {
+            (Some((id, request, Some(other))), None) if request.class_tag == other.class_tag => {
+                (id, request, other)
+            }
+            _ => return None,
+        };
+        let (mine, theirs) = match (
+            &difference(&request.constraints, &other.constraints)[..],
+            &difference(&other.constraints, &request.constraints)[..],
+        ) {
+            ([mine], [theirs]) if mine.field == theirs.field => (*mine, *theirs),
+            _ => return None,
+        };
+
+        let mut values = mine.values()?;
+        for value in theirs.values()? {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        let mut merged = self.clone();
+        let constraint = merged
+            .requests
+            .get_mut(id)
+            .unwrap()
+            .constraints
+            .iter_mut()
+            .find(|c| *c == mine)
+            .unwrap();
+        constraint.kind = ConstraintKind::In;
+        constraint.value = ConstraintValue::Term(Term::from(Value::List(values)));
+        Some(merged)
+    }
+
+    /// Resolve each request in order, substituting the results of earlier requests into
+    /// later ones, and return the results of the last.
+    fn execute<R: Record>(&self, records: &Map<TypeName, Vec<R>>) -> Result<Vec<R>> {
+        let mut results: Map<Id, Vec<R>> = HashMap::new();
+        for id in &self.resolve_order {
+            let request = &self.requests[id];
+            let mut matches = vec![];
+            for record in records.get(&request.class_tag).into_iter().flatten() {
+                if request.check(record, &results)? {
+                    matches.push(record.clone());
+                }
+            }
+            results.insert(*id, matches);
+        }
+        results.remove(&self.result_id).map_or_else(
+            || err_invalid(format!("Result {} is never resolved", self.result_id)),
+            Ok,
+        )
+    }
+
+    /// Drop constraints implied by others, then order the requests by selectivity.
+    fn optimize(&mut self, explain: bool) {
+        for id in &self.resolve_order {
+            let request = self.requests.get_mut(id).unwrap();
+            let before = request.len();
+            request.drop_implied();
+            if explain && request.len() != before {
+                eprintln!(
+                    "* Removed {} redundant constraint(s) from request {}.",
+                    before - request.len(),
+                    id
+                )
+            }
+        }
+
+        let order = self.order_by_selectivity();
+        if explain && order != self.resolve_order {
+            eprintln!("* Reordered requests by selectivity: {:?}.", order)
+        }
+        self.resolve_order = order;
+    }
+
+    /// Order requests so that each one is resolved after the requests it depends on, and
+    /// otherwise the most selective requests are resolved first (so that their results, which
+    /// are inputs to other requests, are small). The result is always resolved last.
+    fn order_by_selectivity(&self) -> Vec<VarId> {
+        let mut pending = self
+            .resolve_order
+            .iter()
+            .filter(|id| **id != self.result_id)
+            .copied()
+            .collect::<Vec<_>>();
+        let mut order = vec![];
+        while !pending.is_empty() {
+            let next = pending
+                .iter()
+                .enumerate()
+                .filter(|(_, id)| {
+                    self.requests[id]
+                        .deps()
+                        .iter()
+                        .all(|dep| order.contains(dep))
+                })
+                .max_by_key(|(i, id)| (self.requests[id].selectivity(), Reverse(*i)))
+                .map(|(i, _)| i);
+            match next {
+                Some(i) => order.push(pending.remove(i)),
+                // Shouldn't happen for a valid result set; leave the order alone.
+                None => return self.resolve_order.clone(),
+            }
+        }
+        order.push(self.result_id);
+        order
+    }
+
     fn build(types: &Types, vars: &Vars, this_type: &str) -> Result<Self> {
         let result_set = ResultSet {
             requests: HashMap::new(),
@@ -518,6 +970,62 @@ impl FetchRequest {
         self.constraints.push(Constraint { kind, field, value });
     }
 
+    fn equivalent(&self, other: &Self) -> bool {
+        self.class_tag == other.class_tag
+            && difference(&self.constraints, &other.constraints).is_empty()
+            && difference(&other.constraints, &self.constraints).is_empty()
+    }
+
+    /// Remove duplicate constraints, and constraints implied by another constraint on
+    /// the same field, e.g. `x < 5` given `x < 3`, or `x in [1, 2]` given `x = 1`.
+    fn drop_implied(&mut self) {
+        let mut constraints: Vec<Constraint> = vec![];
+        for constraint in self.constraints.drain(..) {
+            if !constraints.contains(&constraint) {
+                constraints.push(constraint);
+            }
+        }
+        let mut i = 0;
+        while i < constraints.len() {
+            if constraints
+                .iter()
+                .enumerate()
+                .any(|(j, other)| i != j && other.implies(&constraints[i]))
+            {
+                constraints.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        self.constraints = constraints;
+    }
+
+    /// A rough hint of how selective this request is; higher is more selective.
+    fn selectivity(&self) -> usize {
+        self.constraints
+            .iter()
+            .map(|c| match c.kind {
+                ConstraintKind::Eq => 3,
+                ConstraintKind::In | ConstraintKind::Contains => 2,
+                ConstraintKind::Lt
+                | ConstraintKind::Leq
+                | ConstraintKind::Gt
+                | ConstraintKind::Geq => 1,
+                ConstraintKind::Neq | ConstraintKind::Nin => 0,
+            })
+            .sum()
+    }
+
+    /// Whether `record` satisfies every constraint of this request.
+    fn check<R: Record>(&self, record: &R, results: &Map<Id, Vec<R>>) -> Result<bool> {
+        for constraint in &self.constraints {
+            if !constraint.check(record, results)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     fn deps(&self) -> Vec<Id> {
         self.constraints
             .iter()
@@ -594,6 +1102,8 @@ impl<'a> ResultSetBuilder<'a> {
                 .constrain_in_vars(id, var_type)?
                 .constrain_eq_vars(id)?
                 .constrain_neq_vars(id)?
+                .constrain_compare_vars(id)?
+                .constrain_negations(id, var_type)?
                 .result_set
                 .resolve_order
                 .push(id);
@@ -620,6 +1130,40 @@ impl<'a> ResultSetBuilder<'a> {
         Ok(self)
     }
 
+    /// Exclude the results of each negated conjunction on this var, i.e.
+    /// `_this not in (fetch _this where ...)`.
+    fn constrain_negations(&mut self, id: Id, var_type: &str) -> Result<&mut Self> {
+        if id != self.vars.this_id {
+            return Ok(self);
+        }
+        for negation in &self.vars.negations {
+            let negated = ResultSet::build(self.types, negation, var_type)?;
+            self.result_set.requests.extend(negated.requests);
+            self.result_set.resolve_order.extend(negated.resolve_order);
+            self.result_set.requests.get_mut(&id).unwrap().constrain(
+                ConstraintKind::Nin,
+                None,
+                ConstraintValue::Ref(Ref {
+                    field: None,
+                    result_id: negated.result_id,
+                }),
+            );
+        }
+        Ok(self)
+    }
+
+    fn constrain_compare_vars(&mut self, id: Id) -> Result<&mut Self> {
+        for (kind, other) in self.vars.comparisons.get(&id).into_iter().flatten() {
+            let value = self.comparison_value(None, id, kind, *other)?;
+            self.result_set
+                .requests
+                .get_mut(&id)
+                .unwrap()
+                .constrain(kind.clone(), None, value);
+        }
+        Ok(self)
+    }
+
     fn constrain_eq_vars(&mut self, id: Id) -> Result<&mut Self> {
         if let Some(t) = self.vars.eq_values.get(&id) {
             self.result_set.requests.get_mut(&id).unwrap().constrain(
@@ -717,6 +1261,70 @@ impl<'a> ResultSetBuilder<'a> {
         Ok(self)
     }
 
+    fn constrain_field_compare(&mut self, id: Id, field: &str, child: Id) -> Result<&mut Self> {
+        for (kind, other) in self.vars.comparisons.get(&child).into_iter().flatten() {
+            let value = self.comparison_value(Some(id), child, kind, *other)?;
+            self.result_set.requests.get_mut(&id).unwrap().constrain(
+                kind.clone(),
+                Some(field.to_string()),
+                value,
+            );
+        }
+        Ok(self)
+    }
+
+    /// The value to compare against when `id` is compared with `other`: a literal value,
+    /// another field on the same result (`parent`), or a field on a different result. Skipping
+    /// a comparison would authorize too much, so any other `other` is unsupported.
+    fn comparison_value(
+        &self,
+        parent: Option<Id>,
+        id: Id,
+        kind: &ConstraintKind,
+        other: Id,
+    ) -> Result<ConstraintValue> {
+        if let Some(val) = self.vars.eq_values.get(&other) {
+            return Ok(ConstraintValue::Term(val.clone()));
+        }
+        let value = self
+            .vars
+            .field_relationships
+            .iter()
+            .find_map(|(other_parent, children)| {
+                children
+                    .iter()
+                    .find(|(_, c)| *c == other)
+                    .map(|(other_field, _)| {
+                        if Some(*other_parent) == parent {
+                            ConstraintValue::Field(other_field.clone())
+                        } else {
+                            ConstraintValue::Ref(Ref {
+                                field: Some(other_field.clone()),
+                                result_id: *other_parent,
+                            })
+                        }
+                    })
+            });
+        match value {
+            Some(value) => Ok(value),
+            None => {
+                let name = |id| {
+                    self.var_name(id)
+                        .map_or_else(|| id.to_string(), |name| name.0)
+                };
+                err_unsupported(
+                    format!(
+                        "Unsupported comparison: {} {} {}",
+                        name(id),
+                        kind,
+                        name(other)
+                    ),
+                    Term::from(Value::Variable(Symbol(name(other)))),
+                )
+            }
+        }
+    }
+
     fn constrain_field_others_with_same_parent(
         &mut self,
         id: Id,
@@ -865,6 +1473,7 @@ impl<'a> ResultSetBuilder<'a> {
                         this.constrain_field_eq(id, field, *child)?
                             .constrain_field_neq(id, field, *child)?
                             .constrain_field_contained(id, field, *child)?
+                            .constrain_field_compare(id, field, *child)?
                             .constrain_field_others_with_same_parent(id, field, *child)?
                             .constrain_field_others(id, field, *child)?
                             .ensure_added_constraint(id, field, *child, before)
@@ -902,6 +1511,16 @@ impl Vars {
                 hash_map_set_add(hash_map_set_add(map, a, b), b, a)
             });
 
+        // store each comparison from both sides, so `x < y` is also `y > x`.
+        let comparisons = info
+            .comparisons
+            .into_iter()
+            .fold(HashMap::new(), |map, (a, kind, b)| {
+                let (a, b) = (assign_id(a), assign_id(b));
+                let flipped = kind.flip();
+                hash_map_set_add(hash_map_set_add(map, a, (kind, b)), b, (flipped, a))
+            });
+
         // now convert the remaining VarInfo fields into equivalent Vars fields.
         let in_relationships = info
             .in_relationships
@@ -935,17 +1554,49 @@ impl Vars {
             hash_map_set_add(map, assign_id(p), (f, assign_id(c)))
         });
 
+        let negations = info
+            .negations
+            .into_iter()
+            .map(Self::from_info)
+            .collect::<Result<Vec<_>>>()?;
+
+        // Negated conjunctions are planned independently, so they can't refer to
+        // variables from the enclosing expression other than `_this`.
+        for negation in &negations {
+            if let Some(var) = negation
+                .variables
+                .values()
+                .flatten()
+                .find(|v| v.0 != "_this" && seek_var_id(&variables, v).is_some())
+            {
+                return err_unsupported(
+                    format!(
+                        "Negated constraints that refer to variable {} from outside the negation are not supported for data filtering",
+                        var
+                    ),
+                    term!(var.clone()),
+                );
+            }
+        }
+
+        // a negation constrains `_this` even if nothing else does.
+        if !negations.is_empty() {
+            get_var_id(&mut variables, sym!("_this"), &counter);
+        }
+
         seek_var_id(&variables, &sym!("_this")).map_or_else(
             || err_invalid("No `_this` variable".to_string()),
             |this_id| {
                 Ok(Vars {
                     variables,
                     uncycles,
+                    comparisons,
                     field_relationships,
                     in_relationships,
                     eq_values,
                     contained_values,
                     types,
+                    negations,
                     this_id,
                 })
             },
@@ -1000,6 +1651,16 @@ impl Vars {
         for (x, y) in &self.in_relationships {
             eprintln!("      {} in {}", x, y);
         }
+        eprintln!("    negations");
+        for negation in &self.negations {
+            negation.explain();
+        }
+        eprintln!("    comparisons");
+        for (x, cs) in self.comparisons.iter() {
+            for (kind, y) in cs.iter() {
+                eprintln!("      {} {:?} {}", x, kind, y);
+            }
+        }
     }
 }
 
@@ -1041,6 +1702,37 @@ where
         })
 }
 
+/// The value of `field` on `record`, or the record itself if there's no field.
+fn record_field<R: Record>(record: &R, field: Option<&str>) -> Result<Term> {
+    match field {
+        None => Ok(record.to_term()),
+        Some(field) => record.field(field).map_or_else(
+            || {
+                err_invalid(format!(
+                    "Record {} has no field {}",
+                    record.to_term().to_polar(),
+                    field
+                ))
+            },
+            Ok,
+        ),
+    }
+}
+
+/// The elements of `a` that aren't in `b`, counting duplicates.
+fn difference<'a, A: PartialEq>(a: &'a [A], b: &[A]) -> Vec<&'a A> {
+    let mut b = b.iter().collect::<Vec<_>>();
+    a.iter()
+        .filter(|x| match b.iter().position(|y| y == x) {
+            Some(i) => {
+                b.remove(i);
+                false
+            }
+            None => true,
+        })
+        .collect()
+}
+
 fn index_of<A>(v: &[A], x: &A) -> Option<usize>
 where
     A: PartialEq<A>,
@@ -1166,6 +1858,692 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_field_comparisons() -> TestResult {
+        let pat_a = term!(pattern!(instance!("A")));
+        let level = term!(op!(Dot, var!("_this"), str!("level")));
+        let partial = term!(op!(
+            And,
+            term!(op!(Isa, var!("_this"), pat_a)),
+            term!(op!(Leq, level.clone(), term!(3))),
+            term!(op!(Gt, term!(0), level.clone())),
+            term!(op!(
+                Lt,
+                level,
+                term!(op!(Dot, var!("_this"), str!("max_level")))
+            ))
+        ));
+
+        let bindings = ResultEvent::from(hashmap! {
+            sym!("resource") => partial
+        });
+        let types = hashmap! {
+            "A".to_owned() => hashmap! {
+                "level".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                },
+                "max_level".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                }
+            },
+        };
+
+        // Check the comparisons before the optimizer drops the ones implied by others.
+        let plan = FilterPlan::build_unoptimized(types, vec![bindings], "resource", "A", false)?;
+        let result_set = &plan.result_sets[0];
+        let request = &result_set.requests[&result_set.result_id];
+        let constraint = |kind, field: &str, value| Constraint {
+            kind,
+            field: Some(field.to_owned()),
+            value,
+        };
+        assert!(unord_eq(
+            request.constraints.clone(),
+            vec![
+                constraint(
+                    ConstraintKind::Leq,
+                    "level",
+                    ConstraintValue::Term(term!(3))
+                ),
+                constraint(ConstraintKind::Lt, "level", ConstraintValue::Term(term!(0))),
+                constraint(
+                    ConstraintKind::Lt,
+                    "level",
+                    ConstraintValue::Field("max_level".to_owned())
+                ),
+                constraint(
+                    ConstraintKind::Gt,
+                    "max_level",
+                    ConstraintValue::Field("level".to_owned())
+                ),
+            ]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_optimize_field_comparisons() -> TestResult {
+        let pat_a = term!(pattern!(instance!("A")));
+        let level = term!(op!(Dot, var!("_this"), str!("level")));
+        let partial = term!(op!(
+            And,
+            term!(op!(Isa, var!("_this"), pat_a)),
+            term!(op!(Leq, level.clone(), term!(3))),
+            term!(op!(Gt, term!(0), level.clone())),
+            term!(op!(
+                Lt,
+                level,
+                term!(op!(Dot, var!("_this"), str!("max_level")))
+            ))
+        ));
+
+        let bindings = ResultEvent::from(hashmap! {
+            sym!("resource") => partial
+        });
+        let types = hashmap! {
+            "A".to_owned() => hashmap! {
+                "level".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                },
+                "max_level".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                }
+            },
+        };
+
+        let plan = build_filter_plan(types, vec![bindings], "resource", "A")?;
+        let result_set = &plan.result_sets[0];
+        let request = &result_set.requests[&result_set.result_id];
+        let constraint = |kind, field: &str, value| Constraint {
+            kind,
+            field: Some(field.to_owned()),
+            value,
+        };
+        // `level < 0` implies `level <= 3`.
+        assert!(unord_eq(
+            request.constraints.clone(),
+            vec![
+                constraint(ConstraintKind::Lt, "level", ConstraintValue::Term(term!(0))),
+                constraint(
+                    ConstraintKind::Lt,
+                    "level",
+                    ConstraintValue::Field("max_level".to_owned())
+                ),
+                constraint(
+                    ConstraintKind::Gt,
+                    "max_level",
+                    ConstraintValue::Field("level".to_owned())
+                ),
+            ]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_related_field_comparisons() -> TestResult {
+        let pat_a = term!(pattern!(instance!("A")));
+        let pat_b = term!(pattern!(instance!("B")));
+        let parent = term!(op!(Dot, var!("_this"), str!("parent")));
+        let partial = term!(op!(
+            And,
+            term!(op!(Isa, var!("_this"), pat_a)),
+            term!(op!(Isa, parent.clone(), pat_b)),
+            term!(op!(
+                Unify,
+                term!(op!(Dot, parent.clone(), str!("id"))),
+                term!(1)
+            )),
+            term!(op!(
+                Geq,
+                term!(op!(Dot, var!("_this"), str!("level"))),
+                term!(op!(Dot, parent, str!("level")))
+            ))
+        ));
+
+        let bindings = ResultEvent::from(hashmap! {
+            sym!("resource") => partial
+        });
+        let types = hashmap! {
+            "A".to_owned() => hashmap! {
+                "level".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                },
+                "parent_id".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                },
+                "parent".to_owned() => Type::Relation {
+                    kind: "one".to_owned(),
+                    other_class_tag: "B".to_owned(),
+                    my_field: "parent_id".to_owned(),
+                    other_field: "id".to_owned(),
+                }
+            },
+            "B".to_owned() => hashmap! {
+                "id".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                },
+                "level".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                }
+            },
+        };
+
+        let plan = build_filter_plan(types, vec![bindings], "resource", "A")?;
+        let result_set = &plan.result_sets[0];
+        let this_id = result_set.result_id;
+        let parent_id = result_set.resolve_order[0];
+        assert_eq!(result_set.resolve_order, vec![parent_id, this_id]);
+
+        let constraints = &result_set.requests[&this_id].constraints;
+        assert!(constraints.contains(&Constraint {
+            kind: ConstraintKind::Geq,
+            field: Some("level".to_owned()),
+            value: ConstraintValue::Ref(Ref {
+                field: Some("level".to_owned()),
+                result_id: parent_id,
+            }),
+        }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unresolved_comparison_is_unsupported() {
+        let partial = term!(op!(
+            And,
+            term!(op!(Isa, var!("_this"), term!(pattern!(instance!("A"))))),
+            term!(op!(
+                Lt,
+                term!(op!(Dot, var!("_this"), str!("level"))),
+                var!("limit")
+            ))
+        ));
+        let bindings = ResultEvent::from(hashmap! {
+            sym!("resource") => partial
+        });
+        let types = hashmap! {
+            "A".to_owned() => hashmap! {
+                "level".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                }
+            },
+        };
+        // Dropping the comparison would authorize every `A`.
+        match build_filter_plan(types, vec![bindings], "resource", "A") {
+            Err(RuntimeError::Unsupported { msg, .. }) => {
+                assert!(msg.ends_with("< limit"), "{}", msg)
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    /// Build a filter plan for `x` from the partial results of `allow("alice", action, x)`.
+    fn plan_for(policy: &str, action: &str, types: Types) -> Result<FilterPlan> {
+        use crate::events::QueryEvent;
+        use crate::polar::Polar;
+
+        let polar = Polar::new();
+        polar.load_str(policy).unwrap();
+        let mut query =
+            polar.new_query_from_term(term!(call!("allow", ["alice", action, sym!("x")])), false);
+        let mut results = vec![];
+        loop {
+            match query.next_event().unwrap() {
+                QueryEvent::Result { bindings, .. } => results.push(ResultEvent::new(bindings)),
+                QueryEvent::Done { .. } => break,
+                event => panic!("unexpected event: {:?}", event),
+            }
+        }
+        build_filter_plan(types, results, "x", "Doc")
+    }
+
+    fn doc_types() -> Types {
+        let base = |tag: &str| Type::Base {
+            class_tag: tag.to_owned(),
+        };
+        hashmap! {
+            "Doc".to_owned() => hashmap! {
+                "id".to_owned() => base("Integer"),
+                "tags".to_owned() => base("List"),
+                "members".to_owned() => Type::Relation {
+                    kind: "many".to_owned(),
+                    other_class_tag: "Member".to_owned(),
+                    my_field: "id".to_owned(),
+                    other_field: "doc_id".to_owned(),
+                }
+            },
+            "Member".to_owned() => hashmap! {
+                "doc_id".to_owned() => base("Integer"),
+                "active".to_owned() => base("Boolean"),
+            },
+        }
+    }
+
+    /// Check that `result_set` fetches `Doc`s that aren't among the results of a
+    /// negated request, and return that request's constraints.
+    fn negated_constraints(result_set: &ResultSet) -> Vec<Constraint> {
+        let this = &result_set.requests[&result_set.result_id];
+        let negated_id = this
+            .constraints
+            .iter()
+            .find_map(|c| match c {
+                Constraint {
+                    kind: ConstraintKind::Nin,
+                    field: None,
+                    value:
+                        ConstraintValue::Ref(Ref {
+                            field: None,
+                            result_id,
+                        }),
+                } => Some(*result_id),
+                _ => None,
+            })
+            .expect("an anti-join on _this");
+        assert!(
+            index_of(&result_set.resolve_order, &negated_id)
+                < index_of(&result_set.resolve_order, &result_set.result_id)
+        );
+        let negated = &result_set.requests[&negated_id];
+        assert_eq!(negated.class_tag, "Doc");
+        negated.constraints.clone()
+    }
+
+    #[test]
+    fn test_negated_field_check() -> TestResult {
+        let plan = plan_for(
+            r#"allow(_, "read", doc) if not "secret" in doc.tags;"#,
+            "read",
+            doc_types(),
+        )?;
+        assert_eq!(plan.result_sets.len(), 1);
+        assert_eq!(
+            negated_constraints(&plan.result_sets[0]),
+            vec![Constraint {
+                kind: ConstraintKind::Contains,
+                field: Some("tags".to_owned()),
+                value: ConstraintValue::Term(term!("secret")),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_negated_relation_and_forall() -> TestResult {
+        for policy in [
+            r#"allow(_, "read", doc) if not (m in doc.members and m.active = false);"#,
+            r#"allow(_, "read", doc) if forall(m in doc.members, m.active = true);"#,
+        ] {
+            let plan = plan_for(policy, "read", doc_types())?;
+            assert_eq!(plan.result_sets.len(), 1, "{}", policy);
+            let result_set = &plan.result_sets[0];
+            let negated = negated_constraints(result_set);
+            let member_id = match &negated[..] {
+                [Constraint {
+                    kind: ConstraintKind::In,
+                    field: Some(field),
+                    value:
+                        ConstraintValue::Ref(Ref {
+                            field: Some(other_field),
+                            result_id,
+                        }),
+                }] if field == "id" && other_field == "doc_id" => *result_id,
+                _ => panic!("unexpected constraints {:?} for {}", negated, policy),
+            };
+            let member = &result_set.requests[&member_id];
+            assert_eq!(member.class_tag, "Member");
+            let expected = if policy.contains("forall") {
+                (ConstraintKind::Neq, term!(true))
+            } else {
+                (ConstraintKind::Eq, term!(false))
+            };
+            assert!(
+                member.constraints.contains(&Constraint {
+                    kind: expected.0,
+                    field: Some("active".to_owned()),
+                    value: ConstraintValue::Term(expected.1),
+                }),
+                "{:?}",
+                member.constraints
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_negation_sharing_variables_is_unsupported() {
+        let partial = term!(op!(
+            And,
+            term!(op!(
+                Unify,
+                var!("y"),
+                term!(op!(Dot, var!("_this"), str!("id")))
+            )),
+            term!(op!(
+                Not,
+                term!(op!(
+                    And,
+                    term!(op!(
+                        In,
+                        var!("m"),
+                        term!(op!(Dot, var!("_this"), str!("members")))
+                    )),
+                    term!(op!(
+                        Unify,
+                        term!(op!(Dot, var!("m"), str!("doc_id"))),
+                        var!("y")
+                    ))
+                ))
+            ))
+        ));
+        let bindings = ResultEvent::from(hashmap! {
+            sym!("resource") => partial
+        });
+        match build_filter_plan(doc_types(), vec![bindings], "resource", "Doc") {
+            Err(RuntimeError::Unsupported { msg, .. }) => {
+                assert!(
+                    msg.contains("variable y from outside the negation"),
+                    "{}",
+                    msg
+                )
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_optimize_dedupes_and_merges_result_sets() -> TestResult {
+        let plan = plan_for(
+            r#"allow(_, "read", doc) if doc.id = 1;
+               allow(_, "read", doc) if doc.id = 1;
+               allow(_, "read", doc) if doc.id in [2, 3];"#,
+            "read",
+            doc_types(),
+        )?;
+        assert_eq!(plan.result_sets.len(), 1);
+        let result_set = &plan.result_sets[0];
+        assert_eq!(
+            result_set.requests[&result_set.result_id].constraints,
+            vec![Constraint {
+                kind: ConstraintKind::In,
+                field: Some("id".to_owned()),
+                value: ConstraintValue::Term(term!([1, 2, 3])),
+            }]
+        );
+
+        // Result sets that differ in more than one check can't be merged.
+        let plan = plan_for(
+            r#"allow(_, "read", doc) if doc.id = 1;
+               allow(_, "read", doc) if doc.id = 2 and "a" in doc.tags;"#,
+            "read",
+            doc_types(),
+        )?;
+        assert_eq!(plan.result_sets.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_optimize_drops_implied_constraints() {
+        let constraint = |kind, value| Constraint {
+            kind,
+            field: Some("id".to_owned()),
+            value: ConstraintValue::Term(value),
+        };
+        let mut request = FetchRequest {
+            class_tag: "Doc".to_owned(),
+            constraints: vec![
+                constraint(ConstraintKind::Lt, term!(5)),
+                constraint(ConstraintKind::Leq, term!(3)),
+                constraint(ConstraintKind::Lt, term!(3)),
+                constraint(ConstraintKind::Gt, term!(0)),
+                constraint(ConstraintKind::Gt, term!(0)),
+                constraint(ConstraintKind::Geq, term!(0)),
+            ],
+        };
+        request.drop_implied();
+        assert_eq!(
+            request.constraints,
+            vec![
+                constraint(ConstraintKind::Lt, term!(3)),
+                constraint(ConstraintKind::Gt, term!(0)),
+            ]
+        );
+
+        let mut request = FetchRequest {
+            class_tag: "Doc".to_owned(),
+            constraints: vec![
+                constraint(ConstraintKind::In, term!([1, 2])),
+                constraint(ConstraintKind::Nin, term!([3])),
+                constraint(ConstraintKind::Neq, term!(4)),
+                constraint(ConstraintKind::Eq, term!(1)),
+                constraint(ConstraintKind::Leq, term!(1)),
+            ],
+        };
+        request.drop_implied();
+        assert_eq!(
+            request.constraints,
+            vec![constraint(ConstraintKind::Eq, term!(1))]
+        );
+    }
+
+    #[test]
+    fn test_optimize_orders_by_selectivity() {
+        let request = |class_tag: &str, kind, value| FetchRequest {
+            class_tag: class_tag.to_owned(),
+            constraints: vec![Constraint {
+                kind,
+                field: Some("id".to_owned()),
+                value,
+            }],
+        };
+        let mut result_set = ResultSet {
+            requests: hashmap! {
+                1 => request("Member", ConstraintKind::Neq, ConstraintValue::Term(term!(0))),
+                2 => request("Member", ConstraintKind::Eq, ConstraintValue::Term(term!(1))),
+                3 => request("Doc", ConstraintKind::In, ConstraintValue::Ref(Ref {
+                    field: Some("doc_id".to_owned()),
+                    result_id: 2,
+                })),
+                4 => request("Doc", ConstraintKind::In, ConstraintValue::Ref(Ref {
+                    field: Some("doc_id".to_owned()),
+                    result_id: 1,
+                })),
+            },
+            resolve_order: vec![1, 2, 3, 4],
+            result_id: 3,
+        };
+        result_set.optimize(false);
+        assert_eq!(result_set.resolve_order, vec![2, 1, 4, 3]);
+    }
+
+    #[test]
+    fn test_display_plan() {
+        let plan = FilterPlan {
+            result_sets: vec![
+                ResultSet {
+                    requests: hashmap! {
+                        1 => FetchRequest {
+                            class_tag: "Member".to_owned(),
+                            constraints: vec![Constraint {
+                                kind: ConstraintKind::Eq,
+                                field: Some("active".to_owned()),
+                                value: ConstraintValue::Term(term!(true)),
+                            }],
+                        },
+                        2 => FetchRequest {
+                            class_tag: "Doc".to_owned(),
+                            constraints: vec![
+                                Constraint {
+                                    kind: ConstraintKind::In,
+                                    field: Some("id".to_owned()),
+                                    value: ConstraintValue::Ref(Ref {
+                                        field: Some("doc_id".to_owned()),
+                                        result_id: 1,
+                                    }),
+                                },
+                                Constraint {
+                                    kind: ConstraintKind::Lt,
+                                    field: Some("id".to_owned()),
+                                    value: ConstraintValue::Field("max_id".to_owned()),
+                                },
+                            ],
+                        },
+                    },
+                    resolve_order: vec![1, 2],
+                    result_id: 2,
+                },
+                ResultSet {
+                    requests: hashmap! {
+                        3 => FetchRequest {
+                            class_tag: "Doc".to_owned(),
+                            constraints: vec![Constraint {
+                                kind: ConstraintKind::Nin,
+                                field: None,
+                                value: ConstraintValue::Term(term!(["a", "b"])),
+                            }],
+                        },
+                    },
+                    resolve_order: vec![3],
+                    result_id: 3,
+                },
+            ],
+        };
+        assert_eq!(
+            plan.to_string(),
+            r#"WITH
+  r1 AS (SELECT * FROM Member WHERE active = true)
+SELECT * FROM Doc
+WHERE id IN (SELECT doc_id FROM r1)
+  AND id < max_id
+UNION
+SELECT * FROM Doc
+WHERE this NOT IN ["a", "b"]
+"#
+        );
+        assert_eq!(
+            FilterPlan {
+                result_sets: vec![]
+            }
+            .to_string(),
+            "-- no results\n"
+        );
+    }
+
+    /// `Doc` and `Member` records matching `doc_types`. Docs also have their members as a
+    /// field, so that the policy can be evaluated against them directly.
+    fn doc_records() -> Map<TypeName, Vec<Term>> {
+        let member = |doc_id: i64, active: bool| {
+            term!(btreemap! {
+                sym!("doc_id") => term!(doc_id),
+                sym!("active") => term!(active),
+            })
+        };
+        let docs = [
+            (1, vec!["secret"], vec![true]),
+            (2, vec![], vec![false]),
+            (3, vec!["public"], vec![true, false]),
+            (4, vec!["secret", "public"], vec![]),
+        ];
+        let mut records = hashmap! {
+            "Doc".to_owned() => vec![],
+            "Member".to_owned() => vec![],
+        };
+        for (id, tags, members) in docs {
+            let members = members
+                .into_iter()
+                .map(|active| member(id, active))
+                .collect::<TermList>();
+            records.get_mut("Member").unwrap().extend(members.clone());
+            records.get_mut("Doc").unwrap().push(term!(btreemap! {
+                sym!("id") => term!(id),
+                sym!("tags") => term!(tags.into_iter().map(|t| term!(t)).collect::<TermList>()),
+                sym!("members") => term!(members),
+            }));
+        }
+        records
+    }
+
+    #[test]
+    fn test_execute_matches_query() -> TestResult {
+        use crate::events::QueryEvent;
+        use crate::polar::Polar;
+
+        let records = doc_records();
+        for policy in [
+            r#"allow(_, "read", doc) if doc.id = 1;
+               allow(_, "read", doc) if doc.id in [2, 3];"#,
+            r#"allow(_, "read", doc) if doc.id > 1 and doc.id <= 3;"#,
+            r#"allow(_, "read", doc) if not "secret" in doc.tags;"#,
+            r#"allow(_, "read", doc) if m in doc.members and m.active = true;"#,
+            r#"allow(_, "read", doc) if forall(m in doc.members, m.active = true);"#,
+            r#"allow(_, "read", doc) if
+                 not (m in doc.members and m.active = false) and doc.id != 4;"#,
+        ] {
+            let polar = Polar::new();
+            polar.load_str(policy).unwrap();
+            let is_allowed = |doc: &Term| {
+                let mut query = polar.new_query_from_term(
+                    term!(Call {
+                        name: sym!("allow"),
+                        args: vec![term!("alice"), term!("read"), doc.clone()],
+                        kwargs: None,
+                    }),
+                    false,
+                );
+                let mut allowed = false;
+                loop {
+                    match query.next_event().unwrap() {
+                        QueryEvent::Result { .. } => allowed = true,
+                        QueryEvent::Done { .. } => break,
+                        event => panic!("unexpected event: {:?}", event),
+                    }
+                }
+                allowed
+            };
+            let expected = records["Doc"]
+                .iter()
+                .filter(|doc| is_allowed(doc))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let plan = plan_for(policy, "read", doc_types())?;
+            let actual = plan.execute(&records)?;
+            assert!(
+                unord_eq(actual.clone(), expected.clone()),
+                "{}\n{}\nexpected {:?}\ngot {:?}",
+                policy,
+                plan,
+                expected,
+                actual
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_errors() {
+        let plan = FilterPlan {
+            result_sets: vec![ResultSet {
+                requests: hashmap! {
+                    1 => FetchRequest {
+                        class_tag: "Doc".to_owned(),
+                        constraints: vec![Constraint {
+                            kind: ConstraintKind::Eq,
+                            field: Some("owner".to_owned()),
+                            value: ConstraintValue::Term(term!("alice")),
+                        }],
+                    },
+                },
+                resolve_order: vec![1],
+                result_id: 1,
+            }],
+        };
+        match plan.execute(&doc_records()) {
+            Err(RuntimeError::InvalidState { msg }) => assert!(msg.contains("has no field owner")),
+            result => panic!("unexpected {:?}", result),
+        }
+        // No records of a type means no results.
+        assert!(plan.execute::<Term>(&HashMap::new()).unwrap().is_empty());
+    }
+
     #[test]
     fn test_partition_equivs() {
         let pairs = vec![(1, 2), (2, 3), (4, 3), (5, 6), (8, 8), (6, 7)];
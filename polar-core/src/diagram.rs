@@ -0,0 +1,668 @@
+//! Diagrams of the authorization model declared in resource blocks, in Graphviz (DOT) or Mermaid
+//! syntax.
+//!
+//! Each block is drawn as a cluster holding its type, roles and permissions. Relations connect the
+//! types, and shorthand rules connect roles and permissions, e.g., `"member" on "parent"` to
+//! `"viewer"`. Optionally, the call graph of the policy's rules can be drawn alongside.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::str::FromStr;
+
+use super::error::PolarResult;
+use super::resource_block::{
+    resource_blocks_from_sources, Declaration, Implier, ResourceBlocks, GLOBAL_BLOCK_NAME,
+};
+use super::rules::Rule;
+use super::sources::Source;
+use super::terms::{Operator, Term, Value};
+use super::visitor::{walk_term, Visitor};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    /// An actor or resource type.
+    Type,
+    Role,
+    Permission,
+    /// A rule, in the rule call graph.
+    Rule,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiagramNode {
+    pub id: String,
+    pub label: String,
+    pub kind: NodeKind,
+}
+
+/// Nodes drawn together, e.g., everything declared in one block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiagramCluster {
+    pub label: String,
+    pub nodes: Vec<DiagramNode>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// From a type to the type of one of its relations.
+    Relation,
+    /// From an implier to the head of a shorthand rule.
+    Implication { conditional: bool, deny: bool },
+    /// From a rule to a rule it calls.
+    Call,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiagramEdge {
+    pub from: String,
+    pub to: String,
+    pub label: Option<String>,
+    pub kind: EdgeKind,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModelDiagram {
+    pub clusters: Vec<DiagramCluster>,
+    /// Nodes outside of any cluster, e.g., relation types without a block of their own.
+    pub nodes: Vec<DiagramNode>,
+    pub edges: Vec<DiagramEdge>,
+    next_id: usize,
+}
+
+fn name_of(term: &Term) -> String {
+    match term.value() {
+        Value::String(s) => s.clone(),
+        _ => term.to_string(),
+    }
+}
+
+fn sorted_by_name<'a>(terms: impl IntoIterator<Item = &'a Term>) -> Vec<&'a Term> {
+    let mut terms: Vec<_> = terms.into_iter().collect();
+    terms.sort_by_key(|term| name_of(term));
+    terms
+}
+
+/// Collects the rules called in rule bodies, skipping method calls on application instances.
+struct RuleCallVisitor {
+    calls: BTreeSet<String>,
+}
+
+impl Visitor for RuleCallVisitor {
+    fn visit_term(&mut self, term: &Term) {
+        match term.value() {
+            Value::Expression(op)
+                if op.operator == Operator::Dot || op.operator == Operator::New =>
+            {
+                return
+            }
+            Value::Call(call) => {
+                self.calls.insert(call.name.0.clone());
+            }
+            _ => {}
+        }
+        walk_term(self, term)
+    }
+}
+
+impl ModelDiagram {
+    /// Draw the types, roles and permissions declared in `blocks`, connected by relations and
+    /// shorthand rules.
+    pub fn new(blocks: &ResourceBlocks) -> Self {
+        let mut diagram = Self::default();
+        // Map from `(block, role or permission)` to node ID. Type nodes have an empty declaration.
+        let mut ids: HashMap<(String, String), String> = HashMap::new();
+
+        for resource in sorted_by_name(blocks.declarations().keys()) {
+            let resource_name = name_of(resource);
+            let keyword = if blocks.actors.contains(resource) {
+                "actor"
+            } else {
+                "resource"
+            };
+            let label = format!("{} {}", keyword, resource_name);
+            let id = diagram.new_id();
+            ids.insert((resource_name.clone(), String::new()), id.clone());
+            let mut nodes = vec![DiagramNode {
+                id,
+                label: resource_name.clone(),
+                kind: NodeKind::Type,
+            }];
+
+            let declarations = &blocks.declarations()[resource];
+            for kind in [NodeKind::Role, NodeKind::Permission] {
+                let names = declarations.iter().filter_map(|(name, declaration)| {
+                    match (declaration, kind) {
+                        (Declaration::Role, NodeKind::Role)
+                        | (Declaration::Permission, NodeKind::Permission) => Some(name),
+                        _ => None,
+                    }
+                });
+                for name in sorted_by_name(names) {
+                    let id = diagram.new_id();
+                    ids.insert((resource_name.clone(), name_of(name)), id.clone());
+                    let label = name_of(name);
+                    nodes.push(DiagramNode { id, label, kind });
+                }
+            }
+            diagram.clusters.push(DiagramCluster { label, nodes });
+        }
+
+        let global_roles = blocks.global_roles();
+        if !global_roles.is_empty() {
+            let mut nodes = vec![];
+            for role in sorted_by_name(global_roles) {
+                let id = diagram.new_id();
+                ids.insert((GLOBAL_BLOCK_NAME.to_owned(), name_of(role)), id.clone());
+                let label = name_of(role);
+                let kind = NodeKind::Role;
+                nodes.push(DiagramNode { id, label, kind });
+            }
+            let label = GLOBAL_BLOCK_NAME.to_owned();
+            diagram.clusters.push(DiagramCluster { label, nodes });
+        }
+
+        let mut relations: Vec<_> = blocks
+            .relation_tuples()
+            .into_iter()
+            .map(|(subject, name, object)| (name_of(object), name_of(name), name_of(subject)))
+            .collect();
+        relations.sort();
+        for (object, name, subject) in relations {
+            let key = (subject, String::new());
+            let to = match ids.get(&key) {
+                Some(id) => id.clone(),
+                None => {
+                    let id = diagram.new_id();
+                    let label = key.0.clone();
+                    let kind = NodeKind::Type;
+                    diagram.nodes.push(DiagramNode {
+                        id: id.clone(),
+                        label,
+                        kind,
+                    });
+                    ids.insert(key, id.clone());
+                    id
+                }
+            };
+            diagram.edges.push(DiagramEdge {
+                from: ids[&(object, String::new())].clone(),
+                to,
+                label: Some(name),
+                kind: EdgeKind::Relation,
+            });
+        }
+
+        for resource in sorted_by_name(blocks.shorthand_rules.keys()) {
+            let resource_name = name_of(resource);
+            for rule in &blocks.shorthand_rules[resource] {
+                let to = match ids.get(&(resource_name.clone(), name_of(&rule.head))) {
+                    Some(id) => id.clone(),
+                    None => continue,
+                };
+                for implier in &rule.body {
+                    let (block, name, relation) = match implier {
+                        Implier::Resource(name, None) => (resource_name.clone(), name, None),
+                        Implier::Resource(name, Some((_, relation))) => {
+                            // Follow the relation (path) to the block `name` is declared in.
+                            let hops =
+                                blocks.get_relation_path_in_resource_block(relation, resource);
+                            match hops.ok().as_ref().and_then(|hops| hops.last()) {
+                                Some((_, related)) => (name_of(related), name, Some(relation)),
+                                None => continue,
+                            }
+                        }
+                        Implier::Global(_, role) => (GLOBAL_BLOCK_NAME.to_owned(), role, None),
+                    };
+                    let from = match ids.get(&(block, name_of(name))) {
+                        Some(id) => id.clone(),
+                        None => continue,
+                    };
+
+                    let mut labels = vec![];
+                    if let Some(relation) = relation {
+                        labels.push(format!("on {}", name_of(relation)));
+                    }
+                    if rule.deny.is_some() {
+                        labels.push("deny".to_owned());
+                    }
+                    if rule.condition.is_some() {
+                        labels.push("conditional".to_owned());
+                    }
+                    diagram.edges.push(DiagramEdge {
+                        from,
+                        to: to.clone(),
+                        label: (!labels.is_empty()).then(|| labels.join(", ")),
+                        kind: EdgeKind::Implication {
+                            conditional: rule.condition.is_some(),
+                            deny: rule.deny.is_some(),
+                        },
+                    });
+                }
+            }
+        }
+
+        diagram
+    }
+
+    /// Overlay the call graph of `rules`: a node per rule name and an edge per call from one rule
+    /// to another. Calls to rules that aren't in `rules` are left out.
+    pub fn add_rule_calls<'a>(&mut self, rules: impl IntoIterator<Item = &'a Rule>) {
+        let mut calls: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for rule in rules {
+            let mut visitor = RuleCallVisitor {
+                calls: BTreeSet::new(),
+            };
+            visitor.visit_rule(rule);
+            let callees = calls.entry(rule.name.0.clone()).or_default();
+            callees.append(&mut visitor.calls);
+        }
+        if calls.is_empty() {
+            return;
+        }
+
+        let mut ids = HashMap::new();
+        let mut nodes = vec![];
+        for name in calls.keys() {
+            let id = self.new_id();
+            ids.insert(name, id.clone());
+            let label = name.clone();
+            let kind = NodeKind::Rule;
+            nodes.push(DiagramNode { id, label, kind });
+        }
+        for (caller, callees) in &calls {
+            for callee in callees.iter().filter_map(|callee| ids.get(callee)) {
+                self.edges.push(DiagramEdge {
+                    from: ids[caller].clone(),
+                    to: callee.clone(),
+                    label: None,
+                    kind: EdgeKind::Call,
+                });
+            }
+        }
+        let label = "rules".to_owned();
+        self.clusters.push(DiagramCluster { label, nodes });
+    }
+
+    fn new_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("n{}", self.next_id)
+    }
+}
+
+/// Draw a [`ModelDiagram`] of the resource blocks in `sources` without loading them, optionally
+/// with the call graph of their rules (including the rules shorthand rules expand to). Like
+/// [`permission_matrices_from_sources`](crate::permission_matrix::permission_matrices_from_sources),
+/// this doesn't need the classes the policy refers to to be registered.
+pub fn model_diagram_from_sources(
+    sources: Vec<Source>,
+    rule_calls: bool,
+) -> PolarResult<ModelDiagram> {
+    let (blocks, rules) = resource_blocks_from_sources(sources)?;
+    let mut diagram = ModelDiagram::new(&blocks);
+    if rule_calls {
+        diagram.add_rule_calls(&rules);
+    }
+    Ok(diagram)
+}
+
+/// Output formats for [`ModelDiagram`]s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagramFormat {
+    /// Graphviz DOT.
+    Dot,
+    /// A Mermaid flowchart.
+    Mermaid,
+}
+
+impl FromStr for DiagramFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" | "graphviz" => Ok(DiagramFormat::Dot),
+            "mermaid" => Ok(DiagramFormat::Mermaid),
+            _ => Err(format!(
+                "Unknown diagram format '{}'. Expected one of: dot, mermaid.",
+                s
+            )),
+        }
+    }
+}
+
+impl DiagramFormat {
+    pub fn export(&self, diagram: &ModelDiagram) -> String {
+        match self {
+            DiagramFormat::Dot => to_dot(diagram),
+            DiagramFormat::Mermaid => to_mermaid(diagram),
+        }
+    }
+}
+
+fn dot_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn dot_node(node: &DiagramNode) -> String {
+    let shape = match node.kind {
+        NodeKind::Type => "box3d",
+        NodeKind::Role => "ellipse",
+        NodeKind::Permission => "box",
+        NodeKind::Rule => "hexagon",
+    };
+    format!(
+        "{} [label={}, shape={}];",
+        node.id,
+        dot_string(&node.label),
+        shape
+    )
+}
+
+fn to_dot(diagram: &ModelDiagram) -> String {
+    let mut lines = vec!["digraph authorization_model {".to_owned()];
+    lines.push("  rankdir=LR;".to_owned());
+    for (i, cluster) in diagram.clusters.iter().enumerate() {
+        lines.push(format!("  subgraph cluster_{} {{", i));
+        lines.push(format!("    label={};", dot_string(&cluster.label)));
+        for node in &cluster.nodes {
+            lines.push(format!("    {}", dot_node(node)));
+        }
+        lines.push("  }".to_owned());
+    }
+    for node in &diagram.nodes {
+        lines.push(format!("  {}", dot_node(node)));
+    }
+    for edge in &diagram.edges {
+        let mut attributes = vec![];
+        if let Some(label) = &edge.label {
+            attributes.push(format!("label={}", dot_string(label)));
+        }
+        match edge.kind {
+            EdgeKind::Relation => attributes.push("style=bold".to_owned()),
+            EdgeKind::Implication { conditional, deny } => {
+                if conditional {
+                    attributes.push("style=dashed".to_owned());
+                }
+                if deny {
+                    attributes.push("color=red".to_owned());
+                }
+            }
+            EdgeKind::Call => attributes.push("style=dotted".to_owned()),
+        }
+        let attributes = if attributes.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", attributes.join(", "))
+        };
+        lines.push(format!("  {} -> {}{};", edge.from, edge.to, attributes));
+    }
+    lines.push("}".to_owned());
+    lines.join("\n") + "\n"
+}
+
+fn mermaid_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "#quot;"))
+}
+
+fn mermaid_node(node: &DiagramNode) -> String {
+    let label = mermaid_string(&node.label);
+    match node.kind {
+        NodeKind::Type => format!("{}[[{}]]", node.id, label),
+        NodeKind::Role => format!("{}([{}])", node.id, label),
+        NodeKind::Permission => format!("{}[{}]", node.id, label),
+        NodeKind::Rule => format!("{}{{{{{}}}}}", node.id, label),
+    }
+}
+
+fn to_mermaid(diagram: &ModelDiagram) -> String {
+    let mut lines = vec!["flowchart LR".to_owned()];
+    for (i, cluster) in diagram.clusters.iter().enumerate() {
+        let label = mermaid_string(&cluster.label);
+        lines.push(format!("  subgraph cluster_{} [{}]", i, label));
+        for node in &cluster.nodes {
+            lines.push(format!("    {}", mermaid_node(node)));
+        }
+        lines.push("  end".to_owned());
+    }
+    for node in &diagram.nodes {
+        lines.push(format!("  {}", mermaid_node(node)));
+    }
+    let mut denied = vec![];
+    for (i, edge) in diagram.edges.iter().enumerate() {
+        let arrow = match edge.kind {
+            EdgeKind::Relation => "==>",
+            EdgeKind::Implication {
+                conditional: true, ..
+            }
+            | EdgeKind::Call => "-.->",
+            EdgeKind::Implication { .. } => "-->",
+        };
+        if let EdgeKind::Implication { deny: true, .. } = edge.kind {
+            denied.push(i.to_string());
+        }
+        let label = edge
+            .label
+            .as_ref()
+            .map_or_else(String::new, |label| format!("|{}|", mermaid_string(label)));
+        lines.push(format!("  {} {}{} {}", edge.from, arrow, label, edge.to));
+    }
+    if !denied.is_empty() {
+        lines.push(format!("  linkStyle {} stroke:red", denied.join(",")));
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(src: &str) -> Vec<Source> {
+        vec![Source {
+            filename: None,
+            src: src.to_owned(),
+        }]
+    }
+
+    const POLICY: &str = r#"
+        actor User {}
+
+        global {
+            roles = ["admin"];
+        }
+
+        resource Org {
+            roles = ["member"];
+        }
+
+        resource Repo {
+            permissions = ["read", "delete"];
+            roles = ["viewer"];
+            relations = { parent: Org, creator: User };
+
+            "viewer" if "member" on "parent";
+            "read" if "viewer";
+            "delete" if global "admin" and resource.is_archived;
+            deny "read" if "viewer" and resource.is_secret;
+        }
+
+        allow(actor, action, resource) if has_permission(actor, action, resource);
+        has_role(user: User, name: String, org: Org) if
+            org.is_member(user) and check_role(name);
+        check_role(_);
+    "#;
+
+    fn node(diagram: &ModelDiagram, label: &str) -> String {
+        let mut nodes = diagram
+            .clusters
+            .iter()
+            .flat_map(|c| &c.nodes)
+            .chain(&diagram.nodes);
+        nodes.find(|n| n.label == label).unwrap().id.clone()
+    }
+
+    fn edge(diagram: &ModelDiagram, from: &str, to: &str) -> DiagramEdge {
+        let (from, to) = (node(diagram, from), node(diagram, to));
+        let mut edges = diagram.edges.iter();
+        edges
+            .find(|e| e.from == from && e.to == to)
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn test_model_diagram() {
+        let diagram = model_diagram_from_sources(source(POLICY), false).unwrap();
+        let labels: Vec<_> = diagram.clusters.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec!["resource Org", "resource Repo", "actor User", "global"]
+        );
+        let repo: Vec<_> = diagram.clusters[1]
+            .nodes
+            .iter()
+            .map(|n| (n.label.as_str(), n.kind))
+            .collect();
+        assert_eq!(
+            repo,
+            vec![
+                ("Repo", NodeKind::Type),
+                ("viewer", NodeKind::Role),
+                ("delete", NodeKind::Permission),
+                ("read", NodeKind::Permission),
+            ]
+        );
+
+        let parent = edge(&diagram, "Repo", "Org");
+        assert_eq!(parent.label.as_deref(), Some("parent"));
+        assert_eq!(parent.kind, EdgeKind::Relation);
+        let member = edge(&diagram, "member", "viewer");
+        assert_eq!(member.label.as_deref(), Some("on parent"));
+        let admin = edge(&diagram, "admin", "delete");
+        assert_eq!(
+            admin.kind,
+            EdgeKind::Implication {
+                conditional: true,
+                deny: false
+            }
+        );
+        let implications = diagram
+            .edges
+            .iter()
+            .filter(|e| e.from == node(&diagram, "viewer") && e.to == node(&diagram, "read"));
+        let labels: Vec<_> = implications.map(|e| e.label.as_deref()).collect();
+        assert_eq!(labels, vec![None, Some("deny, conditional")]);
+        assert!(!diagram.edges.iter().any(|e| e.kind == EdgeKind::Call));
+    }
+
+    #[test]
+    fn test_model_diagram_rule_calls() {
+        let diagram = model_diagram_from_sources(source(POLICY), true).unwrap();
+        let rules = diagram.clusters.last().unwrap();
+        assert_eq!(rules.label, "rules");
+        let names: Vec<_> = rules.nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["allow", "check_role", "deny", "has_permission", "has_role"]
+        );
+        let calls: BTreeSet<_> = diagram
+            .edges
+            .iter()
+            .filter(|e| e.kind == EdgeKind::Call)
+            .map(|e| (e.from.clone(), e.to.clone()))
+            .collect();
+        let call = |from, to| (node(&diagram, from), node(&diagram, to));
+        // `org.is_member(user)` is a method call, not a call to a rule.
+        let expected = [
+            call("allow", "has_permission"),
+            call("deny", "has_role"),
+            call("has_permission", "has_role"),
+            call("has_role", "check_role"),
+            // From `"viewer" if "member" on "parent";`.
+            call("has_role", "has_role"),
+        ];
+        assert_eq!(calls, expected.into_iter().collect());
+    }
+
+    #[test]
+    fn test_model_diagram_rule_calls_in_modules() {
+        let billing = r#"
+            module billing;
+            export can_refund;
+            can_refund(user) if check(user);
+            check(_);
+        "#;
+        let policy = r#"
+            import billing::can_refund;
+            allow(actor, "refund", _) if can_refund(actor) and check(actor);
+            check(_);
+        "#;
+        let sources = [source(billing), source(policy)].concat();
+        let diagram = model_diagram_from_sources(sources, true).unwrap();
+        let rules = diagram.clusters.last().unwrap();
+        let names: Vec<_> = rules.nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["allow", "billing::can_refund", "billing::check", "check"]
+        );
+        let calls: BTreeSet<_> = diagram
+            .edges
+            .iter()
+            .filter(|e| e.kind == EdgeKind::Call)
+            .map(|e| (e.from.clone(), e.to.clone()))
+            .collect();
+        let call = |from, to| (node(&diagram, from), node(&diagram, to));
+        let expected = [
+            call("allow", "billing::can_refund"),
+            call("allow", "check"),
+            call("billing::can_refund", "billing::check"),
+        ];
+        assert_eq!(calls, expected.into_iter().collect());
+
+        // Sources are checked against their modules as they are when loading.
+        let error = |policy: &str| {
+            model_diagram_from_sources(source(policy), true)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("module billing; billing::check(_);")
+            .contains("Rules in module billing can't have qualified names."));
+        assert!(
+            error("module billing; resource Repo { roles = [\"reader\"]; }")
+                .contains("Resource blocks can't be declared in module billing")
+        );
+        assert!(error("module billing; export refund;")
+            .contains("Module billing exports undefined rule refund."));
+    }
+
+    #[test]
+    fn test_diagram_formats() {
+        let diagram = model_diagram_from_sources(source(POLICY), false).unwrap();
+
+        let dot = DiagramFormat::Dot.export(&diagram);
+        assert!(dot.starts_with("digraph authorization_model {\n  rankdir=LR;\n"));
+        assert!(dot.contains("  subgraph cluster_1 {\n    label=\"resource Repo\";\n"));
+        assert!(dot.contains(&format!(
+            "  {} -> {} [label=\"on parent\"];\n",
+            node(&diagram, "member"),
+            node(&diagram, "viewer")
+        )));
+        assert!(dot.contains(&format!(
+            "  {} -> {} [label=\"deny, conditional\", style=dashed, color=red];\n",
+            node(&diagram, "viewer"),
+            node(&diagram, "read")
+        )));
+        assert!(dot.ends_with("}\n"));
+
+        let mermaid = DiagramFormat::Mermaid.export(&diagram);
+        assert!(mermaid.starts_with("flowchart LR\n  subgraph cluster_0 [\"resource Org\"]\n"));
+        assert!(mermaid.contains(&format!("    {}[[\"Repo\"]]\n", node(&diagram, "Repo"))));
+        assert!(mermaid.contains(&format!("    {}([\"viewer\"])\n", node(&diagram, "viewer"))));
+        assert!(mermaid.contains(&format!(
+            "  {} ==>|\"parent\"| {}\n",
+            node(&diagram, "Repo"),
+            node(&diagram, "Org")
+        )));
+        assert!(mermaid.contains("  linkStyle "));
+
+        assert_eq!("graphviz".parse::<DiagramFormat>(), Ok(DiagramFormat::Dot));
+        assert!("svg".parse::<DiagramFormat>().is_err());
+    }
+}
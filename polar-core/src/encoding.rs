@@ -0,0 +1,94 @@
+//! Wire encodings for values crossing the FFI boundary.
+//!
+//! Everything that crosses the FFI boundary (`QueryEvent`s, `Term`s, filter plans, etc.)
+//! implements `serde::Serialize` and/or `serde::Deserialize`, so any self-describing serde format
+//! can carry it. `Encoding::Json` is the historical default. `Encoding::MessagePack` produces the
+//! same structure (maps keyed by field and variant names) in a compact binary form, which avoids
+//! the cost of formatting and parsing text for large dictionaries and lists.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::error::{OperationalError, PolarResult};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+    Json = 0,
+    MessagePack = 1,
+}
+
+fn serialization_error<E: std::fmt::Display>(e: E) -> OperationalError {
+    OperationalError::Serialization { msg: e.to_string() }
+}
+
+impl Encoding {
+    pub fn encode<T: Serialize + ?Sized>(self, value: &T) -> PolarResult<Vec<u8>> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(serialization_error),
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(serialization_error),
+        }
+        .map_err(Into::into)
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> PolarResult<T> {
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(serialization_error),
+            Self::MessagePack => rmp_serde::from_slice(bytes).map_err(serialization_error),
+        }
+        .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::terms::*;
+
+    fn round_trip(encoding: Encoding, term: &Term) -> Term {
+        let bytes = encoding.encode(term).unwrap();
+        encoding.decode(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_float_edge_cases_round_trip() {
+        for encoding in [Encoding::Json, Encoding::MessagePack] {
+            for f in [
+                0.0,
+                -0.0,
+                1.5,
+                f64::MIN_POSITIVE / 2.0,
+                f64::MAX,
+                f64::MIN,
+                f64::EPSILON,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+            ] {
+                let term = term!(f);
+                match round_trip(encoding, &term).value() {
+                    Value::Number(Numeric::Float(g)) => {
+                        assert_eq!(f.to_bits(), g.to_bits(), "{:?}: {}", encoding, f)
+                    }
+                    v => panic!("{:?}: expected a float, got {}", encoding, v),
+                }
+            }
+            let term = term!(f64::NAN);
+            assert!(matches!(
+                round_trip(encoding, &term).value(),
+                Value::Number(Numeric::Float(f)) if f.is_nan()
+            ));
+        }
+    }
+
+    #[test]
+    fn test_message_pack_is_smaller_than_json() {
+        let fields = (0..100)
+            .map(|i| (sym!(&format!("key{}", i)), term!(i)))
+            .collect();
+        let term = term!(Value::Dictionary(Dictionary { fields }));
+        let json = Encoding::Json.encode(&term).unwrap();
+        let msgpack = Encoding::MessagePack.encode(&term).unwrap();
+        assert!(msgpack.len() < json.len());
+        assert_eq!(round_trip(Encoding::MessagePack, &term), term);
+    }
+}
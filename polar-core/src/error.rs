@@ -428,6 +428,21 @@ pub enum ValidationError {
         /// Term<Symbol> where the error arose, tracked for lexical context.
         term: Term,
     },
+    InvalidUnion {
+        /// Term where the error arose, tracked for lexical context.
+        term: Term,
+        msg: String,
+    },
+    InvalidModule {
+        /// Term where the error arose, tracked for lexical context.
+        term: Term,
+        msg: String,
+    },
+    InvalidConstant {
+        /// Term where the error arose, tracked for lexical context.
+        term: Term,
+        msg: String,
+    },
 }
 
 impl ValidationError {
@@ -437,6 +452,9 @@ impl ValidationError {
         let context = match &self {
             // These errors track `term`, from which we calculate the span.
             ResourceBlock { term, .. }
+            | InvalidUnion { term, .. }
+            | InvalidModule { term, .. }
+            | InvalidConstant { term, .. }
             | SingletonVariable { term, .. }
             | UndefinedRuleCall { term }
             | UnregisteredClass { term, .. } => term.span().zip(kb.get_term_source(term)),
@@ -486,7 +504,10 @@ impl fmt::Display for ValidationError {
             Self::MissingRequiredRule { rule_type } => {
                 write!(f, "Missing implementation for required rule {}", rule_type)
             }
-            Self::ResourceBlock { msg, .. } => {
+            Self::ResourceBlock { msg, .. }
+            | Self::InvalidUnion { msg, .. }
+            | Self::InvalidModule { msg, .. }
+            | Self::InvalidConstant { msg, .. } => {
                 write!(f, "{}", msg)
             }
             Self::SingletonVariable { term } => {
@@ -0,0 +1,510 @@
+//! Zanzibar-style expansion of a permission on a resource into the tree of actors that have it.
+//!
+//! The shape of the tree comes from the resource blocks: each shorthand rule for a permission or
+//! role is a branch, following relations to related resources and `global` roles. The leaves come
+//! from the host: the actors that hand-written `has_role` and `has_permission` rules grant the role
+//! or permission to, along with the rule (and relationship store fact) that granted it. Each
+//! hand-written rule is queried on its own, with its `relationship` calls rewritten to record the
+//! fact they match. Related resources are looked up with `has_relation`.
+//!
+//! The host runs each query, so that calls to application methods work as usual. Actors are left
+//! unbound, so a leaf is either a value the rule bound the actor to or, like in data filtering,
+//! the constraints the rule puts on it.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::error::{PolarError, RuntimeError};
+use super::folder::{fold_term, Folder};
+use super::formatting::ToPolarString;
+use super::kb::{Bindings, KnowledgeBase};
+use super::numerics::Numeric;
+use super::polar::Polar;
+use super::query::Query;
+use super::relationships::{Relationship, RelationshipStore, RELATIONSHIP_PREDICATE};
+use super::resource_block::{Declaration, Implier, ResourceBlocks};
+use super::rewrites::Renamer;
+use super::rules::Rule;
+use super::sources::SourceInfo;
+use super::terms::*;
+use super::traces::{RuleSpan, TraceResult};
+
+/// At most this many roles and permissions are expanded along any branch of the tree, counting
+/// the one at the root, which stops the expansion of long chains of relationship data.
+const MAX_DEPTH: usize = 32;
+
+const ACTOR: &str = "actor";
+
+/// The results of running a query to completion, with traces.
+pub type Answers = Vec<(Bindings, Option<TraceResult>)>;
+
+/// An actor that a rule grants a role or permission to directly.
+#[derive(Clone, Debug, Serialize)]
+pub struct Grant {
+    /// The actor, or the constraints on it if the rule grants the role or permission to every
+    /// actor that satisfies them.
+    pub actor: Term,
+    /// The rule that granted it.
+    pub rule: String,
+    /// The fact from the relationship store that the rule matched, if any.
+    pub relationship: Option<Relationship>,
+}
+
+/// The branch of a userset tree for one implier of a shorthand rule.
+#[derive(Clone, Debug, Serialize)]
+pub struct Implication {
+    /// The implier as written, e.g., `"maintainer" on "parent"`.
+    pub implier: String,
+    /// The condition after `and`, if any. The branch only applies if it holds.
+    pub condition: Option<String>,
+    /// A tree for each resource the implier is checked on: the resource itself, or the resources
+    /// related to it. A `global` role has a single tree without a resource.
+    pub trees: Vec<UsersetTree>,
+}
+
+/// The actors that have a role or permission on a resource.
+#[derive(Clone, Debug, Serialize)]
+pub struct UsersetTree {
+    /// `None` for a `global` role.
+    pub resource: Option<Term>,
+    pub name: String,
+    /// Actors granted `name` by hand-written rules.
+    pub grants: Vec<Grant>,
+    /// Actors granted `name` by shorthand rules.
+    pub implied: Vec<Implication>,
+    /// Actors that `deny` shorthand rules take `name` away from.
+    pub denied: Vec<Implication>,
+}
+
+impl UsersetTree {
+    fn fmt_indented(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        let pad = "  ".repeat(indent);
+        match &self.resource {
+            Some(resource) => writeln!(f, "{}\"{}\" on {}", pad, self.name, resource.to_polar())?,
+            None => writeln!(f, "{}global \"{}\"", pad, self.name)?,
+        }
+        for grant in &self.grants {
+            let actor = match grant.actor.value() {
+                Value::Variable(_) => "any actor".to_owned(),
+                _ => grant.actor.to_polar(),
+            };
+            write!(f, "{}  granted to {} by {}", pad, actor, grant.rule)?;
+            if let Some(relationship) = &grant.relationship {
+                write!(f, " with {}", relationship)?;
+            }
+            writeln!(f)?;
+        }
+        for (keyword, implications) in [("if", &self.implied), ("unless", &self.denied)] {
+            for implication in implications {
+                write!(f, "{}  {} {}", pad, keyword, implication.implier)?;
+                if let Some(condition) = &implication.condition {
+                    write!(f, " and {}", condition)?;
+                }
+                writeln!(f)?;
+                for tree in &implication.trees {
+                    tree.fmt_indented(f, indent + 2)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UsersetTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+fn string(s: &str) -> Term {
+    Term::new_temporary(Value::String(s.to_owned()))
+}
+
+fn var(name: &str) -> Term {
+    Term::new_temporary(Value::Variable(Symbol::new(name)))
+}
+
+fn call(name: &str, args: Vec<Term>) -> Term {
+    Term::new_temporary(Value::Call(Call {
+        name: Symbol::new(name),
+        args,
+        kwargs: None,
+    }))
+}
+
+fn implier_source(implier: &Implier) -> String {
+    match implier {
+        Implier::Resource(name, None) => name.to_polar(),
+        Implier::Resource(name, Some((_, relation))) => {
+            format!("{} on {}", name.to_polar(), relation.to_polar())
+        }
+        Implier::Global(_, role) => format!("global {}", role.to_polar()),
+    }
+}
+
+/// The rule as written in its source, or formatted if it wasn't parsed from one.
+fn rule_source(kb: &KnowledgeBase, rule: &Rule) -> String {
+    RuleSpan::new(kb, rule).map_or_else(|| rule.to_polar(), |span| span.text)
+}
+
+fn operation(operator: Operator, args: Vec<Term>) -> Term {
+    Term::new_temporary(Value::Expression(Operation { operator, args }))
+}
+
+/// Rewrites each `relationship(subject, relation, object)` call into one alternative per fact in
+/// the relationship store, each of which binds a variable to the index of its fact, so that the
+/// answers to a query say which facts they matched. Rules named `relationship` aren't called.
+struct FactRecorder<'a> {
+    relationships: &'a RelationshipStore,
+    variables: Vec<Symbol>,
+}
+
+impl<'a> Folder for FactRecorder<'a> {
+    fn fold_term(&mut self, term: Term) -> Term {
+        match term.value() {
+            Value::Call(Call {
+                name,
+                args,
+                kwargs: None,
+            }) if name.0 == RELATIONSHIP_PREDICATE && args.len() == 3 => {
+                let variable = Symbol(format!(
+                    "{}_{}",
+                    RELATIONSHIP_PREDICATE,
+                    self.variables.len()
+                ));
+                let alternatives = self
+                    .relationships
+                    .iter()
+                    .enumerate()
+                    .map(|(i, fact)| {
+                        let index = Term::new_temporary(Value::Number(Numeric::Integer(i as i64)));
+                        let recorded = operation(
+                            Operator::Unify,
+                            vec![
+                                Term::new_temporary(Value::Variable(variable.clone())),
+                                index,
+                            ],
+                        );
+                        operation(Operator::And, vec![fact.matches(args), recorded])
+                    })
+                    .collect();
+                self.variables.push(variable);
+                term.clone_with_value(Value::Expression(Operation {
+                    operator: Operator::Or,
+                    args: alternatives,
+                }))
+            }
+            _ => fold_term(term, self),
+        }
+    }
+}
+
+/// A query for `rule` applied to `args`, as the VM applies it: the arguments are unified with
+/// the rule's (renamed) parameters and checked against their specializers before the body runs.
+/// Returns the variables bound to the indexes of the relationship store facts it matches.
+fn rule_query(kb: &KnowledgeBase, rule: &Rule, args: &[Term]) -> (Term, Vec<Symbol>) {
+    let Rule { params, body, .. } = Renamer::new(kb).fold_rule(rule.clone());
+    let mut goals = vec![];
+    for (arg, param) in args.iter().zip(params) {
+        goals.push(operation(
+            Operator::Unify,
+            vec![arg.clone(), param.parameter],
+        ));
+        if let Some(specializer) = param.specializer {
+            goals.push(operation(Operator::Isa, vec![arg.clone(), specializer]));
+        }
+    }
+    let mut recorder = FactRecorder {
+        relationships: &kb.relationships,
+        variables: vec![],
+    };
+    goals.push(recorder.fold_term(body));
+    (operation(Operator::And, goals), recorder.variables)
+}
+
+struct Expander<'a, F> {
+    polar: &'a Polar,
+    blocks: ResourceBlocks,
+    /// The source spans of rules rewritten from shorthand rules, which are expanded from the
+    /// resource blocks instead of queried for grants.
+    shorthand_spans: HashSet<(u64, usize, usize)>,
+    run_query: F,
+    /// The roles and permissions being expanded, to stop at cycles in the shorthand rules.
+    path: Vec<(Term, String)>,
+}
+
+impl<'a, F, E> Expander<'a, F>
+where
+    F: FnMut(Query) -> Result<Answers, E>,
+    E: From<PolarError>,
+{
+    fn error(&self, error: RuntimeError) -> E {
+        error.with_context(&self.polar.kb.read().unwrap()).into()
+    }
+
+    fn unsupported(&self, msg: String, term: &Term) -> E {
+        let term = term.clone();
+        self.error(RuntimeError::Unsupported { msg, term })
+    }
+
+    fn as_string<'t>(&self, term: &'t Term) -> Result<&'t str, E> {
+        term.value().as_string().map_err(|e| self.error(e))
+    }
+
+    fn query(&mut self, term: Term) -> Result<Answers, E> {
+        let query = self.polar.new_query_from_term(term, false);
+        (self.run_query)(query)
+    }
+
+    /// The resource block that `resource` is an instance of.
+    fn resource_type(&mut self, resource: &Term) -> Result<Term, E> {
+        let mut types: Vec<Term> = self.blocks.resources.iter().cloned().collect();
+        types.sort_by_key(|t| t.to_string());
+        for resource_type in types {
+            let pattern = Value::Pattern(Pattern::Instance(InstanceLiteral {
+                tag: resource_type.value().as_symbol().unwrap().clone(),
+                fields: Dictionary::new(),
+            }));
+            let isa = Term::new_temporary(Value::Expression(Operation {
+                operator: Operator::Isa,
+                args: vec![resource.clone(), Term::new_temporary(pattern)],
+            }));
+            if !self.query(isa)?.is_empty() {
+                return Ok(resource_type);
+            }
+        }
+        let msg = format!("{} is not an instance of any resource block.", resource);
+        Err(self.unsupported(msg, resource))
+    }
+
+    /// The hand-written rules named `name` that take `arity` arguments, in the order they were
+    /// loaded.
+    fn handwritten_rules(&self, name: &str, arity: usize) -> Vec<Arc<Rule>> {
+        let kb = self.polar.kb.read().unwrap();
+        let mut rules: Vec<_> = kb
+            .get_rules()
+            .get(&Symbol::new(name))
+            .into_iter()
+            .flat_map(|generic_rule| &generic_rule.rules)
+            .filter(|(_, rule)| {
+                let shorthand = match rule.source_info {
+                    SourceInfo::Parser {
+                        src_id,
+                        left,
+                        right,
+                    } => self.shorthand_spans.contains(&(src_id, left, right)),
+                    _ => false,
+                };
+                rule.params.len() == arity && !shorthand
+            })
+            .collect();
+        rules.sort_by_key(|(id, _)| **id);
+        rules.into_iter().map(|(_, rule)| rule.clone()).collect()
+    }
+
+    /// The actors that hand-written `name` rules grant the role or permission in `args` to. The
+    /// actor is the variable `actor` in `args`.
+    fn grants(&mut self, name: &str, args: Vec<Term>) -> Result<Vec<Grant>, E> {
+        let mut grants = vec![];
+        for rule in self.handwritten_rules(name, args.len()) {
+            let (term, facts) = rule_query(&self.polar.kb.read().unwrap(), &rule, &args);
+            for (bindings, _) in self.query(term)? {
+                let kb = self.polar.kb.read().unwrap();
+                let relationship =
+                    facts
+                        .iter()
+                        .find_map(|fact| match bindings.get(fact)?.value() {
+                            Value::Number(Numeric::Integer(i)) => {
+                                kb.relationships.iter().nth(*i as usize).cloned()
+                            }
+                            _ => None,
+                        });
+                grants.push(Grant {
+                    actor: bindings
+                        .get(&Symbol::new(ACTOR))
+                        .cloned()
+                        .unwrap_or_else(|| var(ACTOR)),
+                    rule: rule_source(&kb, &rule),
+                    relationship,
+                });
+            }
+        }
+        Ok(grants)
+    }
+
+    /// The resources related to `resource` by each hop of a relation path in turn.
+    fn related(&mut self, resource: &Term, hops: &[(Term, Term)]) -> Result<Vec<Term>, E> {
+        let mut resources = vec![resource.clone()];
+        for (relation, _) in hops {
+            let mut related = vec![];
+            for resource in resources {
+                let args = vec![var("related"), relation.clone(), resource];
+                for (bindings, _) in self.query(call("has_relation", args))? {
+                    related.extend(bindings.get(&Symbol::new("related")).cloned());
+                }
+            }
+            resources = related;
+        }
+        Ok(resources)
+    }
+
+    fn implication(
+        &mut self,
+        resource: &Term,
+        resource_type: &Term,
+        implier: &Implier,
+        condition: Option<String>,
+    ) -> Result<Implication, E> {
+        let trees = match implier {
+            Implier::Resource(name, None) => {
+                let name = self.as_string(name)?;
+                self.expand(resource, resource_type, name)?
+                    .into_iter()
+                    .collect()
+            }
+            Implier::Resource(name, Some((_, relation))) => {
+                let name = self.as_string(name)?;
+                let hops: Vec<(Term, Term)> = self
+                    .blocks
+                    .get_relation_path_in_resource_block(relation, resource_type)
+                    .map_err(|e| e.with_context(&self.polar.kb.read().unwrap()))?
+                    .into_iter()
+                    .map(|(hop, related_type)| (hop, related_type.clone()))
+                    .collect();
+                let related_type = hops.last().map_or(resource_type, |(_, t)| t);
+                let mut trees = vec![];
+                for related in self.related(resource, &hops)? {
+                    trees.extend(self.expand(&related, related_type, name)?);
+                }
+                trees
+            }
+            Implier::Global(_, role) => {
+                let name = self.as_string(role)?;
+                let args = vec![var(ACTOR), string(name)];
+                vec![UsersetTree {
+                    resource: None,
+                    name: name.to_owned(),
+                    grants: self.grants("has_role", args)?,
+                    implied: vec![],
+                    denied: vec![],
+                }]
+            }
+        };
+        Ok(Implication {
+            implier: implier_source(implier),
+            condition,
+            trees,
+        })
+    }
+
+    /// Expand `name` on `resource`, or return `None` if it's already being expanded further up
+    /// the tree.
+    fn expand(
+        &mut self,
+        resource: &Term,
+        resource_type: &Term,
+        name: &str,
+    ) -> Result<Option<UsersetTree>, E> {
+        let key = (resource.clone(), name.to_owned());
+        if self.path.contains(&key) {
+            return Ok(None);
+        }
+        if self.path.len() >= MAX_DEPTH {
+            let msg = format!(
+                "Expanding \"{}\" on {} exceeded the maximum depth of {}.",
+                name, resource, MAX_DEPTH
+            );
+            return Err(self.error(RuntimeError::StackOverflow { msg }));
+        }
+
+        let declaration = self
+            .blocks
+            .declarations()
+            .get(resource_type)
+            .and_then(|declarations| declarations.get(&string(name)));
+        let rule_name = match declaration {
+            Some(Declaration::Role) => "has_role",
+            Some(Declaration::Permission) => "has_permission",
+            _ => {
+                let msg = format!(
+                    "\"{}\" is not a role or permission in the {} resource block.",
+                    name, resource_type
+                );
+                return Err(self.unsupported(msg, resource));
+            }
+        };
+
+        self.path.push(key);
+        let args = vec![var(ACTOR), string(name), resource.clone()];
+        let grants = self.grants(rule_name, args)?;
+        let mut implied = vec![];
+        let mut denied = vec![];
+        let rules = self.blocks.shorthand_rules.get(resource_type).cloned();
+        for rule in rules.iter().flatten() {
+            if rule.head.value().as_string().ok() != Some(name) {
+                continue;
+            }
+            let condition = rule.condition.as_ref().map(ToPolarString::to_polar);
+            for implier in &rule.body {
+                let implication =
+                    self.implication(resource, resource_type, implier, condition.clone())?;
+                if rule.deny.is_some() {
+                    denied.push(implication);
+                } else {
+                    implied.push(implication);
+                }
+            }
+        }
+        self.path.pop();
+
+        Ok(Some(UsersetTree {
+            resource: Some(resource.clone()),
+            name: name.to_owned(),
+            grants,
+            implied,
+            denied,
+        }))
+    }
+}
+
+/// Expand `name` (a permission or role) on `resource` into the tree of actors that have it.
+/// `run_query` runs each query to completion, answering any questions for the host, and returns
+/// its results with their traces.
+pub fn expand<F, E>(
+    polar: &Polar,
+    resource: Term,
+    name: &str,
+    run_query: F,
+) -> Result<UsersetTree, E>
+where
+    F: FnMut(Query) -> Result<Answers, E>,
+    E: From<PolarError>,
+{
+    let (blocks, shorthand_spans) = {
+        let kb = polar.kb.read().unwrap();
+        let blocks = kb.resource_blocks.clone();
+        let spans = blocks
+            .shorthand_rules
+            .values()
+            .flatten()
+            .filter_map(|rule| {
+                let (left, right) = rule.head.span()?;
+                Some((rule.head.get_source_id()?, left, right))
+            })
+            .collect();
+        (blocks, spans)
+    };
+    let mut expander = Expander {
+        polar,
+        blocks,
+        shorthand_spans,
+        run_query,
+        path: vec![],
+    };
+    let resource_type = expander.resource_type(&resource)?;
+    let tree = expander.expand(&resource, &resource_type, name)?;
+    Ok(tree.expect("the root is never on the path"))
+}
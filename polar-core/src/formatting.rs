@@ -129,6 +129,11 @@ fn precedence(o: &Operator) -> i32 {
         Operator::New => 10,
         Operator::Cut => 10,
         Operator::ForAll => 10,
+        Operator::Count => 10,
+        Operator::Collect => 10,
+        Operator::Sum => 10,
+        Operator::Min => 10,
+        Operator::Max => 10,
         Operator::Dot => 9,
         Operator::In => 8,
         Operator::Isa => 8,
@@ -352,7 +357,9 @@ pub mod display {
 
 pub mod to_polar {
     use crate::formatting::{format_args, format_params, to_polar_parens};
-    use crate::resource_block::{BlockType, ResourceBlock, ShorthandRule};
+    use crate::resource_block::{
+        BlockType, Implier, ResourceBlock, ShorthandRule, GLOBAL_BLOCK_NAME,
+    };
     use crate::rules::*;
     use crate::terms::*;
 
@@ -419,6 +426,11 @@ pub mod to_polar {
                 In => "in",
                 Cut => "cut",
                 ForAll => "forall",
+                Count => "count",
+                Collect => "collect",
+                Sum => "sum",
+                Min => "min",
+                Max => "max",
                 Debug => "debug",
                 Print => "print",
                 Isa => "matches",
@@ -441,6 +453,13 @@ pub mod to_polar {
                     self.args[0].to_polar(),
                     self.args[1].to_polar()
                 ),
+                Count | Collect | Sum | Min | Max => format!(
+                    "{}({}, {}, {})",
+                    self.operator.to_polar(),
+                    self.args[0].to_polar(),
+                    self.args[1].to_polar(),
+                    self.args[2].to_polar()
+                ),
                 New => {
                     if self.args.len() == 1 {
                         format!("new {}", to_polar_parens(self.operator, &self.args[0]))
@@ -626,18 +645,46 @@ pub mod to_polar {
         fn to_polar(&self) -> String {
             let Self {
                 head,
-                body: (implier, relation),
+                body,
+                condition,
+                deny,
             } = self;
-            if let Some((keyword, relation)) = relation {
-                format!(
-                    "{} if {} {} {};",
-                    head.to_polar(),
-                    implier.to_polar(),
-                    keyword.to_polar(),
-                    relation.to_polar()
-                )
+            let head = match deny {
+                Some(keyword) => format!("{} {}", keyword.to_polar(), head.to_polar()),
+                None => head.to_polar(),
+            };
+            let impliers = body
+                .iter()
+                .map(|implier| match implier {
+                    Implier::Resource(implier, Some((keyword, relation))) => format!(
+                        "{} {} {}",
+                        implier.to_polar(),
+                        keyword.to_polar(),
+                        relation.to_polar()
+                    ),
+                    Implier::Resource(implier, None) => implier.to_polar(),
+                    Implier::Global(keyword, role) => {
+                        format!("{} {}", keyword.to_polar(), role.to_polar())
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" or ");
+            if let Some(condition) = condition {
+                let impliers = if body.len() > 1 {
+                    format!("({})", impliers)
+                } else {
+                    impliers
+                };
+                let condition = match condition.value() {
+                    Value::Expression(Operation {
+                        operator: Operator::Or,
+                        ..
+                    }) => format!("({})", condition.to_polar()),
+                    _ => condition.to_polar(),
+                };
+                format!("{} if {} and {};", head, impliers, condition)
             } else {
-                format!("{} if {};", head.to_polar(), implier.to_polar())
+                format!("{} if {};", head, impliers)
             }
         }
     }
@@ -647,17 +694,22 @@ pub mod to_polar {
             match self {
                 Self::Actor => "actor".to_owned(),
                 Self::Resource => "resource".to_owned(),
+                Self::Global => GLOBAL_BLOCK_NAME.to_owned(),
             }
         }
     }
 
     impl ToPolarString for ResourceBlock {
         fn to_polar(&self) -> String {
-            let mut s = format!(
-                "{} {} {{\n",
-                self.block_type.to_polar(),
-                self.resource.to_polar()
-            );
+            let mut s = if self.block_type == BlockType::Global {
+                format!("{} {{\n", self.block_type.to_polar())
+            } else {
+                format!(
+                    "{} {} {{\n",
+                    self.block_type.to_polar(),
+                    self.resource.to_polar()
+                )
+            };
             if let Some(ref roles) = self.roles {
                 s += &format!("  roles = {};\n", roles.to_polar());
             }
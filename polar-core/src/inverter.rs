@@ -1,5 +1,5 @@
 use std::cell::RefCell;
-use std::collections::hash_map::Entry;
+use std::collections::{hash_map::Entry, HashSet};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering};
 
@@ -9,9 +9,9 @@ use crate::error::RuntimeError;
 use crate::events::QueryEvent;
 use crate::formatting::ToPolarString;
 use crate::kb::Bindings;
-use crate::partial::simplify_bindings;
+use crate::partial::{invert_operation, simplify_bindings};
 use crate::runnable::Runnable;
-use crate::terms::{Operation, Operator, Term, Value};
+use crate::terms::{Operation, Operator, Symbol, Term, Value};
 use crate::vm::{Goals, PolarVirtualMachine};
 
 type Result<T> = core::result::Result<T, RuntimeError>;
@@ -77,8 +77,18 @@ impl Inverter {
 /// 2. AND the inverted constraints together.
 ///
 /// The output constraints are AND[!result1, !result2, ...].
-fn results_to_constraints(results: Vec<BindingManager>) -> Bindings {
-    let inverted = results.into_iter().map(invert_partials).collect();
+///
+/// `vm` and `bsp` describe the state before the inversion started, and are used to tell which
+/// constraints and variables were introduced by the inverted query.
+fn results_to_constraints(
+    results: Vec<BindingManager>,
+    vm: &PolarVirtualMachine,
+    bsp: &Bsp,
+) -> Bindings {
+    let inverted = results
+        .into_iter()
+        .map(|bindings| invert_partials(bindings, vm, bsp))
+        .collect();
     let reduced = reduce_constraints(inverted);
     let simplified = simplify_bindings(reduced).unwrap_or_else(Bindings::new);
 
@@ -96,24 +106,42 @@ fn results_to_constraints(results: Vec<BindingManager>) -> Bindings {
 
 /// Invert constraints in `bindings`.
 ///
-/// Constraints are inverted by getting each binding as a constraint.
+/// Constraints are inverted by getting each binding as a constraint, less the constraints
+/// that already held before the inversion.
 /// Simplification is performed, to subsitute bindings and remove temporary variables.
 /// Then, each simplified expression is inverted.
 /// A binding of `var` to `val` after simplification is converted into `var != val`.
-fn invert_partials(bindings: BindingManager) -> Bindings {
+///
+/// Variables that were unbound before the inversion are existentially quantified within it,
+/// e.g., `m` in `not (m in x.members and m.banned = true)`. Expressions that mention them
+/// can't be inverted operand-by-operand, so they're left as a negated conjunction.
+fn invert_partials(bindings: BindingManager, vm: &PolarVirtualMachine, bsp: &Bsp) -> Bindings {
     let mut new_bindings = Bindings::new();
 
-    for var in bindings.variables() {
-        let constraint = bindings.get_constraints(&var);
-        new_bindings.insert(var.clone(), term!(constraint));
+    let variables = bindings.variables();
+    let existing = variables
+        .iter()
+        .flat_map(|var| vm.constraints_at_point(var, bsp).args)
+        .collect::<HashSet<_>>();
+    for var in variables {
+        let mut constraint = bindings.get_constraints(&var);
+        constraint.args.retain(|c| !existing.contains(c));
+        if !constraint.args.is_empty() {
+            new_bindings.insert(var.clone(), term!(constraint));
+        }
     }
+    let is_local =
+        |var: &Symbol| matches!(vm.variable_state_at_point(var, bsp), VariableState::Unbound);
 
     let simplified = simplify_bindings(new_bindings).unwrap_or_else(Bindings::new);
 
     simplified
         .into_iter()
         .map(|(k, v)| match v.value() {
-            Value::Expression(e) => (k, e.invert().into()),
+            Value::Expression(e) if e.variables().iter().any(|v| v != &k && is_local(v)) => {
+                (k, e.invert().into())
+            }
+            Value::Expression(e) => (k, term!(op!(And, term!(invert_operation(e.clone()))))),
             _ => (
                 k.clone(),
                 term!(op!(And, term!(op!(Neq, term!(k), v.clone())))),
@@ -207,8 +235,11 @@ impl Runnable for Inverter {
                         // If there are results, the inversion should usually fail. However,
                         // if those results have constraints we collect them and pass them
                         // out to the parent VM.
-                        let constraints =
-                            results_to_constraints(self.results.drain(..).collect::<Vec<_>>());
+                        let constraints = results_to_constraints(
+                            self.results.drain(..).collect::<Vec<_>>(),
+                            &self.vm,
+                            &self.bsp,
+                        );
                         let mut bsp = Bsp::default();
                         // Use mem swap to avoid cloning bsps.
                         std::mem::swap(&mut self.bsp, &mut bsp);
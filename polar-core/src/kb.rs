@@ -5,8 +5,13 @@ pub use super::bindings::Bindings;
 use super::counter::Counter;
 use super::diagnostic::Diagnostic;
 use super::error::{PolarResult, RuntimeError, ValidationError};
-use super::resource_block::{ResourceBlocks, ACTOR_UNION_NAME, RESOURCE_UNION_NAME};
+use super::modules::{qualify, Modules};
+use super::parser::{parse_lines, Line};
+use super::relationships::RelationshipStore;
+use super::resource_block::{Implier, ResourceBlocks, ACTOR_UNION_NAME, RESOURCE_UNION_NAME};
+use super::rewrites::{rewrite_rule, rewrite_term};
 use super::rules::*;
+use super::snapshot::{Located, Snapshot};
 use super::sources::*;
 use super::terms::*;
 use super::validations::check_undefined_rule_calls;
@@ -25,6 +30,32 @@ impl RuleParamMatch {
     }
 }
 
+/// A source parsed by [`KnowledgeBase::parse_source`].
+pub struct ParsedSource {
+    pub src_id: u64,
+    /// The module the source declares, if any.
+    pub module: Option<Symbol>,
+    pub lines: Vec<Line>,
+    /// Errors declaring the module, and for the lines dropped because they can't appear in it.
+    pub errors: Vec<ValidationError>,
+}
+
+impl ParsedSource {
+    fn new(
+        src_id: u64,
+        module: Option<Symbol>,
+        lines: Vec<Line>,
+        errors: Vec<ValidationError>,
+    ) -> Self {
+        Self {
+            src_id,
+            module,
+            lines,
+            errors,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct KnowledgeBase {
     /// A map of bindings: variable name → value. The VM uses a stack internally,
@@ -49,6 +80,21 @@ pub struct KnowledgeBase {
 
     /// Resource block bookkeeping.
     pub resource_blocks: ResourceBlocks,
+
+    /// Unions declared in the policy, e.g., `union Principal = User | ApiKey;`, from the name of
+    /// each union to its member classes.
+    unions: HashMap<Symbol, HashSet<Term>>,
+
+    /// Module bookkeeping.
+    pub modules: Modules,
+
+    /// Constants declared in the policy, e.g., `const ADMINS = ["owner", "admin"];`. Rewriting a
+    /// rule or query replaces each of them with its value.
+    policy_constants: HashMap<Symbol, Term>,
+
+    /// Facts queried by the built-in `relationship` predicate. They're data rather than policy, so
+    /// clearing the rules leaves them alone.
+    pub relationships: RelationshipStore,
 }
 
 impl KnowledgeBase {
@@ -260,6 +306,20 @@ impl KnowledgeBase {
                     } else {
                         RuleParamMatch::False(format!("Rule specializer {} on parameter {} did not match rule type specializer {} because the specializer fields did not match.", rule_instance.to_polar(), index, rule_type_instance.to_polar()))
                     }
+                // A declared union matches if each of its members does.
+                } else if let Some(members) = self.unions.get(&rule_instance.tag) {
+                    let mut members: Vec<_> = members.iter().collect();
+                    members.sort_by_key(|member| member.to_string());
+                    for member in members {
+                        let member_pattern = Pattern::Instance(InstanceLiteral {
+                            tag: member.value().as_symbol().expect("parsed as symbol").clone(),
+                            fields: rule_instance.fields.clone(),
+                        });
+                        if let RuleParamMatch::False(msg) = self.check_pattern_param(index, &member_pattern, rule_type_pattern)? {
+                            return Ok(RuleParamMatch::False(format!("Rule specializer {} on parameter {} does not match rule type specializer {} because its member {} does not:\n\t{}", rule_instance.tag, index, rule_type_instance.tag, member, msg)));
+                        }
+                    }
+                    RuleParamMatch::True
                 } else if self.is_union(&term!(sym!(&rule_type_instance.tag.0))) {
                     if self.is_union(&term!(sym!(&rule_instance.tag.0))) {
                         // If both specializers are the same union, check fields.
@@ -273,8 +333,7 @@ impl KnowledgeBase {
                                 return Ok(RuleParamMatch::False(format!("Rule specializer {} on parameter {} did not match rule type specializer {} because the specializer fields did not match.", rule_instance.to_polar(), index, rule_type_instance.to_polar())));
                             }
                         } else {
-                            // TODO(gj): revisit when we have unions beyond Actor & Resource. Union
-                            // A matches union B if union A is a member of union B.
+                            // Unions can't be members of other unions.
                             return Ok(RuleParamMatch::False(format!("Rule specializer {} on parameter {} does not match rule type specializer {}", rule_instance.tag, index, rule_type_instance.tag)));
                         }
                     }
@@ -582,6 +641,14 @@ impl KnowledgeBase {
         self.rule_types.add(rule_type);
     }
 
+    /// Whether calls to the rule named `name` are tabled, i.e., some rule type for it is
+    /// declared with `type tabled`.
+    pub fn is_tabled(&self, name: &Symbol) -> bool {
+        self.rule_types
+            .get(name)
+            .is_some_and(|types| types.iter().any(|rule_type| rule_type.tabled))
+    }
+
     /// Define a constant variable.
     ///
     /// Error on attempts to register the "union" types (Actor & Resource) since those types have
@@ -603,6 +670,53 @@ impl KnowledgeBase {
         self.constants.contains_key(name)
     }
 
+    /// Declare a constant in the policy. Its value can refer to constants declared before it.
+    pub fn add_policy_constant(&mut self, name: Term, value: Term) -> ValidationResult<()> {
+        let error = |term: &Term, msg: String| ValidationError::InvalidConstant {
+            term: term.clone(),
+            msg,
+        };
+        let sym = name.value().as_symbol().expect("parsed as symbol").clone();
+        if self.policy_constants.contains_key(&sym) {
+            let msg = format!("Constant '{}' is already declared.", sym);
+            return Err(error(&name, msg));
+        } else if self.is_constant(&sym) || name.is_actor_union() || name.is_resource_union() {
+            let msg = format!(
+                "Constant '{}' has the same name as a registered constant.",
+                sym
+            );
+            return Err(error(&name, msg));
+        }
+
+        /// The first part of `term` that isn't a literal, if any.
+        fn non_literal(term: &Term) -> Option<&Term> {
+            match term.value() {
+                Value::Number(_) | Value::String(_) | Value::Boolean(_) => None,
+                Value::List(terms) => terms.iter().find_map(non_literal),
+                Value::Dictionary(Dictionary { fields }) => fields.values().find_map(non_literal),
+                _ => Some(term),
+            }
+        }
+
+        let value = rewrite_term(value, self);
+        if let Some(term) = non_literal(&value) {
+            let msg = match term.value() {
+                Value::Variable(v) => format!(
+                    "Constant '{}' refers to '{}', which is not a constant declared before it.",
+                    sym, v
+                ),
+                _ => format!("Constant '{}' must be a literal value.", sym),
+            };
+            return Err(error(term, msg));
+        }
+        self.policy_constants.insert(sym, value);
+        Ok(())
+    }
+
+    pub fn get_policy_constant(&self, name: &Symbol) -> Option<&Term> {
+        self.policy_constants.get(name)
+    }
+
     /// Getter for `constants` map without exposing it for mutation.
     pub fn get_registered_constants(&self) -> &Bindings {
         &self.constants
@@ -644,6 +758,77 @@ impl KnowledgeBase {
         Ok(src_id)
     }
 
+    /// Add `source` and parse it. A leading `module name;` line declares the module the source
+    /// belongs to, and the rules and rule types in the source are given names qualified by it.
+    /// Lines that can't appear in the module are dropped, with an error.
+    pub fn parse_source(&mut self, source: &Source) -> PolarResult<ParsedSource> {
+        let src_id = self.add_source(source.clone())?;
+        let mut lines = parse_lines(src_id, &source.src)
+            // TODO(gj): we still bomb out at the first ParseError.
+            .map_err(|e| e.with_context(source.clone()))?;
+        let mut errors = vec![];
+        // A module declaration applies to the whole source, so it has to come first.
+        let module = match lines.first() {
+            Some(Line::Module { keyword, names })
+                if keyword
+                    .value()
+                    .as_symbol()
+                    .is_ok_and(|sym| sym.0 == "module") =>
+            {
+                let declared = self.modules.declare_module(src_id, keyword, names);
+                lines.remove(0);
+                match declared {
+                    Ok(module) => module,
+                    Err(e) => {
+                        errors.push(e);
+                        return Ok(ParsedSource::new(src_id, None, lines, errors));
+                    }
+                }
+            }
+            _ => return Ok(ParsedSource::new(src_id, None, lines, errors)),
+        };
+
+        let lines = lines
+            .into_iter()
+            .filter_map(|line| match line {
+                Line::Rule(mut rule) => {
+                    if rule.name.is_namespaced_var() {
+                        let msg = format!("Rules in module {} can't have qualified names.", module);
+                        errors.push(ValidationError::InvalidRule { rule, msg });
+                        return None;
+                    }
+                    rule.name = qualify(&module, &rule.name);
+                    Some(Line::Rule(rule))
+                }
+                Line::RuleType(mut rule_type) => {
+                    if rule_type.name.is_namespaced_var() {
+                        let msg = format!(
+                            "Rule types in module {} can't have qualified names.",
+                            module
+                        );
+                        errors.push(ValidationError::InvalidRuleType { rule_type, msg });
+                        return None;
+                    }
+                    rule_type.name = qualify(&module, &rule_type.name);
+                    Some(Line::RuleType(rule_type))
+                }
+                Line::ResourceBlock { resource, .. } => {
+                    let msg = format!(
+                        "Resource blocks can't be declared in module {}, since they define rules in the root namespace.",
+                        module
+                    );
+                    errors.push(ValidationError::InvalidModule {
+                        term: resource,
+                        msg,
+                    });
+                    None
+                }
+                line => Some(line),
+            })
+            .collect();
+        Ok(ParsedSource::new(src_id, Some(module), lines, errors))
+    }
+
     // TODO(gj): Parsed<T> type (or something) that exposes ::get_source_id so we can remove this
     // meaningless distinction between terms & rules.
     pub(crate) fn get_term_source(&self, t: &Term) -> Option<Source> {
@@ -662,6 +847,9 @@ impl KnowledgeBase {
         self.loaded_content.clear();
         self.loaded_files.clear();
         self.resource_blocks.clear();
+        self.unions.clear();
+        self.modules.clear();
+        self.policy_constants.clear();
     }
 
     fn check_file(&self, src: &str, filename: &str) -> Result<(), RuntimeError> {
@@ -721,8 +909,10 @@ impl KnowledgeBase {
         }
 
         if errors.is_empty() {
-            // Add the rewritten rules to the KB.
+            // Add the rewritten rules to the KB. Their bodies can contain conditions, which need
+            // the same rewriting as the bodies of rules in the policy.
             for rule in rules {
+                let rule = rewrite_rule(rule, self);
                 self.add_rule(rule);
             }
         }
@@ -730,6 +920,36 @@ impl KnowledgeBase {
         errors
     }
 
+    /// Rewrite the calls in every rule and inline query to the qualified names of the rules they
+    /// refer to. This has to wait until every source is loaded, since a call can refer to a rule
+    /// defined in another file of the same module.
+    pub fn resolve_module_names(&mut self) -> Vec<ValidationError> {
+        let names = self.rules.keys().cloned().collect::<HashSet<_>>();
+        let mut errors = self.modules.validate(&names);
+
+        for generic_rule in self.rules.values_mut() {
+            for rule in generic_rule.rules.values_mut() {
+                let (body, mut resolve_errors) =
+                    self.modules
+                        .resolve(&names, &rule.source_info, rule.body.clone());
+                if body != rule.body {
+                    Arc::make_mut(rule).body = body;
+                }
+                errors.append(&mut resolve_errors);
+            }
+        }
+
+        for query in &mut self.inline_queries {
+            let (resolved, mut resolve_errors) =
+                self.modules
+                    .resolve(&names, query.source_info(), query.clone());
+            *query = resolved;
+            errors.append(&mut resolve_errors);
+        }
+
+        errors
+    }
+
     pub fn create_resource_specific_rule_types(&mut self) {
         let mut rule_types_to_create = HashMap::new();
 
@@ -744,18 +964,19 @@ impl KnowledgeBase {
         // where users have declared relations ahead of time that are used in
         // rule or resource definitions.
         for (subject, name, object) in self.resource_blocks.relation_tuples() {
-            rule_types_to_create.insert((subject, name, object), false);
+            rule_types_to_create.insert((subject.clone(), name.clone(), object.clone()), false);
         }
 
         // Iterate through resource block shorthand rules and create *required*
         // rule types for each relation which is traversed in the rules.
+        let mut uses_global_roles = false;
         for (object, shorthand_rules) in &self.resource_blocks.shorthand_rules {
-            for shorthand_rule in shorthand_rules {
+            for implier in shorthand_rules.iter().flat_map(|rule| &rule.body) {
                 // We create rule types from shorthand rules in the following scenarios...
-                match &shorthand_rule.body {
+                match implier {
                     // 1. When the the third "relation" term points to a related Resource. E.g.,
                     //    `"admin" if "admin" on "parent";` where `relations = { parent: Org };`.
-                    (implier, Some((_, relation))) => {
+                    Implier::Resource(implier, Some((_, relation))) => {
                         // First, create required rule type for relationship between `object` and
                         // `subject`:
                         //
@@ -771,11 +992,19 @@ impl KnowledgeBase {
                         // resource Org {
                         //   roles = ["admin"];
                         // }
-                        if let Ok(subject) = self
+                        //
+                        // For a relation path like `"project.org"`, we do this for each hop.
+                        if let Ok(hops) = self
                             .resource_blocks
-                            .get_relation_type_in_resource_block(relation, object)
+                            .get_relation_path_in_resource_block(relation, object)
                         {
-                            rule_types_to_create.insert((subject, relation, object), true);
+                            let mut object = object;
+                            for (relation, subject) in hops {
+                                rule_types_to_create
+                                    .insert((subject.clone(), relation, object.clone()), true);
+                                object = subject;
+                            }
+                            let subject = object;
 
                             // Then, if the "implier" term is declared as a relation on `subject`
                             // (as opposed to a permission or role), create required rule type for
@@ -799,8 +1028,10 @@ impl KnowledgeBase {
                                 .resource_blocks
                                 .get_relation_type_in_resource_block(implier, subject)
                             {
-                                rule_types_to_create
-                                    .insert((related_subject, implier, subject), true);
+                                rule_types_to_create.insert(
+                                    (related_subject.clone(), implier.clone(), subject.clone()),
+                                    true,
+                                );
                             }
                         }
                     }
@@ -810,14 +1041,19 @@ impl KnowledgeBase {
                     //    could be a related Resource, but that doesn't make much semantic sense.
                     //    Related resources should be traversed via `"on"` clauses, which are
                     //    captured in the above match arm.
-                    (implier, None) => {
+                    Implier::Resource(implier, None) => {
                         if let Ok(subject) = self
                             .resource_blocks
                             .get_relation_type_in_resource_block(implier, object)
                         {
-                            rule_types_to_create.insert((subject, implier, object), true);
+                            rule_types_to_create
+                                .insert((subject.clone(), implier.clone(), object.clone()), true);
                         }
                     }
+
+                    // 3. Global roles don't relate to anything. Their `has_role/2` rule type is
+                    //    created below.
+                    Implier::Global(..) => uses_global_roles = true,
                 }
             }
         }
@@ -850,13 +1086,27 @@ impl KnowledgeBase {
             );
         }
 
+        // If any shorthand rule is implied by a global role, then we want to add the `has_role`
+        // rule type for global roles, which don't take a resource.
+        if uses_global_roles {
+            rule_types.push(rule!(
+                "has_role",
+                ["actor"; instance!(ACTOR_UNION_NAME), "role"; instance!("String")],
+                true
+            ));
+        }
+
         for rule_type in rule_types {
             self.add_rule_type(rule_type.clone());
         }
     }
 
     pub fn is_union(&self, maybe_union: &Term) -> bool {
-        (maybe_union.is_actor_union()) || (maybe_union.is_resource_union())
+        (maybe_union.is_actor_union())
+            || (maybe_union.is_resource_union())
+            || maybe_union
+                .specializer_tag()
+                .is_some_and(|tag| self.unions.contains_key(tag))
     }
 
     pub fn get_union_members(&self, union: &Term) -> &HashSet<Term> {
@@ -865,13 +1115,171 @@ impl KnowledgeBase {
         } else if union.is_resource_union() {
             &self.resource_blocks.resources
         } else {
-            unreachable!()
+            union
+                .specializer_tag()
+                .and_then(|tag| self.unions.get(tag))
+                .unwrap()
+        }
+    }
+
+    /// Declare a union of registered classes. Unions can't be members of other unions.
+    pub fn add_union(&mut self, name: Term, members: Vec<Term>) -> Vec<ValidationError> {
+        let error = |term: &Term, msg: String| ValidationError::InvalidUnion {
+            term: term.clone(),
+            msg,
+        };
+        let tag = name.value().as_symbol().expect("parsed as symbol").clone();
+        let mut errors = vec![];
+        if name.is_actor_union() || name.is_resource_union() {
+            errors.push(error(&name, format!("'{}' is a built-in union.", tag)));
+        } else if self.unions.contains_key(&tag) {
+            errors.push(error(
+                &name,
+                format!("Union '{}' is already declared.", tag),
+            ));
+        } else if self.is_constant(&tag) {
+            errors.push(error(
+                &name,
+                format!(
+                    "Union '{}' has the same name as a registered constant.",
+                    tag
+                ),
+            ));
+        } else if self.policy_constants.contains_key(&tag) {
+            errors.push(error(
+                &name,
+                format!("Union '{}' has the same name as a constant.", tag),
+            ));
+        }
+        for member in &members {
+            if self.is_union(member) {
+                errors.push(error(
+                    member,
+                    format!(
+                        "Union members must be classes, but '{}' is a union.",
+                        member
+                    ),
+                ));
+            } else if let Err(e) = self.get_registered_class(member) {
+                errors.push(e);
+            }
+        }
+        if errors.is_empty() {
+            self.unions.insert(tag, members.into_iter().collect());
         }
+        errors
     }
 
     pub fn has_rules(&self) -> bool {
         !self.rules.is_empty()
     }
+
+    pub(crate) fn export_snapshot(&self) -> Snapshot {
+        let mut sources: Vec<_> = self
+            .sources
+            .iter()
+            .map(|(id, source)| (*id, source.clone()))
+            .collect();
+        sources.sort_by_key(|(id, _)| *id);
+
+        let mut generic_rules: Vec<_> = self.rules.values().collect();
+        generic_rules.sort_by(|a, b| a.name.cmp(&b.name));
+        let rules = generic_rules
+            .into_iter()
+            .flat_map(|generic_rule| {
+                let mut rules: Vec<_> = generic_rule.rules.iter().collect();
+                rules.sort_by_key(|(id, _)| **id);
+                rules
+                    .into_iter()
+                    .map(|(_, rule)| Located::<Rule>::new(rule))
+            })
+            .collect();
+
+        let rule_types = self.rule_types.iter().map(Located::<Rule>::new).collect();
+        let inline_queries = self
+            .inline_queries
+            .iter()
+            .map(Located::<Term>::new)
+            .collect();
+
+        let mut unions: Vec<_> = self
+            .unions
+            .iter()
+            .map(|(name, members)| {
+                let mut members: Vec<_> = members.iter().cloned().collect();
+                members.sort_by_key(|member| member.to_string());
+                (name.clone(), members)
+            })
+            .collect();
+        unions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut policy_constants: Vec<_> = self
+            .policy_constants
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        policy_constants.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut constants: Vec<_> = self.constants.keys().cloned().collect();
+        constants.sort();
+
+        Snapshot::new(
+            sources,
+            rules,
+            rule_types,
+            inline_queries,
+            self.resource_blocks.clone(),
+            unions,
+            policy_constants,
+            self.modules.export_snapshot(),
+            self.relationships.iter().cloned().collect(),
+            constants,
+        )
+    }
+
+    /// Restore a policy exported with `export_snapshot`, returning the names of the constants
+    /// that were registered at export time.
+    pub(crate) fn import_snapshot(&mut self, snapshot: Snapshot) -> PolarResult<Vec<Symbol>> {
+        // Sources are assigned fresh IDs so they can't collide with IDs already handed out by
+        // this KB.
+        let mut src_ids = HashMap::new();
+        for (id, source) in snapshot.sources {
+            // The placeholder `<Unknown>` source is always present.
+            if id != 0 {
+                src_ids.insert(id, self.add_source(source)?);
+            }
+        }
+
+        for rule in snapshot.rules {
+            self.add_rule(rule.restore(&src_ids));
+        }
+        self.rule_types.replace(
+            snapshot
+                .rule_types
+                .into_iter()
+                .map(|rule_type| rule_type.restore(&src_ids)),
+        );
+        self.inline_queries.extend(
+            snapshot
+                .inline_queries
+                .into_iter()
+                .map(|query| query.restore(&src_ids)),
+        );
+        self.resource_blocks = snapshot.resource_blocks;
+        self.unions = snapshot
+            .unions
+            .into_iter()
+            .map(|(name, members)| (name, members.into_iter().collect()))
+            .collect();
+        self.policy_constants = snapshot.policy_constants.into_iter().collect();
+        self.modules.import_snapshot(snapshot.modules, &src_ids);
+        // Facts added before the snapshot is loaded are kept.
+        for relationship in snapshot.relationships {
+            self.relationships.add(relationship);
+        }
+
+        Ok(snapshot.constants)
+    }
 }
 
 #[cfg(test)]
@@ -1472,6 +1880,97 @@ mod tests {
         assert!(kb.validate_rules().is_empty());
     }
 
+    #[test]
+    fn test_declared_unions() {
+        let mut kb = KnowledgeBase::new();
+        for (id, (name, mro)) in [
+            ("User", vec![1]),
+            ("ServiceAccount", vec![2]),
+            ("Admin", vec![3, 1]),
+            ("Doc", vec![4]),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            kb.register_constant(
+                sym!(name),
+                term!(Value::ExternalInstance(ExternalInstance {
+                    instance_id: id as u64 + 1,
+                    constructor: None,
+                    repr: None
+                })),
+            )
+            .unwrap();
+            kb.add_mro(sym!(name), mro).unwrap();
+        }
+        kb.resource_blocks.actors.insert(term!(sym!("User")));
+        kb.resource_blocks
+            .actors
+            .insert(term!(sym!("ServiceAccount")));
+        let principal = || term!(sym!("Principal"));
+        let members = || vec![term!(sym!("User")), term!(sym!("ServiceAccount"))];
+        assert!(kb.add_union(principal(), members()).is_empty());
+        assert!(kb.is_union(&principal()));
+        assert!(kb.is_union(&term!(pattern!(instance!("Principal")))));
+        assert_eq!(kb.get_union_members(&principal()).len(), 2);
+
+        let errors = |kb: &mut KnowledgeBase, name: &str, members: Vec<&str>| {
+            kb.add_union(
+                term!(sym!(name)),
+                members.into_iter().map(|m| term!(sym!(m))).collect(),
+            )
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors(&mut kb, "Principal", vec!["User"]),
+            vec!["Union 'Principal' is already declared."]
+        );
+        assert_eq!(
+            errors(&mut kb, ACTOR_UNION_NAME, vec!["User"]),
+            vec!["'Actor' is a built-in union."]
+        );
+        assert_eq!(
+            errors(&mut kb, "Doc", vec!["User"]),
+            vec!["Union 'Doc' has the same name as a registered constant."]
+        );
+        assert_eq!(
+            errors(&mut kb, "Anyone", vec!["User", "Principal", "Robot"]),
+            vec![
+                "Union members must be classes, but 'Principal' is a union.",
+                "Unregistered class: Robot"
+            ]
+        );
+        assert!(!kb.is_union(&term!(sym!("Anyone"))));
+
+        // Members and their subclasses match a union rule type, and a union matches a rule type
+        // if all of its members do.
+        kb.add_rule_type(rule!("f", ["_"; instance!("Principal")]));
+        kb.add_rule(rule!("f", ["_"; instance!("Principal")]));
+        kb.add_rule(rule!("f", ["_"; instance!("ServiceAccount")]));
+        kb.add_rule(rule!("f", ["_"; instance!("Admin")]));
+        kb.add_rule_type(rule!("g", ["_"; instance!(ACTOR_UNION_NAME)]));
+        kb.add_rule(rule!("g", ["_"; instance!("Principal")]));
+        assert!(kb.validate_rules().is_empty());
+
+        kb.add_rule_type(rule!("h", ["_"; instance!("User")]));
+        kb.add_rule(rule!("h", ["_"; instance!("Principal")]));
+        let diagnostics = kb.validate_rules();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].to_string().contains(
+            "Rule specializer Principal on parameter 1 does not match rule type specializer User because its member ServiceAccount does not"
+        ));
+
+        kb.rules.clear();
+        kb.add_rule(rule!("f", ["_"; instance!("Doc")]));
+        assert_eq!(kb.validate_rules().len(), 1);
+
+        // Unions are cleared along with the rest of the policy.
+        kb.clear_rules();
+        assert!(!kb.is_union(&principal()));
+    }
+
     #[test]
     fn test_rule_type_validation_errors_for_non_class_specializers() {
         let mut kb = KnowledgeBase::new();
@@ -7,29 +7,38 @@ extern crate maplit;
 #[macro_use]
 pub mod macros;
 
+mod aggregator;
 mod bindings;
 mod counter;
 pub mod data_filtering;
 mod debugger;
 pub mod diagnostic;
+pub mod diagram;
+pub mod encoding;
 pub mod error;
 pub mod events;
+pub mod expand;
 mod folder;
 pub mod formatting;
 mod inverter;
 pub mod kb;
 mod lexer;
 pub mod messages;
+mod modules;
 mod numerics;
 pub mod parser;
 mod partial;
+pub mod permission_matrix;
 pub mod polar;
 pub mod query;
+pub mod relationships;
 mod resource_block;
 mod rewrites;
 pub mod rules;
 mod runnable;
+pub mod snapshot;
 pub mod sources;
+mod tabling;
 pub mod terms;
 pub mod traces;
 mod validations;
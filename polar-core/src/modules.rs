@@ -0,0 +1,459 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::error::ValidationError;
+use super::folder::{fold_term, Folder};
+use super::sources::SourceInfo;
+use super::terms::*;
+
+type Result<T> = core::result::Result<T, ValidationError>;
+
+/// The name of rule `name` defined in `module`, e.g., `billing::can_refund`.
+pub fn qualify(module: &Symbol, name: &Symbol) -> Symbol {
+    Symbol(format!("{}::{}", module, name))
+}
+
+fn invalid_module(term: &Term, msg: String) -> ValidationError {
+    ValidationError::InvalidModule {
+        term: term.clone(),
+        msg,
+    }
+}
+
+/// The exports and imports of a module, or of the root namespace.
+#[derive(Default)]
+struct Scope {
+    /// Map from the unqualified names of the rules the module exports to the exporting term.
+    exports: HashMap<Symbol, Term>,
+    /// Map from unqualified name to the term naming the imported rule, e.g., `billing::refund`.
+    imports: HashMap<Symbol, Term>,
+}
+
+/// The exports and imports of a scope in a policy snapshot, sorted by name.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScopeSnapshot {
+    module: Option<Symbol>,
+    exports: Vec<(Symbol, Term)>,
+    imports: Vec<(Symbol, Term)>,
+}
+
+/// The module bookkeeping of a loaded policy, as stored in a policy snapshot.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ModulesSnapshot {
+    /// Module declared by each source, sorted by source ID.
+    sources: Vec<(u64, Symbol)>,
+    scopes: Vec<ScopeSnapshot>,
+}
+
+fn sorted(names: &HashMap<Symbol, Term>) -> Vec<(Symbol, Term)> {
+    let mut names: Vec<_> = names
+        .iter()
+        .map(|(name, term)| (name.clone(), term.clone()))
+        .collect();
+    names.sort_by(|(a, _), (b, _)| a.cmp(b));
+    names
+}
+
+/// Module bookkeeping.
+///
+/// Rules in a source that starts with `module name;` are added to the KB under qualified names
+/// (`name::rule`). Once every source is loaded, `Resolver` rewrites the calls in each rule to
+/// the qualified names they refer to.
+#[derive(Default)]
+pub struct Modules {
+    /// Map from source ID to the module declared by that source.
+    sources: HashMap<u64, Symbol>,
+    /// Map from module name (or `None` for the root namespace) to its exports and imports.
+    scopes: HashMap<Option<Symbol>, Scope>,
+}
+
+impl Modules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.sources.clear();
+        self.scopes.clear();
+    }
+
+    pub fn export_snapshot(&self) -> ModulesSnapshot {
+        let mut sources: Vec<_> = self
+            .sources
+            .iter()
+            .map(|(id, module)| (*id, module.clone()))
+            .collect();
+        sources.sort_by_key(|(id, _)| *id);
+
+        let mut scopes: Vec<_> = self
+            .scopes
+            .iter()
+            .map(|(module, scope)| ScopeSnapshot {
+                module: module.clone(),
+                exports: sorted(&scope.exports),
+                imports: sorted(&scope.imports),
+            })
+            .collect();
+        scopes.sort_by(|a, b| a.module.cmp(&b.module));
+
+        ModulesSnapshot { sources, scopes }
+    }
+
+    /// Restore the modules exported with `export_snapshot`, translating source IDs from the
+    /// exporting KB to this one.
+    pub fn import_snapshot(&mut self, snapshot: ModulesSnapshot, src_ids: &HashMap<u64, u64>) {
+        for (id, module) in snapshot.sources {
+            let id = src_ids.get(&id).copied().unwrap_or(id);
+            self.sources.insert(id, module);
+        }
+        for scope in snapshot.scopes {
+            self.scopes.insert(
+                scope.module,
+                Scope {
+                    exports: scope.exports.into_iter().collect(),
+                    imports: scope.imports.into_iter().collect(),
+                },
+            );
+        }
+    }
+
+    /// Rewrite the calls in `term`, from the source described by `source_info`, to the qualified
+    /// names of the `rules` they refer to.
+    pub fn resolve(
+        &self,
+        rules: &HashSet<Symbol>,
+        source_info: &SourceInfo,
+        term: Term,
+    ) -> (Term, Vec<ValidationError>) {
+        let module = self.module_of(source_info).cloned();
+        let mut resolver = Resolver::new(self, rules, module);
+        let term = resolver.fold_term(term);
+        (term, resolver.errors)
+    }
+
+    /// Declare that the source with `src_id` belongs to the module named by `names`.
+    pub fn declare_module(
+        &mut self,
+        src_id: u64,
+        keyword: &Term,
+        names: &[Term],
+    ) -> Result<Symbol> {
+        let name = match names {
+            [name] => name.value().as_symbol().unwrap().clone(),
+            _ => {
+                let msg = "A module declaration takes a single module name.".to_owned();
+                return Err(invalid_module(keyword, msg));
+            }
+        };
+        self.sources.insert(src_id, name.clone());
+        self.scopes.entry(Some(name.clone())).or_default();
+        Ok(name)
+    }
+
+    /// The module that the term or rule with `source_info` was loaded from.
+    pub fn module_of(&self, source_info: &SourceInfo) -> Option<&Symbol> {
+        match source_info {
+            SourceInfo::Parser { src_id, .. } => self.sources.get(src_id),
+            _ => None,
+        }
+    }
+
+    /// Record the names declared by an `export` or `import` line in `module`.
+    pub fn add_declaration(
+        &mut self,
+        module: Option<&Symbol>,
+        keyword: &Term,
+        names: Vec<Term>,
+    ) -> Vec<ValidationError> {
+        let mut errors = vec![];
+        let scope = self.scopes.entry(module.cloned()).or_default();
+        match keyword.value().as_symbol().unwrap().0.as_ref() {
+            "export" if module.is_none() => {
+                let msg = "Only modules can export rules. Declare the module with `module <name>;` at the top of the file.".to_owned();
+                errors.push(invalid_module(keyword, msg));
+            }
+            "export" => {
+                for name in names {
+                    let sym = name.value().as_symbol().unwrap().clone();
+                    if sym.is_namespaced_var() {
+                        let msg = format!(
+                            "Exported rule names must be unqualified, but found '{}'.",
+                            sym
+                        );
+                        errors.push(invalid_module(&name, msg));
+                    } else {
+                        scope.exports.entry(sym).or_insert(name);
+                    }
+                }
+            }
+            "import" => {
+                for name in names {
+                    let sym = name.value().as_symbol().unwrap();
+                    let local = match sym.0.rsplit_once("::") {
+                        Some((_, local)) => Symbol::new(local),
+                        None => {
+                            let msg = format!(
+                                "Imported rule names must be qualified with their module, e.g., `import billing::{}`.",
+                                sym
+                            );
+                            errors.push(invalid_module(&name, msg));
+                            continue;
+                        }
+                    };
+                    match scope.imports.get(&local) {
+                        Some(other) if other.value() != name.value() => {
+                            let msg = format!(
+                                "Import of {} is ambiguous: {} is already imported from {}.",
+                                sym, local, other
+                            );
+                            errors.push(invalid_module(&name, msg));
+                        }
+                        Some(_) => (),
+                        None => {
+                            scope.imports.insert(local, name);
+                        }
+                    }
+                }
+            }
+            "module" => {
+                let msg = "A module declaration must be the first line of a file.".to_owned();
+                errors.push(invalid_module(keyword, msg));
+            }
+            _ => {
+                let msg = format!(
+                    "Expected 'module', 'export', or 'import' but found '{}'.",
+                    keyword
+                );
+                errors.push(invalid_module(keyword, msg));
+            }
+        }
+        errors
+    }
+
+    /// Check every export and import against the names of the rules in the KB.
+    pub fn validate(&self, rules: &HashSet<Symbol>) -> Vec<ValidationError> {
+        let mut errors = vec![];
+        let mut scopes = self.scopes.iter().collect::<Vec<_>>();
+        scopes.sort_by_key(|(module, _)| *module);
+        for (module, scope) in scopes {
+            let mut exports = scope.exports.iter().collect::<Vec<_>>();
+            exports.sort_by_key(|(_, term)| term.offset());
+            for (name, term) in exports {
+                let module = module.as_ref().unwrap();
+                if !rules.contains(&qualify(module, name)) {
+                    let msg = format!("Module {} exports undefined rule {}.", module, name);
+                    errors.push(invalid_module(term, msg));
+                }
+            }
+
+            let mut imports = scope.imports.iter().collect::<Vec<_>>();
+            imports.sort_by_key(|(_, term)| term.offset());
+            for (local, term) in imports {
+                let name = term.value().as_symbol().unwrap();
+                let (from, _) = name.0.rsplit_once("::").unwrap();
+                let from = Symbol::new(from);
+                let msg = match self.scopes.get(&Some(from.clone())) {
+                    None => format!("Cannot import {}: there is no module {}.", name, from),
+                    Some(_) if !rules.contains(name) => {
+                        format!(
+                            "Cannot import {}: module {} has no rule {}.",
+                            name, from, local
+                        )
+                    }
+                    Some(other)
+                        if module.as_ref() != Some(&from) && !other.exports.contains_key(local) =>
+                    {
+                        format!(
+                            "Cannot import {}: module {} does not export {}.",
+                            name, from, local
+                        )
+                    }
+                    _ => {
+                        let (defined, scope_name) = match module {
+                            Some(module) => (qualify(module, local), format!("module {}", module)),
+                            None => (local.clone(), "the root namespace".to_owned()),
+                        };
+                        if defined == *name || !rules.contains(&defined) {
+                            continue;
+                        }
+                        format!(
+                            "Import of {} is ambiguous: {} is also defined in {}.",
+                            name, local, scope_name
+                        )
+                    }
+                };
+                errors.push(invalid_module(term, msg));
+            }
+        }
+        errors
+    }
+}
+
+/// Rewrites the calls in a rule or query loaded from `module` to the qualified names of the
+/// rules they refer to.
+///
+/// An unqualified name refers to a rule defined in the same module, then to an imported rule,
+/// and otherwise to a rule in the root namespace. A qualified name refers to the rule with that
+/// name, which must be exported unless it's in the same module.
+pub struct Resolver<'a> {
+    modules: &'a Modules,
+    rules: &'a HashSet<Symbol>,
+    module: Option<Symbol>,
+    pub errors: Vec<ValidationError>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(modules: &'a Modules, rules: &'a HashSet<Symbol>, module: Option<Symbol>) -> Self {
+        Self {
+            modules,
+            rules,
+            module,
+            errors: vec![],
+        }
+    }
+
+    fn resolve(&self, term: &Term, name: &Symbol) -> Result<Symbol> {
+        if let Some((module, local)) = name.0.rsplit_once("::") {
+            let module = Symbol::new(module);
+            if self.module.as_ref() != Some(&module) && self.rules.contains(name) {
+                if let Some(scope) = self.modules.scopes.get(&Some(module.clone())) {
+                    if !scope.exports.contains_key(&Symbol::new(local)) {
+                        let msg = format!("Rule {} is not exported from module {}.", local, module);
+                        return Err(invalid_module(term, msg));
+                    }
+                }
+            }
+            return Ok(name.clone());
+        }
+
+        if let Some(module) = &self.module {
+            let qualified = qualify(module, name);
+            if self.rules.contains(&qualified) {
+                return Ok(qualified);
+            }
+        }
+        let import = self
+            .modules
+            .scopes
+            .get(&self.module)
+            .and_then(|scope| scope.imports.get(name));
+        match import {
+            Some(import) => Ok(import.value().as_symbol().unwrap().clone()),
+            None => Ok(name.clone()),
+        }
+    }
+}
+
+impl<'a> Folder for Resolver<'a> {
+    fn fold_term(&mut self, t: Term) -> Term {
+        match t.value() {
+            // Method calls and constructors aren't rule calls.
+            Value::Expression(Operation {
+                operator: Operator::Dot | Operator::New,
+                ..
+            }) => t,
+            Value::Call(call) => match self.resolve(&t, &call.name) {
+                Ok(name) if name == call.name => fold_term(t, self),
+                Ok(name) => {
+                    let call = Call {
+                        name,
+                        ..call.clone()
+                    };
+                    fold_term(t.clone_with_value(Value::Call(call)), self)
+                }
+                Err(error) => {
+                    self.errors.push(error);
+                    fold_term(t, self)
+                }
+            },
+            _ => fold_term(t, self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> HashSet<Symbol> {
+        names.iter().map(|name| sym!(*name)).collect()
+    }
+
+    #[test]
+    fn test_resolve_names() {
+        let mut modules = Modules::new();
+        let billing = modules
+            .declare_module(1, &var!("module"), &[var!("billing")])
+            .unwrap();
+        assert!(modules
+            .add_declaration(Some(&billing), &var!("export"), vec![var!("can_refund")])
+            .is_empty());
+        assert!(modules
+            .add_declaration(None, &var!("import"), vec![var!("billing::can_refund")])
+            .is_empty());
+        let rules = names(&["billing::can_refund", "billing::limit", "limit", "has_role"]);
+        assert!(modules.validate(&rules).is_empty());
+
+        let resolver = Resolver::new(&modules, &rules, Some(billing));
+        let term = term!(call!("x"));
+        let resolve = |name| resolver.resolve(&term, &sym!(name)).map(|s| s.0);
+        assert_eq!(resolve("limit").unwrap(), "billing::limit");
+        assert_eq!(resolve("has_role").unwrap(), "has_role");
+        assert_eq!(resolve("billing::limit").unwrap(), "billing::limit");
+
+        let resolver = Resolver::new(&modules, &rules, None);
+        let resolve = |name| resolver.resolve(&term, &sym!(name)).map(|s| s.0);
+        assert_eq!(resolve("can_refund").unwrap(), "billing::can_refund");
+        assert_eq!(resolve("limit").unwrap(), "limit");
+        assert!(resolve("billing::limit")
+            .unwrap_err()
+            .to_string()
+            .contains("Rule limit is not exported from module billing."));
+    }
+
+    #[test]
+    fn test_invalid_declarations() {
+        let mut modules = Modules::new();
+        assert!(modules
+            .declare_module(1, &var!("module"), &[var!("a"), var!("b")])
+            .is_err());
+        let errors = modules.add_declaration(None, &var!("export"), vec![var!("f")]);
+        assert!(errors[0]
+            .to_string()
+            .contains("Only modules can export rules."));
+        let errors = modules.add_declaration(None, &var!("import"), vec![var!("f")]);
+        assert!(errors[0].to_string().contains("must be qualified"));
+        let errors = modules.add_declaration(None, &var!("exports"), vec![var!("f")]);
+        assert!(errors[0].to_string().contains("found 'exports'"));
+
+        let billing = modules
+            .declare_module(1, &var!("module"), &[var!("billing")])
+            .unwrap();
+        let errors = modules.add_declaration(
+            None,
+            &var!("import"),
+            vec![var!("billing::f"), var!("shipping::f")],
+        );
+        assert!(errors[0].to_string().contains(
+            "Import of shipping::f is ambiguous: f is already imported from billing::f."
+        ));
+        modules.add_declaration(Some(&billing), &var!("export"), vec![var!("g")]);
+        modules.add_declaration(None, &var!("import"), vec![var!("billing::h")]);
+
+        let mut errors = modules
+            .validate(&names(&["f", "billing::f", "billing::h"]))
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                "Cannot import billing::f: module billing does not export f.",
+                "Cannot import billing::h: module billing does not export h.",
+                "Module billing exports undefined rule g.",
+            ]
+        );
+    }
+}
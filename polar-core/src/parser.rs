@@ -8,6 +8,37 @@ pub enum ValueOrLogical {
     Either(Term),
 }
 
+impl ValueOrLogical {
+    fn expect_value(self, loc: usize) -> Result<Term, ParseError<usize, Token, error::ParseError>> {
+        match self {
+            Self::Logical(term) => Err(ParseError::User {
+                error: error::ParseError::WrongValueType {
+                    loc,
+                    term,
+                    expected: "value".to_string(),
+                },
+            }),
+            Self::Value(term) | Self::Either(term) => Ok(term),
+        }
+    }
+
+    fn expect_logical(
+        self,
+        loc: usize,
+    ) -> Result<Term, ParseError<usize, Token, error::ParseError>> {
+        match self {
+            Self::Value(term) => Err(ParseError::User {
+                error: error::ParseError::WrongValueType {
+                    loc,
+                    term,
+                    expected: "logical expression".to_string(),
+                },
+            }),
+            Self::Logical(term) | Self::Either(term) => Ok(term),
+        }
+    }
+}
+
 lalrpop_mod!(
     #[allow(clippy::all, dead_code, unused_imports, unused_mut)]
     polar
@@ -29,6 +60,20 @@ pub enum Line {
         resource: Term,
         productions: Vec<Production>,
     },
+    Union {
+        keyword: Term,
+        name: Term,
+        members: Vec<Term>,
+    },
+    Module {
+        keyword: Term,
+        names: Vec<Term>,
+    },
+    Constant {
+        keyword: Term,
+        name: Term,
+        value: Term,
+    },
 }
 
 fn to_parse_error(e: ParseError<usize, lexer::Token, error::ParseError>) -> error::ParseError {
@@ -224,6 +269,100 @@ mod tests {
         super::parse_lines(0, rule_type).expect_err("parse error");
     }
 
+    #[test]
+    fn test_parse_tabled_rule_type() {
+        let line = parse_lines("type tabled ancestor(x: Folder, y: Folder);");
+        match &line[0] {
+            Line::RuleType(rule_type) => {
+                assert!(rule_type.tabled);
+                assert_eq!(rule_type.to_polar(), "ancestor(x: Folder{}, y: Folder{});");
+            }
+            line => panic!("expected a rule type, got {:?}", line),
+        }
+        match &parse_lines("type ancestor(x, y);")[0] {
+            Line::RuleType(rule_type) => assert!(!rule_type.tabled),
+            line => panic!("expected a rule type, got {:?}", line),
+        }
+        assert!(matches!(
+            super::parse_lines(0, "type memoized ancestor(x, y);"),
+            Err(UnrecognizedToken { token, .. }) if token == "memoized"
+        ));
+    }
+
+    #[test]
+    fn test_parse_union() {
+        let line = parse_lines("union Principal = User | ServiceAccount;");
+        match &line[0] {
+            Line::Union {
+                keyword,
+                name,
+                members,
+            } => {
+                assert_eq!(keyword.value().as_symbol().unwrap().0, "union");
+                assert_eq!(name.value().as_symbol().unwrap().0, "Principal");
+                let members: Vec<_> = members.iter().map(|m| m.to_polar()).collect();
+                assert_eq!(members, vec!["User", "ServiceAccount"]);
+            }
+            line => panic!("expected a union, got {:?}", line),
+        }
+        assert!(super::parse_lines(0, "union Principal = ;").is_err());
+        assert!(matches!(
+            &parse_lines("union Principal = User;")[0],
+            Line::Union { members, .. } if members.len() == 1
+        ));
+        assert!(matches!(
+            &parse_lines("const ROLES = ADMIN_ROLES;")[0],
+            Line::Constant { value, .. } if value.to_polar() == "ADMIN_ROLES"
+        ));
+    }
+
+    #[test]
+    fn test_parse_module_declarations() {
+        let lines =
+            parse_lines("module billing; export can_refund, refund_limit; import users::is_admin;");
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|line| match line {
+                Line::Module { keyword, names } => (
+                    keyword.to_polar(),
+                    names.iter().map(|name| name.to_polar()).collect::<Vec<_>>(),
+                ),
+                line => panic!("expected a module declaration, got {:?}", line),
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("module".to_owned(), vec!["billing".to_owned()]),
+                (
+                    "export".to_owned(),
+                    vec!["can_refund".to_owned(), "refund_limit".to_owned()]
+                ),
+                ("import".to_owned(), vec!["users::is_admin".to_owned()]),
+            ]
+        );
+        assert!(super::parse_lines(0, "export can_refund refund_limit;").is_err());
+    }
+
+    #[test]
+    fn test_parse_constant() {
+        let line = parse_lines(r#"const ADMIN_ROLES = ["owner", ROLE];"#);
+        match &line[0] {
+            Line::Constant {
+                keyword,
+                name,
+                value,
+            } => {
+                assert_eq!(keyword.to_polar(), "const");
+                assert_eq!(name.to_polar(), "ADMIN_ROLES");
+                assert_eq!(value.to_polar(), r#"["owner", ROLE]"#);
+            }
+            line => panic!("expected a constant, got {:?}", line),
+        }
+        // Constants have to be literals.
+        assert!(super::parse_lines(0, "const A = x.y;").is_err());
+    }
+
     #[test]
     fn test_parse_new() {
         let f = r#"a(x) if x = new Foo(a: 1);"#;
@@ -311,6 +450,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_aggregates() {
+        let q = "count(x, x in [1, 2], n)";
+        assert_eq!(
+            parse_query(q),
+            term!(op!(
+                Count,
+                term!(sym!("x")),
+                term!(op!(In, term!(sym!("x")), term!([1, 2]))),
+                term!(sym!("n"))
+            )),
+        );
+        assert_eq!(parse_query(q).to_polar(), q);
+
+        // Aggregate names are still valid field names.
+        let q = "x.max";
+        assert_eq!(
+            parse_query(q),
+            term!(op!(Dot, term!(sym!("x")), term!("max"))),
+        );
+
+        // They aren't reserved words, and calls whose second argument isn't a logical
+        // expression aren't aggregates.
+        assert_eq!(
+            parse_rule("max(a, b, a) if a > b;").to_polar(),
+            "max(a, b, a) if a > b;"
+        );
+        assert_eq!(
+            parse_rule("f(count) if count > 1;").to_polar(),
+            "f(count) if count > 1;"
+        );
+        assert_eq!(
+            parse_query("sum(x, y)"),
+            term!(call!("sum", [sym!("x"), sym!("y")]))
+        );
+        assert_eq!(
+            parse_query("count(x, 1, n)"),
+            term!(call!("count", [sym!("x"), 1, sym!("n")]))
+        );
+        assert_eq!(
+            parse_query("x.min(a, b, c)"),
+            term!(op!(
+                Dot,
+                term!(sym!("x")),
+                term!(call!("min", [sym!("a"), sym!("b"), sym!("c")]))
+            ))
+        );
+    }
+
     #[test]
     fn test_catching_wrong_types() {
         for bad_query in &[
@@ -322,6 +510,7 @@ mod tests {
             "1 and 2",
             "1 + print(\"x\")",
             "forall([1, 2, 3], x < 1)",
+            "x = count(y, y in z, n)",
             "x = (1 or 2)",
             "x = (1 = 2)",
             "foo.bar(x or y)",
@@ -58,9 +58,9 @@ pub fn invert_operation(Operation { operator, args }: Operation) -> Operation {
         Operator::Debug | Operator::Print | Operator::New | Operator::Dot => {
             Operation { operator, args }
         }
-        Operator::Isa => Operation {
+        Operator::Isa | Operator::In => Operation {
             operator: Operator::Not,
-            args: vec![term!(op!(Isa, args[0].clone(), args[1].clone()))],
+            args: vec![term!(Operation { operator, args })],
         },
         Operator::Not => args[0]
             .value()
@@ -1496,6 +1496,48 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_negated_conjunction_with_local_variables() -> TestResult {
+        let p = Polar::new();
+        p.load_str(
+            r#"f(x) if not 1 in x.blocked;
+               g(x) if not (m in x.members and m.banned = true);
+               h(x) if forall(m in x.members, m.active = true) and x.public = false;
+               k(x) if m in x.members and not m.banned = true;"#,
+        )?;
+
+        let mut q = p.new_query_from_term(term!(call!("f", [sym!("x")])), false);
+        assert_partial_expression!(next_binding(&mut q)?, "x", "not 1 in _this.blocked");
+        assert_query_done!(q);
+
+        // `m` is local to the negation, so the negation can't be pushed into the conjunction.
+        let mut q = p.new_query_from_term(term!(call!("g", [sym!("x")])), false);
+        assert_partial_expression!(
+            next_binding(&mut q)?,
+            "x",
+            "not (_m_20 in _this.members and true = _m_20.banned)"
+        );
+        assert_query_done!(q);
+
+        let mut q = p.new_query_from_term(term!(call!("h", [sym!("x")])), false);
+        assert_partial_expression!(
+            next_binding(&mut q)?,
+            "x",
+            "not (_m_30 in _this.members and true != _m_30.active) and false = _this.public"
+        );
+        assert_query_done!(q);
+
+        // `m` is bound outside of the negation, so only the negated constraint is inverted.
+        let mut q = p.new_query_from_term(term!(call!("k", [sym!("x")])), false);
+        assert_partial_expression!(
+            next_binding(&mut q)?,
+            "x",
+            "_m_41 in _this.members and true != _m_41.banned"
+        );
+        assert_query_done!(q);
+        Ok(())
+    }
+
     #[test]
     fn test_negate_disjunctions() -> TestResult {
         let p = Polar::new();
@@ -1668,6 +1710,55 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_aggregate_with_partial() -> TestResult {
+        let p = Polar::new();
+        p.load_str(
+            r#"f(x) if count(m, m in x.members, n) and n > 1;
+               g(x) if max(y, y in [1, 3, 2], n) and x = n;"#,
+        )?;
+        let mut q = p.new_query_from_term(term!(call!("f", [sym!("x")])), false);
+        match q.next_event().unwrap_err() {
+            PolarError {
+                kind: ErrorKind::Runtime(RuntimeError::Unsupported { msg, .. }),
+                ..
+            } => assert_eq!(msg, "cannot use count with partial evaluation"),
+            error => panic!("unexpected error {}", error),
+        }
+
+        // Aggregates that don't involve a partial are fine.
+        let mut q = p.new_query_from_term(term!(call!("g", [sym!("x")])), false);
+        assert_eq!(next_binding(&mut q)?[&sym!("x")], term!(3));
+        assert_query_done!(q);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tabled_rule_with_partial() -> TestResult {
+        let p = Polar::new();
+        p.load_str(
+            r#"type tabled member(x, y);
+               member(x, y) if x in y.members;
+               f(x) if member(1, x);
+               g(x) if member(x, {members: [1, 2]});"#,
+        )?;
+        let mut q = p.new_query_from_term(term!(call!("f", [sym!("x")])), false);
+        match q.next_event().unwrap_err() {
+            PolarError {
+                kind: ErrorKind::Runtime(RuntimeError::Unsupported { msg, .. }),
+                ..
+            } => assert!(msg.starts_with("cannot use tabled rule member(1, ")),
+            error => panic!("unexpected error {}", error),
+        }
+
+        // Tabled calls that don't involve a partial are fine.
+        let mut q = p.new_query_from_term(term!(call!("g", [sym!("x")])), false);
+        assert_eq!(next_binding(&mut q)?[&sym!("x")], term!(1));
+        assert_eq!(next_binding(&mut q)?[&sym!("x")], term!(2));
+        assert_query_done!(q);
+        Ok(())
+    }
+
     #[test]
     fn test_cut_with_partial() -> TestResult {
         let p = Polar::new();
@@ -6,6 +6,7 @@ use crate::error::RuntimeError;
 use crate::folder::{fold_term, Folder};
 use crate::formatting::ToPolarString;
 use crate::terms::*;
+use crate::visitor::{walk_operation, Visitor};
 
 use super::partial::{invert_operation, FALSE, TRUE};
 
@@ -67,6 +68,31 @@ impl Folder for VariableSubber {
     }
 }
 
+/// Collects the variables that occur in a term outside of any negation.
+struct UnnegatedVariables(HashSet<Symbol>);
+
+impl Visitor for UnnegatedVariables {
+    fn visit_variable(&mut self, v: &Symbol) {
+        self.0.insert(v.clone());
+    }
+
+    fn visit_rest_variable(&mut self, v: &Symbol) {
+        self.0.insert(v.clone());
+    }
+
+    fn visit_operation(&mut self, o: &Operation) {
+        if o.operator != Operator::Not {
+            walk_operation(self, o)
+        }
+    }
+}
+
+fn unnegated_variables(term: &Term) -> HashSet<Symbol> {
+    let mut visitor = UnnegatedVariables(HashSet::new());
+    visitor.visit_term(term);
+    visitor.0
+}
+
 /// Substitute `sym!("_this")` for a variable in a partial.
 pub fn sub_this(this: Symbol, term: Term) -> Term {
     if term
@@ -119,6 +145,7 @@ pub fn simplify_partial(
     track_performance: bool,
 ) -> (Term, Option<PerfCounters>) {
     let mut simplifier = Simplifier::new(output_vars, track_performance);
+    simplifier.unnegated_vars = unnegated_variables(&term);
     simplify_debug!("*** simplify partial {:?}", var);
     simplifier.simplify_partial(&mut term);
     term = simplify_trivial_constraint(var.clone(), term);
@@ -305,6 +332,8 @@ impl PerfCounters {
 pub struct Simplifier {
     bindings: Bindings,
     output_vars: HashSet<Symbol>,
+    /// Variables that occur outside of the negation currently being simplified.
+    unnegated_vars: HashSet<Symbol>,
     seen: HashSet<Term>,
 
     counters: PerfCounters,
@@ -317,6 +346,7 @@ impl Simplifier {
         Self {
             bindings: Bindings::new(),
             output_vars,
+            unnegated_vars: HashSet::new(),
             seen: HashSet::new(),
             counters: PerfCounters::new(track_performance),
         }
@@ -517,18 +547,34 @@ impl Simplifier {
 
             // Negation. Simplify the negated term, saving & restoring the
             // current bindings because bindings may not leak out of a negation.
+            //
+            // Variables that only occur inside a negated conjunction are
+            // existentially quantified within it (`not (x in y.z and x.w = 1)`),
+            // so the negation can't be pushed into its operands; keep it as is.
             Operator::Not => {
                 assert_eq!(o.args.len(), 1);
                 let mut simplified = o.args[0].clone();
                 let mut simplifier = self.clone();
+                simplifier
+                    .unnegated_vars
+                    .extend(unnegated_variables(&simplified));
                 simplifier.simplify_partial(&mut simplified);
-                *o = invert_operation(
-                    simplified
-                        .value()
-                        .as_expression()
-                        .expect("a simplified expression")
-                        .clone(),
-                )
+                let simplified = simplified
+                    .value()
+                    .as_expression()
+                    .expect("a simplified expression")
+                    .clone();
+                let local = simplified.operator == Operator::And
+                    && simplified.variables().into_iter().any(|v| {
+                        !self.output_vars.contains(&v)
+                            && !self.unnegated_vars.contains(&v)
+                            && !self.is_bound(&v)
+                    });
+                *o = if local {
+                    op!(Not, term!(simplified))
+                } else {
+                    invert_operation(simplified)
+                };
             }
 
             // Default case.
@@ -0,0 +1,590 @@
+//! Role × permission matrices computed from the shorthand rules in resource blocks.
+//!
+//! For each block that declares permissions, the matrix lists every role that grants one of them:
+//! roles declared in the block itself, roles on related resources (through `on` relations and
+//! relation paths), and `global` roles. Only shorthand rules are taken into account; access granted
+//! by hand-written `has_role` or `has_permission` rules doesn't show up.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use super::error::PolarResult;
+use super::resource_block::{
+    resource_blocks_from_sources, Declaration, Implier, ResourceBlocks, GLOBAL_BLOCK_NAME,
+};
+use super::sources::Source;
+use super::terms::Term;
+
+/// How a role grants a permission.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    /// Every shorthand rule on the way from the role to the permission is unconditional.
+    Granted,
+    /// The permission is granted only if a shorthand rule condition (`and ...`) holds, or it is
+    /// taken away again by a conditional `deny` shorthand rule.
+    Conditional,
+    /// A `deny` shorthand rule unconditionally takes the permission away from the role.
+    Denied,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Granted => write!(f, "granted"),
+            Access::Conditional => write!(f, "conditional"),
+            Access::Denied => write!(f, "denied"),
+        }
+    }
+}
+
+/// A role that grants (or is denied) permissions on a resource.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct MatrixRole {
+    /// The name of the role.
+    pub role: String,
+    /// The relation (path) that leads to the resource on which `role` is declared, e.g.,
+    /// `"parent"` for `"admin" on "parent"`. `None` for the resource's own and global roles.
+    pub relation: Option<String>,
+    /// Whether `role` is declared in the `global` block.
+    pub global: bool,
+}
+
+impl fmt::Display for MatrixRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.global {
+            write!(f, "{} ", GLOBAL_BLOCK_NAME)?;
+        }
+        write!(f, "{:?}", self.role)?;
+        if let Some(relation) = &self.relation {
+            write!(f, " on {:?}", relation)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MatrixRow {
+    #[serde(flatten)]
+    pub role: MatrixRole,
+    /// Map from permission to the role's access to it. Permissions the role doesn't grant are
+    /// left out.
+    pub access: BTreeMap<String, Access>,
+}
+
+/// The role × permission matrix for a single resource block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PermissionMatrix {
+    pub resource: String,
+    /// The permissions declared in the resource's block, sorted by name.
+    pub permissions: Vec<String>,
+    /// The resource's own roles (whether or not they grant anything), followed by roles on related
+    /// resources and global roles that grant at least one permission.
+    pub rows: Vec<MatrixRow>,
+}
+
+/// Output formats for [`PermissionMatrix`] exports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatrixFormat {
+    /// One Markdown table per resource.
+    Markdown,
+    /// A single CSV table with one `resource,role,relation,global,permission,access` line per
+    /// granted (or denied) permission, with the columns of a [`MatrixRole`] as in the JSON export.
+    Csv,
+    /// A JSON array of [`PermissionMatrix`] objects.
+    Json,
+}
+
+impl FromStr for MatrixFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(MatrixFormat::Markdown),
+            "csv" => Ok(MatrixFormat::Csv),
+            "json" => Ok(MatrixFormat::Json),
+            _ => Err(format!(
+                "Unknown matrix format '{}'. Expected one of: markdown, csv, json.",
+                s
+            )),
+        }
+    }
+}
+
+impl MatrixFormat {
+    pub fn export(&self, matrices: &[PermissionMatrix]) -> String {
+        match self {
+            MatrixFormat::Markdown => to_markdown(matrices),
+            MatrixFormat::Csv => to_csv(matrices),
+            MatrixFormat::Json => serde_json::to_string_pretty(matrices).unwrap(),
+        }
+    }
+}
+
+fn to_markdown(matrices: &[PermissionMatrix]) -> String {
+    let mut tables = vec![];
+    for matrix in matrices {
+        let mut lines = vec![format!("## {}", matrix.resource), String::new()];
+        let mut header = vec!["Role".to_owned()];
+        header.extend(matrix.permissions.iter().cloned());
+        lines.push(format!("| {} |", header.join(" | ")));
+        lines.push(format!("|{}", " --- |".repeat(header.len())));
+        for row in &matrix.rows {
+            let mut cells = vec![format!("`{}`", row.role)];
+            cells.extend(matrix.permissions.iter().map(|permission| {
+                row.access
+                    .get(permission)
+                    .map_or_else(String::new, ToString::to_string)
+            }));
+            lines.push(format!("| {} |", cells.join(" | ")));
+        }
+        tables.push(lines.join("\n"));
+    }
+    let mut markdown = tables.join("\n\n");
+    markdown.push('\n');
+    markdown
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn to_csv(matrices: &[PermissionMatrix]) -> String {
+    let mut csv = "resource,role,relation,global,permission,access\n".to_owned();
+    for matrix in matrices {
+        for row in &matrix.rows {
+            for permission in &matrix.permissions {
+                if let Some(access) = row.access.get(permission) {
+                    let fields = [
+                        csv_field(&matrix.resource),
+                        csv_field(&row.role.role),
+                        row.role
+                            .relation
+                            .as_deref()
+                            .map_or_else(String::new, csv_field),
+                        row.role.global.to_string(),
+                        csv_field(permission),
+                        access.to_string(),
+                    ];
+                    csv.push_str(&fields.join(","));
+                    csv.push('\n');
+                }
+            }
+        }
+    }
+    csv
+}
+
+/// A chain of relations from the resource whose matrix we're computing, as
+/// `(relation, resource declaring the relation)` pairs.
+type Path = Vec<(String, Term)>;
+
+/// Walks shorthand rules backwards, from a permission to the roles that imply it.
+struct Walker<'a> {
+    blocks: &'a ResourceBlocks,
+    /// Map from each visited `(path, resource, role or permission)` to whether it was only reached
+    /// conditionally.
+    visited: HashMap<(Path, Term, Term), bool>,
+    /// Map from each role found to whether it was only reached conditionally.
+    roles: HashMap<MatrixRole, bool>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(blocks: &'a ResourceBlocks) -> Self {
+        Self {
+            blocks,
+            visited: HashMap::new(),
+            roles: HashMap::new(),
+        }
+    }
+
+    fn found(&mut self, role: MatrixRole, conditional: bool) {
+        let entry = self.roles.entry(role).or_insert(conditional);
+        *entry &= conditional;
+    }
+
+    fn visit(&mut self, path: Path, resource: &Term, name: &Term, conditional: bool) {
+        // Only revisit if we've found an unconditional way to get here.
+        let key = (path, resource.clone(), name.clone());
+        match self.visited.get(&key) {
+            Some(&seen_conditional) if !seen_conditional || conditional => return,
+            _ => self.visited.insert(key.clone(), conditional),
+        };
+        let (path, _, _) = key;
+
+        let declaration = self
+            .blocks
+            .declarations()
+            .get(resource)
+            .and_then(|declarations| declarations.get(name));
+        if let (Some(Declaration::Role), Ok(role)) = (declaration, name.value().as_string()) {
+            let relation = (!path.is_empty()).then(|| {
+                let relations: Vec<_> =
+                    path.iter().map(|(relation, _)| relation.as_str()).collect();
+                relations.join(".")
+            });
+            let role = MatrixRole {
+                role: role.to_owned(),
+                relation,
+                global: false,
+            };
+            self.found(role, conditional);
+        }
+
+        let rules = self.blocks.shorthand_rules.get(resource).into_iter();
+        for rule in rules.flatten() {
+            if rule.deny.is_some() || &rule.head != name {
+                continue;
+            }
+            let conditional = conditional || rule.condition.is_some();
+            for implier in &rule.body {
+                self.follow(implier, &path, resource, conditional);
+            }
+        }
+    }
+
+    fn follow(
+        &mut self,
+        implier: &Implier,
+        path: &[(String, Term)],
+        resource: &Term,
+        conditional: bool,
+    ) {
+        match implier {
+            Implier::Resource(name, None) => self.visit(path.to_vec(), resource, name, conditional),
+            Implier::Resource(name, Some((_, relation))) => {
+                let hops = match self
+                    .blocks
+                    .get_relation_path_in_resource_block(relation, resource)
+                {
+                    Ok(hops) => hops,
+                    Err(_) => return,
+                };
+                let mut path = path.to_vec();
+                let mut current = resource;
+                for (hop, related) in hops {
+                    let hop = match hop.value().as_string() {
+                        Ok(hop) => (hop.to_owned(), current.clone()),
+                        Err(_) => return,
+                    };
+                    // Stop at cycles, e.g., `"reader" if "reader" on "parent";`.
+                    if path.contains(&hop) {
+                        return;
+                    }
+                    path.push(hop);
+                    current = related;
+                }
+                self.visit(path, current, name, conditional)
+            }
+            Implier::Global(_, role) => {
+                if let Ok(role) = role.value().as_string() {
+                    let role = MatrixRole {
+                        role: role.to_owned(),
+                        relation: None,
+                        global: true,
+                    };
+                    self.found(role, conditional);
+                }
+            }
+        }
+    }
+}
+
+fn permission_matrix(blocks: &ResourceBlocks, resource: &Term) -> Option<PermissionMatrix> {
+    let declarations = blocks.declarations().get(resource)?;
+    let names_of = |wanted: fn(&Declaration) -> bool| {
+        let mut names: Vec<_> = declarations
+            .iter()
+            .filter(|(_, declaration)| wanted(declaration))
+            .filter_map(|(term, _)| Some((term.value().as_string().ok()?, term)))
+            .collect();
+        names.sort_by_key(|(name, _)| *name);
+        names
+    };
+    let permissions = names_of(|d| matches!(d, Declaration::Permission));
+    if permissions.is_empty() {
+        return None;
+    }
+
+    let mut rows: BTreeMap<MatrixRole, BTreeMap<String, Access>> =
+        names_of(|d| matches!(d, Declaration::Role))
+            .into_iter()
+            .map(|(role, _)| {
+                let role = MatrixRole {
+                    role: role.to_owned(),
+                    relation: None,
+                    global: false,
+                };
+                (role, BTreeMap::new())
+            })
+            .collect();
+
+    for (permission, term) in &permissions {
+        let mut grants = Walker::new(blocks);
+        grants.visit(vec![], resource, term, false);
+
+        let mut denials = Walker::new(blocks);
+        let rules = blocks.shorthand_rules.get(resource).into_iter();
+        for rule in rules.flatten() {
+            if rule.deny.is_some() && &rule.head == *term {
+                for implier in &rule.body {
+                    denials.follow(implier, &[], resource, rule.condition.is_some());
+                }
+            }
+        }
+
+        for (role, granted_conditionally) in &grants.roles {
+            let access = match denials.roles.get(role) {
+                None if !granted_conditionally => Access::Granted,
+                Some(false) => Access::Denied,
+                _ => Access::Conditional,
+            };
+            let row = rows.entry(role.clone()).or_default();
+            row.insert(permission.to_string(), access);
+        }
+        for (role, denied_conditionally) in &denials.roles {
+            if !denied_conditionally {
+                let row = rows.entry(role.clone()).or_default();
+                row.insert(permission.to_string(), Access::Denied);
+            }
+        }
+    }
+
+    // Own roles first, then roles on related resources, then global roles.
+    let mut rows: Vec<_> = rows
+        .into_iter()
+        .map(|(role, access)| MatrixRow { role, access })
+        .collect();
+    rows.sort_by_key(|row| (row.role.global, row.role.relation.is_some()));
+
+    Some(PermissionMatrix {
+        resource: resource.to_string(),
+        permissions: permissions
+            .into_iter()
+            .map(|(name, _)| name.to_owned())
+            .collect(),
+        rows,
+    })
+}
+
+/// Compute the role × permission matrix of every resource block that declares permissions, sorted
+/// by resource name.
+pub fn permission_matrices(blocks: &ResourceBlocks) -> Vec<PermissionMatrix> {
+    let mut matrices: Vec<_> = blocks
+        .declarations()
+        .keys()
+        .filter_map(|resource| permission_matrix(blocks, resource))
+        .collect();
+    matrices.sort_by(|a, b| a.resource.cmp(&b.resource));
+    matrices
+}
+
+/// Compute permission matrices straight from the resource blocks in `sources`, without loading
+/// them. Unlike [`Polar::permission_matrices`](crate::polar::Polar::permission_matrices), this
+/// doesn't need the classes the blocks refer to to be registered, so it works on policy files
+/// alone, e.g., from the command line.
+pub fn permission_matrices_from_sources(
+    sources: Vec<Source>,
+) -> PolarResult<Vec<PermissionMatrix>> {
+    let (blocks, _) = resource_blocks_from_sources(sources)?;
+    Ok(permission_matrices(&blocks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::polar::Polar;
+    use crate::terms::*;
+
+    fn matrices(policy: &str) -> Vec<PermissionMatrix> {
+        let polar = Polar::new();
+        polar
+            .register_constant(sym!("User"), term!("unused"))
+            .unwrap();
+        polar
+            .register_constant(sym!("Org"), term!("unused"))
+            .unwrap();
+        polar
+            .register_constant(sym!("Repo"), term!("unused"))
+            .unwrap();
+        polar.load_str(policy).unwrap();
+        polar.permission_matrices()
+    }
+
+    fn role(name: &str, relation: Option<&str>, global: bool) -> MatrixRole {
+        MatrixRole {
+            role: name.to_owned(),
+            relation: relation.map(ToOwned::to_owned),
+            global,
+        }
+    }
+
+    fn row(role: MatrixRole, access: &[(&str, Access)]) -> MatrixRow {
+        let access = access
+            .iter()
+            .map(|(permission, access)| (permission.to_string(), *access))
+            .collect();
+        MatrixRow { role, access }
+    }
+
+    const POLICY: &str = r#"
+        actor User {}
+
+        global {
+            roles = ["superuser"];
+        }
+
+        resource Org {
+            roles = ["owner", "member"];
+            "member" if "owner";
+        }
+
+        resource Repo {
+            permissions = ["read", "push", "delete"];
+            roles = ["reader", "writer", "contractor"];
+            relations = { parent: Org };
+
+            "read" if "reader";
+            "reader" if "writer" or "member" on "parent";
+            "writer" if "owner" on "parent";
+            "push" if "writer" and resource.is_active;
+            "delete" if global "superuser";
+            deny "push" if "contractor";
+            deny "read" if "contractor" and resource.is_private;
+        }
+
+        has_role(_: User, _: String, _: Org);
+        has_role(_: User, _: String, _: Repo);
+        has_role(_: User, _: String);
+        has_relation(_: Org, "parent", _: Repo);
+        allow(_, _, _);
+    "#;
+
+    #[test]
+    fn test_permission_matrix() {
+        let matrices = matrices(POLICY);
+        assert_eq!(matrices.len(), 1);
+        let matrix = &matrices[0];
+        assert_eq!(matrix.resource, "Repo");
+        assert_eq!(matrix.permissions, vec!["delete", "push", "read"]);
+        assert_eq!(
+            matrix.rows,
+            vec![
+                row(role("contractor", None, false), &[("push", Access::Denied)]),
+                row(role("reader", None, false), &[("read", Access::Granted)]),
+                row(
+                    role("writer", None, false),
+                    &[("push", Access::Conditional), ("read", Access::Granted)]
+                ),
+                row(
+                    role("member", Some("parent"), false),
+                    &[("read", Access::Granted)]
+                ),
+                row(
+                    role("owner", Some("parent"), false),
+                    &[("push", Access::Conditional), ("read", Access::Granted)]
+                ),
+                row(
+                    role("superuser", None, true),
+                    &[("delete", Access::Granted)]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_permission_matrix_relation_cycles() {
+        let matrices = matrices(
+            r#"
+            actor User {}
+            resource Repo {
+                permissions = ["read"];
+                roles = ["reader"];
+                relations = { parent: Repo };
+
+                "read" if "reader";
+                "reader" if "reader" on "parent";
+                "reader" if "reader" on "parent.parent";
+            }
+            has_role(_: User, _: String, _: Repo);
+            has_relation(_: Repo, "parent", _: Repo);
+            allow(_, _, _);
+            "#,
+        );
+        assert_eq!(
+            matrices[0].rows,
+            vec![
+                row(role("reader", None, false), &[("read", Access::Granted)]),
+                row(
+                    role("reader", Some("parent"), false),
+                    &[("read", Access::Granted)]
+                ),
+            ]
+        );
+    }
+
+    fn source(src: &str) -> Vec<Source> {
+        vec![Source {
+            filename: None,
+            src: src.to_owned(),
+        }]
+    }
+
+    #[test]
+    fn test_permission_matrices_from_sources() {
+        // No classes registered.
+        assert_eq!(
+            permission_matrices_from_sources(source(POLICY)).unwrap(),
+            matrices(POLICY)
+        );
+
+        let matrices = permission_matrices_from_sources(source(
+            r#"resource Repo { permissions = ["read"]; }"#,
+        ))
+        .unwrap();
+        assert!(matrices[0].rows.is_empty());
+
+        let invalid = source(r#"resource Repo { permissions = "read"; }"#);
+        assert!(permission_matrices_from_sources(invalid).is_err());
+    }
+
+    #[test]
+    fn test_matrix_formats() {
+        let matrices = matrices(POLICY);
+
+        let markdown = MatrixFormat::Markdown.export(&matrices);
+        assert!(markdown.starts_with(
+            "## Repo\n\n| Role | delete | push | read |\n| --- | --- | --- | --- |\n"
+        ));
+        assert!(markdown.contains("| `\"owner\" on \"parent\"` |  | conditional | granted |\n"));
+        assert!(markdown.contains("| `global \"superuser\"` | granted |  |  |\n"));
+
+        let csv = MatrixFormat::Csv.export(&matrices);
+        assert!(csv.starts_with("resource,role,relation,global,permission,access\n"));
+        assert!(csv.contains("Repo,contractor,,false,push,denied\n"));
+        assert!(csv.contains("Repo,owner,parent,false,read,granted\n"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&MatrixFormat::Json.export(&matrices)).unwrap();
+        assert_eq!(
+            json[0]["rows"][3],
+            serde_json::json!({
+                "role": "member",
+                "relation": "parent",
+                "global": false,
+                "access": { "read": "granted" }
+            })
+        );
+
+        assert_eq!("md".parse::<MatrixFormat>(), Ok(MatrixFormat::Markdown));
+        assert!("xml".parse::<MatrixFormat>().is_err());
+    }
+}
@@ -2,18 +2,25 @@ use std::sync::{Arc, RwLock};
 
 use super::data_filtering::{build_filter_plan, FilterPlan, PartialResults, Types};
 use super::diagnostic::Diagnostic;
-use super::error::{PolarResult, RuntimeError, ValidationError};
+use super::diagram::ModelDiagram;
+use super::error::{PolarError, PolarResult, RuntimeError, ValidationError};
+use super::expand::{expand, Answers, UsersetTree};
 use super::kb::*;
 use super::messages::*;
 use super::parser;
+use super::permission_matrix::{permission_matrices, PermissionMatrix};
 use super::query::Query;
+use super::relationships::Relationship;
 use super::resource_block::resource_block_from_productions;
 use super::rewrites::*;
+use super::snapshot::Snapshot;
 use super::sources::*;
 use super::terms::*;
+use super::traces::{RuleSpan, Trace};
 use super::validations::{
-    check_ambiguous_precedence, check_no_allow_rule, check_resource_blocks_missing_has_permission,
-    check_singletons,
+    check_ambiguous_precedence, check_no_allow_rule, check_resource_block_usage,
+    check_resource_blocks_missing_has_permission, check_shadowed_constants, check_singletons,
+    check_unreachable_deny_rules,
 };
 use super::vm::*;
 
@@ -32,6 +39,30 @@ impl Default for Polar {
 const MULTIPLE_LOAD_ERROR_MSG: &str =
     "Cannot load additional Polar code -- all Polar code must be loaded at the same time.";
 
+/// `deny` rules take precedence over `allow` rules, so while any are loaded a query for
+/// `allow(args)` runs as `allow(args) and not deny(args)`.
+fn deny_precedence(term: Term, kb: &KnowledgeBase) -> Term {
+    match term.value() {
+        Value::Call(call @ Call { kwargs: None, .. })
+            if call.name.0 == "allow" && kb.get_rules().contains_key(&sym!("deny")) =>
+        {
+            let deny = term.clone_with_value(Value::Call(Call {
+                name: sym!("deny"),
+                ..call.clone()
+            }));
+            let not_deny = term.clone_with_value(Value::Expression(Operation {
+                operator: Operator::Not,
+                args: vec![deny],
+            }));
+            term.clone_with_value(Value::Expression(Operation {
+                operator: Operator::And,
+                args: vec![term.clone(), not_deny],
+            }))
+        }
+        _ => term,
+    }
+}
+
 impl Polar {
     pub fn new() -> Self {
         // TODO(@gkaemmer): pulling this from an environment variable is a hack
@@ -49,23 +80,29 @@ impl Polar {
 
     /// Load `sources` into the KB, returning compile-time diagnostics accumulated during the load.
     pub fn diagnostic_load(&self, sources: Vec<Source>) -> Vec<Diagnostic> {
-        // we extract this into a separate function
-        // so that any errors returned with `?` are captured
-        fn load_source(
-            source_id: u64,
-            source: &Source,
-            kb: &mut KnowledgeBase,
-        ) -> PolarResult<Vec<Diagnostic>> {
-            let mut lines = parser::parse_lines(source_id, &source.src)
-                // TODO(gj): we still bomb out at the first ParseError.
-                .map_err(|e| e.with_context(source.clone()))?;
+        fn load_source(parsed: ParsedSource, kb: &mut KnowledgeBase) -> Vec<Diagnostic> {
+            let ParsedSource {
+                module,
+                lines,
+                errors,
+                ..
+            } = parsed;
+            let mut diagnostics = errors
+                .into_iter()
+                .map(|e| Diagnostic::Error(e.with_context(&*kb)))
+                .collect::<Vec<_>>();
+            // Declare unions first so that rules can use them regardless of order.
+            let (unions, rest): (Vec<_>, Vec<_>) = lines
+                .into_iter()
+                .partition(|line| matches!(line, parser::Line::Union { .. }));
+            let mut lines: Vec<_> = unions.into_iter().chain(rest).collect();
             lines.reverse();
-            let mut diagnostics = vec![];
             while let Some(line) = lines.pop() {
                 match line {
                     parser::Line::Rule(rule) => {
                         diagnostics.append(&mut check_singletons(&rule, kb));
                         diagnostics.append(&mut check_ambiguous_precedence(&rule, kb));
+                        diagnostics.append(&mut check_shadowed_constants(&rule, kb));
                         let rule = rewrite_rule(rule, kb);
                         kb.add_rule(rule);
                     }
@@ -108,23 +145,81 @@ impl Polar {
                             .map(|e| Diagnostic::Error(e.with_context(&*kb)));
                         diagnostics.append(&mut errors.collect());
                     }
+                    parser::Line::Union {
+                        keyword,
+                        name,
+                        members,
+                    } => {
+                        let errors = match keyword.value().as_symbol() {
+                            Ok(sym) if sym.0 == "union" => kb.add_union(name, members),
+                            _ => vec![ValidationError::InvalidUnion {
+                                msg: format!("Expected 'union' but found '{}'.", keyword),
+                                term: keyword,
+                            }],
+                        };
+                        let errors = errors
+                            .into_iter()
+                            .map(|e| Diagnostic::Error(e.with_context(&*kb)));
+                        diagnostics.append(&mut errors.collect());
+                    }
+                    parser::Line::Constant { .. } => {
+                        unreachable!("constants are declared before sources are loaded")
+                    }
+                    parser::Line::Module { keyword, names } => {
+                        let errors = kb.modules.add_declaration(module.as_ref(), &keyword, names);
+                        let errors = errors
+                            .into_iter()
+                            .map(|e| Diagnostic::Error(e.with_context(&*kb)));
+                        diagnostics.append(&mut errors.collect());
+                    }
                 }
             }
-            Ok(diagnostics)
+            diagnostics
         }
 
         let mut kb = self.kb.write().unwrap();
         let mut diagnostics = vec![];
 
+        let mut parsed = vec![];
         for source in &sources {
-            let result = kb.add_source(source.clone());
-            let result = result.and_then(|source_id| load_source(source_id, source, &mut kb));
-            match result {
-                Ok(mut ds) => diagnostics.append(&mut ds),
+            match kb.parse_source(source) {
+                Ok(source) => parsed.push(source),
                 Err(e) => diagnostics.push(Diagnostic::Error(e)),
             }
         }
 
+        // Declare the constants in every source before loading any rules, so that rules can use
+        // constants declared in other sources.
+        for ParsedSource { lines, .. } in &mut parsed {
+            let (constants, rest) = std::mem::take(lines)
+                .into_iter()
+                .partition(|line| matches!(line, parser::Line::Constant { .. }));
+            *lines = rest;
+            for constant in constants {
+                let result = match constant {
+                    parser::Line::Constant {
+                        keyword,
+                        name,
+                        value,
+                    } => match keyword.value().as_symbol() {
+                        Ok(sym) if sym.0 == "const" => kb.add_policy_constant(name, value),
+                        _ => Err(ValidationError::InvalidConstant {
+                            msg: format!("Expected 'const' but found '{}'.", keyword),
+                            term: keyword,
+                        }),
+                    },
+                    _ => unreachable!(),
+                };
+                if let Err(e) = result {
+                    diagnostics.push(Diagnostic::Error(e.with_context(&*kb)));
+                }
+            }
+        }
+
+        for source in parsed {
+            diagnostics.append(&mut load_source(source, &mut kb));
+        }
+
         // NOTE(gj): need to bomb out before rewriting shorthand rules to avoid emitting
         // correct-but-unhelpful errors, e.g., when there's an invalid `relations` declaration that
         // will result in a second error when rewriting a shorthand rule involving the relation
@@ -134,6 +229,19 @@ impl Polar {
             return diagnostics;
         }
 
+        // Now that every module is loaded, point each call at the rule it refers to.
+        diagnostics.append(
+            &mut kb
+                .resolve_module_names()
+                .into_iter()
+                .map(|e| Diagnostic::Error(e.with_context(&*kb)))
+                .collect(),
+        );
+
+        // Analyze resource blocks while the KB only holds the rules written in the policy, but only
+        // report the results if the shorthand rules rewrite cleanly.
+        let mut resource_block_warnings = check_resource_block_usage(&kb);
+
         // Rewrite shorthand rules in resource blocks before validating rule types.
         diagnostics.append(
             &mut kb
@@ -154,6 +262,8 @@ impl Polar {
             return diagnostics;
         }
 
+        diagnostics.append(&mut resource_block_warnings);
+
         // Generate appropriate rule_type definitions using the types contained in policy resource
         // blocks.
         kb.create_resource_specific_rule_types();
@@ -168,6 +278,8 @@ impl Polar {
             }
         }
 
+        diagnostics.append(&mut check_unreachable_deny_rules(&kb));
+
         // Check for has_permission calls alongside resource block definitions
         if let Some(w) = check_resource_blocks_missing_has_permission(&kb) {
             diagnostics.push(Diagnostic::Warning(w.with_context(&*kb)))
@@ -207,6 +319,51 @@ impl Polar {
         Ok(())
     }
 
+    /// Restore a `Polar` from a snapshot produced by `export_snapshot`.
+    ///
+    /// Constants registered when the snapshot was exported must be registered again before
+    /// querying.
+    pub fn from_snapshot(bytes: &[u8]) -> PolarResult<Self> {
+        let polar = Self::new();
+        {
+            let mut kb = polar.kb.write().unwrap();
+            let snapshot = Snapshot::decode(bytes, &kb)?;
+            kb.import_snapshot(snapshot)?;
+        }
+        Ok(polar)
+    }
+
+    /// Serialize the loaded policy into a snapshot that can be restored with `from_snapshot` or
+    /// `load_snapshot` without parsing, rewriting or validating the policy again.
+    pub fn export_snapshot(&self) -> PolarResult<Vec<u8>> {
+        self.kb.read().unwrap().export_snapshot().encode()
+    }
+
+    /// Load a snapshot produced by `export_snapshot` into the KB.
+    ///
+    /// Like `load`, this can only be called once. Since the policy isn't validated again, a
+    /// warning is emitted for each constant that was registered when the snapshot was exported
+    /// but hasn't been registered yet.
+    pub fn load_snapshot(&self, bytes: &[u8]) -> PolarResult<()> {
+        let mut kb = self.kb.write().unwrap();
+        if kb.has_rules() {
+            let msg = MULTIPLE_LOAD_ERROR_MSG.to_owned();
+            return Err(RuntimeError::FileLoading { msg }.with_context(&*kb));
+        }
+
+        let snapshot = Snapshot::decode(bytes, &kb)?;
+        let constants = kb.import_snapshot(snapshot)?;
+        let missing = constants.into_iter().filter(|name| !kb.is_constant(name));
+        self.messages.extend(missing.map(|name| Message {
+            kind: MessageKind::Warning,
+            msg: format!(
+                "The policy snapshot was exported with the constant `{}` registered, but it has not been registered.",
+                name
+            ),
+        }));
+        Ok(())
+    }
+
     // Used in integration tests
     pub fn load_str(&self, src: &str) -> PolarResult<()> {
         self.load(vec![Source {
@@ -245,6 +402,7 @@ impl Polar {
     pub fn new_query_from_term(&self, mut term: Term, trace: bool) -> Query {
         {
             let mut kb = self.kb.write().unwrap();
+            term = deny_precedence(term, &kb);
             term = rewrite_term(term, &mut kb);
         }
         let query = Goal::Query { term: term.clone() };
@@ -288,6 +446,72 @@ impl Polar {
             .map_err(|e| e.with_context(&*self.kb.read().unwrap()))
     }
 
+    /// Expand `name`, a permission or role, on `resource` into the tree of actors that have it,
+    /// following the shorthand rules and relations in the resource blocks. `run_query` runs each
+    /// query to completion for the host and returns its results along with their traces.
+    pub fn expand<F, E>(&self, resource: Term, name: &str, run_query: F) -> Result<UsersetTree, E>
+    where
+        F: FnMut(Query) -> Result<Answers, E>,
+        E: From<PolarError>,
+    {
+        expand(self, resource, name, run_query)
+    }
+
+    /// Where each rule applied in `trace` is defined, in the order they were first applied.
+    /// Rules that weren't parsed from a loaded source are left out.
+    pub fn rule_spans(&self, trace: &Trace) -> Vec<RuleSpan> {
+        let kb = self.kb.read().unwrap();
+        let mut spans: Vec<RuleSpan> = vec![];
+        for span in trace
+            .rules()
+            .into_iter()
+            .filter_map(|rule| RuleSpan::new(&kb, rule))
+        {
+            if !spans.contains(&span) {
+                spans.push(span);
+            }
+        }
+        spans
+    }
+
+    /// Add a fact to the relationship store queried by the built-in `relationship` predicate.
+    pub fn add_relationship(&self, subject: Term, relation: &str, object: Term) {
+        let relationship = Relationship::new(subject, relation, object);
+        self.kb.write().unwrap().relationships.add(relationship);
+    }
+
+    /// Add the facts from a JSON array of `{"subject": ..., "relation": ..., "object": ...}`
+    /// objects to the relationship store.
+    pub fn load_relationships_json(&self, json: &str) -> PolarResult<()> {
+        self.kb.write().unwrap().relationships.load_json(json)
+    }
+
+    /// Add the facts from `subject,relation,object` CSV lines to the relationship store.
+    pub fn load_relationships_csv(&self, csv: &str) -> PolarResult<()> {
+        self.kb.write().unwrap().relationships.load_csv(csv)
+    }
+
+    /// Remove every fact from the relationship store.
+    pub fn clear_relationships(&self) {
+        self.kb.write().unwrap().relationships.clear();
+    }
+
+    /// Compute the role × permission matrix of every resource block that declares permissions.
+    pub fn permission_matrices(&self) -> Vec<PermissionMatrix> {
+        permission_matrices(&self.kb.read().unwrap().resource_blocks)
+    }
+
+    /// Draw a diagram of the resource blocks, optionally with the call graph of all loaded rules.
+    pub fn model_diagram(&self, rule_calls: bool) -> ModelDiagram {
+        let kb = self.kb.read().unwrap();
+        let mut diagram = ModelDiagram::new(&kb.resource_blocks);
+        if rule_calls {
+            let rules = kb.get_rules().values().flat_map(|rule| rule.rules.values());
+            diagram.add_rule_calls(rules.map(AsRef::as_ref));
+        }
+        diagram
+    }
+
     // TODO(@gkaemmer): this is a hack and should not be used for similar cases.
     // Ideally, we'd have a single "configuration" entrypoint for both the Polar
     // and Query types.
@@ -27,6 +27,16 @@ impl Query {
         self.vm.set_logging_options(rust_log, polar_log);
     }
 
+    /// Override the query timeout for this query. `0` disables the timeout.
+    pub fn set_query_timeout_ms(&mut self, timeout_ms: u64) {
+        self.vm.set_query_timeout_ms(timeout_ms);
+    }
+
+    /// Override the maximum goal stack size for this query.
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.vm.set_stack_limit(limit);
+    }
+
     /// Runnable lifecycle
     ///
     /// 1. Get Runnable A from the top of the Runnable stack, defaulting to the VM.
@@ -0,0 +1,501 @@
+//! An in-memory store of `(subject, relation, object)` facts.
+//!
+//! Policies query the store with the built-in `relationship(subject, relation, object)` predicate,
+//! e.g., `has_role(user: User, "member", org: Org) if relationship(user, "member", org);`. This
+//! lets a policy run without any host methods to look up relationship data, which is handy for
+//! prototyping, tests and the CLI.
+//!
+//! Each end of a fact is either a plain value (`"alice"`, `42`) or a reference to an application
+//! object by type and ID, stored as the pattern `Type{id: ...}`. Matching an argument against a
+//! reference is an `isa` check, so references work the same way for application instances and for
+//! partials: during partial evaluation, `relationship(actor, "member", org)` constrains `actor` to
+//! `_this matches User{} and _this.id = 1`, which data filtering turns into a filter on `id`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use super::error::{OperationalError, PolarResult};
+use super::terms::*;
+use super::vm::{Goal, Goals};
+
+/// The name of the built-in predicate that queries the store.
+pub const RELATIONSHIP_PREDICATE: &str = "relationship";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Relationship {
+    pub subject: Term,
+    pub relation: String,
+    pub object: Term,
+}
+
+impl Relationship {
+    pub fn new(subject: Term, relation: &str, object: Term) -> Self {
+        Self {
+            subject,
+            relation: relation.to_owned(),
+            object,
+        }
+    }
+
+    /// A term that unifies `args` with this fact.
+    pub(crate) fn matches(&self, args: &[Term]) -> Term {
+        Term::new_temporary(Value::Expression(Operation {
+            operator: Operator::And,
+            args: vec![
+                end_matches(&args[0], &self.subject),
+                end_matches(
+                    &args[1],
+                    &Term::new_temporary(Value::String(self.relation.clone())),
+                ),
+                end_matches(&args[2], &self.object),
+            ],
+        }))
+    }
+
+    /// The goals that unify `args` with this fact.
+    fn goals(&self, args: &[Term]) -> Goals {
+        vec![
+            Goal::TraceStackPush,
+            Goal::Query {
+                term: self.matches(args),
+            },
+            Goal::TraceStackPop,
+        ]
+    }
+}
+
+impl fmt::Display for Relationship {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "relationship({}, \"{}\", {})",
+            self.subject, self.relation, self.object
+        )
+    }
+}
+
+/// A reference to an application object: the pattern `Type{id: id}`.
+pub fn reference(type_name: &str, id: Term) -> Term {
+    let mut fields = BTreeMap::new();
+    fields.insert(Symbol::new("id"), id);
+    Term::new_temporary(Value::Pattern(Pattern::Instance(InstanceLiteral {
+        tag: Symbol::new(type_name),
+        fields: Dictionary { fields },
+    })))
+}
+
+fn end_matches(arg: &Term, end: &Term) -> Term {
+    let operator = match end.value() {
+        Value::Pattern(_) => Operator::Isa,
+        _ => Operator::Unify,
+    };
+    Term::new_temporary(Value::Expression(Operation {
+        operator,
+        args: vec![arg.clone(), end.clone()],
+    }))
+}
+
+/// Whether an argument whose value is `arg` could match `end`. Only string arguments are
+/// compared; anything else is left to unification.
+fn could_match(arg: &Term, end: &Value) -> bool {
+    match arg.value() {
+        Value::String(_) => end == arg.value(),
+        _ => true,
+    }
+}
+
+fn parse_error<T>(msg: String) -> PolarResult<T> {
+    Err(OperationalError::Serialization { msg }.into())
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RelationshipStore {
+    relationships: Vec<Relationship>,
+}
+
+impl RelationshipStore {
+    pub fn add(&mut self, relationship: Relationship) {
+        self.relationships.push(relationship);
+    }
+
+    pub fn clear(&mut self) {
+        self.relationships.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.relationships.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.relationships.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Relationship> {
+        self.relationships.iter()
+    }
+
+    /// Add the facts from a JSON array of objects with `subject`, `relation` and `object` keys.
+    /// Each end is a string, number or boolean, or an object `{"type": "User", "id": 1}` for a
+    /// reference. Nothing is added unless every fact is valid.
+    pub fn load_json(&mut self, json: &str) -> PolarResult<()> {
+        let records = match serde_json::from_str(json) {
+            Ok(JsonValue::Array(records)) => records,
+            Ok(_) => return parse_error("relationships must be a JSON array".to_owned()),
+            Err(e) => return parse_error(format!("invalid relationships JSON: {}", e)),
+        };
+        let relationships = records
+            .iter()
+            .enumerate()
+            .map(|(i, record)| {
+                json_relationship(record).map_err(|msg| OperationalError::Serialization {
+                    msg: format!("relationship {}: {}", i, msg),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.relationships.extend(relationships);
+        Ok(())
+    }
+
+    /// Add the facts from CSV with one `subject,relation,object` line per fact. An optional
+    /// header line and blank lines are skipped. An unquoted end of the form `Type:id` is a
+    /// reference, an unquoted number is a number, and anything else is a string. Nothing is added
+    /// unless every line is valid.
+    pub fn load_csv(&mut self, csv: &str) -> PolarResult<()> {
+        let mut relationships = vec![];
+        for (i, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || (i == 0 && line.eq_ignore_ascii_case("subject,relation,object")) {
+                continue;
+            }
+            let relationship = csv_fields(line).and_then(|fields| match &fields[..] {
+                [subject, (relation, _), object] => Ok(Relationship::new(
+                    csv_end(subject),
+                    relation,
+                    csv_end(object),
+                )),
+                _ => Err(format!("expected 3 fields, found {}", fields.len())),
+            });
+            match relationship {
+                Ok(relationship) => relationships.push(relationship),
+                Err(msg) => return parse_error(format!("line {}: {}", i + 1, msg)),
+            }
+        }
+        self.relationships.extend(relationships);
+        Ok(())
+    }
+
+    /// One alternative per fact that could match the arguments of a `relationship` call. `args`
+    /// are the arguments as called, and `values` their dereferenced values, which are used to
+    /// skip facts that can't match.
+    pub(crate) fn alternatives(&self, args: &[Term], values: &[Term]) -> Vec<Goals> {
+        self.relationships
+            .iter()
+            .filter(|r| {
+                could_match(&values[0], r.subject.value())
+                    && could_match(&values[1], &Value::String(r.relation.clone()))
+                    && could_match(&values[2], r.object.value())
+            })
+            .map(|r| r.goals(args))
+            .collect()
+    }
+}
+
+fn json_end(value: &JsonValue) -> Result<Term, String> {
+    let value = match value {
+        JsonValue::String(s) => Value::String(s.clone()),
+        JsonValue::Bool(b) => Value::Boolean(*b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Value::Number(Numeric::Integer(i)),
+            None => Value::Number(Numeric::Float(n.as_f64().unwrap_or(f64::NAN))),
+        },
+        JsonValue::Object(object) => match (object.get("type"), object.get("id")) {
+            (Some(JsonValue::String(type_name)), Some(id)) if object.len() == 2 => {
+                return Ok(reference(type_name, json_end(id)?))
+            }
+            _ => return Err("a reference must be an object with `type` and `id` keys".to_owned()),
+        },
+        _ => return Err(format!("unsupported value {}", value)),
+    };
+    Ok(Term::new_temporary(value))
+}
+
+fn json_relationship(record: &JsonValue) -> Result<Relationship, String> {
+    let field = |name| {
+        record
+            .get(name)
+            .ok_or_else(|| format!("missing `{}`", name))
+    };
+    let relation = match field("relation")? {
+        JsonValue::String(relation) => relation,
+        _ => return Err("`relation` must be a string".to_owned()),
+    };
+    Ok(Relationship::new(
+        json_end(field("subject")?)?,
+        relation,
+        json_end(field("object")?)?,
+    ))
+}
+
+/// Split a CSV line into fields, each with whether it was quoted. Quoted fields may contain
+/// commas and `""` for a quote.
+fn csv_fields(line: &str) -> Result<Vec<(String, bool)>, String> {
+    let mut fields = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek() == Some(&' ') {
+            chars.next();
+        }
+        let mut field = String::new();
+        let quoted = chars.peek() == Some(&'"');
+        if quoted {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quoted field".to_owned()),
+                }
+            }
+            while chars.peek() == Some(&' ') {
+                chars.next();
+            }
+        } else {
+            while let Some(c) = chars.peek().filter(|c| **c != ',') {
+                field.push(*c);
+                chars.next();
+            }
+            field = field.trim_end().to_owned();
+        }
+        fields.push((field, quoted));
+        match chars.next() {
+            None => return Ok(fields),
+            Some(',') => continue,
+            Some(c) => return Err(format!("unexpected `{}` after quoted field", c)),
+        }
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn csv_value(s: &str) -> Term {
+    match s.parse::<i64>() {
+        Ok(i) => Term::new_temporary(Value::Number(Numeric::Integer(i))),
+        Err(_) => Term::new_temporary(Value::String(s.to_owned())),
+    }
+}
+
+fn csv_end((field, quoted): &(String, bool)) -> Term {
+    if *quoted {
+        return Term::new_temporary(Value::String(field.clone()));
+    }
+    match field.split_once(':') {
+        Some((type_name, id)) if is_identifier(type_name) && !id.is_empty() => {
+            reference(type_name, csv_value(id))
+        }
+        _ => csv_value(field),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::events::QueryEvent;
+    use crate::formatting::ToPolarString;
+    use crate::polar::Polar;
+    use crate::query::Query;
+
+    fn results(query: &mut Query, var: &str) -> Vec<String> {
+        let mut results = vec![];
+        loop {
+            match query.next_event().unwrap() {
+                QueryEvent::Result { bindings, .. } => {
+                    results.push(bindings[&Symbol::new(var)].to_polar())
+                }
+                QueryEvent::Done { .. } => return results,
+                event => panic!("unexpected event: {:?}", event),
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_json() {
+        let mut store = RelationshipStore::default();
+        store
+            .load_json(
+                r#"[
+                    {"subject": {"type": "User", "id": 1}, "relation": "member", "object": {"type": "Org", "id": "acme"}},
+                    {"subject": "alice", "relation": "friend", "object": 2.5}
+                ]"#,
+            )
+            .unwrap();
+        let relationships: Vec<_> = store.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            relationships,
+            vec![
+                r#"relationship(User{id: 1}, "member", Org{id: "acme"})"#,
+                r#"relationship("alice", "friend", 2.5)"#,
+            ]
+        );
+
+        for (json, msg) in [
+            ("{}", "JSON array"),
+            (r#"[{"subject": "a", "object": "b"}]"#, "missing `relation`"),
+            (
+                r#"[{"subject": {"type": "User"}, "relation": "r", "object": "b"}]"#,
+                "`type` and `id`",
+            ),
+            (
+                r#"[{"subject": null, "relation": "r", "object": "b"}]"#,
+                "unsupported",
+            ),
+        ] {
+            let err = store.load_json(json).unwrap_err().to_string();
+            assert!(err.contains(msg), "{}", err);
+        }
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn test_load_csv() {
+        let mut store = RelationshipStore::default();
+        store
+            .load_csv(
+                "subject,relation,object\n\
+                 User:1,member,Org:acme\n\
+                 \n\
+                 alice, friend , 42\n\
+                 \"User:1\",\"a, b\",\"say \"\"hi\"\"\"\n",
+            )
+            .unwrap();
+        let relationships: Vec<_> = store.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            relationships,
+            vec![
+                r#"relationship(User{id: 1}, "member", Org{id: "acme"})"#,
+                r#"relationship("alice", "friend", 42)"#,
+                r#"relationship("User:1", "a, b", "say "hi"")"#,
+            ]
+        );
+
+        let err = store.load_csv("a,b,c\na,b\n").unwrap_err().to_string();
+        assert!(
+            err.contains("line 2: expected 3 fields, found 2"),
+            "{}",
+            err
+        );
+        let err = store.load_csv("a,\"b,c\n").unwrap_err().to_string();
+        assert!(err.contains("line 1: unterminated"), "{}", err);
+        assert_eq!(store.len(), 3);
+    }
+
+    #[test]
+    fn test_matches() {
+        let subject = reference(
+            "User",
+            Term::new_temporary(Value::Number(Numeric::Integer(1))),
+        );
+        let object = Term::new_temporary(Value::String("acme".to_owned()));
+        let relationship = Relationship::new(subject.clone(), "member", object.clone());
+        let args =
+            ["x", "r", "y"].map(|name| Term::new_temporary(Value::Variable(Symbol::new(name))));
+        let term = relationship.matches(&args);
+        let ends = match term.value() {
+            Value::Expression(Operation {
+                operator: Operator::And,
+                args,
+            }) => args
+                .iter()
+                .map(|arg| match arg.value() {
+                    Value::Expression(op) => (op.operator, op.args[1].clone()),
+                    value => panic!("unexpected term: {:?}", value),
+                })
+                .collect::<Vec<_>>(),
+            value => panic!("unexpected term: {:?}", value),
+        };
+        let relation = Term::new_temporary(Value::String("member".to_owned()));
+        assert_eq!(
+            ends,
+            vec![
+                (Operator::Isa, subject),
+                (Operator::Unify, relation),
+                (Operator::Unify, object),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_relationships() {
+        let polar = Polar::new();
+        polar
+            .load_str(
+                r#"member(user, org) if relationship(user, "member", org);
+                   relationship("carol", "member", "acme");"#,
+            )
+            .unwrap();
+        polar
+            .load_relationships_csv("alice,member,acme\nbob,member,initech\nalice,owner,acme\n")
+            .unwrap();
+
+        let mut query = polar.new_query(r#"member(x, "acme")"#, false).unwrap();
+        assert_eq!(results(&mut query, "x"), vec![r#""alice""#, r#""carol""#]);
+        let mut query = polar
+            .new_query(r#"relationship("alice", r, _)"#, false)
+            .unwrap();
+        assert_eq!(results(&mut query, "r"), vec![r#""member""#, r#""owner""#]);
+
+        polar.clear_rules();
+        let mut query = polar
+            .new_query(r#"relationship(x, "member", "initech")"#, false)
+            .unwrap();
+        assert_eq!(results(&mut query, "x"), vec![r#""bob""#]);
+
+        polar.clear_relationships();
+        let mut query = polar
+            .new_query(r#"relationship(x, "member", "initech")"#, false)
+            .unwrap();
+        assert!(results(&mut query, "x").is_empty());
+    }
+
+    #[test]
+    fn test_query_relationships_partial() {
+        let polar = Polar::new();
+        polar.add_relationship(
+            reference(
+                "User",
+                Term::new_temporary(Value::Number(Numeric::Integer(1))),
+            ),
+            "member",
+            reference("Org", Term::new_temporary(Value::String("acme".to_owned()))),
+        );
+        polar
+            .load_str(r#"member(user: User, org: Org) if relationship(user, "member", org);"#)
+            .unwrap();
+
+        let mut query = polar.new_query(r#"member(x, y)"#, false).unwrap();
+        let event = query.next_event().unwrap();
+        let bindings = match event {
+            QueryEvent::Result { bindings, .. } => bindings,
+            event => panic!("unexpected event: {:?}", event),
+        };
+        assert_eq!(
+            bindings[&Symbol::new("x")].to_polar(),
+            "_this matches User{} and _this.id = 1"
+        );
+        assert_eq!(
+            bindings[&Symbol::new("y")].to_polar(),
+            r#"_this matches Org{} and _this.id = "acme""#
+        );
+    }
+}
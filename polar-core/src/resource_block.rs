@@ -1,12 +1,18 @@
 use std::collections::{HashMap, HashSet};
 
-use super::error::ValidationError;
+use serde::{Deserialize, Serialize};
+
+use super::error::{PolarResult, ValidationError};
+use super::folder::{fold_term, Folder};
 use super::kb::KnowledgeBase;
+use super::parser::Line;
 use super::rules::*;
+use super::sources::Source;
 use super::terms::*;
 
 pub const ACTOR_UNION_NAME: &str = "Actor";
 pub const RESOURCE_UNION_NAME: &str = "Resource";
+pub const GLOBAL_BLOCK_NAME: &str = "global";
 
 type Result<T> = core::result::Result<T, ValidationError>;
 
@@ -23,7 +29,41 @@ type Result<T> = core::result::Result<T, ValidationError>;
 #[derive(Clone, Debug, PartialEq)]
 pub enum Production {
     Declaration((Term, Term)), // (Symbol, List<String> | Dict<Symbol, Symbol>)
-    ShorthandRule(Term, (Term, Option<(Term, Term)>)), // (String, (String, Option<(Symbol, String)>))
+    ShorthandRule(Option<Term>, Term, (Vec<Implier>, Option<Term>)), // (Option<Symbol>, String, (Vec<Implier>, Option<Term>))
+}
+
+/// A role, permission, or relation in the body of a shorthand rule.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Implier {
+    /// Declared in the current block, or in a related block if followed by a relation. E.g.,
+    /// `"owner"` or `"owner" on "parent"`.
+    Resource(Term, Option<(Term, Term)>), // (String, Option<(Symbol, String)>)
+    /// A role declared in the `global` block. E.g., `global "admin"`.
+    Global(Term, Term), // (Symbol, String)
+}
+
+fn validate_global_keyword(keyword: &Term) -> Result<()> {
+    if keyword.value().as_symbol().unwrap().0 != GLOBAL_BLOCK_NAME {
+        let msg = format!(
+            "Unexpected keyword '{}' before role. Did you mean 'global'?",
+            keyword
+        );
+        let term = keyword.clone();
+        return Err(ValidationError::ResourceBlock { msg, term });
+    }
+    Ok(())
+}
+
+fn validate_deny_keyword(keyword: &Term) -> Result<()> {
+    if keyword.value().as_symbol().unwrap().0 != "deny" {
+        let msg = format!(
+            "Unexpected keyword '{}' before shorthand rule. Did you mean 'deny'?",
+            keyword
+        );
+        let term = keyword.clone();
+        return Err(ValidationError::ResourceBlock { msg, term });
+    }
+    Ok(())
 }
 
 fn validate_relation_keyword(keyword: &Term) -> Result<()> {
@@ -82,6 +122,8 @@ fn block_type_from_keyword(keyword: Option<Term>, resource: &Term) -> Result<Blo
                 term: keyword.clone(),
             }),
         }
+    } else if resource.value().as_symbol().unwrap().0 == GLOBAL_BLOCK_NAME {
+        Ok(BlockType::Global)
     } else {
         // TODO(gj): add `resource` into this message -- e.g., ("Expected `actor {resource}` or
         // `resource {resource}` ...", resource=resource).
@@ -156,9 +198,14 @@ pub fn resource_block_from_productions(
                     Err(e) => errors.push(e),
                 }
             }
-            Production::ShorthandRule(head, body) => {
+            Production::ShorthandRule(deny, head, (body, condition)) => {
                 // TODO(gj): Warn the user on duplicate rule definitions.
-                shorthand_rules.push(ShorthandRule { head, body });
+                shorthand_rules.push(ShorthandRule {
+                    head,
+                    body,
+                    condition,
+                    deny,
+                });
             }
         }
     }
@@ -176,7 +223,7 @@ pub fn resource_block_from_productions(
     )
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Declaration {
     Role,
     Permission,
@@ -184,27 +231,52 @@ pub enum Declaration {
     Relation(Term),
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ShorthandRule {
     /// `Term` is a `String`. E.g., `"member"` in `"member" if "owner";`.
     pub head: Term,
-    /// The first `Term` is the 'implier' `String`, e.g., `"owner"` in `"member" if "owner";`. The
-    /// `Option` is the optional 'relation' `Symbol` and `String`, e.g., `on "parent"` in `"member"
-    /// if "owner" on "parent";`.
-    pub body: (Term, Option<(Term, Term)>),
+    /// The 'impliers' joined by `or`, e.g., `"owner"` and `"admin" on "parent"` in `"member" if
+    /// "owner" or "admin" on "parent";`. The rule holds if any of them does.
+    pub body: Vec<Implier>,
+    /// The optional condition after `and`, e.g., `resource.is_draft` in `"edit" if "member" and
+    /// resource.is_draft;`.
+    pub condition: Option<Term>,
+    /// The optional `deny` keyword (a `Symbol`), e.g., in `deny "read" if "contractor";`. A deny
+    /// shorthand rule is rewritten to a `deny` rule for its head permission.
+    pub deny: Option<Term>,
 }
 
 impl ShorthandRule {
     pub fn as_rule(&self, resource_name: &Term, blocks: &ResourceBlocks) -> Result<Rule> {
-        let Self { head, body } = self;
+        let Self {
+            head,
+            body,
+            condition,
+            deny,
+        } = self;
         // Copy SourceInfo from head of shorthand rule.
         // TODO(gj): assert these can only be None in tests.
         let src_id = head.get_source_id().unwrap_or(0);
         let (start, end) = head.span().unwrap_or((0, 0));
 
-        let name = blocks.get_rule_name_for_declaration_in_resource_block(head, resource_name)?;
+        let name = if let Some(keyword) = deny {
+            validate_deny_keyword(keyword)?;
+            match blocks.get_declaration_in_resource_block(head, resource_name)? {
+                Declaration::Permission => sym!("deny"),
+                _ => {
+                    let msg = format!(
+                        "{}: Only permissions can be denied, but {} is not declared as a permission.",
+                        resource_name, head
+                    );
+                    let term = head.clone();
+                    return Err(ValidationError::ResourceBlock { msg, term });
+                }
+            }
+        } else {
+            blocks.get_rule_name_for_declaration_in_resource_block(head, resource_name)?
+        };
         let params = shorthand_rule_head_to_params(head, resource_name);
-        let body = shorthand_rule_body_to_rule_body(body, resource_name, blocks)?;
+        let body = shorthand_rule_body_to_rule_body(body, condition, resource_name, blocks)?;
 
         Ok(Rule::new_from_parser(
             src_id, start, end, name, params, body,
@@ -225,11 +297,13 @@ impl Declaration {
 }
 
 // TODO(gj): this will go away when we have true unions in the future.
-/// Resource blocks can either be declared as actors or resources.
+/// Resource blocks can either be declared as actors or resources. The `global` block declares
+/// roles that aren't scoped to any resource.
 #[derive(Clone, Debug, PartialEq)]
 pub enum BlockType {
     Actor,
     Resource,
+    Global,
 }
 
 /// Successfully-parsed but not-yet-fully-validated-or-persisted resource block.
@@ -243,7 +317,7 @@ pub struct ResourceBlock {
     pub shorthand_rules: Vec<ShorthandRule>,
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct ResourceBlocks {
     /// Map from resource (`Symbol`) to the declarations in that resource's block.
     declarations: HashMap<Term, Declarations>,
@@ -255,6 +329,8 @@ pub struct ResourceBlocks {
     /// Set of all resource block types declared as resources. Internally treated like a union type
     /// where all declared types are members of the union.
     pub resources: HashSet<Term>,
+    /// Roles declared in the `global` block, if there is one.
+    global: Option<Declarations>,
 }
 
 impl ResourceBlocks {
@@ -264,6 +340,7 @@ impl ResourceBlocks {
             shorthand_rules: HashMap::new(),
             actors: HashSet::new(),
             resources: HashSet::new(),
+            global: None,
         }
     }
 
@@ -272,6 +349,7 @@ impl ResourceBlocks {
         self.shorthand_rules.clear();
         self.actors.clear();
         self.resources.clear();
+        self.global = None;
     }
 
     fn add(
@@ -290,9 +368,36 @@ impl ResourceBlocks {
                 self.resources.insert(resource)
             }
             BlockType::Resource => self.resources.insert(resource),
+            BlockType::Global => unreachable!("the global block is added with add_global"),
         };
     }
 
+    fn add_global(&mut self, declarations: Declarations) {
+        self.global = Some(declarations);
+    }
+
+    /// Roles declared in the `global` block.
+    pub fn global_roles(&self) -> Vec<&Term> {
+        self.global.iter().flat_map(HashMap::keys).collect()
+    }
+
+    /// Look up `role` in the `global` block.
+    fn get_global_role(&self, role: &Term) -> Result<()> {
+        if self
+            .global
+            .as_ref()
+            .is_some_and(|roles| roles.contains_key(role))
+        {
+            Ok(())
+        } else {
+            let msg = format!("Undeclared global role {} referenced in rule. Did you mean to declare it in the global block: `global {{ roles = [{}]; }}`?", role, role);
+            Err(ValidationError::ResourceBlock {
+                msg,
+                term: role.clone(),
+            })
+        }
+    }
+
     fn exists(&self, resource: &Term) -> bool {
         self.declarations.contains_key(resource)
     }
@@ -335,6 +440,46 @@ impl ResourceBlocks {
         }
     }
 
+    /// Follow a relation path such as `"project.org"` from `resource` block, one declared relation
+    /// at a time. Returns each hop's relation (a `String` spanned like `relation`) along with the
+    /// type it leads to. A path without dots is a single hop.
+    pub fn get_relation_path_in_resource_block(
+        &self,
+        relation: &Term,
+        resource: &Term,
+    ) -> Result<Vec<(Term, &Term)>> {
+        let path = relation
+            .value()
+            .as_string()
+            .map_err(|_| ValidationError::ResourceBlock {
+                term: relation.clone(),
+                msg: format!("Expected a relation string; got: {}", relation),
+            })?;
+
+        let mut hops = vec![];
+        let mut current = resource;
+        for name in path.split('.') {
+            if name.is_empty() {
+                return Err(ValidationError::ResourceBlock {
+                    term: relation.clone(),
+                    msg: format!("Invalid relation path {}: relation names must be separated by single dots.", relation),
+                });
+            }
+            if !hops.is_empty() && !self.exists(current) {
+                let msg = format!("{}: Relation path {} passes through '{}', but no such resource block exists. Try declaring one: `resource {} {{}}`", resource, relation, current, current);
+                return Err(ValidationError::ResourceBlock {
+                    msg,
+                    term: relation.clone(),
+                });
+            }
+            let hop = relation.clone_with_value(value!(name));
+            let related = self.get_relation_type_in_resource_block(&hop, current)?;
+            hops.push((hop, related));
+            current = related;
+        }
+        Ok(hops)
+    }
+
     /// Look up `declaration` in `resource` block and return the appropriate rule name for
     /// rewriting.
     fn get_rule_name_for_declaration_in_resource_block(
@@ -502,13 +647,87 @@ fn resource_name_as_var(resource_name: &Term, related: bool) -> Value {
     value!(sym!(lowercased))
 }
 
-/// Turn a shorthand rule body into an `And`-wrapped call (for a local rule) or pair of calls (for
-/// a cross-resource rule).
+/// Renames the `resource` variable in a shorthand rule's condition to the variable the rewritten
+/// rule binds the resource to.
+struct ResourceVarRenamer {
+    resource_var: Symbol,
+}
+
+impl Folder for ResourceVarRenamer {
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        if v.0 == "resource" {
+            self.resource_var.clone()
+        } else {
+            v
+        }
+    }
+}
+
+/// Turn a shorthand rule body into an `And`-wrapped rule body: the rewritten implier, or an `Or` of
+/// the rewritten impliers if there's more than one, followed by the condition, if any.
 fn shorthand_rule_body_to_rule_body(
-    (implier, relation): &(Term, Option<(Term, Term)>),
+    impliers: &[Implier],
+    condition: &Option<Term>,
     resource_name: &Term,
     blocks: &ResourceBlocks,
 ) -> Result<Term> {
+    let mut bodies = vec![];
+    for implier in impliers {
+        bodies.push(implier_to_rule_body(implier, resource_name, blocks)?);
+    }
+
+    let mut body = if bodies.len() == 1 {
+        bodies.remove(0)
+    } else {
+        // The parser guarantees at least one implier.
+        let first = &bodies[0];
+        let alternatives = first.clone_with_value(Value::Expression(Operation {
+            operator: Operator::Or,
+            args: bodies.clone(),
+        }));
+        first.clone_with_value(value!(op!(And, alternatives)))
+    };
+
+    if let Some(condition) = condition {
+        // The condition can refer to the resource as `resource`, whatever the resource variable in
+        // the rewritten rule is named. The actor variable is always named `actor`.
+        let resource_var = match resource_name_as_var(resource_name, false) {
+            Value::Variable(v) => v,
+            _ => unreachable!("resource variable must be a variable"),
+        };
+        let condition = fold_term(condition.clone(), &mut ResourceVarRenamer { resource_var });
+        if let Value::Expression(Operation { args, .. }) = body.mut_value() {
+            args.push(condition);
+        }
+    }
+
+    Ok(body)
+}
+
+/// Turn a shorthand rule implier into an `And`-wrapped call (for a local or global rule) or
+/// series of calls (for a cross-resource rule).
+fn implier_to_rule_body(
+    implier: &Implier,
+    resource_name: &Term,
+    blocks: &ResourceBlocks,
+) -> Result<Term> {
+    let (implier, relation) = match implier {
+        Implier::Resource(implier, relation) => (implier, relation),
+        Implier::Global(keyword, role) => {
+            validate_global_keyword(keyword)?;
+            blocks.get_global_role(role)?;
+
+            // Global roles don't depend on the resource, so the rewritten call is just
+            // `has_role(actor, <role>)`.
+            let role_call = role.clone_with_value(value!(Call {
+                name: sym!("has_role"),
+                args: vec![role.clone_with_value(value!(sym!("actor"))), role.clone()],
+                kwargs: None
+            }));
+            return Ok(role.clone_with_value(value!(op!(And, role_call))));
+        }
+    };
+
     // Create a variable derived from the current block's resource name. E.g., if we're in the
     // `Repo` resource block, the variable name will be `repo`.
     let resource_var = implier.clone_with_value(resource_name_as_var(resource_name, false));
@@ -521,43 +740,68 @@ fn shorthand_rule_body_to_rule_body(
         // ...then we need to validate the keyword...
         validate_relation_keyword(keyword)?;
 
-        // ...and then link the rewritten `<implier>` and `<relation>` rules via a shared variable.
-        // To be clever, we'll name the variable according to the type of the relation, e.g., if
-        // the declared relation is `parent: Org` we'll name the variable `org`.
-        let relation_type = blocks.get_relation_type_in_resource_block(relation, resource_name)?;
-        let relation_type_var =
-            relation.clone_with_value(resource_name_as_var(relation_type, true));
-
-        // For the rewritten `<relation>` call, the rule name will always be `has_relation` and the
-        // arguments, in order, will be: the shared variable we just created above, the
-        // `<relation>` string, and the resource variable we created at the top of the function.
-        // E.g., `vec![org, "parent", repo]`.
-        let relation_call = relation.clone_with_value(value!(Call {
-            name: sym!("has_relation"),
-            args: vec![relation_type_var.clone(), relation.clone(), resource_var],
-            kwargs: None
-        }));
+        // ...and then link the rewritten `<relation>` and `<implier>` rules via shared variables.
+        // `<relation>` may be a path like `"project.org"`, in which case there's a `has_relation`
+        // call and a shared variable per hop. To be clever, we'll name each variable according to
+        // the type of its relation, e.g., if the declared relation is `parent: Org` we'll name the
+        // variable `related_org`.
+        let hops = blocks.get_relation_path_in_resource_block(relation, resource_name)?;
+        let mut var_names = HashSet::new();
+        let mut calls = vec![];
+        let mut subject_var = resource_var;
+        let mut subject_type = resource_name;
+        let mut last_hop = (relation, resource_name);
+        for (hop, relation_type) in &hops {
+            let mut var = resource_name_as_var(relation_type, true);
+            // Resources can be related to resources of the same type, e.g., via `"parent.parent"`,
+            // so number repeated variable names.
+            let name = var.as_symbol().expect("sym").0.clone();
+            let mut n = 1;
+            while !var_names.insert(var.clone()) {
+                n += 1;
+                var = value!(sym!(format!("{}_{}", name, n)));
+            }
+            let relation_type_var = relation.clone_with_value(var);
+
+            // For each hop, the rule name will always be `has_relation` and the arguments, in
+            // order, will be: the shared variable we just created above, the hop's relation
+            // string, and the variable for the resource the hop starts from. E.g.,
+            // `vec![related_org, "parent", repo]`.
+            calls.push(relation.clone_with_value(value!(Call {
+                name: sym!("has_relation"),
+                args: vec![relation_type_var.clone(), hop.clone(), subject_var],
+                kwargs: None
+            })));
+
+            last_hop = (hop, subject_type);
+            subject_var = relation_type_var;
+            subject_type = relation_type;
+        }
 
         // To get the rule name for the rewritten `<implier>` call, we need to figure out what type
         // (role, permission, or relation) `<implier>` is declared as _in the resource block
         // related to the current resource block via `<relation>`_. That is, given
         // `resource Repo { roles=["writer"]; relations={parent:Org}; "writer" if "owner" on "parent"; }`,
         // we need to find out whether `"owner"` is declared as a role, permission, or relation in
-        // the `Org` resource block. The args for the rewritten `<implier>` call are, in order: the
-        // actor variable, the `<implier>` string, and the shared variable we created above for the
-        // related type.
-        let implier_call = implier.clone_with_value(value!(Call {
+        // the `Org` resource block. For a path, that's the block at the end of the last hop. The
+        // args for the rewritten `<implier>` call are, in order: the actor variable, the
+        // `<implier>` string, and the shared variable for the last hop.
+        let (last_relation, last_resource) = last_hop;
+        calls.push(implier.clone_with_value(value!(Call {
             name: blocks.get_rule_name_for_declaration_in_related_resource_block(
                 implier,
-                relation,
-                resource_name
+                last_relation,
+                last_resource
             )?,
-            args: vec![actor_var, implier.clone(), relation_type_var],
+            args: vec![actor_var, implier.clone(), subject_var],
             kwargs: None
-        }));
+        })));
 
         // Wrap the rewritten `<relation>` and `<implier>` calls in an `And`.
-        Ok(implier.clone_with_value(value!(op!(And, relation_call, implier_call))))
+        Ok(implier.clone_with_value(Value::Expression(Operation {
+            operator: Operator::And,
+            args: calls,
+        })))
     } else {
         // If there's no `<relation>` (e.g., `... if "writer";`), we're dealing with a local rule,
         // and the rewriting process is a bit simpler. To get the appropriate rule name, we look up
@@ -612,9 +856,22 @@ impl ResourceBlock {
     pub fn add_to_kb(self, kb: &mut KnowledgeBase) -> Vec<ValidationError> {
         let mut errors = vec![];
         // Check that resource block's resource has been registered as a class.
-        errors.extend(kb.get_registered_class(&self.resource).err());
+        if self.block_type != BlockType::Global {
+            errors.extend(kb.get_registered_class(&self.resource).err());
+        }
+        errors.append(&mut self.add_to_blocks(&mut kb.resource_blocks));
         errors
-            .extend(check_for_duplicate_resource_blocks(&kb.resource_blocks, &self.resource).err());
+    }
+
+    /// Validate and index the block's declarations into `blocks`, without checking the block's
+    /// resource against the classes registered in a KB.
+    pub fn add_to_blocks(self, blocks: &mut ResourceBlocks) -> Vec<ValidationError> {
+        if self.block_type == BlockType::Global {
+            return self.add_global_to_blocks(blocks);
+        }
+
+        let mut errors = vec![];
+        errors.extend(check_for_duplicate_resource_blocks(blocks, &self.resource).err());
 
         let ResourceBlock {
             block_type,
@@ -626,10 +883,37 @@ impl ResourceBlock {
         } = self;
 
         match index_declarations(roles, permissions, relations, &resource) {
-            Ok(declarations) => {
-                kb.resource_blocks
-                    .add(block_type, resource, declarations, shorthand_rules);
-            }
+            Ok(declarations) => blocks.add(block_type, resource, declarations, shorthand_rules),
+            Err(e) => errors.push(e),
+        }
+
+        errors
+    }
+
+    /// The `global` block can only declare roles, which are granted with `has_role(actor, role)`
+    /// rules instead of per-resource ones.
+    fn add_global_to_blocks(self, blocks: &mut ResourceBlocks) -> Vec<ValidationError> {
+        let mut errors = vec![];
+        if blocks.global.is_some() {
+            let msg = "Duplicate declaration of global block.".to_owned();
+            let term = self.resource.clone();
+            errors.push(ValidationError::ResourceBlock { msg, term });
+        }
+        let unsupported = self
+            .permissions
+            .as_ref()
+            .or(self.relations.as_ref())
+            .cloned();
+        let unsupported =
+            unsupported.or_else(|| self.shorthand_rules.first().map(|rule| rule.head.clone()));
+        if let Some(term) = unsupported {
+            let msg = "The global block can only declare roles.".to_owned();
+            errors.push(ValidationError::ResourceBlock { msg, term });
+        }
+
+        match index_declarations(self.roles, None, None, &self.resource) {
+            Ok(declarations) if errors.is_empty() => blocks.add_global(declarations),
+            Ok(_) => {}
             Err(e) => errors.push(e),
         }
 
@@ -637,6 +921,72 @@ impl ResourceBlock {
     }
 }
 
+/// Parse `sources` and index their resource blocks without loading anything into a KB, so that
+/// tooling can inspect a policy without the classes it refers to being registered. Returns the
+/// blocks along with the sources' rules and the rules their shorthand rules expand to. As when
+/// loading, rules in a module have qualified names and calls refer to the rules they resolve to.
+pub fn resource_blocks_from_sources(
+    sources: Vec<Source>,
+) -> PolarResult<(ResourceBlocks, Vec<Rule>)> {
+    // Only used for error context and module bookkeeping.
+    let mut kb = KnowledgeBase::new();
+    let mut blocks = ResourceBlocks::new();
+    let mut rules = vec![];
+    for source in sources {
+        let parsed = kb.parse_source(&source)?;
+        if let Some(error) = parsed.errors.into_iter().next() {
+            return Err(error.with_context(&kb));
+        }
+        for line in parsed.lines {
+            match line {
+                Line::Rule(rule) => rules.push(rule),
+                Line::Module { keyword, names } => {
+                    let module = parsed.module.as_ref();
+                    let errors = kb.modules.add_declaration(module, &keyword, names);
+                    if let Some(error) = errors.into_iter().next() {
+                        return Err(error.with_context(&kb));
+                    }
+                }
+                Line::ResourceBlock {
+                    keyword,
+                    resource,
+                    productions,
+                } => {
+                    let (block, mut errors) =
+                        resource_block_from_productions(keyword, resource, productions);
+                    errors.append(&mut block.add_to_blocks(&mut blocks));
+                    if let Some(error) = errors.into_iter().next() {
+                        return Err(error.with_context(&kb));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    let names = rules.iter().map(|rule| rule.name.clone()).collect();
+    if let Some(error) = kb.modules.validate(&names).into_iter().next() {
+        return Err(error.with_context(&kb));
+    }
+    for rule in &mut rules {
+        let (body, errors) = kb
+            .modules
+            .resolve(&names, &rule.source_info, rule.body.clone());
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error.with_context(&kb));
+        }
+        rule.body = body;
+    }
+    for (resource, shorthand_rules) in &blocks.shorthand_rules {
+        for shorthand_rule in shorthand_rules {
+            let rule = shorthand_rule
+                .as_rule(resource, &blocks)
+                .map_err(|e| e.with_context(&kb))?;
+            rules.push(rule);
+        }
+    }
+    Ok((blocks, rules))
+}
+
 #[cfg(test)]
 mod tests {
     use permute::permute;
@@ -691,7 +1041,12 @@ mod tests {
         );
         let shorthand_rule = ShorthandRule {
             head: term!("reader"),
-            body: (term!("member"), Some((term!(sym!("on")), term!("parent")))),
+            body: vec![Implier::Resource(
+                term!("member"),
+                Some((term!(sym!("on")), term!("parent"))),
+            )],
+            condition: None,
+            deny: None,
         };
         let rewritten_role_role = shorthand_rule
             .as_rule(&term!(sym!("repo")), &blocks)
@@ -719,7 +1074,12 @@ mod tests {
 
         let shorthand_rule = ShorthandRule {
             head: term!("read"),
-            body: (term!("read"), Some((term!(sym!("on")), term!("parent")))),
+            body: vec![Implier::Resource(
+                term!("read"),
+                Some((term!(sym!("on")), term!("parent"))),
+            )],
+            condition: None,
+            deny: None,
         };
         let rewritten_role_role = shorthand_rule.as_rule(&resource, &blocks).unwrap();
 
@@ -739,7 +1099,9 @@ mod tests {
         blocks.add(BlockType::Resource, resource, declarations.unwrap(), vec![]);
         let shorthand_rule = ShorthandRule {
             head: term!("member"),
-            body: (term!("owner"), None),
+            body: vec![Implier::Resource(term!("owner"), None)],
+            condition: None,
+            deny: None,
         };
         let rewritten_role_role = shorthand_rule
             .as_rule(&term!(sym!("Org")), &blocks)
@@ -751,7 +1113,9 @@ mod tests {
 
         let shorthand_rule = ShorthandRule {
             head: term!("invite"),
-            body: (term!("owner"), None),
+            body: vec![Implier::Resource(term!("owner"), None)],
+            condition: None,
+            deny: None,
         };
         let rewritten_permission_role = shorthand_rule
             .as_rule(&term!(sym!("Org")), &blocks)
@@ -763,7 +1127,9 @@ mod tests {
 
         let shorthand_rule = ShorthandRule {
             head: term!("create_repo"),
-            body: (term!("invite"), None),
+            body: vec![Implier::Resource(term!("invite"), None)],
+            condition: None,
+            deny: None,
         };
         let rewritten_permission_permission = shorthand_rule
             .as_rule(&term!(sym!("Org")), &blocks)
@@ -774,6 +1140,107 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_resource_block_rewrite_conditional_shorthand_rules() {
+        let policy = r#"resource Repo {
+            roles = ["reader", "writer", "commenter"];
+            permissions = ["read", "edit"];
+            relations = { parent: Org };
+
+            "read" if "reader" or "commenter" or "member" on "parent";
+            "edit" if "writer" and resource.is_draft and actor.active;
+            "read" if ("reader" or "writer") and resource.public;
+            "edit" if "writer" and (resource.is_draft or actor.admin);
+        }
+        resource Org {
+            roles = ["member"];
+        }"#;
+        let mut blocks = ResourceBlocks::new();
+        let mut shorthand_rules = vec![];
+        for line in parse_lines(0, policy).unwrap() {
+            if let Line::ResourceBlock {
+                keyword,
+                resource,
+                productions,
+            } = line
+            {
+                let (block, errors) =
+                    resource_block_from_productions(keyword, resource, productions);
+                assert!(errors.is_empty());
+                let declarations = index_declarations(
+                    block.roles,
+                    block.permissions,
+                    block.relations,
+                    &block.resource,
+                );
+                shorthand_rules.extend(block.shorthand_rules);
+                blocks.add(
+                    BlockType::Resource,
+                    block.resource,
+                    declarations.unwrap(),
+                    vec![],
+                );
+            }
+        }
+
+        let rewritten = shorthand_rules
+            .iter()
+            .map(|rule| {
+                rule.as_rule(&term!(sym!("Repo")), &blocks)
+                    .unwrap()
+                    .to_polar()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rewritten,
+            vec![
+                format!("has_permission(actor: {}{{}}, \"read\", repo: Repo{{}}) if (has_role(actor, \"reader\", repo) or has_role(actor, \"commenter\", repo) or has_relation(related_org, \"parent\", repo) and has_role(actor, \"member\", related_org));", ACTOR_UNION_NAME),
+                format!("has_permission(actor: {}{{}}, \"edit\", repo: Repo{{}}) if has_role(actor, \"writer\", repo) and repo.is_draft and actor.active;", ACTOR_UNION_NAME),
+                format!("has_permission(actor: {}{{}}, \"read\", repo: Repo{{}}) if (has_role(actor, \"reader\", repo) or has_role(actor, \"writer\", repo)) and repo.public;", ACTOR_UNION_NAME),
+                format!("has_permission(actor: {}{{}}, \"edit\", repo: Repo{{}}) if has_role(actor, \"writer\", repo) and (repo.is_draft or actor.admin);", ACTOR_UNION_NAME),
+            ]
+        );
+
+        // Shorthand rules with conditions round-trip through the formatter.
+        assert_eq!(
+            shorthand_rules
+                .iter()
+                .map(|rule| rule.to_polar())
+                .collect::<Vec<_>>(),
+            vec![
+                r#""read" if "reader" or "commenter" or "member" on "parent";"#,
+                r#""edit" if "writer" and resource.is_draft and actor.active;"#,
+                r#""read" if ("reader" or "writer") and resource.public;"#,
+                r#""edit" if "writer" and (resource.is_draft or actor.admin);"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shorthand_rule_alternatives_with_condition_need_parentheses() {
+        let policy = |body: &str| format!("resource Repo {{ \"read\" if {}; }}", body);
+        let error =
+            parse_lines(0, &policy(r#""reader" or "writer" and resource.public"#)).unwrap_err();
+        assert!(matches!(
+            error,
+            crate::error::ParseError::UnrecognizedToken { token, .. } if token == "and"
+        ));
+        assert!(parse_lines(0, &policy(r#""reader" and resource.public"#)).is_ok());
+        assert!(parse_lines(0, &policy(r#"("reader") and resource.public"#)).is_ok());
+    }
+
+    #[test]
+    fn test_shorthand_rule_condition_with_or_needs_parentheses() {
+        let policy = |body: &str| format!("resource Repo {{ \"read\" if {}; }}", body);
+        let error =
+            parse_lines(0, &policy(r#""reader" and resource.a or resource.b"#)).unwrap_err();
+        assert!(matches!(
+            error,
+            crate::error::ParseError::UnrecognizedToken { token, .. } if token == "or"
+        ));
+        assert!(parse_lines(0, &policy(r#""reader" and (resource.a or resource.b)"#)).is_ok());
+    }
+
     #[test]
     fn test_resource_block_nonlocal_rewrite_shorthand_rules() {
         let repo_resource = term!(sym!("Repo"));
@@ -799,7 +1266,12 @@ mod tests {
         );
         let shorthand_rule = ShorthandRule {
             head: term!("reader"),
-            body: (term!("member"), Some((term!(sym!("on")), term!("parent")))),
+            body: vec![Implier::Resource(
+                term!("member"),
+                Some((term!(sym!("on")), term!("parent"))),
+            )],
+            condition: None,
+            deny: None,
         };
         let rewritten_role_role = shorthand_rule
             .as_rule(&term!(sym!("Repo")), &blocks)
@@ -890,6 +1362,217 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_resource_block_rewrite_shorthand_rules_with_relation_paths() {
+        let mut blocks = ResourceBlocks::new();
+        let mut add = |resource: &str, roles: Term, relations: Option<Term>| {
+            let resource = term!(sym!(resource));
+            let declarations = index_declarations(Some(roles), None, relations, &resource);
+            blocks.add(BlockType::Resource, resource, declarations.unwrap(), vec![]);
+        };
+        add(
+            "Repo",
+            term!(["admin"]),
+            Some(term!(
+                btreemap! { sym!("project") => term!(sym!("Project")) }
+            )),
+        );
+        add(
+            "Project",
+            term!(["member"]),
+            Some(term!(btreemap! { sym!("org") => term!(sym!("Org")) })),
+        );
+        add("Org", term!(["owner"]), None);
+        add(
+            "Dir",
+            term!(["reader"]),
+            Some(term!(btreemap! { sym!("parent") => term!(sym!("Dir")) })),
+        );
+
+        let shorthand_rule = ShorthandRule {
+            head: term!("admin"),
+            body: vec![Implier::Resource(
+                term!("owner"),
+                Some((term!(sym!("on")), term!("project.org"))),
+            )],
+            condition: None,
+            deny: None,
+        };
+        assert_eq!(
+            shorthand_rule.as_rule(&term!(sym!("Repo")), &blocks).unwrap().to_polar(),
+            format!("has_role(actor: {}{{}}, \"admin\", repo: Repo{{}}) if has_relation(related_project, \"project\", repo) and has_relation(related_org, \"org\", related_project) and has_role(actor, \"owner\", related_org);", ACTOR_UNION_NAME),
+        );
+
+        let shorthand_rule = ShorthandRule {
+            head: term!("reader"),
+            body: vec![Implier::Resource(
+                term!("reader"),
+                Some((term!(sym!("on")), term!("parent.parent"))),
+            )],
+            condition: None,
+            deny: None,
+        };
+        assert_eq!(
+            shorthand_rule.as_rule(&term!(sym!("Dir")), &blocks).unwrap().to_polar(),
+            format!("has_role(actor: {}{{}}, \"reader\", dir: Dir{{}}) if has_relation(related_dir, \"parent\", dir) and has_relation(related_dir_2, \"parent\", related_dir) and has_role(actor, \"reader\", related_dir_2);", ACTOR_UNION_NAME),
+        );
+
+        let expect_error = |relation: &str, expected: &str| {
+            let shorthand_rule = ShorthandRule {
+                head: term!("admin"),
+                body: vec![Implier::Resource(
+                    term!("owner"),
+                    Some((term!(sym!("on")), term!(relation))),
+                )],
+                condition: None,
+                deny: None,
+            };
+            let error = shorthand_rule
+                .as_rule(&term!(sym!("Repo")), &blocks)
+                .unwrap_err();
+            assert!(error.to_string().contains(expected), "{}", error);
+        };
+        expect_error("project.", r#"Invalid relation path "project.""#);
+        expect_error(
+            "project.parent",
+            r#"Undeclared term "parent" referenced in rule in the 'Project' resource block"#,
+        );
+        expect_error(
+            "project.org.org",
+            r#"Undeclared term "org" referenced in rule in the 'Org' resource block"#,
+        );
+        expect_error(
+            "project",
+            r#"Term "owner" not declared in related resource block 'Project'"#,
+        );
+    }
+
+    #[test]
+    fn test_resource_block_global_roles() {
+        let p = Polar::new();
+        p.register_constant(sym!("User"), term!("unimportant"))
+            .unwrap();
+        p.register_constant(sym!("Repo"), term!("unimportant"))
+            .unwrap();
+
+        p.load_str(
+            r#"global {
+                roles = ["admin"];
+            }
+            actor User {}
+            resource Repo {
+                permissions = ["delete"];
+                "delete" if global "admin";
+            }
+            has_role(_: User, "admin");
+            has_permission(_: User, "delete", _: Repo);"#,
+        )
+        .unwrap();
+        p.clear_rules();
+
+        expect_error(
+            &p,
+            r#"actor User {}
+            resource Repo {
+                permissions = ["delete"];
+                "delete" if global "admin";
+            }"#,
+            r#"Undeclared global role "admin" referenced in rule."#,
+        );
+        expect_error(
+            &p,
+            r#"global { roles = ["admin"]; }
+            actor User {}
+            resource Repo {
+                permissions = ["delete"];
+                "delete" if globl "admin";
+            }"#,
+            "Unexpected keyword 'globl' before role. Did you mean 'global'?",
+        );
+        expect_error(
+            &p,
+            r#"global { roles = ["admin"]; }
+            global { roles = ["support"]; }"#,
+            "Duplicate declaration of global block.",
+        );
+        expect_error(
+            &p,
+            r#"global { roles = ["admin"]; permissions = ["delete"]; }"#,
+            "The global block can only declare roles.",
+        );
+        expect_error(
+            &p,
+            r#"global {
+                roles = ["admin", "support"];
+                "support" if "admin";
+            }"#,
+            "The global block can only declare roles.",
+        );
+        let error = p
+            .load_str(
+                r#"global { roles = ["admin"]; }
+                actor User {}
+                resource Repo {
+                    permissions = ["delete"];
+                    "delete" if global "admin";
+                }"#,
+            )
+            .unwrap_err();
+        assert!(error.to_string().contains(
+            "Missing implementation for required rule has_role(actor: Actor{}, role: String{});"
+        ));
+
+        // Global roles that no shorthand rule refers to don't require a `has_role` rule.
+        p.load_str(
+            r#"global { roles = ["admin"]; }
+            actor User {}
+            resource Repo {
+                permissions = ["delete"];
+            }"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_resource_block_deny_shorthand_rules() {
+        let resource = term!(sym!("Doc"));
+        let roles = term!(["viewer", "contractor"]);
+        let permissions = term!(["read"]);
+        let declarations = index_declarations(Some(roles), Some(permissions), None, &resource);
+        let mut blocks = ResourceBlocks::new();
+        blocks.add(BlockType::Resource, resource, declarations.unwrap(), vec![]);
+
+        let deny = |head: &str, keyword: &str| ShorthandRule {
+            head: term!(head),
+            body: vec![Implier::Resource(term!("contractor"), None)],
+            condition: Some(term!(op!(Dot, var!("resource"), term!("confidential")))),
+            deny: Some(term!(sym!(keyword))),
+        };
+        let rule = deny("read", "deny");
+        assert_eq!(
+            rule.to_polar(),
+            r#"deny "read" if "contractor" and resource.confidential;"#
+        );
+        assert_eq!(
+            rule.as_rule(&term!(sym!("Doc")), &blocks).unwrap().to_polar(),
+            format!("deny(actor: {}{{}}, \"read\", doc: Doc{{}}) if has_role(actor, \"contractor\", doc) and doc.confidential;", ACTOR_UNION_NAME),
+        );
+
+        let error = deny("viewer", "deny")
+            .as_rule(&term!(sym!("Doc")), &blocks)
+            .unwrap_err();
+        assert!(error.to_string().contains(
+            r#"Doc: Only permissions can be denied, but "viewer" is not declared as a permission."#
+        ));
+
+        let error = deny("read", "refuse")
+            .as_rule(&term!(sym!("Doc")), &blocks)
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Unexpected keyword 'refuse' before shorthand rule. Did you mean 'deny'?"));
+    }
+
     #[test]
     #[ignore = "probably easier after the entity PR goes in"]
     fn test_resource_block_resource_relations_can_only_appear_after_on() {
@@ -1027,19 +1710,30 @@ mod tests {
                 // TODO(gj): shorthand_rule! macro
                 ShorthandRule {
                     head: term!("pull"),
-                    body: (term!("reader"), None),
+                    body: vec![Implier::Resource(term!("reader"), None)],
+                    condition: None,
+                    deny: None,
                 },
                 ShorthandRule {
                     head: term!("push"),
-                    body: (term!("writer"), None),
+                    body: vec![Implier::Resource(term!("writer"), None)],
+                    condition: None,
+                    deny: None,
                 },
                 ShorthandRule {
                     head: term!("writer"),
-                    body: (term!("creator"), None),
+                    body: vec![Implier::Resource(term!("creator"), None)],
+                    condition: None,
+                    deny: None,
                 },
                 ShorthandRule {
                     head: term!("reader"),
-                    body: (term!("member"), Some((term!(sym!("on")), term!("parent")))),
+                    body: vec![Implier::Resource(
+                        term!("member"),
+                        Some((term!(sym!("on")), term!("parent"))),
+                    )],
+                    condition: None,
+                    deny: None,
                 },
             ],
         };
@@ -73,6 +73,42 @@ impl<'kb> Rewriter<'kb> {
             _ => false,
         }
     }
+
+    /// Rewrite `arg` in its own stack frame, adding the rewrites to it.
+    fn fold_scoped(&mut self, arg: Term) -> Term {
+        let arg_operator = arg.value().as_expression().map(|e| e.operator).ok();
+
+        self.stack.push(vec![]);
+        let mut arg = self.fold_term(arg);
+        let mut rewrites = self.stack.pop().unwrap();
+        // Decide whether to prepend, or append
+
+        // If the current operator is unify and rewrites are only
+        // dot operations we append the rewrites after the temporary variable.
+        // This ensures that grounding does not occur when performing dot
+        // operations on a partial.
+        //
+        // Append:
+        // - x.foo.bar = 1 => _value_1 = 1 and x.foo = _value_2 and _value_2.bar = _value_1
+        //
+        // Prepend:
+        //
+        // - x = new Foo(x: new Bar(x: 1)) =>
+        //   _instance_2 = new Bar(x: 1) and _instance_1 = new Foo(x: _instance_2) and x = _instance_1
+        //
+        // We prepend when the rewritten variable needs to be bound before it is
+        // used.
+        if only_dots(&rewrites) && arg_operator.map_or(false, |o| o == Operator::Unify) {
+            for rewrite in rewrites {
+                and_append(&mut arg, rewrite);
+            }
+        } else {
+            for rewrite in rewrites.drain(..).rev() {
+                and_prepend(&mut arg, rewrite);
+            }
+        }
+        arg
+    }
 }
 
 fn temp_name(o: &Operator) -> &'static str {
@@ -97,6 +133,7 @@ impl<'kb> Folder for Rewriter<'kb> {
             params,
             source_info,
             required,
+            tabled,
         }: Rule,
     ) -> Rule {
         let mut body = self.fold_term(body);
@@ -117,6 +154,7 @@ impl<'kb> Folder for Rewriter<'kb> {
             body,
             source_info,
             required,
+            tabled,
         }
     }
 
@@ -135,6 +173,11 @@ impl<'kb> Folder for Rewriter<'kb> {
 
                 new
             }
+            // Constants declared in the policy are replaced with their values.
+            Value::Variable(v) if self.kb.get_policy_constant(v).is_some() => {
+                let value = self.kb.get_policy_constant(v).unwrap();
+                t.clone_with_value(value.value().clone())
+            }
             Value::Expression(o) if self.needs_rewrite(o) => {
                 // Rewrite sub-expressions, then push a temp onto the args.
                 let mut new = fold_operation(o.clone(), self);
@@ -156,49 +199,37 @@ impl<'kb> Folder for Rewriter<'kb> {
 
     fn fold_operation(&mut self, o: Operation) -> Operation {
         match o.operator {
-            Operator::And | Operator::Or | Operator::Not => Operation {
+            Operator::And | Operator::Or | Operator::Not | Operator::ForAll => Operation {
                 operator: fold_operator(o.operator, self),
                 args: o
                     .args
                     .into_iter()
-                    .map(|arg| {
-                        let arg_operator = arg.value().as_expression().map(|e| e.operator).ok();
-
-                        self.stack.push(vec![]);
-                        let mut arg = self.fold_term(arg);
-                        let mut rewrites = self.stack.pop().unwrap();
-                        // Decide whether to prepend, or append
-
-                        // If the current operator is unify and rewrites are only
-                        // dot operations we append the rewrites after the temporary variable.
-                        // This ensures that grounding does not occur when performing dot
-                        // operations on a partial.
-                        //
-                        // Append:
-                        // - x.foo.bar = 1 => _value_1 = 1 and x.foo = _value_2 and _value_2.bar = _value_1
-                        //
-                        // Prepend:
-                        //
-                        // - x = new Foo(x: new Bar(x: 1)) =>
-                        //   _instance_2 = new Bar(x: 1) and _instance_1 = new Foo(x: _instance_2) and x = _instance_1
-                        //
-                        // We prepend when the rewritten variable needs to be bound before it is
-                        // used.
-                        if only_dots(&rewrites)
-                            && arg_operator.map_or(false, |o| o == Operator::Unify)
-                        {
-                            for rewrite in rewrites {
-                                and_append(&mut arg, rewrite);
-                            }
-                        } else {
-                            for rewrite in rewrites.drain(..).rev() {
-                                and_prepend(&mut arg, rewrite);
-                            }
-                        }
-                        arg
-                    })
+                    .map(|arg| self.fold_scoped(arg))
                     .collect(),
             },
+            // The goal of an aggregate gets its own frame, like the arguments of `forall`.
+            // Lookups in the template are made after each solution of the goal, so their
+            // rewrites are appended to it.
+            Operator::Count | Operator::Collect | Operator::Sum | Operator::Min | Operator::Max
+                if o.args.len() == 3 =>
+            {
+                let mut args = o.args.into_iter();
+                let (template, goal, result) = (
+                    args.next().unwrap(),
+                    args.next().unwrap(),
+                    args.next().unwrap(),
+                );
+                let mut goal = self.fold_scoped(goal);
+                self.stack.push(vec![]);
+                let template = self.fold_term(template);
+                for rewrite in self.stack.pop().unwrap() {
+                    and_append(&mut goal, rewrite);
+                }
+                Operation {
+                    operator: fold_operator(o.operator, self),
+                    args: vec![template, goal, self.fold_term(result)],
+                }
+            }
             _ => fold_operation(o, self),
         }
     }
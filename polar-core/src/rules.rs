@@ -27,6 +27,9 @@ pub struct Rule {
     // TODO @patrickod: refactor Rule into Rule & RuleType structs
     // `required` is used exclusively with rule *types* and not normal rules.
     pub required: bool,
+    // `tabled` is also only used with rule types: answers to calls of rules with a tabled rule
+    // type are memoized for the rest of the query.
+    pub tabled: bool,
 }
 
 impl PartialEq for Rule {
@@ -66,6 +69,7 @@ impl Rule {
             body,
             source_info: SourceInfo::Test,
             required: false,
+            tabled: false,
         }
     }
 
@@ -88,6 +92,7 @@ impl Rule {
                 right,
             },
             required: false,
+            tabled: false,
         }
     }
 }
@@ -112,6 +117,11 @@ impl RuleTypes {
             "allow",
             [sym!("actor"), sym!("_action"), sym!("resource")]
         ));
+        // type deny(actor, action, resource);
+        self.add(rule!(
+            "deny",
+            [sym!("actor"), sym!("_action"), sym!("resource")]
+        ));
         // type allow_field(actor, action, resource, field);
         self.add(rule!(
             "allow_field",
@@ -142,6 +152,18 @@ impl RuleTypes {
         self.add_default_rule_types()
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Rule> {
+        self.0.values().flatten()
+    }
+
+    /// Replace all rule types, including the defaults, with `rule_types`.
+    pub fn replace<I: IntoIterator<Item = Rule>>(&mut self, rule_types: I) {
+        self.0.clear();
+        for rule_type in rule_types {
+            self.add(rule_type);
+        }
+    }
+
     pub fn required_rule_types(&self) -> Vec<&Rule> {
         self.0
             .values()
@@ -0,0 +1,320 @@
+//! Serialized snapshots of a loaded `KnowledgeBase`.
+//!
+//! A snapshot captures the KB *after* parsing, rewriting and validation: rules (including the
+//! rules generated from resource block shorthand rules), rule types, resource blocks, modules,
+//! relationship facts, pending inline queries and the policy sources. Restoring a snapshot only re-indexes the rules, which
+//! makes it considerably cheaper than loading the sources again.
+//!
+//! Registered constants are host objects and can't be persisted, so a snapshot only records the
+//! *names* of the constants that were registered when it was exported. The host is responsible
+//! for registering them again.
+//!
+//! `SourceInfo` is not part of the serde representation of `Term`s and `Rule`s (it's meaningless
+//! across the FFI boundary), so each rule and inline query is stored alongside a side table of
+//! source info collected in traversal order. This keeps error messages pointing at the original
+//! policy source after a snapshot is restored.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::encoding::Encoding;
+use super::error::{PolarResult, RuntimeError};
+use super::folder::{fold_term, Folder};
+use super::kb::KnowledgeBase;
+use super::modules::ModulesSnapshot;
+use super::relationships::Relationship;
+use super::resource_block::ResourceBlocks;
+use super::rules::Rule;
+use super::sources::{Source, SourceInfo};
+use super::terms::{Symbol, Term};
+
+/// Version of the snapshot layout. Bump whenever `Snapshot` (or anything it contains) changes
+/// in a backwards-incompatible way.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 7;
+
+const POLAR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    format_version: u32,
+    polar_version: String,
+}
+
+impl Header {
+    fn current() -> Self {
+        Self {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            polar_version: POLAR_VERSION.to_owned(),
+        }
+    }
+
+    fn check(&self) -> Result<(), RuntimeError> {
+        if self.format_version != SNAPSHOT_FORMAT_VERSION || self.polar_version != POLAR_VERSION {
+            let msg = format!(
+                "Incompatible policy snapshot: snapshot was exported by polar {} (format version {}), but this is polar {} (format version {}).",
+                self.polar_version, self.format_version, POLAR_VERSION, SNAPSHOT_FORMAT_VERSION
+            );
+            return Err(RuntimeError::FileLoading { msg });
+        }
+        Ok(())
+    }
+}
+
+/// Only the header of a snapshot, used to check compatibility before decoding the rest.
+#[derive(Deserialize)]
+struct Versioned {
+    header: Header,
+}
+
+/// A value along with the `SourceInfo` of every term it contains, in traversal order.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Located<T> {
+    value: T,
+    source_info: Vec<SourceInfo>,
+}
+
+/// Records the source info of every term, in pre-order.
+struct SourceInfoCollector(Vec<SourceInfo>);
+
+impl Folder for SourceInfoCollector {
+    fn fold_term(&mut self, t: Term) -> Term {
+        self.0.push(t.source_info().clone());
+        fold_term(t, self)
+    }
+}
+
+/// Restores source info recorded by a `SourceInfoCollector`, translating source IDs from the
+/// exporting KB to the importing KB.
+struct SourceInfoRestorer<'a> {
+    source_info: std::vec::IntoIter<SourceInfo>,
+    src_ids: &'a HashMap<u64, u64>,
+}
+
+impl<'a> SourceInfoRestorer<'a> {
+    fn next(&mut self) -> SourceInfo {
+        match self.source_info.next() {
+            Some(SourceInfo::Parser {
+                src_id,
+                left,
+                right,
+            }) => SourceInfo::Parser {
+                src_id: self.src_ids.get(&src_id).copied().unwrap_or(src_id),
+                left,
+                right,
+            },
+            Some(source_info) => source_info,
+            None => SourceInfo::Ffi,
+        }
+    }
+}
+
+impl<'a> Folder for SourceInfoRestorer<'a> {
+    fn fold_term(&mut self, t: Term) -> Term {
+        let source_info = self.next();
+        let mut t = fold_term(t, self);
+        t.set_source_info(source_info);
+        t
+    }
+}
+
+impl Located<Term> {
+    pub fn new(term: &Term) -> Self {
+        let mut collector = SourceInfoCollector(vec![]);
+        let value = collector.fold_term(term.clone());
+        Self {
+            value,
+            source_info: collector.0,
+        }
+    }
+
+    pub fn restore(self, src_ids: &HashMap<u64, u64>) -> Term {
+        let mut restorer = SourceInfoRestorer {
+            source_info: self.source_info.into_iter(),
+            src_ids,
+        };
+        restorer.fold_term(self.value)
+    }
+}
+
+impl Located<Rule> {
+    pub fn new(rule: &Rule) -> Self {
+        let mut collector = SourceInfoCollector(vec![rule.source_info.clone()]);
+        let value = collector.fold_rule(rule.clone());
+        Self {
+            value,
+            source_info: collector.0,
+        }
+    }
+
+    pub fn restore(self, src_ids: &HashMap<u64, u64>) -> Rule {
+        let mut restorer = SourceInfoRestorer {
+            source_info: self.source_info.into_iter(),
+            src_ids,
+        };
+        let source_info = restorer.next();
+        Rule {
+            source_info,
+            ..restorer.fold_rule(self.value)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    header: Header,
+    pub sources: Vec<(u64, Source)>,
+    /// Rules in definition order, which determines the order in which they're applied.
+    pub rules: Vec<Located<Rule>>,
+    pub rule_types: Vec<Located<Rule>>,
+    pub inline_queries: Vec<Located<Term>>,
+    pub resource_blocks: ResourceBlocks,
+    /// Declared unions and their members, sorted by name.
+    pub unions: Vec<(Symbol, Vec<Term>)>,
+    /// Constants declared in the policy, sorted by name. They're already substituted into the
+    /// rules, but queries can still use them.
+    pub policy_constants: Vec<(Symbol, Term)>,
+    pub modules: ModulesSnapshot,
+    /// Relationship facts, in the order they were added.
+    pub relationships: Vec<Relationship>,
+    /// Names of the constants registered at export time.
+    pub constants: Vec<Symbol>,
+}
+
+impl Snapshot {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sources: Vec<(u64, Source)>,
+        rules: Vec<Located<Rule>>,
+        rule_types: Vec<Located<Rule>>,
+        inline_queries: Vec<Located<Term>>,
+        resource_blocks: ResourceBlocks,
+        unions: Vec<(Symbol, Vec<Term>)>,
+        policy_constants: Vec<(Symbol, Term)>,
+        modules: ModulesSnapshot,
+        relationships: Vec<Relationship>,
+        constants: Vec<Symbol>,
+    ) -> Self {
+        Self {
+            header: Header::current(),
+            sources,
+            rules,
+            rule_types,
+            inline_queries,
+            resource_blocks,
+            unions,
+            policy_constants,
+            modules,
+            relationships,
+            constants,
+        }
+    }
+
+    pub fn encode(&self) -> PolarResult<Vec<u8>> {
+        Encoding::MessagePack.encode(self)
+    }
+
+    /// Decode a snapshot, rejecting snapshots produced by a different version of polar.
+    pub fn decode(bytes: &[u8], kb: &KnowledgeBase) -> PolarResult<Self> {
+        let Versioned { header } = Encoding::MessagePack.decode(bytes)?;
+        header.check().map_err(|e| e.with_context(kb))?;
+        Encoding::MessagePack.decode(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::{ErrorKind, PolarError};
+    use crate::polar::Polar;
+
+    #[test]
+    fn test_located_rule_round_trips_source_info() {
+        let polar = Polar::new();
+        polar.load_str("f(x: Integer, {a: y}) if x > y;").unwrap();
+        let kb = polar.kb.read().unwrap();
+        let rule = kb.get_generic_rule(&sym!("f")).unwrap().rules[&0].clone();
+
+        let located = Located::<Rule>::new(&rule);
+        let src_ids = hashmap! {rule.get_source_id().unwrap() => 42};
+        let restored = located.restore(&src_ids);
+
+        assert_eq!(*rule, restored);
+        assert_eq!(restored.get_source_id(), Some(42));
+        assert_eq!(restored.span(), rule.span());
+        let param = &restored.params[1].parameter;
+        assert_eq!(param.get_source_id(), Some(42));
+        assert_eq!(param.span(), rule.params[1].parameter.span());
+    }
+
+    #[test]
+    fn test_snapshots_include_modules() {
+        let polar = Polar::new();
+        polar
+            .load(vec![
+                Source {
+                    src: "module billing;\nexport refund;\nrefund(1);".to_owned(),
+                    filename: Some("billing.polar".to_owned()),
+                },
+                Source {
+                    src: "import billing::refund;\nf(x) if refund(x);".to_owned(),
+                    filename: Some("policy.polar".to_owned()),
+                },
+            ])
+            .unwrap();
+        let snapshot = polar.export_snapshot().unwrap();
+        let restored = Polar::from_snapshot(&snapshot).unwrap();
+
+        let kb = restored.kb.read().unwrap();
+        let module_of = |name: &str| {
+            let rule = &kb.get_generic_rule(&sym!(name)).unwrap().rules[&0];
+            kb.modules.module_of(&rule.source_info).cloned()
+        };
+        assert_eq!(module_of("billing::refund"), Some(sym!("billing")));
+        assert_eq!(module_of("f"), None);
+    }
+
+    #[test]
+    fn test_incompatible_snapshots_are_rejected() {
+        let kb = KnowledgeBase::new();
+        let mut snapshot = Snapshot::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            ResourceBlocks::new(),
+            vec![],
+            vec![],
+            ModulesSnapshot::default(),
+            vec![],
+            vec![],
+        );
+        assert!(Snapshot::decode(&snapshot.encode().unwrap(), &kb).is_ok());
+
+        snapshot.header.format_version += 1;
+        let err = Snapshot::decode(&snapshot.encode().unwrap(), &kb)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            PolarError {
+                kind: ErrorKind::Runtime(RuntimeError::FileLoading { .. }),
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("Incompatible policy snapshot"));
+
+        snapshot.header = Header {
+            polar_version: "0.0.1".to_owned(),
+            ..Header::current()
+        };
+        assert!(Snapshot::decode(&snapshot.encode().unwrap(), &kb).is_err());
+
+        let err = Snapshot::decode(b"not a snapshot", &kb).err().unwrap();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::Operational(crate::error::OperationalError::Serialization { .. })
+        ));
+    }
+}
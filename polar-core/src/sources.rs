@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
 pub enum SourceInfo {
     // From the parser
     Parser {
@@ -62,4 +62,8 @@ impl Sources {
     pub fn get_source(&self, src_id: u64) -> Option<Source> {
         self.sources.get(&src_id).cloned()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&u64, &Source)> {
+        self.sources.iter()
+    }
 }
@@ -0,0 +1,303 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::counter::Counter;
+use crate::error::RuntimeError;
+use crate::events::QueryEvent;
+use crate::folder::Folder;
+use crate::formatting::ToPolarString;
+use crate::runnable::Runnable;
+use crate::terms::{Symbol, Term};
+use crate::vm::{Goals, PolarVirtualMachine};
+
+type Result<T> = core::result::Result<T, RuntimeError>;
+
+/// Rename the variables in a term to `_1`, `_2`, ... in order of appearance, so that calls
+/// (and answers) that differ only in the names of their variables are equal.
+pub fn variant(term: &Term) -> Term {
+    struct Canonicalizer {
+        names: HashMap<Symbol, Symbol>,
+    }
+
+    impl Folder for Canonicalizer {
+        fn fold_variable(&mut self, v: Symbol) -> Symbol {
+            let next = self.names.len() + 1;
+            self.names
+                .entry(v)
+                .or_insert_with(|| Symbol(format!("_{}", next)))
+                .clone()
+        }
+
+        fn fold_rest_variable(&mut self, r: Symbol) -> Symbol {
+            self.fold_variable(r)
+        }
+    }
+
+    Canonicalizer {
+        names: HashMap::new(),
+    }
+    .fold_term(term.clone())
+}
+
+/// Tabled calls can't be partially evaluated, since tables only record the bindings of each
+/// answer, not the constraints on it.
+pub fn unsupported_partial(call: &Term) -> RuntimeError {
+    RuntimeError::Unsupported {
+        msg: format!(
+            "cannot use tabled rule {} with partial evaluation",
+            call.to_polar()
+        ),
+        term: call.clone(),
+    }
+}
+
+/// The answers found so far for one variant of a tabled call.
+#[derive(Default)]
+struct Table {
+    answers: Vec<Term>,
+    seen: HashSet<Term>,
+    complete: bool,
+}
+
+/// A tabled call being evaluated.
+struct Frame {
+    call: Term,
+
+    /// The stack index of the oldest call this one (or a call it made) consumed answers from
+    /// while that call was still being evaluated.
+    leader: usize,
+}
+
+/// What to do about a call to a tabled rule.
+pub enum Tabled {
+    /// Use these answers instead of evaluating the call.
+    Answers(Vec<Term>),
+
+    /// Evaluate the call with a `Tabler`.
+    Evaluate,
+}
+
+/// The answer tables for the tabled calls made during one query, shared by the VM and all of its
+/// sub-VMs. Calls are keyed by `variant`.
+///
+/// Evaluation follows linear tabling: a call that's already being evaluated further up the
+/// stack doesn't recurse, it consumes the answers found so far. The call it depends on then
+/// re-evaluates itself until it finds no new answers, at which point its table, and the tables
+/// of the calls evaluated underneath it, are complete.
+#[derive(Default)]
+pub struct Tables {
+    tables: HashMap<Term, Table>,
+
+    /// Calls being evaluated, outermost first.
+    stack: Vec<Frame>,
+
+    /// Evaluated calls whose tables are not complete, because they depend on a call that's
+    /// still being evaluated, with the stack index of that call.
+    incomplete: HashMap<Term, usize>,
+
+    /// The number of answers in all tables, to tell when an evaluation has reached a fixpoint.
+    answer_count: usize,
+}
+
+impl Tables {
+    pub fn lookup(&mut self, call: &Term) -> Tabled {
+        if let Some(index) = self.stack.iter().position(|frame| &frame.call == call) {
+            // A recursive call: every call above this one on the stack depends on it.
+            for frame in &mut self.stack[index..] {
+                frame.leader = frame.leader.min(index);
+            }
+            return Tabled::Answers(self.answers(call));
+        }
+        match self.tables.get(call) {
+            Some(table) if table.complete => Tabled::Answers(table.answers.clone()),
+            _ => Tabled::Evaluate,
+        }
+    }
+
+    fn answers(&self, call: &Term) -> Vec<Term> {
+        self.tables
+            .get(call)
+            .map(|table| table.answers.clone())
+            .unwrap_or_default()
+    }
+
+    /// Start evaluating `call`, returning its stack index.
+    fn push(&mut self, call: Term) -> usize {
+        self.incomplete.remove(&call);
+        self.tables.entry(call.clone()).or_default();
+        self.stack.push(Frame {
+            call,
+            leader: usize::MAX,
+        });
+        self.stack.len() - 1
+    }
+
+    fn add_answer(&mut self, call: &Term, answer: Term) {
+        let table = self.tables.entry(call.clone()).or_default();
+        if table.seen.insert(answer.clone()) {
+            table.answers.push(answer);
+            self.answer_count += 1;
+        }
+    }
+
+    /// Whether the call at `index` depends on itself, and so must be evaluated again if the
+    /// last evaluation found new answers.
+    fn is_recursive(&self, index: usize) -> bool {
+        self.stack[index].leader <= index
+    }
+
+    /// Finish evaluating the call at the top of the stack, returning its answers.
+    fn pop(&mut self) -> Vec<Term> {
+        let frame = self.stack.pop().expect("tabled call stack is empty");
+        let index = self.stack.len();
+        if frame.leader < index {
+            // Depends on a call that's still being evaluated; so does its caller.
+            let leader = frame.leader;
+            for dependency in self.incomplete.values_mut() {
+                if *dependency >= index {
+                    *dependency = leader;
+                }
+            }
+            self.incomplete.insert(frame.call.clone(), leader);
+            let caller = self.stack.last_mut().expect("tabled call has no caller");
+            caller.leader = caller.leader.min(leader);
+        } else {
+            let tables = &mut self.tables;
+            self.incomplete.retain(|call, dependency| {
+                if *dependency >= index {
+                    tables.get_mut(call).unwrap().complete = true;
+                    false
+                } else {
+                    true
+                }
+            });
+            self.tables.get_mut(&frame.call).unwrap().complete = true;
+        }
+        self.answers(&frame.call)
+    }
+}
+
+/// The tabler evaluates calls to rules with a tabled rule type.
+///
+/// It is a `Runnable` that runs the rules for `call` to exhaustion using copies of `vm`, adding
+/// each solution to the call's table, and repeats that while the call depends on itself and
+/// new answers turn up. The answers are left in `answers` for the parent VM to unify with the
+/// call.
+#[derive(Clone)]
+pub struct Tabler {
+    vm: PolarVirtualMachine,
+    goals: Goals,
+
+    /// The VM for the current evaluation of the call.
+    running: Option<PolarVirtualMachine>,
+
+    /// The call, and its variant, which keys its table.
+    call: Term,
+    variant: Term,
+
+    /// Stack index of the call, once evaluation has started.
+    index: Option<usize>,
+
+    /// Total number of answers when the current evaluation started.
+    answer_count: usize,
+
+    tables: Rc<RefCell<Tables>>,
+
+    /// The answers to return to the parent VM.
+    answers: Rc<RefCell<Vec<Term>>>,
+}
+
+impl Tabler {
+    pub fn new(
+        vm: &PolarVirtualMachine,
+        goals: Goals,
+        call: Term,
+        answers: Rc<RefCell<Vec<Term>>>,
+    ) -> Self {
+        Self {
+            vm: vm.clone_with_goals(vec![]),
+            goals,
+            running: None,
+            variant: variant(&call),
+            call,
+            index: None,
+            answer_count: 0,
+            tables: vm.tables.clone(),
+            answers,
+        }
+    }
+}
+
+/// A Runnable that evaluates a tabled call:
+///
+/// 1. Pass the call's answers back to the parent VM.
+/// 2. Return true; the parent VM fails if there are no answers.
+impl Runnable for Tabler {
+    fn run(&mut self, _: Option<&mut Counter>) -> Result<QueryEvent> {
+        let index = match self.index {
+            Some(index) => index,
+            None => {
+                let index = self.tables.borrow_mut().push(self.variant.clone());
+                self.index = Some(index);
+                index
+            }
+        };
+        loop {
+            if self.running.is_none() {
+                self.answer_count = self.tables.borrow().answer_count;
+                self.running = Some(self.vm.clone_with_goals(self.goals.clone()));
+            }
+            let vm = self.running.as_mut().unwrap();
+
+            // Pass most events through, but collect results as answers.
+            match vm.run(None)? {
+                QueryEvent::Done { .. } => {
+                    self.running = None;
+                    let mut tables = self.tables.borrow_mut();
+                    if tables.is_recursive(index) && tables.answer_count != self.answer_count {
+                        continue;
+                    }
+                    *self.answers.borrow_mut() = tables.pop();
+                    return Ok(QueryEvent::Done { result: true });
+                }
+                QueryEvent::Result { .. } => {
+                    let mut variables = HashSet::new();
+                    self.call.variables(&mut variables);
+                    if variables.iter().any(|var| vm.is_constrained(var)) {
+                        return Err(unsupported_partial(&self.call));
+                    }
+                    let answer = variant(&vm.deref(&self.call));
+                    self.tables.borrow_mut().add_answer(&self.variant, answer);
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn external_question_result(&mut self, call_id: u64, answer: bool) -> Result<()> {
+        self.running
+            .as_mut()
+            .unwrap()
+            .external_question_result(call_id, answer)
+    }
+
+    fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> Result<()> {
+        self.running
+            .as_mut()
+            .unwrap()
+            .external_call_result(call_id, term)
+    }
+
+    fn debug_command(&mut self, command: &str) -> Result<()> {
+        self.running.as_mut().unwrap().debug_command(command)
+    }
+
+    fn clone_runnable(&self) -> Box<dyn Runnable> {
+        Box::new(self.clone())
+    }
+
+    fn handle_error(&mut self, error: RuntimeError) -> Result<QueryEvent> {
+        self.running.as_mut().unwrap().handle_error(error)
+    }
+}
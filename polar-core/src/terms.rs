@@ -117,6 +117,11 @@ pub enum Operator {
     And,
     ForAll,
     Assign,
+    Count,
+    Collect,
+    Sum,
+    Min,
+    Max,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
@@ -393,6 +398,14 @@ impl Term {
         self.value = Arc::new(value);
     }
 
+    pub(crate) fn source_info(&self) -> &SourceInfo {
+        &self.source_info
+    }
+
+    pub(crate) fn set_source_info(&mut self, source_info: SourceInfo) {
+        self.source_info = source_info;
+    }
+
     pub fn offset(&self) -> usize {
         if let SourceInfo::Parser { left, .. } = self.source_info {
             left
@@ -495,6 +508,15 @@ impl Term {
         }
     }
 
+    /// The class or union a specializer refers to: the tag of an instance pattern, or a name.
+    pub fn specializer_tag(&self) -> Option<&Symbol> {
+        match self.value() {
+            Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. }))
+            | Value::Variable(tag) => Some(tag),
+            _ => None,
+        }
+    }
+
     pub fn is_actor_union(&self) -> bool {
         matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.0 == ACTOR_UNION_NAME)
     }
@@ -1,4 +1,7 @@
+use super::kb::KnowledgeBase;
+use super::lexer::loc_to_pos;
 use super::rules::*;
+use super::sources::SourceInfo;
 use super::terms::*;
 use serde::{Deserialize, Serialize};
 use std::rc::Rc;
@@ -24,6 +27,18 @@ impl Trace {
             None
         }
     }
+
+    /// The rules applied in this trace and its children, in the order they were applied.
+    pub fn rules(&self) -> Vec<&Arc<Rule>> {
+        let mut rules = vec![];
+        if let Node::Rule(rule) = &self.node {
+            rules.push(rule);
+        }
+        for child in &self.children {
+            rules.extend(child.rules());
+        }
+        rules
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -31,3 +46,92 @@ pub struct TraceResult {
     pub trace: Rc<Trace>,
     pub formatted: String,
 }
+
+/// Where a rule is defined in the loaded policy.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RuleSpan {
+    /// The file the rule was loaded from, if it was loaded from one.
+    pub filename: Option<String>,
+    /// The line and column of the start of the rule, counting from 1.
+    pub line: usize,
+    pub column: usize,
+    /// The rule as written.
+    pub text: String,
+}
+
+impl RuleSpan {
+    /// The span of `rule`, or `None` if it wasn't parsed from a source loaded into `kb`.
+    pub fn new(kb: &KnowledgeBase, rule: &Rule) -> Option<Self> {
+        // The rule's own source info only covers its head.
+        if let (
+            SourceInfo::Parser { src_id, left, .. },
+            SourceInfo::Parser {
+                src_id: body_src_id,
+                right,
+                ..
+            },
+        ) = (&rule.source_info, rule.body.source_info())
+        {
+            if src_id != body_src_id {
+                return None;
+            }
+            let source = kb.sources.get_source(*src_id)?;
+            let (row, column) = loc_to_pos(&source.src, *left);
+            let text: String = source.src.chars().take(*right).skip(*left).collect();
+            return Some(Self {
+                filename: source.filename,
+                line: row + 1,
+                column: column + 1,
+                text: format!("{};", text),
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::QueryEvent;
+    use crate::polar::Polar;
+    use crate::sources::Source;
+
+    #[test]
+    fn test_rule_spans() {
+        let polar = Polar::new();
+        polar
+            .load(vec![Source {
+                src: "f(x) if g(x);\n  g(x) if\n    x = 1;".to_owned(),
+                filename: Some("test.polar".to_owned()),
+            }])
+            .unwrap();
+        let mut query = polar.new_query("f(1)", true).unwrap();
+        let trace = match query.next_event().unwrap() {
+            QueryEvent::Result { trace, .. } => trace.unwrap().trace,
+            event => panic!("unexpected event {:?}", event),
+        };
+        let kb = polar.kb.read().unwrap();
+        let spans: Vec<_> = trace
+            .rules()
+            .into_iter()
+            .filter_map(|rule| RuleSpan::new(&kb, rule))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                RuleSpan {
+                    filename: Some("test.polar".to_owned()),
+                    line: 1,
+                    column: 1,
+                    text: "f(x) if g(x);".to_owned(),
+                },
+                RuleSpan {
+                    filename: Some("test.polar".to_owned()),
+                    line: 2,
+                    column: 3,
+                    text: "g(x) if\n    x = 1;".to_owned(),
+                },
+            ]
+        );
+    }
+}
@@ -3,9 +3,11 @@ use std::collections::{HashMap, HashSet};
 use super::diagnostic::Diagnostic;
 use super::error::ValidationError;
 use super::kb::*;
+use super::relationships::RELATIONSHIP_PREDICATE;
+use super::resource_block::{Declaration, Implier, GLOBAL_BLOCK_NAME};
 use super::rules::*;
 use super::terms::*;
-use super::visitor::{walk_call, walk_rule, walk_term, Visitor};
+use super::visitor::{walk_call, walk_operation, walk_rule, walk_term, Visitor};
 use super::warning::ValidationWarning;
 
 /// Record singleton variables and unknown specializers in a rule.
@@ -55,7 +57,8 @@ impl<'kb> Visitor for SingletonVisitor<'kb> {
                 if !v.is_temporary_var()
                     && !v.is_namespaced_var()
                     && !self.kb.is_constant(v)
-                    && !self.kb.is_union(t) =>
+                    && !self.kb.is_union(t)
+                    && self.kb.get_policy_constant(v).is_none() =>
             {
                 self.singletons
                     .entry(v.clone())
@@ -74,6 +77,73 @@ pub fn check_singletons(rule: &Rule, kb: &KnowledgeBase) -> Vec<Diagnostic> {
     visitor.warnings()
 }
 
+struct ShadowedConstantVisitor<'kb> {
+    kb: &'kb KnowledgeBase,
+    bindings: Vec<Term>,
+}
+
+impl<'kb> Visitor for ShadowedConstantVisitor<'kb> {
+    fn visit_operation(&mut self, o: &Operation) {
+        // `x := ...` and `forall(x in ..., ...)` introduce `x`, whereas any other use of a
+        // constant's name is a reference to the constant.
+        let binding = match (o.operator, o.args.first().map(Term::value)) {
+            (Operator::Assign, _) => o.args.first(),
+            (Operator::ForAll, Some(Value::Expression(quantifier)))
+                if quantifier.operator == Operator::In =>
+            {
+                quantifier.args.first()
+            }
+            _ => None,
+        };
+        if let Some(term) = binding {
+            if let Value::Variable(v) = term.value() {
+                if self.kb.get_policy_constant(v).is_some() {
+                    self.bindings.push(term.clone());
+                }
+            }
+        }
+        walk_operation(self, o)
+    }
+}
+
+/// A rule variable with the same name as a constant would be replaced by the constant's value,
+/// so it couldn't bind anything.
+pub fn check_shadowed_constants(rule: &Rule, kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    let shadowed = |term: Term, what: &str| {
+        let msg = format!(
+            "{} '{}' of rule {} shadows the constant '{}'; rename the {}.",
+            what,
+            term,
+            rule.name,
+            term,
+            what.to_lowercase()
+        );
+        let error = ValidationError::InvalidConstant { term, msg };
+        Diagnostic::Error(error.with_context(kb))
+    };
+    let mut diagnostics: Vec<_> = rule
+        .params
+        .iter()
+        .filter(|param| match param.parameter.value() {
+            Value::Variable(v) => kb.get_policy_constant(v).is_some(),
+            _ => false,
+        })
+        .map(|param| shadowed(param.parameter.clone(), "Parameter"))
+        .collect();
+    let mut visitor = ShadowedConstantVisitor {
+        kb,
+        bindings: vec![],
+    };
+    visitor.visit_term(&rule.body);
+    diagnostics.extend(
+        visitor
+            .bindings
+            .into_iter()
+            .map(|term| shadowed(term, "Variable")),
+    );
+    diagnostics
+}
+
 struct AndOrPrecendenceCheck<'kb> {
     kb: &'kb KnowledgeBase,
     unparenthesized_expr: Vec<Term>,
@@ -144,6 +214,50 @@ pub fn check_no_allow_rule(kb: &KnowledgeBase) -> Option<Diagnostic> {
     }
 }
 
+/// Whether two rule parameters could match the same argument. Only literal parameters are
+/// compared, so this errs on the side of overlapping.
+fn parameters_overlap(left: &Parameter, right: &Parameter) -> bool {
+    let is_literal = |param: &Parameter| {
+        matches!(
+            param.parameter.value(),
+            Value::Number(_) | Value::String(_) | Value::Boolean(_)
+        )
+    };
+    !(is_literal(left) && is_literal(right) && left.parameter != right.parameter)
+}
+
+/// Deny rules only take effect when an allow rule applies to the same arguments, so warn about
+/// deny rules that don't overlap any allow rule.
+pub fn check_unreachable_deny_rules(kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    let rules = |name| {
+        let mut rules = kb
+            .get_rules()
+            .get(&sym!(name))
+            .map(|generic_rule| generic_rule.rules.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        rules.sort_by_key(|(id, _)| **id);
+        rules.into_iter().map(|(_, rule)| rule).collect::<Vec<_>>()
+    };
+    let allow_rules = rules("allow");
+    rules("deny")
+        .into_iter()
+        .filter(|deny| {
+            !allow_rules.iter().any(|allow| {
+                allow.params.len() == deny.params.len()
+                    && allow
+                        .params
+                        .iter()
+                        .zip(&deny.params)
+                        .all(|(a, d)| parameters_overlap(a, d))
+            })
+        })
+        .map(|deny| {
+            let rule = deny.as_ref().clone();
+            Diagnostic::Warning(ValidationWarning::UnreachableDenyRule { rule }.with_context(kb))
+        })
+        .collect()
+}
+
 struct ResourceBlocksMissingHasPermissionVisitor {
     calls_has_permission: bool,
 }
@@ -186,6 +300,227 @@ pub fn check_resource_blocks_missing_has_permission(
     visitor.warnings()
 }
 
+/// Collects the arguments passed in calls to one rule, e.g., to `has_role`.
+struct CallArgsVisitor<'a> {
+    name: &'a str,
+    args: Vec<Vec<Term>>,
+}
+
+impl<'a> Visitor for CallArgsVisitor<'a> {
+    fn visit_call(&mut self, call: &Call) {
+        if call.name.0 == self.name {
+            self.args.push(call.args.clone());
+        }
+        walk_call(self, call)
+    }
+}
+
+/// The names in position `index` of the `name` rule's heads (if `heads` is true) or of the calls to
+/// it, e.g., the roles in `has_role(user: User, "admin", org: Org)`. Returns `None` if any of them
+/// could be any name, e.g., because it's a variable.
+fn referenced_names(
+    kb: &KnowledgeBase,
+    name: &str,
+    index: usize,
+    heads: bool,
+) -> Option<HashSet<String>> {
+    let terms: Vec<Term> = if heads {
+        kb.get_rules()
+            .get(&sym!(name))
+            .into_iter()
+            .flat_map(|generic_rule| generic_rule.rules.values())
+            .filter_map(|rule| rule.params.get(index))
+            .map(|param| param.parameter.clone())
+            .collect()
+    } else {
+        let mut visitor = CallArgsVisitor { name, args: vec![] };
+        for rule in kb.get_rules().values() {
+            visitor.visit_generic_rule(rule);
+        }
+        let args = visitor.args.into_iter();
+        args.filter_map(|mut args| (index < args.len()).then(|| args.swap_remove(index)))
+            .collect()
+    };
+    terms
+        .iter()
+        .map(|term| term.value().as_string().ok().map(ToOwned::to_owned))
+        .collect()
+}
+
+fn sorted_terms<'a>(terms: impl IntoIterator<Item = &'a Term>) -> Vec<&'a Term> {
+    let mut terms: Vec<_> = terms.into_iter().collect();
+    terms.sort_by_key(|term| term.to_string());
+    terms
+}
+
+/// A role, permission or relation, identified by `(block, name)`.
+type Declared<'a> = (&'a Term, &'a Term);
+
+/// Every node reachable from `start` by following one or more `edges`.
+fn reachable_from<'a>(
+    start: Vec<Declared<'a>>,
+    edges: &HashMap<Declared<'a>, Vec<Declared<'a>>>,
+) -> HashSet<Declared<'a>> {
+    let mut reached = HashSet::new();
+    let mut stack = start;
+    while let Some(node) = stack.pop() {
+        for next in edges.get(&node).into_iter().flatten() {
+            if reached.insert(*next) {
+                stack.push(*next);
+            }
+        }
+    }
+    reached
+}
+
+/// Analyze how the roles, permissions and relations declared in resource blocks are used, and warn
+/// about permissions no role grants, roles that neither grant anything nor are assigned, relations
+/// no shorthand rule follows, and roles that imply each other.
+///
+/// Has to run before shorthand rules are rewritten, so that the rules in the KB are the ones
+/// written in the policy.
+pub fn check_resource_block_usage(kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    let blocks = &kb.resource_blocks;
+    let global = term!(sym!(GLOBAL_BLOCK_NAME));
+
+    let mut implications: HashMap<Declared, Vec<Declared>> = HashMap::new();
+    let mut equivalences: HashMap<Declared, Vec<Declared>> = HashMap::new();
+    let mut impliers = HashSet::new();
+    let mut used_relations = HashSet::new();
+    for (resource, shorthand_rules) in &blocks.shorthand_rules {
+        for rule in shorthand_rules {
+            let head = (resource, &rule.head);
+            for implier in &rule.body {
+                let implier = match implier {
+                    Implier::Resource(name, None) => (resource, name),
+                    Implier::Resource(name, Some((_, relation))) => {
+                        let hops =
+                            match blocks.get_relation_path_in_resource_block(relation, resource) {
+                                Ok(hops) => hops,
+                                Err(_) => continue,
+                            };
+                        let mut current = resource;
+                        for (hop, related) in hops {
+                            used_relations.insert((current.clone(), hop));
+                            current = related;
+                        }
+                        (current, name)
+                    }
+                    Implier::Global(_, role) => (&global, role),
+                };
+                impliers.insert(implier);
+                if rule.deny.is_none() {
+                    implications.entry(implier).or_default().push(head);
+                    if implier.0 == resource && rule.condition.is_none() {
+                        equivalences.entry(implier).or_default().push(head);
+                    }
+                }
+            }
+        }
+    }
+    let granted_permissions = referenced_names(kb, "has_permission", 1, true);
+    let assigned_roles = referenced_names(kb, "has_role", 1, true);
+    let checked_roles = referenced_names(kb, "has_role", 1, false);
+    let checked_relations = referenced_names(kb, "has_relation", 1, false);
+    let mentions = |names: &Option<HashSet<String>>, term: &Term| match (names, term.value()) {
+        (Some(names), Value::String(name)) => names.contains(name),
+        _ => true,
+    };
+
+    // Everything implied by a role, or by a permission granted by a `has_permission` rule.
+    let mut grantors = vec![];
+    for role in blocks.global_roles() {
+        grantors.push((&global, role));
+    }
+    let resources = sorted_terms(blocks.declarations().keys());
+    for resource in &resources {
+        for (name, declaration) in &blocks.declarations()[*resource] {
+            match declaration {
+                Declaration::Role => grantors.push((*resource, name)),
+                Declaration::Permission if mentions(&granted_permissions, name) => {
+                    grantors.push((*resource, name))
+                }
+                _ => {}
+            }
+        }
+    }
+    let granted = reachable_from(grantors, &implications);
+
+    let mut warnings = vec![];
+    for resource in resources {
+        let declarations = &blocks.declarations()[resource];
+        for name in sorted_terms(declarations.keys()) {
+            let node = (resource, name);
+            let warning = match &declarations[name] {
+                Declaration::Permission
+                    if !granted.contains(&node) && !mentions(&granted_permissions, name) =>
+                {
+                    ValidationWarning::UnreachablePermission {
+                        term: name.clone(),
+                        resource: resource.clone(),
+                    }
+                }
+                Declaration::Role
+                    if !impliers.contains(&node)
+                        && !mentions(&checked_roles, name)
+                        && !granted.contains(&node)
+                        && !mentions(&assigned_roles, name) =>
+                {
+                    ValidationWarning::UnusedRole {
+                        term: name.clone(),
+                        resource: resource.clone(),
+                    }
+                }
+                Declaration::Relation(_)
+                    if !used_relations.contains(&(resource.clone(), name.clone()))
+                        && !impliers.contains(&node)
+                        && !mentions(&checked_relations, name) =>
+                {
+                    ValidationWarning::UnusedRelation {
+                        term: name.clone(),
+                        resource: resource.clone(),
+                    }
+                }
+                _ => continue,
+            };
+            warnings.push(warning);
+        }
+
+        // Roles that unconditionally imply each other within the same block can only ever be held
+        // together.
+        let mut seen = HashSet::new();
+        for name in sorted_terms(declarations.keys()) {
+            if seen.contains(name) || !matches!(declarations[name], Declaration::Role) {
+                continue;
+            }
+            let implied = reachable_from(vec![(resource, name)], &equivalences);
+            let mut cycle: Vec<_> = implied
+                .into_iter()
+                .filter(|(_, other)| other != &name)
+                .filter(|other| {
+                    reachable_from(vec![*other], &equivalences).contains(&(resource, name))
+                })
+                .map(|(_, other)| other)
+                .collect();
+            if cycle.is_empty() {
+                continue;
+            }
+            cycle.push(name);
+            let roles: Vec<_> = sorted_terms(cycle).into_iter().cloned().collect();
+            seen.extend(roles.iter().cloned());
+            warnings.push(ValidationWarning::EquivalentRoles {
+                roles,
+                resource: resource.clone(),
+            });
+        }
+    }
+
+    warnings
+        .into_iter()
+        .map(|w| Diagnostic::Warning(w.with_context(kb)))
+        .collect()
+}
+
 struct UndefinedRuleCallVisitor<'kb> {
     call_terms: Vec<Term>,
     defined_rules: HashSet<&'kb Symbol>,
@@ -204,7 +539,8 @@ impl<'kb> UndefinedRuleCallVisitor<'kb> {
             .into_iter()
             .filter(|term| {
                 let call = term.value().as_call().unwrap();
-                !self.defined_rules.contains(&call.name)
+                let builtin = call.name.0 == RELATIONSHIP_PREDICATE && call.args.len() == 3;
+                !(builtin || self.defined_rules.contains(&call.name))
             })
             .map(|term| ValidationError::UndefinedRuleCall { term })
             .collect()
@@ -325,4 +661,113 @@ mod tests {
         kb.add_rule(rule!("defined_rule", [sym!("x")]));
         assert!(check_undefined_rule_calls(&kb).is_empty());
     }
+
+    #[test]
+    fn test_check_unreachable_deny_rules() {
+        let mut kb = KnowledgeBase::new();
+        kb.add_rule(rule!("allow", [sym!("actor"), "read", sym!("resource")]));
+        kb.add_rule(rule!("deny", [sym!("actor"), "read", sym!("resource")]));
+        kb.add_rule(rule!(
+            "deny",
+            [sym!("actor"), sym!("action"), sym!("resource")]
+        ));
+        assert!(check_unreachable_deny_rules(&kb).is_empty());
+
+        kb.add_rule(rule!("deny", [sym!("actor"), "delete", sym!("resource")]));
+        let warnings = check_unreachable_deny_rules(&kb);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            r#"Deny rule can never take effect, because no allow rule applies to the same arguments: deny(actor, "delete", resource);"#
+        );
+
+        kb.add_rule(rule!(
+            "allow",
+            [sym!("actor"), sym!("action"), sym!("resource")]
+        ));
+        assert!(check_unreachable_deny_rules(&kb).is_empty());
+    }
+
+    #[test]
+    fn test_check_resource_block_usage() {
+        use crate::polar::Polar;
+        use crate::sources::Source;
+
+        let warnings = |src: &str| {
+            let polar = Polar::new();
+            for class in ["User", "Org", "Repo"] {
+                polar.register_constant(sym!(class), term!(1)).unwrap();
+            }
+            let source = Source {
+                filename: None,
+                src: src.to_owned(),
+            };
+            let mut warnings = vec![];
+            for diagnostic in polar.diagnostic_load(vec![source]) {
+                match diagnostic {
+                    Diagnostic::Warning(w) => match w.kind {
+                        ValidationWarning::UnreachablePermission { .. }
+                        | ValidationWarning::UnusedRole { .. }
+                        | ValidationWarning::UnusedRelation { .. }
+                        | ValidationWarning::EquivalentRoles { .. } => {
+                            assert!(w.context.is_some());
+                            warnings.push(w.kind.to_string())
+                        }
+                        _ => {}
+                    },
+                    Diagnostic::Error(e) => panic!("{}", e),
+                }
+            }
+            warnings
+        };
+
+        let policy = r#"
+            allow(actor, action, resource) if has_permission(actor, action, resource);
+            actor User {}
+            resource Org {
+                roles = ["member"];
+            }
+            resource Repo {
+                permissions = ["read", "push", "delete", "archive"];
+                roles = ["reader", "writer", "maintainer", "auditor", "guest"];
+                relations = { parent: Org, mirror: Repo };
+
+                "read" if "reader" or "member" on "parent";
+                "reader" if "writer";
+                "writer" if "maintainer";
+                "maintainer" if "writer";
+                "push" if "writer";
+                "archive" if "delete";
+            }
+            has_role(_: User, "member", _: Org);
+            has_role(_: User, "reader", _: Repo);
+            has_role(_: User, "auditor", _: Repo);
+            has_relation(_: Org, "parent", _: Repo);
+        "#;
+        assert_eq!(
+            warnings(policy),
+            vec![
+                r#"Permission "archive" in the 'Repo' resource block is not granted by any role."#,
+                r#"Permission "delete" in the 'Repo' resource block is not granted by any role."#,
+                r#"Role "guest" in the 'Repo' resource block doesn't grant anything and is never assigned by a has_role rule."#,
+                r#"Relation "mirror" in the 'Repo' resource block is never used in a shorthand rule."#,
+                r#"Roles "maintainer", "writer" in the 'Repo' resource block imply each other, so they are equivalent. Did you mean to declare a single role?"#,
+            ]
+        );
+
+        // Rules written in the policy can grant permissions, assign roles and follow relations.
+        let policy = policy.replace(
+            r#"has_role(_: User, "auditor", _: Repo);"#,
+            r#"has_role(_: User, "auditor", _: Repo);
+            has_permission(_: User, "delete", _: Repo);
+            has_role(_: User, name: String, _: Repo) if name = "guest";
+            allow(_, "mirror", repo: Repo) if has_relation(_, "mirror", repo);"#,
+        );
+        assert_eq!(
+            warnings(&policy),
+            vec![
+                r#"Roles "maintainer", "writer" in the 'Repo' resource block imply each other, so they are equivalent. Did you mean to declare a single role?"#,
+            ]
+        );
+    }
 }
@@ -10,6 +10,7 @@ use std::sync::{Arc, RwLock, RwLockReadGuard};
 use wasm_bindgen::prelude::*;
 
 use super::visitor::{walk_term, Visitor};
+use crate::aggregator::Aggregator;
 use crate::bindings::{
     Binding, BindingManager, BindingStack, Bindings, Bsp, FollowerId, VariableState,
 };
@@ -26,10 +27,12 @@ use crate::lexer::loc_to_pos;
 use crate::messages::*;
 use crate::numerics::*;
 use crate::partial::{simplify_bindings_opt, simplify_partial, sub_this, IsaConstraintCheck};
+use crate::relationships::RELATIONSHIP_PREDICATE;
 use crate::rewrites::Renamer;
 use crate::rules::*;
 use crate::runnable::Runnable;
 use crate::sources::*;
+use crate::tabling::{unsupported_partial, variant, Tabled, Tabler, Tables};
 use crate::terms::*;
 use crate::traces::*;
 
@@ -134,6 +137,18 @@ pub enum Goal {
     AddConstraintsBatch {
         add_constraints: Rc<RefCell<Bindings>>,
     },
+
+    /// Unify `left` with the value left in `result` by a runnable (e.g., an aggregate).
+    UnifyResult {
+        left: Term,
+        result: Rc<RefCell<Option<Term>>>,
+    },
+
+    /// Unify `left` with each of the answers left in `answers` by a tabled call, in turn.
+    UnifyAnswers {
+        left: Term,
+        answers: Rc<RefCell<Vec<Term>>>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -262,6 +277,9 @@ pub struct PolarVirtualMachine {
     pub query_contains_partial: bool,
     pub inverting: bool,
 
+    /// Answers to tabled calls, shared with sub-VMs.
+    pub tables: Rc<RefCell<Tables>>,
+
     /// Output messages.
     pub messages: MessageQueue,
 }
@@ -336,6 +354,7 @@ impl PolarVirtualMachine {
             polar_log_mute: false,
             query_contains_partial: false,
             inverting: false,
+            tables: Rc::new(RefCell::new(Tables::default())),
             messages,
         };
         vm.bind_constants(constants);
@@ -395,15 +414,23 @@ impl PolarVirtualMachine {
         let mut vm = Self::new(self.kb.clone(), self.tracing, goals, self.messages.clone());
         vm.binding_manager.clone_from(&self.binding_manager);
         vm.query_contains_partial = self.query_contains_partial;
+        vm.tables = self.tables.clone();
         vm.debugger = self.debugger.clone();
+        vm.query_timeout_ms = self.query_timeout_ms;
+        vm.stack_limit = self.stack_limit;
         vm
     }
 
-    #[cfg(test)]
-    fn set_stack_limit(&mut self, limit: usize) {
+    /// Set the maximum size of the goal and choice stacks.
+    pub fn set_stack_limit(&mut self, limit: usize) {
         self.stack_limit = limit;
     }
 
+    /// Override the query timeout, which otherwise comes from `POLAR_TIMEOUT_MS`. `0` disables it.
+    pub fn set_query_timeout_ms(&mut self, timeout_ms: u64) {
+        self.query_timeout_ms = timeout_ms;
+    }
+
     pub fn kb(&self) -> RwLockReadGuard<KnowledgeBase> {
         self.kb.read().unwrap()
     }
@@ -528,6 +555,14 @@ impl PolarVirtualMachine {
                     .try_for_each(|(_, constraint)| self.add_constraint(&constraint))?
             }
             Goal::Run { runnable } => return self.run_runnable(runnable.clone_runnable()),
+            Goal::UnifyResult { left, result } => match result.borrow().clone() {
+                Some(right) => self.unify(left, &right)?,
+                None => self.push_goal(Goal::Backtrack)?,
+            },
+            Goal::UnifyAnswers { left, answers } => {
+                let answers = answers.borrow().clone();
+                self.unify_answers(left, answers)?
+            }
         }
         Ok(QueryEvent::None)
     }
@@ -718,13 +753,23 @@ impl PolarVirtualMachine {
         self.binding_manager.variable_state_at_point(variable, bsp)
     }
 
+    /// Return the constraints on a variable at some point.
+    pub fn constraints_at_point(&self, variable: &Symbol, bsp: &Bsp) -> Operation {
+        self.binding_manager.get_constraints_at_point(variable, bsp)
+    }
+
     /// Investigate the current state of a variable and return a variable state variant.
     pub fn variable_state(&self, variable: &Symbol) -> VariableState {
         self.binding_manager.variable_state(variable)
     }
 
+    /// Whether a variable has constraints, i.e., is a partial.
+    pub fn is_constrained(&self, variable: &Symbol) -> bool {
+        self.binding_manager.is_constrained(variable)
+    }
+
     /// Recursively dereference variables in a term, including subterms, except operations.
-    fn deref(&self, term: &Term) -> Term {
+    pub fn deref(&self, term: &Term) -> Term {
         self.binding_manager.deep_deref(term)
     }
 
@@ -735,6 +780,13 @@ impl PolarVirtualMachine {
         renamer.fold_rule(rule.clone())
     }
 
+    /// Generate a fresh set of variables for a term.
+    fn rename_vars(&self, term: &Term) -> Term {
+        let kb = &*self.kb.read().unwrap();
+        let mut renamer = Renamer::new(kb);
+        renamer.fold_term(term.clone())
+    }
+
     /// Push or print a message to the output stream.
     #[cfg(not(target_arch = "wasm32"))]
     fn print<S: Into<String>>(&self, message: S) {
@@ -984,14 +1036,8 @@ impl PolarVirtualMachine {
             }
 
             _ if self.kb.read().unwrap().is_union(left) => {
-                // A union (currently) only matches itself.
-                //
-                // TODO(gj): when we have unions beyond `Actor` and `Resource`, we'll need to be
-                // smarter about this check since UnionA is more specific than UnionB if UnionA is
-                // a member of UnionB.
-                let unions_match = (left.is_actor_union() && right.is_actor_union())
-                    || (left.is_resource_union() && right.is_resource_union());
-                if !unions_match {
+                // A union only matches itself, since unions can't be members of other unions.
+                if left.specializer_tag() != right.specializer_tag() {
                     return self.push_goal(Goal::Backtrack);
                 }
             }
@@ -1501,8 +1547,41 @@ impl PolarVirtualMachine {
                 predicate.to_polar()
             ));
         }
-        let goals = match self.kb.read().unwrap().get_generic_rule(&predicate.name) {
+        if predicate.name.0 == RELATIONSHIP_PREDICATE && predicate.args.len() == 3 {
+            return self.query_for_relationship(predicate);
+        }
+        if self.kb().is_tabled(&predicate.name) {
+            return self.query_for_tabled_predicate(predicate);
+        }
+        let goals = match self.filter_rules_goal(predicate)? {
             None => vec![Goal::Backtrack],
+            Some(filter_rules) => vec![Goal::TraceStackPush, filter_rules, Goal::TraceStackPop],
+        };
+        self.append_goals(goals)
+    }
+
+    /// Query the relationship store, then any rules named `relationship`.
+    fn query_for_relationship(&mut self, predicate: Call) -> Result<()> {
+        let values: Vec<Term> = predicate.args.iter().map(|t| self.deref(t)).collect();
+        let mut alternatives = self
+            .kb()
+            .relationships
+            .alternatives(&predicate.args, &values);
+        if let Some(filter_rules) = self.filter_rules_goal(predicate)? {
+            alternatives.push(vec![
+                Goal::TraceStackPush,
+                filter_rules,
+                Goal::TraceStackPop,
+            ]);
+        }
+        self.choose(alternatives)
+    }
+
+    /// Pre-filter the rules for predicate, and return a goal that filters them by
+    /// applicability. Return `None` if there are no rules for predicate.
+    fn filter_rules_goal(&mut self, predicate: Call) -> Result<Option<Goal>> {
+        match self.kb.read().unwrap().get_generic_rule(&predicate.name) {
+            None => Ok(None),
             Some(generic_rule) => {
                 if generic_rule.name != predicate.name {
                     return invalid_state(format!(
@@ -1518,18 +1597,56 @@ impl PolarVirtualMachine {
                 self.polar_log_mute = true;
 
                 // Filter rules by applicability.
-                vec![
-                    Goal::TraceStackPush,
-                    Goal::FilterRules {
-                        applicable_rules: vec![],
-                        unfiltered_rules: pre_filter,
-                        args: predicate.args,
-                    },
-                    Goal::TraceStackPop,
-                ]
+                Ok(Some(Goal::FilterRules {
+                    applicable_rules: vec![],
+                    unfiltered_rules: pre_filter,
+                    args: predicate.args,
+                }))
             }
-        };
-        self.append_goals(goals)
+        }
+    }
+
+    /// Answer a call to a tabled rule from its table, evaluating the call
+    /// first if it hasn't been already.
+    fn query_for_tabled_predicate(&mut self, predicate: Call) -> Result<()> {
+        let call = self.deref(&Term::from(Value::Call(predicate.clone())));
+        let mut variables = HashSet::new();
+        call.variables(&mut variables);
+        if variables.iter().any(|var| self.is_constrained(var)) {
+            return Err(unsupported_partial(&call));
+        }
+
+        let lookup = self.tables.borrow_mut().lookup(&variant(&call));
+        match lookup {
+            Tabled::Answers(answers) => self.unify_answers(&call, answers),
+            Tabled::Evaluate => {
+                let goals = self.filter_rules_goal(predicate)?.into_iter().collect();
+                let answers = Rc::new(RefCell::new(vec![]));
+                let tabler = Box::new(Tabler::new(self, goals, call.clone(), answers.clone()));
+                self.choose_conditional(
+                    vec![Goal::Run { runnable: tabler }],
+                    vec![Goal::UnifyAnswers {
+                        left: call,
+                        answers,
+                    }],
+                    vec![Goal::Backtrack],
+                )
+            }
+        }
+    }
+
+    /// Create a choice over unifying `call` with each of the tabled `answers`.
+    fn unify_answers(&mut self, call: &Term, answers: Vec<Term>) -> Result<()> {
+        let alternatives = answers
+            .iter()
+            .map(|answer| {
+                vec![Goal::Unify {
+                    left: call.clone(),
+                    right: self.rename_vars(answer),
+                }]
+            })
+            .collect::<Vec<_>>();
+        self.choose(alternatives)
     }
 
     fn query_for_operation(&mut self, term: &Term) -> Result<QueryEvent> {
@@ -1748,6 +1865,33 @@ impl PolarVirtualMachine {
                     term: double_negation,
                 })?;
             }
+            Operator::Count | Operator::Collect | Operator::Sum | Operator::Min | Operator::Max => {
+                // Query in a sub-VM and aggregate the results.
+                if args.len() != 3 {
+                    return wrong_arity();
+                }
+                let result = args.pop().unwrap();
+                let goal = args.pop().unwrap();
+                let template = args.pop().unwrap();
+                let aggregate = Rc::new(RefCell::new(None));
+                let aggregator = Box::new(Aggregator::new(
+                    self,
+                    term.clone(),
+                    template,
+                    goal,
+                    aggregate.clone(),
+                ));
+                self.choose_conditional(
+                    vec![Goal::Run {
+                        runnable: aggregator,
+                    }],
+                    vec![Goal::UnifyResult {
+                        left: result,
+                        result: aggregate,
+                    }],
+                    vec![Goal::Backtrack],
+                )?;
+            }
         }
         Ok(QueryEvent::None)
     }
@@ -2635,11 +2779,8 @@ impl PolarVirtualMachine {
         for ((left_param, right_param), arg) in zipped {
             match (&left_param.specializer, &right_param.specializer) {
                 // If both specs are unions, they have the same specificity regardless of whether
-                // they're the same or different unions.
-                //
-                // TODO(gj): when we have unions beyond `Actor` and `Resource`, we'll need to be
-                // smarter about this check since UnionA is more specific than UnionB if UnionA is
-                // a member of UnionB.
+                // they're the same or different unions, since unions can't be members of other
+                // unions.
                 (Some(left_spec), Some(right_spec))
                     if self.kb.read().unwrap().is_union(left_spec)
                         && self.kb.read().unwrap().is_union(right_spec) => {}
@@ -2793,7 +2934,7 @@ impl PolarVirtualMachine {
         rule.to_polar()
     }
 
-    fn type_error(&self, term: &Term, msg: String) -> RuntimeError {
+    pub fn type_error(&self, term: &Term, msg: String) -> RuntimeError {
         RuntimeError::TypeError {
             msg,
             stack_trace: self.stack_trace(),
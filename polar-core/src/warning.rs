@@ -4,6 +4,7 @@ use indoc::indoc;
 
 use super::diagnostic::{Context, Range};
 use super::kb::KnowledgeBase;
+use super::rules::Rule;
 use super::terms::{InstanceLiteral, Pattern, Symbol, Term, Value};
 
 #[derive(Debug)]
@@ -34,6 +35,16 @@ pub enum ValidationWarning {
     // TODO(gj): won't need `sym` once we have an easier, infallible way of going from `Term` ->
     // `Pattern` -> `InstanceLiteral` -> `tag` (`Symbol`).
     UnknownSpecializer { term: Term, sym: Symbol },
+    // Category: enforcement
+    UnreachableDenyRule { rule: Rule },
+    // Category: resource blocks
+    UnreachablePermission { term: Term, resource: Term },
+    // Category: resource blocks
+    UnusedRole { term: Term, resource: Term },
+    // Category: resource blocks
+    UnusedRelation { term: Term, resource: Term },
+    // Category: resource blocks
+    EquivalentRoles { roles: Vec<Term>, resource: Term },
 }
 
 impl ValidationWarning {
@@ -41,9 +52,15 @@ impl ValidationWarning {
         use ValidationWarning::*;
 
         let context = match &self {
-            AmbiguousPrecedence { term } | UnknownSpecializer { term, .. } => {
-                term.span().zip(kb.get_term_source(term))
-            }
+            AmbiguousPrecedence { term }
+            | UnknownSpecializer { term, .. }
+            | UnreachablePermission { term, .. }
+            | UnusedRole { term, .. }
+            | UnusedRelation { term, .. } => term.span().zip(kb.get_term_source(term)),
+            EquivalentRoles { roles, .. } => roles
+                .first()
+                .and_then(|term| term.span().zip(kb.get_term_source(term))),
+            UnreachableDenyRule { rule } => rule.span().zip(kb.get_rule_source(rule)),
             MissingAllowRule | MissingHasPermissionRule => None,
         };
 
@@ -142,6 +159,35 @@ impl fmt::Display for ValidationWarning {
                     write!(f, ", did you mean {}?", suggestion)?;
                 }
             }
+            UnreachableDenyRule { rule } => write!(
+                f,
+                "Deny rule can never take effect, because no allow rule applies to the same arguments: {}",
+                rule
+            )?,
+            UnreachablePermission { term, resource } => write!(
+                f,
+                "Permission {} in the '{}' resource block is not granted by any role.",
+                term, resource
+            )?,
+            UnusedRole { term, resource } => write!(
+                f,
+                "Role {} in the '{}' resource block doesn't grant anything and is never assigned by a has_role rule.",
+                term, resource
+            )?,
+            UnusedRelation { term, resource } => write!(
+                f,
+                "Relation {} in the '{}' resource block is never used in a shorthand rule.",
+                term, resource
+            )?,
+            EquivalentRoles { roles, resource } => {
+                let roles = roles.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(
+                    f,
+                    "Roles {} in the '{}' resource block imply each other, so they are equivalent. Did you mean to declare a single role?",
+                    roles.join(", "),
+                    resource
+                )?
+            }
         }
 
         Ok(())
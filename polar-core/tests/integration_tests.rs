@@ -1869,6 +1869,77 @@ fn test_circular_data() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_query_limits() -> TestResult {
+    let p = polar();
+    p.load_str("f(x) if f(x);")?;
+
+    let mut query = p.new_query("f(1)", false)?;
+    query.set_stack_limit(10);
+    assert!(matches!(
+        query.next_event().unwrap_err().kind,
+        ErrorKind::Runtime(RuntimeError::StackOverflow { .. })
+    ));
+
+    let mut query = p.new_query("f(1)", false)?;
+    query.set_query_timeout_ms(1);
+    query.set_stack_limit(usize::MAX);
+    assert!(matches!(
+        query.next_event().unwrap_err().kind,
+        ErrorKind::Runtime(RuntimeError::QueryTimeout { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_policy_snapshots() -> TestResult {
+    let p = polar();
+    p.register_constant(sym!("x"), term!(1))?;
+    p.load(vec![polar_core::sources::Source {
+        filename: Some("policy.polar".to_owned()),
+        src: indoc! {r#"
+            f(1);
+            f(2);
+            f(x) if x = 3;
+            g(x) if x + "a" = 1;
+            ?= f(1);
+        "#}
+        .to_owned(),
+    }])?;
+    p.add_relationship(term!(1), "parent", term!(2));
+    let snapshot = p.export_snapshot()?;
+
+    let restored = Polar::from_snapshot(&snapshot)?;
+    qvar(&restored, "f(x)", "x", values![1, 2, 3]);
+    qvar(&restored, r#"relationship(1, "parent", y)"#, "y", values![2]);
+    assert!(restored.next_inline_query(false).is_some());
+    assert!(restored.next_inline_query(false).is_none());
+
+    // Errors still point at the original source.
+    let err = restored.new_query("g(1)", false)?.next_event().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("at line 4, column 9 of file policy.polar"),
+        "{}",
+        err
+    );
+
+    // Snapshots can only be loaded once, and warn about constants that haven't been registered.
+    let restored = polar();
+    restored.load_snapshot(&snapshot)?;
+    let msg = restored.next_message().unwrap();
+    assert!(matches!(msg.kind, MessageKind::Warning));
+    assert!(msg.msg.contains("constant `x`"), "{}", msg.msg);
+    assert!(matches!(
+        restored.load_snapshot(&snapshot).unwrap_err().kind,
+        ErrorKind::Runtime(RuntimeError::FileLoading { .. })
+    ));
+    qvar(&restored, "f(x)", "x", values![1, 2, 3]);
+
+    assert!(Polar::from_snapshot(&snapshot[1..]).is_err());
+    Ok(())
+}
+
 #[test]
 fn test_data_filtering_dict_specializers() -> TestResult {
     let pol_a = "allow(x, \"read\", _y: { x: x });";
@@ -2140,6 +2211,403 @@ fn test_forall() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_aggregates() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"approved(doc) if count(a, a in doc.approvers and a.signed, n) and n >= 2;
+           g(1);
+           g(2);
+           g(2);"#,
+    )?;
+
+    qvar(&p, "count(x, g(x), n)", "n", values![3]);
+    qvar(&p, "count(x, g(x) and x > 5, n)", "n", values![0]);
+    qvar(&p, "count(x, x = y, n)", "n", values![1]);
+    qvar(&p, "collect(x, g(x), l)", "l", vec![value!([1, 2, 2])]);
+    qvar(
+        &p,
+        "collect(x, g(x) and x > 5, l)",
+        "l",
+        vec![Value::List(vec![])],
+    );
+    qvar(
+        &p,
+        "collect([x, y], x in [1, 2] and y = x * 2, l)",
+        "l",
+        vec![value!([value!([1, 2]), value!([2, 4])])],
+    );
+    qvar(&p, "sum(x, g(x), s)", "s", values![5]);
+    qvar(&p, "sum(x, x in [1, 2.5], s)", "s", values![3.5]);
+    qvar(&p, "sum(x, g(x) and x > 5, s)", "s", values![0]);
+    qvar(&p, "min(x, x in [3, 1, 2], m)", "m", values![1]);
+    qvar(
+        &p,
+        "max(x, x in [\"b\", \"c\", \"a\"], m)",
+        "m",
+        values!["c"],
+    );
+    qnull(&p, "min(x, g(x) and x > 5, m)");
+    qnull(&p, "max(x, x in [], m)");
+
+    // The result can be ground, and the goal's bindings aren't visible outside of it.
+    qeval(&p, "count(x, g(x), 3)");
+    qnull(&p, "count(x, g(x), 2)");
+    qvar(&p, "count(x, g(x), 3) and x = 4", "x", values![4]);
+
+    // Lookups in the template are made for each solution.
+    qvar(
+        &p,
+        "collect(d.name, d in [{name: \"a\"}, {name: \"b\"}], l)",
+        "l",
+        vec![value!(["a", "b"])],
+    );
+
+    qeval(
+        &p,
+        "approved({approvers: [{signed: true}, {signed: false}, {signed: true}]})",
+    );
+    qnull(
+        &p,
+        "approved({approvers: [{signed: true}, {signed: false}]})",
+    );
+
+    qruntime!(
+        &p,
+        "sum(x, x in [1, \"a\"], s)",
+        RuntimeError::TypeError { .. }
+    );
+    qruntime!(
+        &p,
+        "collect(y, x in [1], l)",
+        RuntimeError::TypeError { .. }
+    );
+    qruntime!(
+        &p,
+        "min(x, x in [1, \"a\"], m)",
+        RuntimeError::Unsupported { .. }
+    );
+    Ok(())
+}
+
+#[test]
+fn test_deny_takes_precedence_over_allow() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"allow(_, action, _) if action in ["read", "write"];
+           deny(_, "write", "archive");"#,
+    )?;
+    qeval(&p, r#"allow("alice", "write", "doc")"#);
+    qnull(&p, r#"allow("alice", "write", "archive")"#);
+    qvar(
+        &p,
+        r#"allow("alice", action, "archive")"#,
+        "action",
+        values!["read"],
+    );
+    // Only queries for `allow` itself check `deny` rules.
+    qeval(&p, r#"allow("alice", "write", "archive") and true"#);
+    Ok(())
+}
+
+#[test]
+fn test_aggregate_names_arent_reserved() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"f(count) if count > 1;
+           max(a, b, a) if a > b;
+           max(a, b, b) if a <= b;
+           g(sum) if sum = 1;
+           max(x, y) if x > y;"#,
+    )?;
+    qeval(&p, "f(2)");
+    qnull(&p, "f(1)");
+    qeval(&p, "g(1)");
+    // Calls whose second argument isn't a logical expression aren't aggregates.
+    qvar(&p, "max(1, 2, m)", "m", values![2]);
+    qvar(&p, "x = 3 and max(x, 2, m)", "m", values![3]);
+    qeval(&p, "max(2, 1)");
+    qeval(&p, "x = {count: 1, min: 2} and x.count < x.min");
+    Ok(())
+}
+
+#[test]
+fn test_tabling() -> TestResult {
+    let p = Polar::new();
+    p.load_str(
+        r#"edge("a", "b");
+           edge("b", "c");
+           edge("c", "a");
+           edge("c", "d");
+           edge("b", "d");
+
+           # Left recursion over a cycle.
+           type tabled left(x, y);
+           left(x, y) if edge(x, y);
+           left(x, y) if left(x, z) and edge(z, y);
+
+           # Right recursion over a cycle.
+           type tabled right(x, y);
+           right(x, y) if edge(x, y);
+           right(x, y) if edge(x, z) and right(z, y);
+
+           # Mutual recursion.
+           type tabled even(x, y);
+           type tabled odd(x, y);
+           even(x, x) if edge(x, _) or edge(_, x);
+           even(x, y) if odd(x, z) and edge(z, y);
+           odd(x, y) if even(x, z) and edge(z, y);
+
+           # Answers with unbound variables.
+           type tabled pair(x, y);
+           pair(x, [x, _]) if edge(x, _);"#,
+    )?;
+
+    let all = || vec![value!("a"), value!("b"), value!("c"), value!("d")];
+    let sorted = |mut values: Vec<Value>| {
+        values.sort_by_key(|v| v.to_polar());
+        values
+    };
+
+    // Each answer is found once, and both directions terminate.
+    assert_eq!(sorted(var(&p, r#"left("a", y)"#, "y")), all());
+    assert_eq!(sorted(var(&p, r#"right("a", y)"#, "y")), all());
+    assert_eq!(
+        sorted(var(&p, r#"left(x, "d")"#, "x")),
+        vec![value!("a"), value!("b"), value!("c")]
+    );
+    assert_eq!(var(&p, r#"right("d", y)"#, "y"), vec![]);
+    assert_eq!(vars(&p, "left(x, y)", &["x", "y"]).len(), 12);
+    qeval(&p, r#"right("c", "b") and left("c", "b")"#);
+    qnull(&p, r#"left("d", "a")"#);
+
+    // A path of even length from "a" to itself is a -> b -> c -> a -> b -> c -> a.
+    qeval(&p, r#"even("a", "a") and odd("a", "a")"#);
+    qnull(&p, r#"odd("d", _)"#);
+    assert_eq!(sorted(var(&p, r#"odd("a", y)"#, "y")), all());
+
+    // Answers can contain variables, which are fresh for each use.
+    assert_eq!(
+        var(
+            &p,
+            r#"pair("a", ["a", 1]) and pair("a", [_, y]) and y = 2"#,
+            "y"
+        ),
+        vec![value!(2)]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_modules() -> TestResult {
+    let source = |filename: &str, src: &str| polar_core::sources::Source {
+        filename: Some(filename.to_owned()),
+        src: src.to_owned(),
+    };
+    let billing = source(
+        "billing.polar",
+        r#"module billing;
+           export can_refund;
+           has_role("alice", "refunder");
+           can_refund(user, amount) if has_role(user, "refunder") and limit(cap) and amount < cap;"#,
+    );
+    let billing_limits = source(
+        "billing_limits.polar",
+        r#"module billing;
+           limit(100);"#,
+    );
+    let shipping = source(
+        "shipping.polar",
+        r#"module shipping;
+           export can_ship;
+           has_role("bob", "shipper");
+           can_ship(user) if has_role(user, "shipper");"#,
+    );
+    let root = source(
+        "policy.polar",
+        r#"import billing::can_refund;
+           has_role(_, "customer");
+           allow(user, "refund", amount) if can_refund(user, amount);
+           allow(user, "ship", _) if shipping::can_ship(user);
+           ?= allow("alice", "refund", 10);"#,
+    );
+
+    let p = Polar::new();
+    p.load(vec![
+        billing.clone(),
+        billing_limits.clone(),
+        shipping.clone(),
+        root,
+    ])?;
+
+    // Each module's `has_role` rule is separate from the others.
+    qeval(&p, r#"allow("alice", "refund", 10)"#);
+    qnull(&p, r#"allow("alice", "refund", 1000)"#);
+    qnull(&p, r#"allow("bob", "refund", 10)"#);
+    qeval(&p, r#"allow("bob", "ship", 1)"#);
+    qnull(&p, r#"allow("alice", "ship", 1)"#);
+    qvar(
+        &p,
+        r#"has_role("carol", role)"#,
+        "role",
+        values!["customer"],
+    );
+    qvar(
+        &p,
+        r#"billing::has_role(user, _)"#,
+        "user",
+        values!["alice"],
+    );
+    assert!(p.next_inline_query(false).is_some());
+
+    let load_err = |sources: Vec<polar_core::sources::Source>| {
+        let p = Polar::new();
+        let err = p.load(sources).unwrap_err();
+        assert!(!p.kb.read().unwrap().has_rules());
+        err.to_string()
+    };
+
+    // Rules have to be exported to be called from outside their module.
+    let private = source(
+        "private.polar",
+        r#"allow(_, "refund", amount) if billing::limit(amount);"#,
+    );
+    let err = load_err(vec![billing.clone(), billing_limits.clone(), private]);
+    assert!(
+        err.starts_with("Rule limit is not exported from module billing."),
+        "{}",
+        err
+    );
+
+    // Imports can't clash with each other or with rules in the importing module.
+    let clash = source(
+        "clash.polar",
+        r#"import billing::can_refund;
+           can_refund(_, _);"#,
+    );
+    let err = load_err(vec![billing.clone(), billing_limits.clone(), clash]);
+    assert!(err.starts_with(
+        "Import of billing::can_refund is ambiguous: can_refund is also defined in the root namespace."
+    ));
+    let clash = source(
+        "clash.polar",
+        r#"import billing::can_refund, shipping::can_refund;
+           allow(_, _, _);"#,
+    );
+    let err = load_err(vec![billing, billing_limits, shipping, clash]);
+    assert!(err.starts_with(
+        "Import of shipping::can_refund is ambiguous: can_refund is already imported from billing::can_refund."
+    ));
+
+    // Names that don't resolve are undefined.
+    let undefined = source(
+        "undefined.polar",
+        r#"module billing;
+           can_refund(user) if shipping::can_ship(user);"#,
+    );
+    let err = load_err(vec![undefined]);
+    assert!(
+        err.starts_with("Call to undefined rule: shipping::can_ship(user)"),
+        "{}",
+        err
+    );
+
+    let late = source(
+        "late.polar",
+        r#"allow(_, _, _);
+           module billing;"#,
+    );
+    let err = load_err(vec![late]);
+    assert!(err.starts_with("A module declaration must be the first line of a file."));
+    Ok(())
+}
+
+#[test]
+fn test_policy_constants() -> TestResult {
+    let source = |filename: &str, src: &str| polar_core::sources::Source {
+        filename: Some(filename.to_owned()),
+        src: src.to_owned(),
+    };
+    let p = polar();
+    p.load(vec![
+        source(
+            "roles.polar",
+            r#"has_role(user, role) if role in ADMIN_ROLES and user = "alice";
+               ?= has_role("alice", "owner");"#,
+        ),
+        source(
+            "constants.polar",
+            r#"const OWNER = "owner";
+               const ADMIN_ROLES = [OWNER, "admin"];
+               const ROLES = ADMIN_ROLES;
+               const LIMITS = {refund: 100, discount: 0.5};
+               allowed_refund(amount) if amount <= LIMITS.refund;"#,
+        ),
+    ])?;
+
+    // Constants can be used in rules from any source, and in queries.
+    qvar(
+        &p,
+        r#"has_role("alice", role)"#,
+        "role",
+        values!["owner", "admin"],
+    );
+    qnull(&p, r#"has_role("bob", "owner")"#);
+    qeval(&p, "allowed_refund(100)");
+    qnull(&p, "allowed_refund(101)");
+    qvar(&p, "x in ROLES", "x", values!["owner", "admin"]);
+    qeval(&p, "LIMITS.discount = 0.5");
+    assert!(p.next_inline_query(false).is_some());
+
+    // Policy constants survive snapshots.
+    let restored = Polar::from_snapshot(&p.export_snapshot()?)?;
+    qvar(&restored, "x = OWNER", "x", values!["owner"]);
+
+    let load_err = |src: &str| {
+        let p = polar();
+        let err = p.load_str(src).unwrap_err();
+        assert!(!p.kb.read().unwrap().has_rules());
+        err.to_string()
+    };
+    let err = load_err(r#"const OWNER = "owner"; f(OWNER) if OWNER = "owner";"#);
+    assert!(
+        err.starts_with("Parameter 'OWNER' of rule f shadows the constant 'OWNER'"),
+        "{}",
+        err
+    );
+    let err = load_err(r#"const OWNER = "owner"; f(x) if OWNER := x.role and OWNER = "admin";"#);
+    assert!(
+        err.starts_with("Variable 'OWNER' of rule f shadows the constant 'OWNER'"),
+        "{}",
+        err
+    );
+    let err = load_err(r#"const ROLE = "owner"; f(xs) if forall(ROLE in xs, ROLE = "admin");"#);
+    assert!(
+        err.starts_with("Variable 'ROLE' of rule f shadows the constant 'ROLE'"),
+        "{}",
+        err
+    );
+    let err = load_err(r#"const A = 1; const A = 2; f(x) if x = A;"#);
+    assert!(
+        err.starts_with("Constant 'A' is already declared."),
+        "{}",
+        err
+    );
+    let err = load_err(r#"const A = [B]; const B = 1; f(x) if x = A;"#);
+    assert!(
+        err.starts_with("Constant 'A' refers to 'B', which is not a constant declared before it."),
+        "{}",
+        err
+    );
+    let err = load_err(r#"const A = [1 + 2]; f(x) if x = A;"#);
+    assert!(
+        err.starts_with("Constant 'A' must be a literal value."),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
 #[test]
 fn test_emoji_policy() -> TestResult {
     let p = polar();
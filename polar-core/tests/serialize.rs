@@ -2,7 +2,7 @@
 mod tests {
     use std::collections::BTreeMap;
 
-    use polar_core::{error::*, events::*, rules::*, term, terms::*, value};
+    use polar_core::{encoding::Encoding, error::*, events::*, rules::*, term, terms::*, value};
 
     #[test]
     fn serialize_test() {
@@ -80,4 +80,70 @@ mod tests {
         );
         eprintln!("{}", rule);
     }
+
+    #[test]
+    fn encodings_round_trip_every_value_variant() {
+        let external = Term::new_from_test(Value::ExternalInstance(ExternalInstance {
+            instance_id: 1,
+            constructor: Some(term!(Value::Call(Call {
+                name: Symbol::new("Foo"),
+                args: vec![term!(1)],
+                kwargs: None,
+            }))),
+            repr: Some("Foo(1)".to_owned()),
+        }));
+        let mut fields = BTreeMap::new();
+        fields.insert(Symbol::new("x"), term!(1.5));
+        let dict = Dictionary { fields };
+        let values = vec![
+            value!(0),
+            value!(i64::MIN),
+            value!(f64::INFINITY),
+            value!(-0.0),
+            value!("hello"),
+            value!(true),
+            external.value().clone(),
+            Value::Dictionary(dict.clone()),
+            Value::Pattern(Pattern::Dictionary(dict.clone())),
+            Value::Pattern(Pattern::Instance(InstanceLiteral {
+                tag: Symbol::new("Foo"),
+                fields: dict,
+            })),
+            Value::Call(Call {
+                name: Symbol::new("f"),
+                args: vec![term!(1)],
+                kwargs: Some(BTreeMap::new()),
+            }),
+            value!([1, "two", false]),
+            Value::Variable(Symbol::new("x")),
+            Value::RestVariable(Symbol::new("rest")),
+            Value::Expression(Operation {
+                operator: Operator::ForAll,
+                args: vec![term!(1), term!(2)],
+            }),
+        ];
+
+        for encoding in [Encoding::Json, Encoding::MessagePack] {
+            for value in &values {
+                let term = Term::new_from_test(value.clone());
+                let bytes = encoding.encode(&term).unwrap();
+                let decoded: Term = encoding.decode(&bytes).unwrap();
+                assert_eq!(decoded, term, "{:?}", encoding);
+            }
+
+            let event = QueryEvent::ExternalCall {
+                call_id: 2,
+                instance: external.clone(),
+                attribute: Symbol::new("foo"),
+                args: Some(vec![term!(1)]),
+                kwargs: None,
+            };
+            let bytes = encoding.encode(&event).unwrap();
+            let json: serde_json::Value = match encoding {
+                Encoding::Json => serde_json::from_slice(&bytes).unwrap(),
+                Encoding::MessagePack => Encoding::MessagePack.decode(&bytes).unwrap(),
+            };
+            assert_eq!(json, serde_json::to_value(&event).unwrap());
+        }
+    }
 }
@@ -1,13 +1,17 @@
+use serde::Serialize;
 use wasm_bindgen::JsValue;
 
+use polar_core::diagnostic::{self, Context, Range};
 use polar_core::error::{
     ErrorKind, FormattedPolarError, OperationalError, ParseError, PolarError, RuntimeError,
     ValidationError,
 };
+use polar_core::warning::ValidationWarning;
 
 pub struct Error {
     pub kind: String,
     inner: FormattedPolarError,
+    context: Option<Context>,
 }
 
 pub fn serialization_error(msg: String) -> JsValue {
@@ -51,17 +55,38 @@ fn kind(err: &PolarError) -> String {
         Validation(UndefinedRuleCall { .. }) => "ValidationError::UndefinedRuleCall",
         Validation(SingletonVariable { .. }) => "ValidationError::SingletonVariable",
         Validation(UnregisteredClass { .. }) => "ValidationError::UnregisteredClass",
+        Validation(InvalidUnion { .. }) => "ValidationError::InvalidUnion",
+        Validation(InvalidModule { .. }) => "ValidationError::InvalidModule",
+        Validation(InvalidConstant { .. }) => "ValidationError::InvalidConstant",
         Validation(MissingRequiredRule { .. }) => "ValidationError::MissingRequiredRule",
     }
     .to_owned()
 }
 
+fn warning_kind(warning: &ValidationWarning) -> String {
+    use ValidationWarning::*;
+    match warning {
+        AmbiguousPrecedence { .. } => "ValidationWarning::AmbiguousPrecedence",
+        MissingAllowRule => "ValidationWarning::MissingAllowRule",
+        MissingHasPermissionRule => "ValidationWarning::MissingHasPermissionRule",
+        UnknownSpecializer { .. } => "ValidationWarning::UnknownSpecializer",
+        UnreachableDenyRule { .. } => "ValidationWarning::UnreachableDenyRule",
+        UnreachablePermission { .. } => "ValidationWarning::UnreachablePermission",
+        UnusedRole { .. } => "ValidationWarning::UnusedRole",
+        UnusedRelation { .. } => "ValidationWarning::UnusedRelation",
+        EquivalentRoles { .. } => "ValidationWarning::EquivalentRoles",
+    }
+    .to_owned()
+}
+
 impl From<PolarError> for Error {
     fn from(err: PolarError) -> Self {
         let kind = kind(&err);
+        let context = err.context.clone();
         Self {
             inner: err.into(),
             kind,
+            context,
         }
     }
 }
@@ -70,6 +95,16 @@ impl From<Error> for js_sys::Error {
     fn from(err: Error) -> Self {
         let e = Self::new(&err.inner.formatted);
         e.set_name(&err.kind);
+        // Expose the location of the error alongside the formatted message so that callers don't
+        // need to parse it back out of the message.
+        if let Some(Context { source, range }) = err.context {
+            if let Ok(range) = serde_wasm_bindgen::to_value(&range) {
+                let _ = js_sys::Reflect::set(&e, &"range".into(), &range);
+            }
+            if let Some(filename) = source.filename {
+                let _ = js_sys::Reflect::set(&e, &"filename".into(), &filename.into());
+            }
+        }
         e
     }
 }
@@ -79,3 +114,42 @@ impl From<Error> for JsValue {
         js_sys::Error::from(err).into()
     }
 }
+
+/// Serializable form of a `polar_core::diagnostic::Diagnostic`. See the `Diagnostic` TypeScript
+/// type in `types.rs`.
+#[derive(Serialize)]
+pub struct Diagnostic {
+    severity: &'static str,
+    kind: String,
+    message: String,
+    formatted: String,
+    range: Option<Range>,
+    filename: Option<String>,
+}
+
+impl From<diagnostic::Diagnostic> for Diagnostic {
+    fn from(diagnostic: diagnostic::Diagnostic) -> Self {
+        let formatted = diagnostic.to_string();
+        let (severity, kind, message, context) = match diagnostic {
+            diagnostic::Diagnostic::Error(e) => ("error", kind(&e), e.kind.to_string(), e.context),
+            diagnostic::Diagnostic::Warning(w) => (
+                "warning",
+                warning_kind(&w.kind),
+                w.kind.to_string(),
+                w.context,
+            ),
+        };
+        let (range, filename) = match context {
+            Some(Context { source, range }) => (Some(range), source.filename),
+            None => (None, None),
+        };
+        Self {
+            severity,
+            kind,
+            message,
+            formatted,
+            range,
+            filename,
+        }
+    }
+}
@@ -1,6 +1,7 @@
 mod errors;
 mod polar;
 mod query;
+mod types;
 
 pub use polar::Polar;
 pub use query::Query;
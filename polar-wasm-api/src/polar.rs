@@ -1,7 +1,8 @@
-use polar_core::{polar, sources::Source, terms::Symbol};
+use polar_core::{encoding::Encoding, polar, sources::Source, terms::Symbol};
 use wasm_bindgen::prelude::*;
 
-use crate::errors::{serialization_error, Error};
+use crate::errors::{serialization_error, Diagnostic, Error};
+use crate::types::{JsDiagnostics, JsFilterPlan, JsMessage, JsPartialResults, JsTerm, JsTypes};
 use crate::JsResult;
 use crate::Query;
 
@@ -25,38 +26,79 @@ impl Polar {
             .map_err(Error::into)
     }
 
+    /// Load `sources`, returning every diagnostic (errors and warnings) produced while loading
+    /// rather than throwing the first error. If any diagnostic is an error, no rules are loaded.
+    #[wasm_bindgen(js_class = Polar, js_name = diagnosticLoad)]
+    pub fn wasm_diagnostic_load(&self, sources: JsValue) -> JsResult<JsDiagnostics> {
+        let sources: Vec<Source> = serde_wasm_bindgen::from_value(sources)?;
+        let diagnostics = self
+            .0
+            .diagnostic_load(sources)
+            .into_iter()
+            .map(Diagnostic::from)
+            .collect::<Vec<_>>();
+        serde_wasm_bindgen::to_value(&diagnostics)
+            .map(JsCast::unchecked_into)
+            .map_err(|e| serialization_error(e.to_string()))
+    }
+
+    /// Load a snapshot produced by `exportSnapshot` in place of calling `load`.
+    #[wasm_bindgen(js_class = Polar, js_name = loadSnapshot)]
+    pub fn wasm_load_snapshot(&self, snapshot: &[u8]) -> JsResult<()> {
+        self.0
+            .load_snapshot(snapshot)
+            .map_err(Error::from)
+            .map_err(Error::into)
+    }
+
+    /// Serialize the loaded policy into a snapshot that can be restored with `loadSnapshot`.
+    #[wasm_bindgen(js_class = Polar, js_name = exportSnapshot)]
+    pub fn wasm_export_snapshot(&self) -> JsResult<Vec<u8>> {
+        self.0
+            .export_snapshot()
+            .map_err(Error::from)
+            .map_err(Error::into)
+    }
+
     #[wasm_bindgen(js_class = Polar, js_name = clearRules)]
     pub fn wasm_clear_rules(&self) {
         self.0.clear_rules()
     }
 
     #[wasm_bindgen(js_class = Polar, js_name = registerConstant)]
-    pub fn wasm_register_constant(&mut self, name: &str, term: JsValue) -> JsResult<()> {
-        let term = serde_wasm_bindgen::from_value(term)?;
+    pub fn wasm_register_constant(&mut self, name: &str, term: JsTerm) -> JsResult<()> {
+        let term = serde_wasm_bindgen::from_value(term.into())?;
         self.0
             .register_constant(Symbol::new(name), term)
             .map_err(Error::from)?;
         Ok(())
     }
 
+    /// When `trace` is `true`, `Result` events include a `TraceResult`.
     #[wasm_bindgen(js_class = Polar, js_name = nextInlineQuery)]
-    pub fn wasm_next_inline_query(&self) -> Option<Query> {
-        self.0.next_inline_query(false).map(Query::from)
+    pub fn wasm_next_inline_query(&self, trace: Option<bool>) -> Option<Query> {
+        self.0
+            .next_inline_query(trace.unwrap_or(false))
+            .map(Query::from)
     }
 
+    /// When `trace` is `true`, `Result` events include a `TraceResult`.
     #[wasm_bindgen(js_class = Polar, js_name = newQueryFromStr)]
-    pub fn wasm_new_query_from_str(&self, src: &str) -> JsResult<Query> {
+    pub fn wasm_new_query_from_str(&self, src: &str, trace: Option<bool>) -> JsResult<Query> {
         self.0
-            .new_query(src, false)
+            .new_query(src, trace.unwrap_or(false))
             .map(Query::from)
             .map_err(Error::from)
             .map_err(Error::into)
     }
 
+    /// When `trace` is `true`, `Result` events include a `TraceResult`.
     #[wasm_bindgen(js_class = Polar, js_name = newQueryFromTerm)]
-    pub fn wasm_new_query_from_term(&self, term: JsValue) -> JsResult<Query> {
-        let term = serde_wasm_bindgen::from_value(term)?;
-        Ok(Query::from(self.0.new_query_from_term(term, false)))
+    pub fn wasm_new_query_from_term(&self, term: JsTerm, trace: Option<bool>) -> JsResult<Query> {
+        let term = serde_wasm_bindgen::from_value(term.into())?;
+        Ok(Query::from(
+            self.0.new_query_from_term(term, trace.unwrap_or(false)),
+        ))
     }
 
     #[wasm_bindgen(js_class = Polar, js_name = newId)]
@@ -65,9 +107,11 @@ impl Polar {
     }
 
     #[wasm_bindgen(js_class = Polar, js_name = nextMessage)]
-    pub fn wasm_next_message(&self) -> JsResult<JsValue> {
+    pub fn wasm_next_message(&self) -> JsResult<JsMessage> {
         let message = self.0.next_message();
-        serde_wasm_bindgen::to_value(&message).map_err(|e| serialization_error(e.to_string()))
+        serde_wasm_bindgen::to_value(&message)
+            .map(JsCast::unchecked_into)
+            .map_err(|e| serialization_error(e.to_string()))
     }
 
     #[wasm_bindgen(js_class = Polar, js_name = registerMro)]
@@ -82,22 +126,45 @@ impl Polar {
     #[wasm_bindgen(js_class = Polar, js_name = buildFilterPlan)]
     pub fn wasm_build_filter_plan(
         &self,
-        types: JsValue,
-        partial_results: JsValue,
+        types: JsTypes,
+        partial_results: JsPartialResults,
         variable: &str,
         class_tag: &str,
-    ) -> JsResult<JsValue> {
-        let types = serde_wasm_bindgen::from_value(types)?;
-        let partial_results = serde_wasm_bindgen::from_value(partial_results)?;
+    ) -> JsResult<JsFilterPlan> {
+        let types = serde_wasm_bindgen::from_value(types.into())?;
+        let partial_results = serde_wasm_bindgen::from_value(partial_results.into())?;
         self.0
             .build_filter_plan(types, partial_results, variable, class_tag)
             .map_err(Error::from)
             .map_err(Error::into)
             .and_then(|plan| {
-                serde_wasm_bindgen::to_value(&plan).map_err(|e| serialization_error(e.to_string()))
+                serde_wasm_bindgen::to_value(&plan)
+                    .map(JsCast::unchecked_into)
+                    .map_err(|e| serialization_error(e.to_string()))
             })
     }
 
+    /// Like `buildFilterPlan`, but takes `types` and `partial_results` and returns the plan
+    /// encoded as MessagePack.
+    #[wasm_bindgen(js_class = Polar, js_name = buildFilterPlanMessagePack)]
+    pub fn wasm_build_filter_plan_message_pack(
+        &self,
+        types: &[u8],
+        partial_results: &[u8],
+        variable: &str,
+        class_tag: &str,
+    ) -> JsResult<Vec<u8>> {
+        let types = Encoding::MessagePack.decode(types).map_err(Error::from)?;
+        let partial_results = Encoding::MessagePack
+            .decode(partial_results)
+            .map_err(Error::from)?;
+        self.0
+            .build_filter_plan(types, partial_results, variable, class_tag)
+            .and_then(|plan| Encoding::MessagePack.encode(&plan))
+            .map_err(Error::from)
+            .map_err(Error::into)
+    }
+
     // TODO(@gkaemmer): this is a hack and should not be used for similar cases.
     // Ideally, we'd have a single "configuration" entrypoint for both the Polar
     // and Query types.
@@ -1,7 +1,8 @@
-use polar_core::{query, terms::Symbol};
+use polar_core::{encoding::Encoding, query, terms::Symbol};
 use wasm_bindgen::prelude::*;
 
 use crate::errors::{serialization_error, Error};
+use crate::types::{JsMessage, JsQueryEvent, JsTerm};
 use crate::JsResult;
 
 #[wasm_bindgen]
@@ -16,19 +17,50 @@ impl From<query::Query> for Query {
 #[wasm_bindgen]
 impl Query {
     #[wasm_bindgen(js_class = Query, js_name = nextEvent)]
-    pub fn wasm_next_event(&mut self) -> JsResult<JsValue> {
+    pub fn wasm_next_event(&mut self) -> JsResult<JsQueryEvent> {
         self.0
             .next_event()
             .map_err(Error::from)
             .map_err(Error::into)
             .and_then(|event| {
-                serde_wasm_bindgen::to_value(&event).map_err(|e| serialization_error(e.to_string()))
+                serde_wasm_bindgen::to_value(&event)
+                    .map(JsCast::unchecked_into)
+                    .map_err(|e| serialization_error(e.to_string()))
             })
     }
 
+    /// Like `nextEvent`, but returns the event encoded as MessagePack.
+    #[wasm_bindgen(js_class = Query, js_name = nextEventMessagePack)]
+    pub fn wasm_next_event_message_pack(&mut self) -> JsResult<Vec<u8>> {
+        self.0
+            .next_event()
+            .and_then(|event| Encoding::MessagePack.encode(&event))
+            .map_err(Error::from)
+            .map_err(Error::into)
+    }
+
     #[wasm_bindgen(js_class = Query, js_name = callResult)]
-    pub fn wasm_call_result(&mut self, call_id: f64, term: JsValue) -> JsResult<()> {
-        let term = serde_wasm_bindgen::from_value(term)?;
+    pub fn wasm_call_result(&mut self, call_id: f64, term: Option<JsTerm>) -> JsResult<()> {
+        let term = term
+            .map(|term| serde_wasm_bindgen::from_value(term.into()))
+            .transpose()?;
+        self.0
+            .call_result(call_id as u64, term)
+            .map_err(Error::from)
+            .map_err(Error::into)
+    }
+
+    /// Like `callResult`, but takes the term encoded as MessagePack.
+    #[wasm_bindgen(js_class = Query, js_name = callResultMessagePack)]
+    pub fn wasm_call_result_message_pack(
+        &mut self,
+        call_id: f64,
+        term: Option<Vec<u8>>,
+    ) -> JsResult<()> {
+        let term = term
+            .map(|bytes| Encoding::MessagePack.decode(&bytes))
+            .transpose()
+            .map_err(Error::from)?;
         self.0
             .call_result(call_id as u64, term)
             .map_err(Error::from)
@@ -60,9 +92,11 @@ impl Query {
     }
 
     #[wasm_bindgen(js_class = Query, js_name = nextMessage)]
-    pub fn wasm_next_message(&self) -> JsResult<JsValue> {
+    pub fn wasm_next_message(&self) -> JsResult<JsMessage> {
         let message = self.0.next_message();
-        serde_wasm_bindgen::to_value(&message).map_err(|e| serialization_error(e.to_string()))
+        serde_wasm_bindgen::to_value(&message)
+            .map(JsCast::unchecked_into)
+            .map_err(|e| serialization_error(e.to_string()))
     }
 
     #[wasm_bindgen(js_class = Query, js_name = source)]
@@ -71,14 +105,26 @@ impl Query {
     }
 
     #[wasm_bindgen(js_class = Query, js_name = bind)]
-    pub fn wasm_bind(&mut self, name: &str, term: JsValue) -> JsResult<()> {
-        let term = serde_wasm_bindgen::from_value(term)?;
+    pub fn wasm_bind(&mut self, name: &str, term: JsTerm) -> JsResult<()> {
+        let term = serde_wasm_bindgen::from_value(term.into())?;
         self.0
             .bind(Symbol::new(name), term)
             .map_err(Error::from)
             .map_err(Error::into)
     }
 
+    /// Override the query timeout in milliseconds. `0` disables the timeout.
+    #[wasm_bindgen(js_class = Query, js_name = setQueryTimeout)]
+    pub fn wasm_set_query_timeout(&mut self, timeout_ms: f64) {
+        self.0.set_query_timeout_ms(timeout_ms as u64);
+    }
+
+    /// Override the maximum goal stack size.
+    #[wasm_bindgen(js_class = Query, js_name = setStackLimit)]
+    pub fn wasm_set_stack_limit(&mut self, limit: usize) {
+        self.0.set_stack_limit(limit);
+    }
+
     #[cfg(target_arch = "wasm32")]
     #[wasm_bindgen(js_class = Query, js_name = setLoggingOptions)]
     pub fn wasm_set_logging_options(
@@ -0,0 +1,248 @@
+//! TypeScript types for the values exchanged with JavaScript.
+//!
+//! Values cross the boundary via `serde_wasm_bindgen`, so the shapes below mirror the serde
+//! representation of the corresponding `polar_core` types: structs become plain objects, maps
+//! become `Map`s, and enums are externally tagged (`{ Variant: { ... } }`), with unit variants
+//! represented as strings. The declarations are emitted into the `.d.ts` file generated by
+//! `wasm-bindgen`, and the `extern` types below are used in method signatures so that every
+//! method returns a typed value instead of `any`.
+//!
+//! `languages/js/src/wasmTypes.test.ts` checks the generated declarations against the values the
+//! API returns.
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TYPESCRIPT_TYPES: &'static str = r#"
+export type Numeric = { Integer: number } | { Float: number | "NaN" | "Infinity" | "-Infinity" };
+
+export type Operator =
+  | "Debug" | "Print" | "Cut" | "In" | "Isa" | "New" | "Dot" | "Not" | "Mul" | "Div" | "Mod"
+  | "Rem" | "Add" | "Sub" | "Eq" | "Geq" | "Leq" | "Neq" | "Gt" | "Lt" | "Unify" | "Or" | "And"
+  | "ForAll" | "Assign" | "Count" | "Collect" | "Sum" | "Min" | "Max";
+
+export interface Dictionary {
+  fields: Map<string, Term>;
+}
+
+export interface InstanceLiteral {
+  tag: string;
+  fields: Dictionary;
+}
+
+export interface ExternalInstance {
+  instance_id: number;
+  constructor: Term | undefined;
+  repr: string | undefined;
+}
+
+export interface Call {
+  name: string;
+  args: Term[];
+  kwargs: Map<string, Term> | undefined;
+}
+
+export interface Operation {
+  operator: Operator;
+  args: Term[];
+}
+
+export type Pattern = { Dictionary: Dictionary } | { Instance: InstanceLiteral };
+
+export type Value =
+  | { Number: Numeric }
+  | { String: string }
+  | { Boolean: boolean }
+  | { ExternalInstance: ExternalInstance }
+  | { Dictionary: Dictionary }
+  | { Pattern: Pattern }
+  | { Call: Call }
+  | { List: Term[] }
+  | { Variable: string }
+  | { RestVariable: string }
+  | { Expression: Operation };
+
+export interface Term {
+  value: Value;
+}
+
+export interface Parameter {
+  parameter: Term;
+  specializer: Term | undefined;
+}
+
+export interface Rule {
+  name: string;
+  params: Parameter[];
+  body: Term;
+  required: boolean;
+  tabled: boolean;
+}
+
+export type TraceNode = { Rule: Rule } | { Term: Term };
+
+export interface Trace {
+  node: TraceNode;
+  children: Trace[];
+}
+
+export interface TraceResult {
+  trace: Trace;
+  formatted: string;
+}
+
+export type QueryEvent =
+  | "None"
+  | { Done: { result: boolean } }
+  | { Debug: { message: string } }
+  | { MakeExternal: { instance_id: number; constructor: Term } }
+  | {
+      ExternalCall: {
+        call_id: number;
+        instance: Term;
+        attribute: string;
+        args: Term[] | undefined;
+        kwargs: Map<string, Term> | undefined;
+      };
+    }
+  | { ExternalIsa: { call_id: number; instance: Term; class_tag: string } }
+  | {
+      ExternalIsaWithPath: {
+        call_id: number;
+        base_tag: string;
+        path: Term[];
+        class_tag: string;
+      };
+    }
+  | {
+      ExternalIsSubSpecializer: {
+        call_id: number;
+        instance_id: number;
+        left_class_tag: string;
+        right_class_tag: string;
+      };
+    }
+  | {
+      ExternalIsSubclass: {
+        call_id: number;
+        left_class_tag: string;
+        right_class_tag: string;
+      };
+    }
+  | { Result: { bindings: Map<string, Term>; trace: TraceResult | undefined } }
+  | { ExternalOp: { call_id: number; operator: Operator; args: Term[] } }
+  | { NextExternal: { call_id: number; iterable: Term } };
+
+export interface Message {
+  kind: "Print" | "Warning";
+  msg: string;
+}
+
+export interface Position {
+  row: number;
+  column: number;
+}
+
+export interface Range {
+  start: Position;
+  end: Position;
+}
+
+export interface Diagnostic {
+  severity: "error" | "warning";
+  /** E.g., `ValidationError::UndefinedRuleCall` or `ValidationWarning::MissingAllowRule`. */
+  kind: string;
+  /** The message without source context. */
+  message: string;
+  /** The full message, including source context. */
+  formatted: string;
+  /** Zero-indexed location of the diagnostic, if it has one. */
+  range: Range | undefined;
+  filename: string | undefined;
+}
+
+export type DataFilteringType =
+  | { Base: { class_tag: string } }
+  | {
+      Relation: {
+        kind: string;
+        other_class_tag: string;
+        my_field: string;
+        other_field: string;
+      };
+    };
+
+export type Types = Map<string, Map<string, DataFilteringType>>;
+
+export interface PartialResult {
+  bindings: Map<string, Term>;
+}
+
+export type ConstraintValue =
+  | { Term: Term }
+  | { Ref: { field: string | undefined; result_id: number } }
+  | { Field: string };
+
+export type ConstraintKind =
+  | "Eq"
+  | "In"
+  | "Contains"
+  | "Neq"
+  | "Nin"
+  | "Lt"
+  | "Leq"
+  | "Gt"
+  | "Geq";
+
+export interface Constraint {
+  kind: ConstraintKind;
+  field: string | undefined;
+  value: ConstraintValue;
+}
+
+export interface FetchRequest {
+  class_tag: string;
+  constraints: Constraint[];
+}
+
+export interface ResultSet {
+  requests: Map<number, FetchRequest>;
+  resolve_order: number[];
+  result_id: number;
+}
+
+export interface FilterPlan {
+  result_sets: ResultSet[];
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[derive(Debug)]
+    #[wasm_bindgen(typescript_type = "QueryEvent")]
+    pub type JsQueryEvent;
+
+    #[derive(Debug)]
+    #[wasm_bindgen(typescript_type = "Message | undefined")]
+    pub type JsMessage;
+
+    #[derive(Debug)]
+    #[wasm_bindgen(typescript_type = "Diagnostic[]")]
+    pub type JsDiagnostics;
+
+    #[derive(Debug)]
+    #[wasm_bindgen(typescript_type = "FilterPlan")]
+    pub type JsFilterPlan;
+
+    #[derive(Debug)]
+    #[wasm_bindgen(typescript_type = "Types")]
+    pub type JsTypes;
+
+    #[derive(Debug)]
+    #[wasm_bindgen(typescript_type = "PartialResult[]")]
+    pub type JsPartialResults;
+
+    #[derive(Debug)]
+    #[wasm_bindgen(typescript_type = "Term")]
+    pub type JsTerm;
+}
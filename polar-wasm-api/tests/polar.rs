@@ -39,6 +39,29 @@ fn load_file_errors() {
     ));
 }
 
+#[wasm_bindgen_test]
+fn snapshots_can_be_exported_and_loaded() {
+    let polar = polar_wasm_api::Polar::wasm_new();
+    let source = Source {
+        src: "?= 1 = 1;".to_owned(),
+        filename: None,
+    };
+    let sources: JsValue = serde_wasm_bindgen::to_value(&vec![source]).unwrap();
+    polar.wasm_load(sources).unwrap();
+    let snapshot = polar.wasm_export_snapshot().unwrap();
+
+    let polar = polar_wasm_api::Polar::wasm_new();
+    polar.wasm_load_snapshot(&snapshot).unwrap();
+    assert!(polar.wasm_next_inline_query(None).is_some());
+
+    let err: Error = polar
+        .wasm_load_snapshot(&snapshot[1..])
+        .unwrap_err()
+        .dyn_into()
+        .unwrap();
+    assert_eq!(err.name(), "OperationalError::Serialization");
+}
+
 #[wasm_bindgen_test]
 fn next_inline_query_succeeds() {
     let polar = polar_wasm_api::Polar::wasm_new();
@@ -50,7 +73,7 @@ fn next_inline_query_succeeds() {
     let res = polar.wasm_load(sources);
     assert!(matches!(res, Ok(())));
 
-    let mut query = polar.wasm_next_inline_query().unwrap();
+    let mut query = polar.wasm_next_inline_query(None).unwrap();
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     let event_kind: JsValue = "Result".into();
     let event_data = Reflect::get(&event, &event_kind).unwrap();
@@ -61,7 +84,7 @@ fn next_inline_query_succeeds() {
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     assert!(is_done_event(event));
 
-    assert!(polar.wasm_next_inline_query().is_none());
+    assert!(polar.wasm_next_inline_query(None).is_none());
 }
 
 #[wasm_bindgen_test]
@@ -74,12 +97,12 @@ fn next_inline_query_errors() {
     let sources: JsValue = serde_wasm_bindgen::to_value(&vec![source]).unwrap();
     let res = polar.wasm_load(sources);
     assert!(matches!(res, Ok(())));
-    let mut query = polar.wasm_next_inline_query().unwrap();
+    let mut query = polar.wasm_next_inline_query(None).unwrap();
 
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     assert!(is_done_event(event));
 
-    assert!(polar.wasm_next_inline_query().is_none());
+    assert!(polar.wasm_next_inline_query(None).is_none());
 }
 
 #[wasm_bindgen_test]
@@ -92,7 +115,8 @@ fn register_constant_succeeds() {
             constructor: None,
             repr: None,
         })))
-        .unwrap(),
+        .unwrap()
+        .unchecked_into(),
     );
     assert!(matches!(res, Ok(())));
 }
@@ -100,7 +124,7 @@ fn register_constant_succeeds() {
 #[wasm_bindgen_test]
 fn new_query_from_str_succeeds() {
     let polar = polar_wasm_api::Polar::wasm_new();
-    let mut query = polar.wasm_new_query_from_str("x()").unwrap();
+    let mut query = polar.wasm_new_query_from_str("x()", None).unwrap();
 
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     assert!(is_done_event(event));
@@ -109,7 +133,7 @@ fn new_query_from_str_succeeds() {
 #[wasm_bindgen_test]
 fn new_query_from_str_errors() {
     let polar = polar_wasm_api::Polar::wasm_new();
-    let mut query = polar.wasm_new_query_from_str("[]").unwrap();
+    let mut query = polar.wasm_new_query_from_str("[]", None).unwrap();
     let err: Error = query.wasm_next_event().unwrap_err().dyn_into().unwrap();
     assert_eq!(err.name(), "RuntimeError::TypeError");
     assert!(err.message().starts_with("trace (most recent evaluation last):\n  in query at line 1, column 1\n    []\nType error: [] isn\'t something that is true or false so can\'t be a condition at line 1, column 1", 0));
@@ -124,7 +148,9 @@ fn new_query_from_term_succeeds() {
         kwargs: None,
     }));
     let term = serde_wasm_bindgen::to_value(&term).unwrap();
-    let mut query = polar.wasm_new_query_from_term(term).unwrap();
+    let mut query = polar
+        .wasm_new_query_from_term(term.unchecked_into(), None)
+        .unwrap();
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     assert!(is_done_event(event));
 }
@@ -132,7 +158,7 @@ fn new_query_from_term_succeeds() {
 #[wasm_bindgen_test]
 fn new_query_from_term_errors() {
     let polar = polar_wasm_api::Polar::wasm_new();
-    let res = polar.wasm_new_query_from_term("".into());
+    let res = polar.wasm_new_query_from_term(JsValue::from("").unchecked_into(), None);
     if let Err(err) = res {
         let err: Error = err.dyn_into().unwrap();
         assert_eq!(err.name(), "Error");
@@ -152,3 +178,50 @@ fn get_external_id_succeeds() {
     assert_eq!(polar.wasm_get_external_id(), 1.0);
     assert_eq!(polar.wasm_get_external_id(), 2.0);
 }
+
+#[wasm_bindgen_test]
+fn diagnostic_load_returns_all_diagnostics() {
+    let polar = polar_wasm_api::Polar::wasm_new();
+    let source = Source {
+        src: "f() if g();".to_owned(),
+        filename: Some("foo.polar".to_owned()),
+    };
+    let sources: JsValue = serde_wasm_bindgen::to_value(&vec![source]).unwrap();
+    let diagnostics: js_sys::Array = polar
+        .wasm_diagnostic_load(sources)
+        .unwrap()
+        .unchecked_into();
+    assert_eq!(diagnostics.length(), 2);
+
+    let get = |target: &JsValue, key: &str| Reflect::get(target, &key.into()).unwrap();
+    let error = diagnostics.get(0);
+    assert_eq!(get(&error, "severity"), "error");
+    assert_eq!(get(&error, "kind"), "ValidationError::UndefinedRuleCall");
+    assert_eq!(get(&error, "message"), "Call to undefined rule: g()");
+    assert_eq!(get(&error, "filename"), "foo.polar");
+    let start = get(&get(&error, "range"), "start");
+    assert_eq!(get(&start, "row"), 0);
+    assert_eq!(get(&start, "column"), 7);
+
+    let warning = diagnostics.get(1);
+    assert_eq!(get(&warning, "severity"), "warning");
+    assert_eq!(get(&warning, "kind"), "ValidationWarning::MissingAllowRule");
+    assert!(get(&warning, "range").is_undefined());
+}
+
+#[wasm_bindgen_test]
+fn errors_expose_their_range() {
+    let polar = polar_wasm_api::Polar::wasm_new();
+    let source = Source {
+        src: "f(x) if\n  ;".to_owned(),
+        filename: Some("foo.polar".to_owned()),
+    };
+    let sources: JsValue = serde_wasm_bindgen::to_value(&vec![source]).unwrap();
+    let err = polar.wasm_load(sources).unwrap_err();
+    let start = Reflect::get(
+        &Reflect::get(&err, &"range".into()).unwrap(),
+        &"start".into(),
+    );
+    assert_eq!(Reflect::get(&start.unwrap(), &"row".into()).unwrap(), 1);
+    assert_eq!(Reflect::get(&err, &"filename".into()).unwrap(), "foo.polar");
+}
@@ -15,14 +15,16 @@ fn call_result_succeeds() {
         repr: None,
     }));
     let term = serde_wasm_bindgen::to_value(&term).unwrap();
-    polar.wasm_register_constant("y", term).unwrap();
+    polar
+        .wasm_register_constant("y", term.unchecked_into())
+        .unwrap();
     let source = Source {
         src: "x() if y.z;".to_owned(),
         filename: None,
     };
     let sources: JsValue = serde_wasm_bindgen::to_value(&vec![source]).unwrap();
     polar.wasm_load(sources).unwrap();
-    let mut query = polar.wasm_new_query_from_str("x()").unwrap();
+    let mut query = polar.wasm_new_query_from_str("x()", None).unwrap();
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     let event_kind: JsValue = "ExternalCall".into();
     let event_data = Reflect::get(&event, &event_kind).unwrap();
@@ -32,7 +34,9 @@ fn call_result_succeeds() {
     assert_eq!(call_id, 3.0);
 
     let call_result = serde_wasm_bindgen::to_value(&Term::from(Value::Boolean(true))).unwrap();
-    query.wasm_call_result(3.0, call_result).unwrap();
+    query
+        .wasm_call_result(3.0, Some(call_result.unchecked_into()))
+        .unwrap();
 
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     let event_kind: JsValue = "Result".into();
@@ -41,7 +45,7 @@ fn call_result_succeeds() {
     let bindings = Reflect::get(&event_data, &data_key).unwrap();
     assert_eq!(bindings.dyn_into::<Map>().unwrap().size(), 0);
 
-    query.wasm_call_result(3.0, JsValue::undefined()).unwrap();
+    query.wasm_call_result(3.0, None).unwrap();
 
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     let event_kind: JsValue = "Done".into();
@@ -58,14 +62,16 @@ fn app_error_succeeds() {
         repr: None,
     }));
     let term = serde_wasm_bindgen::to_value(&term).unwrap();
-    polar.wasm_register_constant("y", term).unwrap();
+    polar
+        .wasm_register_constant("y", term.unchecked_into())
+        .unwrap();
     let source = Source {
         src: "x() if y.z;".to_owned(),
         filename: None,
     };
     let sources: JsValue = serde_wasm_bindgen::to_value(&vec![source]).unwrap();
     polar.wasm_load(sources).unwrap();
-    let mut query = polar.wasm_new_query_from_str("x()").unwrap();
+    let mut query = polar.wasm_new_query_from_str("x()", None).unwrap();
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     let event_kind: JsValue = "ExternalCall".into();
     let event_data = Reflect::get(&event, &event_kind).unwrap();
@@ -85,7 +91,7 @@ fn app_error_succeeds() {
 #[wasm_bindgen_test]
 fn debug_command_succeeds() {
     let polar = polar_wasm_api::Polar::wasm_new();
-    let mut query = polar.wasm_new_query_from_str("x()").unwrap();
+    let mut query = polar.wasm_new_query_from_str("x()", None).unwrap();
     query.wasm_debug_command("h").unwrap();
     let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
     let event_kind: JsValue = "Debug".into();
@@ -96,3 +102,39 @@ fn debug_command_succeeds() {
     let msg: JsString = msg.dyn_into().unwrap();
     assert!(msg.includes("Debugger Commands", 0));
 }
+
+#[wasm_bindgen_test]
+fn traced_queries_return_trace_results() {
+    let polar = polar_wasm_api::Polar::wasm_new();
+    let source = Source {
+        src: "f(1);".to_owned(),
+        filename: None,
+    };
+    let sources: JsValue = serde_wasm_bindgen::to_value(&vec![source]).unwrap();
+    polar.wasm_load(sources).unwrap();
+    let mut query = polar.wasm_new_query_from_str("f(x)", Some(true)).unwrap();
+    let event: Object = query.wasm_next_event().unwrap().dyn_into().unwrap();
+    let result = Reflect::get(&event, &"Result".into()).unwrap();
+    let trace = Reflect::get(&result, &"trace".into()).unwrap();
+    let formatted: JsString = Reflect::get(&trace, &"formatted".into())
+        .unwrap()
+        .dyn_into()
+        .unwrap();
+    assert!(formatted.includes("f(1)", 0));
+    assert!(Reflect::get(&trace, &"trace".into()).unwrap().is_object());
+}
+
+#[wasm_bindgen_test]
+fn query_limits_can_be_configured() {
+    let polar = polar_wasm_api::Polar::wasm_new();
+    let source = Source {
+        src: "f(x) if f(x);".to_owned(),
+        filename: None,
+    };
+    let sources: JsValue = serde_wasm_bindgen::to_value(&vec![source]).unwrap();
+    polar.wasm_load(sources).unwrap();
+    let mut query = polar.wasm_new_query_from_str("f(1)", None).unwrap();
+    query.wasm_set_stack_limit(10);
+    let err: Error = query.wasm_next_event().unwrap_err().dyn_into().unwrap();
+    assert_eq!(err.name(), "RuntimeError::StackOverflow");
+}